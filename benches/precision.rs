@@ -0,0 +1,39 @@
+//! renders in the precision the crate was built with. compare the two with
+//! `cargo bench --bench precision -- --save-baseline f64`, then
+//! `cargo bench --bench precision --features f32 -- --baseline f64`
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use raytracer::integrator::PathTracer;
+use raytracer::math::{seed_rng, Float, Normalize, Vec3};
+use raytracer::render::Film;
+use raytracer::rt::*;
+
+fn spheres() -> Scene {
+    seed_rng(0);
+    scenes::spheres()
+}
+
+fn precision(c: &mut Criterion) {
+    let scene = spheres();
+    let camera = scene.view.camera(16. / 9.);
+    c.bench_function("render pass", |b| {
+        let mut film = Film::new(80, 45, false);
+        b.iter(|| film.render_pass(&camera, &scene.world, &PathTracer::new(8), 1))
+    });
+
+    let ray = Ray::new(
+        Vec3::new(0., 0.2, -10.),
+        Vec3::new(0.01, 0., 1.).normalize(),
+    );
+    c.bench_function("world hit", |b| {
+        b.iter(|| {
+            scene
+                .world
+                .hit(black_box(ray), 0.001..Float::INFINITY)
+                .is_some()
+        })
+    });
+}
+
+criterion_group!(benches, precision);
+criterion_main!(benches);
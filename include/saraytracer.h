@@ -0,0 +1,84 @@
+/* the c api of saraytracer, for embedding it in programs written in other languages. build the
+ * crate as a cdylib (`cargo build --release`) and link against the library in target/release.
+ *
+ *     SrtScene *scene = srt_scene_new();
+ *     double center[3] = {0, 0, 0};
+ *     SrtMaterial gold = {SRT_METAL, {0.9, 0.7, 0.4}, 0.1};
+ *     srt_add_sphere(scene, center, 1, gold);
+ *     uint8_t *pixels = malloc(400 * 225 * 4);
+ *     srt_render(scene, 400, 225, 64, 8, pixels, NULL, NULL);
+ *     srt_scene_free(scene);
+ *
+ * functions returning int32_t return SRT_OK, SRT_INVALID_ARGUMENT for a null pointer, a number
+ * out of range or an unknown material kind, SRT_CANCELLED when a progress callback stops a
+ * render, or SRT_PANICKED if the tracer panicked, which may leave the scene half changed. a
+ * scene mustn't be used from two threads at once */
+
+#ifndef SARAYTRACER_H
+#define SARAYTRACER_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define SRT_OK 0
+#define SRT_INVALID_ARGUMENT (-1)
+#define SRT_CANCELLED 1
+#define SRT_PANICKED (-2)
+
+/* shapes, lights and a camera, behind an opaque pointer */
+typedef struct SrtScene SrtScene;
+
+typedef enum SrtMaterialKind {
+    SRT_DIFFUSE = 0,
+    /* param is how fuzzy reflections are, from 0 for a mirror */
+    SRT_METAL = 1,
+    /* param is the refraction index. the color is left out */
+    SRT_GLASS = 2,
+    /* glows with color as its radiance, and is sampled directly as an area light */
+    SRT_LIGHT = 3,
+} SrtMaterialKind;
+
+typedef struct SrtMaterial {
+    /* one of SrtMaterialKind */
+    uint32_t kind;
+    /* linear rgb */
+    double color[3];
+    double param;
+} SrtMaterial;
+
+/* called after each pass of srt_render with the samples taken of every pixel so far, out of
+ * those asked for. return false to stop the render */
+typedef bool (*SrtProgress)(uint32_t samples, uint32_t spp, void *user_data);
+
+/* a new scene under the default sky, seen from 10 units down -z */
+SrtScene *srt_scene_new(void);
+void srt_scene_free(SrtScene *scene);
+
+/* center is 3 doubles */
+int32_t srt_add_sphere(SrtScene *scene, const double *center, double radius, SrtMaterial material);
+/* positions are 3 doubles for each vertex, indices 3 for each triangle, counter-clockwise seen
+ * from the front. shaded flat */
+int32_t srt_add_mesh(SrtScene *scene, const double *positions, size_t vertices,
+                     const uint32_t *indices, size_t triangles, SrtMaterial material);
+/* a solid color in place of the sky, black for scenes lit only by their lights */
+int32_t srt_set_background(SrtScene *scene, const double *rgb);
+/* vfov is the vertical field of view in degrees, aperture the lens diameter, 0 for a pinhole.
+ * look_at is in focus */
+int32_t srt_set_camera(SrtScene *scene, const double *eye, const double *look_at, double vfov,
+                       double aperture);
+
+/* path traces the scene and tone maps it into pixels, which need room for width * height * 4
+ * bytes of 8 bit rgba, row by row from the top. progress may be NULL */
+int32_t srt_render(const SrtScene *scene, uint32_t width, uint32_t height, uint32_t spp,
+                   uint32_t max_depth, uint8_t *pixels, SrtProgress progress, void *user_data);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
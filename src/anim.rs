@@ -0,0 +1,252 @@
+//! keyframed animation: values set at a few moments and blended in between, for cameras and the
+//! poses of objects, plus the frames an animation is rendered as
+
+use crate::math::{Float, Mat4, Vec3};
+use crate::rt::View;
+use serde::{Deserialize, Serialize};
+
+/// values that can be blended from one keyframe to the next
+pub trait Lerp {
+    /// `self` at `t` = 0 through to `other` at `t` = 1
+    fn lerp(&self, other: &Self, t: Float) -> Self;
+}
+
+impl Lerp for Float {
+    fn lerp(&self, other: &Self, t: Float) -> Self {
+        self + (other - self) * t
+    }
+}
+
+impl Lerp for Vec3 {
+    fn lerp(&self, other: &Self, t: Float) -> Self {
+        Vec3::lerp(*self, *other, t)
+    }
+}
+
+impl Lerp for View {
+    fn lerp(&self, other: &Self, t: Float) -> Self {
+        View {
+            eye: self.eye.lerp(other.eye, t),
+            look_at: self.look_at.lerp(other.look_at, t),
+            up: self.up.lerp(other.up, t),
+            vfov: self.vfov.lerp(&other.vfov, t),
+            aperture: self.aperture.lerp(&other.aperture, t),
+            aperture_shape: self.aperture_shape,
+            projection: self.projection,
+            shutter: self.shutter,
+            focus: self.focus,
+        }
+    }
+}
+
+/// how the blend into a keyframe speeds up and slows down
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Ease {
+    /// at an even pace
+    #[default]
+    Linear,
+    /// starting and stopping gently
+    Smooth,
+}
+
+impl Ease {
+    fn apply(self, t: Float) -> Float {
+        match self {
+            Ease::Linear => t,
+            Ease::Smooth => t * t * (3. - 2. * t),
+        }
+    }
+}
+
+/// a value at a moment, in seconds
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Key<T> {
+    pub time: Float,
+    pub value: T,
+    /// blend into this key from the one before
+    #[serde(default)]
+    pub ease: Ease,
+}
+
+impl<T> Key<T> {
+    pub fn new(time: Float, value: T) -> Self {
+        Self {
+            time,
+            value,
+            ease: Ease::Linear,
+        }
+    }
+
+    pub fn with_ease(self, ease: Ease) -> Self {
+        Self { ease, ..self }
+    }
+
+    /// the same key with its value converted
+    pub fn map<U>(&self, f: impl FnOnce(&T) -> U) -> Key<U> {
+        Key {
+            time: self.time,
+            value: f(&self.value),
+            ease: self.ease,
+        }
+    }
+}
+
+/// a value that changes over time, blended between keys. it holds still before the first key
+/// and after the last
+#[derive(Clone, Debug, PartialEq)]
+pub struct Keyframes<T> {
+    keys: Vec<Key<T>>,
+}
+
+impl<T: Lerp + Clone> Keyframes<T> {
+    /// constructor. the keys can be given in any order
+    pub fn new(mut keys: Vec<Key<T>>) -> Self {
+        keys.sort_by(|a, b| a.time.total_cmp(&b.time));
+        Self { keys }
+    }
+
+    /// the value at `time`, or none if there are no keys
+    pub fn at(&self, time: Float) -> Option<T> {
+        let next = self.keys.partition_point(|k| k.time <= time);
+        match (
+            next.checked_sub(1).map(|i| &self.keys[i]),
+            self.keys.get(next),
+        ) {
+            (Some(a), Some(b)) => {
+                let t = (time - a.time) / (b.time - a.time);
+                Some(a.value.lerp(&b.value, b.ease.apply(t)))
+            }
+            (Some(key), None) | (None, Some(key)) => Some(key.value.clone()),
+            (None, None) => None,
+        }
+    }
+}
+
+/// where an object sits: scaled, then rotated, then moved. rotations are in degrees around
+/// the x, y and z axes, in that order, so they keep turning past 360 between keys
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Pose {
+    pub translate: Vec3,
+    pub rotate: Vec3,
+    pub scale: Vec3,
+}
+
+impl Default for Pose {
+    fn default() -> Self {
+        Self {
+            translate: Vec3::ZERO,
+            rotate: Vec3::ZERO,
+            scale: Vec3::ONE,
+        }
+    }
+}
+
+impl Pose {
+    /// object to world
+    pub fn matrix(&self) -> Mat4 {
+        Mat4::translate(self.translate)
+            * Mat4::rotate(Vec3::Z, self.rotate.z)
+            * Mat4::rotate(Vec3::Y, self.rotate.y)
+            * Mat4::rotate(Vec3::X, self.rotate.x)
+            * Mat4::scale(self.scale)
+    }
+}
+
+impl Lerp for Pose {
+    fn lerp(&self, other: &Self, t: Float) -> Self {
+        Pose {
+            translate: self.translate.lerp(other.translate, t),
+            rotate: self.rotate.lerp(other.rotate, t),
+            scale: self.scale.lerp(other.scale, t),
+        }
+    }
+}
+
+/// how long an animation runs and how many frames a second it's rendered at
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Animation {
+    pub fps: Float,
+    /// in seconds. a still scene lasts no time and has a single frame
+    #[serde(default)]
+    pub duration: Float,
+}
+
+impl Default for Animation {
+    fn default() -> Self {
+        Self {
+            fps: 24.,
+            duration: 0.,
+        }
+    }
+}
+
+impl Animation {
+    /// frames to render. the last frame comes a frame before the end, so looping animations
+    /// don't show the same moment twice
+    pub fn frames(&self) -> u32 {
+        ((self.duration * self.fps).round() as u32).max(1)
+    }
+
+    /// the moment a frame shows, counting frames from 0
+    pub fn time(&self, frame: u32) -> Float {
+        frame as Float / self.fps
+    }
+}
+
+/// the path a frame is saved to: the run of `#`s in `pattern` replaced by the frame number,
+/// padded with zeros to the same width. without one, `_0001` and so on go before the extension
+pub fn frame_path(pattern: &str, frame: u32) -> String {
+    match pattern.find('#') {
+        Some(start) => {
+            let width = pattern[start..].chars().take_while(|&c| c == '#').count();
+            let end = start + width;
+            format!("{}{frame:0width$}{}", &pattern[..start], &pattern[end..])
+        }
+        None => {
+            let name_start = pattern.rfind('/').map_or(0, |i| i + 1);
+            match pattern[name_start..].rfind('.') {
+                Some(dot) => {
+                    let (stem, extension) = pattern.split_at(name_start + dot);
+                    format!("{stem}_{frame:04}{extension}")
+                }
+                None => format!("{pattern}_{frame:04}"),
+            }
+        }
+    }
+}
+
+#[test]
+fn keyframes_blend() {
+    let keys = Keyframes::new(vec![
+        Key::new(2., 10.).with_ease(Ease::Smooth),
+        Key::new(0., 0.),
+        Key::new(3., 20.),
+    ]);
+    assert_eq!(keys.at(-1.), Some(0.));
+    assert_eq!(keys.at(0.5), Some(1.5625));
+    assert_eq!(keys.at(2.), Some(10.));
+    assert_eq!(keys.at(2.5), Some(15.));
+    assert_eq!(keys.at(9.), Some(20.));
+    assert!((keys.at(1.5).unwrap() - 10. * 0.84375).abs() < 1e-6);
+    assert_eq!(Keyframes::<Float>::new(vec![]).at(0.), None);
+
+    let turned = Pose {
+        rotate: Vec3::new(0., 90., 0.),
+        translate: Vec3::new(0., 1., 0.),
+        ..Pose::default()
+    };
+    let p = turned.matrix().transform_point(Vec3::X);
+    assert!((p - Vec3::new(0., 1., -1.)).is_zero());
+
+    let animation = Animation {
+        fps: 24.,
+        duration: 2.,
+    };
+    assert_eq!(animation.frames(), 48);
+    assert_eq!(animation.time(12), 0.5);
+    assert_eq!(Animation::default().frames(), 1);
+    assert_eq!(frame_path("out/frame_####.png", 7), "out/frame_0007.png");
+    assert_eq!(frame_path("out/frame_##.png", 123), "out/frame_123.png");
+    assert_eq!(frame_path("a.b/frame.png", 1), "a.b/frame_0001.png");
+    assert_eq!(frame_path("a.b/frame", 1), "a.b/frame_0001");
+}
@@ -0,0 +1,215 @@
+//! images, meshes and models loaded from disk, kept so every material and object using the same
+//! file shares one copy of it. nothing is read until something asks for it, and then only once,
+//! however many times it's asked for, and whichever frame of an animation asks
+
+use crate::rt::{ColorSpace, HdrTexture, ImageTexture, MeshData};
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    fmt, fs,
+    hash::Hash,
+    io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+/// something read from a file that's worth keeping, such as a mesh or a model
+pub trait Asset: Any + Send + Sync {
+    /// bytes it takes up, roughly
+    fn memory(&self) -> usize;
+}
+
+impl Asset for MeshData {
+    fn memory(&self) -> usize {
+        MeshData::memory(self)
+    }
+}
+
+/// an asset of any type, and the bytes it took up when loaded
+type AnyAsset = (Arc<dyn Any + Send + Sync>, usize);
+
+#[derive(Default)]
+struct Loaded {
+    /// images by path and the color space they were read in
+    images: HashMap<(PathBuf, ColorSpace), ImageTexture>,
+    /// images loaded for their alpha channels, which are kept apart from their colors
+    alphas: HashMap<PathBuf, ImageTexture>,
+    hdrs: HashMap<PathBuf, HdrTexture>,
+    /// everything else, by path and type, as one file may be read as more than one thing
+    others: HashMap<(PathBuf, TypeId), AnyAsset>,
+}
+
+/// a cache of loaded files. clones share it
+#[derive(Clone, Default)]
+pub struct Assets {
+    loaded: Arc<Mutex<Loaded>>,
+}
+
+/// the same file however it's reached, so `a/../b.png` and `b.png` load once
+fn key(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn invalid(path: &Path, e: impl fmt::Display) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{}: {e}", path.display()),
+    )
+}
+
+impl Assets {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn loaded(&self) -> MutexGuard<'_, Loaded> {
+        self.loaded.lock().unwrap()
+    }
+
+    /// the asset for `path` in the cache `kind` picks, loading it with `load` the first time.
+    /// the cache isn't locked while loading, so loaders can ask for other assets, such as a
+    /// mesh for its textures
+    fn get<K: Eq + Hash, T: Clone>(
+        &self,
+        kind: fn(&mut Loaded) -> &mut HashMap<K, T>,
+        key: K,
+        path: &Path,
+        load: impl FnOnce(&Path) -> io::Result<T>,
+    ) -> io::Result<T> {
+        if let Some(asset) = kind(&mut self.loaded()).get(&key) {
+            return Ok(asset.clone());
+        }
+        let asset = load(path)?;
+        kind(&mut self.loaded()).insert(key, asset.clone());
+        Ok(asset)
+    }
+
+    /// an image, in any format the `image` crate reads, with its values in `space`
+    pub fn image(&self, path: &Path, space: ColorSpace) -> io::Result<ImageTexture> {
+        self.get(
+            |loaded| &mut loaded.images,
+            (key(path), space),
+            path,
+            |path| ImageTexture::open(path, space).map_err(|e| invalid(path, e)),
+        )
+    }
+
+    /// an image's alpha channel, as with `ImageTexture::open_alpha`
+    pub fn alpha(&self, path: &Path) -> io::Result<ImageTexture> {
+        self.get(
+            |loaded| &mut loaded.alphas,
+            key(path),
+            path,
+            |path| ImageTexture::open_alpha(path).map_err(|e| invalid(path, e)),
+        )
+    }
+
+    /// a high dynamic range image
+    pub fn hdr(&self, path: &Path) -> io::Result<HdrTexture> {
+        self.get(
+            |loaded| &mut loaded.hdrs,
+            key(path),
+            path,
+            |path| HdrTexture::open(path).map_err(|e| invalid(path, e)),
+        )
+    }
+
+    /// anything else, such as a mesh, read by `load` from whichever format it's in
+    pub fn load<T: Asset>(
+        &self,
+        path: &Path,
+        load: impl FnOnce(&Path) -> io::Result<T>,
+    ) -> io::Result<Arc<T>> {
+        let (asset, _) = self.get(
+            |loaded| &mut loaded.others,
+            (key(path), TypeId::of::<T>()),
+            path,
+            |path| {
+                let asset = load(path)?;
+                let memory = asset.memory();
+                Ok((Arc::new(asset) as Arc<dyn Any + Send + Sync>, memory))
+            },
+        )?;
+        Ok(asset.downcast().expect("assets are kept by their type"))
+    }
+
+    /// how many files have been loaded
+    pub fn len(&self) -> usize {
+        let loaded = self.loaded();
+        loaded.images.len() + loaded.alphas.len() + loaded.hdrs.len() + loaded.others.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// bytes taken up by everything loaded
+    pub fn memory(&self) -> usize {
+        let loaded = self.loaded();
+        let images = loaded.images.values().chain(loaded.alphas.values());
+        images.map(ImageTexture::memory).sum::<usize>()
+            + loaded.hdrs.values().map(HdrTexture::memory).sum::<usize>()
+            + loaded
+                .others
+                .values()
+                .map(|(_, memory)| memory)
+                .sum::<usize>()
+    }
+}
+
+/// how many files are loaded, and the memory they take up
+impl fmt::Display for Assets {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let memory = self.memory() as f64 / (1024. * 1024.);
+        let count = self.len();
+        let plural = if count == 1 { "" } else { "s" };
+        write!(f, "{count} asset{plural} in {memory:.1} MiB")
+    }
+}
+
+impl fmt::Debug for Assets {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Assets({self})")
+    }
+}
+
+#[test]
+fn assets_load_once() {
+    use crate::math::Vec3;
+    let dir = std::env::temp_dir().join("saraytracer-assets");
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("checks.png");
+    image::RgbImage::from_fn(4, 2, |x, _| image::Rgb([(x * 60) as u8; 3]))
+        .save(&path)
+        .unwrap();
+
+    let assets = Assets::new();
+    let first = assets.image(&path, ColorSpace::Srgb).unwrap();
+    let again = assets
+        .image(
+            &dir.join("../saraytracer-assets/checks.png"),
+            ColorSpace::Srgb,
+        )
+        .unwrap();
+    assert!(Arc::ptr_eq(&first.image, &again.image));
+    assert_eq!(assets.len(), 1);
+    // the image, and its 2x1 and 1x1 copies
+    assert_eq!(assets.memory(), (8 + 2 + 1) * 3);
+
+    let triangle = |_: &Path| {
+        Ok(MeshData::new(
+            vec![Vec3::ZERO, Vec3::X, Vec3::Y],
+            vec![[0, 1, 2]],
+        ))
+    };
+    let mesh = assets.load(&dir.join("triangle.obj"), triangle).unwrap();
+    let shared = assets
+        .load::<MeshData>(&dir.join("triangle.obj"), |_| panic!("loaded twice"))
+        .unwrap();
+    assert!(Arc::ptr_eq(&mesh, &shared));
+    assert_eq!(assets.len(), 2);
+    assert!(assets
+        .image(&dir.join("missing.png"), ColorSpace::Srgb)
+        .is_err());
+    fs::remove_dir_all(dir).unwrap();
+}
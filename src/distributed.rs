@@ -0,0 +1,361 @@
+//! rendering a film split into tiles of rows between worker processes, possibly on other
+//! machines. a coordinator listens for workers, sends each the job, then hands out tiles until
+//! they're all rendered. workers send every tile back as a checkpoint of its rows
+
+use crate::integrator::{Clamp, PathTracer};
+use crate::math::{seed_rng, Float, Sampler};
+use crate::render::Film;
+use crate::rt::{scenes, Scene};
+use crate::scene::{CameraDesc, SceneFile};
+use serde::{Deserialize, Serialize};
+use std::{
+    io::{self, BufReader, BufWriter, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    ops::Range,
+    path::PathBuf,
+    sync::Mutex,
+    thread,
+    time::Duration,
+};
+
+/// how long to wait before looking for new workers or tiles again
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// longest a worker can take over a tile before it's given up on and the tile handed to another
+const TILE_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+/// everything a worker needs to render its tiles the same as the coordinator would
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Job {
+    /// ron of the scene file, or none for a preset scene
+    pub scene: Option<String>,
+    /// name of the scene from `rt::scenes` to render without a scene file. the demo scene if
+    /// none
+    #[serde(default)]
+    pub preset: Option<String>,
+    /// directory the scene's paths are relative to. workers need the same files there
+    pub dir: PathBuf,
+    /// camera to render from, overriding the scene's
+    pub camera: CameraDesc,
+    /// seconds into the scene's animation
+    pub time: Float,
+    pub width: u32,
+    pub height: u32,
+    /// samples per pixel
+    pub spp: u32,
+    /// samples per pass, which the random numbers drawn depend on
+    pub pass_samples: u32,
+    pub max_depth: u32,
+    pub seed: u64,
+    pub clamp_bounce: Option<Float>,
+    pub clamp_sample: Option<Float>,
+    pub sampler: Sampler,
+    /// whether to follow single wavelengths, for dispersion
+    #[serde(default)]
+    pub spectral: bool,
+    /// lights resampled down to one shadow ray a bounce, if any
+    #[serde(default)]
+    pub light_candidates: Option<u32>,
+    /// whether to trace the first-hit aovs too
+    pub aovs: bool,
+}
+
+impl Job {
+    /// builds the scene to render, with the job's camera. the random numbers scenes are built
+    /// with, like noise tables, are drawn from the job's seed so every worker builds the same one
+    pub fn scene(&self) -> io::Result<Scene> {
+        seed_rng(self.seed);
+        let scene = match &self.scene {
+            Some(text) => SceneFile::parse(text)?.scene_at(&self.dir, self.time)?,
+            None => {
+                let name = self.preset.as_deref().unwrap_or("spheres");
+                scenes::by_name(name).ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::NotFound, format!("no preset scene {name}"))
+                })?
+            }
+        };
+        let aspect_ratio = self.width as Float / self.height as Float;
+        Ok(Scene {
+            view: self.camera.view().autofocus(&scene.world, aspect_ratio),
+            ..scene
+        })
+    }
+
+    /// an empty film of some rows of the image, with the job's settings
+    pub fn film(&self, rows: Range<u32>) -> Film {
+        let mut film = Film::tile(self.width, self.height, rows, self.aovs);
+        film.seed = self.seed;
+        film.sampler = self.sampler;
+        film.spectral = self.spectral;
+        film
+    }
+
+    /// the path tracer to render with, with the job's settings
+    pub fn integrator(&self) -> PathTracer {
+        PathTracer {
+            max_depth: self.max_depth,
+            clamp: Clamp {
+                bounce: self.clamp_bounce,
+                sample: self.clamp_sample,
+            },
+            light_candidates: self.light_candidates,
+        }
+    }
+
+    /// renders some rows of the image with all the job's samples
+    pub fn render(&self, scene: &Scene, rows: Range<u32>) -> Film {
+        let camera = scene
+            .view
+            .camera(self.width as Float / self.height as Float);
+        let (mut film, integrator) = (self.film(rows), self.integrator());
+        while film.samples < self.spp {
+            let samples = self.pass_samples.max(1).min(self.spp - film.samples);
+            film.render_pass(&camera, &scene.world, &integrator, samples);
+        }
+        film
+    }
+}
+
+/// something that happened over a render, for `coordinate` to report
+#[derive(Debug)]
+pub enum Event {
+    /// a worker connected
+    Joined(SocketAddr),
+    /// a worker dropped out, and any tile it had goes to another
+    Left(SocketAddr, io::Error),
+    /// a worker sent back a tile, leaving some others to render or still being rendered
+    Rendered { rows: Range<u32>, left: usize },
+}
+
+/// the tiles still to render and the film they're put together in
+struct Progress {
+    pending: Vec<Range<u32>>,
+    rendering: usize,
+    film: Film,
+}
+
+/// hands out tiles of `tile_rows` rows of the job to every worker that connects to `listener`,
+/// until the whole image is rendered. a tile a worker fails to send back goes to another,
+/// so workers can drop out, and join, at any time. `report` hears of each as it happens
+pub fn coordinate(
+    listener: &TcpListener,
+    job: &Job,
+    tile_rows: u32,
+    report: impl Fn(Event) + Sync,
+) -> io::Result<Film> {
+    let tile_rows = tile_rows.max(1);
+    let pending = (0..job.height)
+        .step_by(tile_rows as usize)
+        .map(|start| start..(start + tile_rows).min(job.height))
+        .rev()
+        .collect();
+    let progress = Mutex::new(Progress {
+        pending,
+        rendering: 0,
+        film: job.film(0..job.height),
+    });
+    let finished = |p: &Progress| p.pending.is_empty() && p.rendering == 0;
+
+    listener.set_nonblocking(true)?;
+    thread::scope(|scope| {
+        while !finished(&progress.lock().unwrap()) {
+            match listener.accept() {
+                Ok((stream, addr)) => {
+                    report(Event::Joined(addr));
+                    let (progress, report) = (&progress, &report);
+                    scope.spawn(move || {
+                        if let Err(e) = serve(stream, job, progress, report) {
+                            report(Event::Left(addr, e));
+                        }
+                    });
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => thread::sleep(POLL_INTERVAL),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    })?;
+    Ok(progress.into_inner().unwrap().film)
+}
+
+/// sends tiles to a worker and puts the ones it renders in the film, until there are none left
+fn serve(
+    stream: TcpStream,
+    job: &Job,
+    progress: &Mutex<Progress>,
+    report: &impl Fn(Event),
+) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(TILE_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+    write_message(&mut writer, job)?;
+    loop {
+        let rows = {
+            let mut progress = progress.lock().unwrap();
+            match progress.pending.pop() {
+                Some(rows) => {
+                    progress.rendering += 1;
+                    Some(rows)
+                }
+                None if progress.rendering == 0 => None,
+                // another worker could still fail and leave its tile to this one
+                None => {
+                    drop(progress);
+                    thread::sleep(POLL_INTERVAL);
+                    continue;
+                }
+            }
+        };
+        let Some(rows) = rows else {
+            // an empty tile tells the worker it's done
+            write_rows(&mut writer, 0..0)?;
+            return Ok(());
+        };
+
+        let tile = write_rows(&mut writer, rows.clone())
+            .and_then(|()| Film::read_tile(&mut reader, job.width, job.height, rows.clone()))
+            .map_err(|e| match e.kind() {
+                // timeouts come back as either, depending on the platform
+                io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "worker took too long over its tile",
+                ),
+                _ => e,
+            })
+            .and_then(|tile| {
+                if tile.samples == job.spp {
+                    Ok(tile)
+                } else {
+                    Err(invalid(
+                        "worker sent back a tile with a different sample count",
+                    ))
+                }
+            });
+        let mut progress = progress.lock().unwrap();
+        progress.rendering -= 1;
+        match tile.and_then(|tile| progress.film.insert(&tile)) {
+            Ok(()) => {
+                let left = progress.pending.len() + progress.rendering;
+                report(Event::Rendered { rows, left });
+            }
+            Err(e) => {
+                progress.pending.push(rows);
+                return Err(e);
+            }
+        }
+    }
+}
+
+/// renders tiles for the coordinator on the other end of `stream` until it runs out of them
+pub fn work(stream: TcpStream) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+    let job: Job = read_message(&mut reader)?;
+    let scene = job.scene()?;
+    loop {
+        let rows = read_rows(&mut reader)?;
+        if rows.is_empty() {
+            return Ok(());
+        }
+        if rows.end > job.height {
+            return Err(invalid("tile is out of the image"));
+        }
+        job.render(&scene, rows).write_checkpoint(&mut writer)?;
+        writer.flush()?;
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/// writes a value as ron, after its length
+fn write_message<T: Serialize>(mut writer: impl Write, value: &T) -> io::Result<()> {
+    let text = ron::to_string(value).map_err(|e| invalid(&e.to_string()))?;
+    writer.write_all(&(text.len() as u64).to_le_bytes())?;
+    writer.write_all(text.as_bytes())?;
+    writer.flush()
+}
+
+fn read_message<T: for<'de> Deserialize<'de>>(mut reader: impl Read) -> io::Result<T> {
+    let mut len = [0; 8];
+    reader.read_exact(&mut len)?;
+    let mut text = String::new();
+    reader
+        .take(u64::from_le_bytes(len))
+        .read_to_string(&mut text)?;
+    ron::from_str(&text).map_err(|e| invalid(&e.to_string()))
+}
+
+fn write_rows(mut writer: impl Write, rows: Range<u32>) -> io::Result<()> {
+    writer.write_all(&rows.start.to_le_bytes())?;
+    writer.write_all(&rows.end.to_le_bytes())?;
+    writer.flush()
+}
+
+fn read_rows(mut reader: impl Read) -> io::Result<Range<u32>> {
+    let mut read_u32 = || {
+        let mut bytes = [0; 4];
+        reader
+            .read_exact(&mut bytes)
+            .map(|()| u32::from_le_bytes(bytes))
+    };
+    Ok(read_u32()?..read_u32()?)
+}
+
+#[test]
+fn workers_render_every_tile() {
+    // the worn scene's noise has to come out the same on every worker
+    for path in ["scenes/cornell.ron", "scenes/worn.ron"] {
+        render_distributed(path);
+    }
+}
+
+#[cfg(test)]
+fn render_distributed(path: &str) {
+    let job = Job {
+        scene: Some(std::fs::read_to_string(path).unwrap()),
+        preset: None,
+        dir: PathBuf::from("scenes"),
+        camera: SceneFile::open(path).unwrap().camera,
+        time: 0.,
+        width: 6,
+        height: 7,
+        spp: 2,
+        pass_samples: 1,
+        max_depth: 5,
+        seed: 1,
+        clamp_bounce: None,
+        clamp_sample: None,
+        sampler: Sampler::Sobol,
+        spectral: false,
+        light_candidates: None,
+        aovs: false,
+    };
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let workers: Vec<_> = (0..2)
+        .map(|_| thread::spawn(move || work(TcpStream::connect(addr).unwrap())))
+        .collect();
+    let rendered = Mutex::new(vec![]);
+    let film = coordinate(&listener, &job, 3, |event| {
+        if let Event::Rendered { rows, left } = event {
+            rendered.lock().unwrap().push((rows, left));
+        }
+    })
+    .unwrap();
+    // a worker that connected too late to get a tile is turned away
+    drop(listener);
+    for worker in workers {
+        worker.join().unwrap().ok();
+    }
+
+    let whole = job.render(&job.scene().unwrap(), 0..job.height);
+    assert_eq!(film.samples, 2);
+    let mut rendered = rendered.into_inner().unwrap();
+    assert_eq!(rendered.last().map(|&(_, left)| left), Some(0));
+    rendered.sort_by_key(|(rows, _)| rows.start);
+    let rows: Vec<_> = rendered.into_iter().map(|(rows, _)| rows).collect();
+    assert_eq!(rows, [0..3, 3..6, 6..7]);
+    assert_eq!(film.color(), whole.color());
+}
@@ -0,0 +1,378 @@
+//! a small c api, for embedding the tracer in programs written in other languages. a scene is
+//! built up behind an opaque pointer, then rendered into a buffer the caller owns. see
+//! `include/saraytracer.h` for the declarations to include
+//!
+//! every function taking pointers is unsafe: they must be null or point to what the docs say,
+//! and a scene mustn't be used from two threads at once
+
+use crate::integrator::PathTracer;
+use crate::math::{Float, Vec3};
+use crate::output::display_rgba8;
+use crate::post::{Exposure, Pipeline};
+use crate::render::Film;
+use crate::rt::*;
+use std::ffi::c_void;
+use std::panic::{self, AssertUnwindSafe};
+use std::{ptr, slice};
+
+/// what went wrong, as returned by the functions here
+pub const SRT_OK: i32 = 0;
+/// a pointer was null, or a number out of range
+pub const SRT_INVALID_ARGUMENT: i32 = -1;
+/// the progress callback asked to stop
+pub const SRT_CANCELLED: i32 = 1;
+/// the tracer panicked. the scene may be left half changed
+pub const SRT_PANICKED: i32 = -2;
+
+/// the kinds of material shapes can be given
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SrtMaterialKind {
+    Diffuse = 0,
+    /// `param` is how fuzzy reflections are, from 0 for a mirror
+    Metal = 1,
+    /// `param` is the refraction index. the color is left out
+    Glass = 2,
+    /// glows with `color` as its radiance, and is sampled directly as an area light
+    Light = 3,
+}
+
+impl TryFrom<u32> for SrtMaterialKind {
+    /// the number that isn't a kind
+    type Error = u32;
+
+    fn try_from(kind: u32) -> Result<Self, u32> {
+        Ok(match kind {
+            0 => Self::Diffuse,
+            1 => Self::Metal,
+            2 => Self::Glass,
+            3 => Self::Light,
+            _ => return Err(kind),
+        })
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SrtMaterial {
+    /// an `SrtMaterialKind`, kept as a number since c can put any there
+    pub kind: u32,
+    /// linear rgb
+    pub color: [f64; 3],
+    pub param: f64,
+}
+
+/// called after each pass of `srt_render` with the samples taken of every pixel so far, out of
+/// those asked for, and the caller's `user_data`. returning false stops the render
+pub type SrtProgress =
+    Option<extern "C" fn(samples: u32, spp: u32, user_data: *mut c_void) -> bool>;
+
+fn vec3([x, y, z]: [f64; 3]) -> Vec3 {
+    Vec3::new(x as Float, y as Float, z as Float)
+}
+
+fn color([r, g, b]: [f64; 3]) -> Color {
+    Color::new(r as Float, g as Float, b as Float)
+}
+
+/// runs the body of a function called from c, which mustn't unwind back into it
+fn guard(body: impl FnOnce() -> i32) -> i32 {
+    panic::catch_unwind(AssertUnwindSafe(body)).unwrap_or(SRT_PANICKED)
+}
+
+/// inserts a shape made with the given material into the world
+fn insert<S, F>(world: &mut World, material: &SrtMaterial, shape: F) -> i32
+where
+    S: Shape + Send + Sync + 'static,
+    F: FnOnce(AnyMaterial) -> S,
+{
+    let Ok(kind) = SrtMaterialKind::try_from(material.kind) else {
+        return SRT_INVALID_ARGUMENT;
+    };
+    let c = color(material.color);
+    let param = material.param as Float;
+    match kind {
+        SrtMaterialKind::Diffuse => world.insert(shape(AnyMaterial::new(Diffuse::from(c)))),
+        SrtMaterialKind::Metal => {
+            let metal = Metal::new(SolidColor::from(c), param);
+            world.insert(shape(AnyMaterial::new(metal)))
+        }
+        SrtMaterialKind::Glass => world.insert(shape(AnyMaterial::new(Dielectric::new(param)))),
+        SrtMaterialKind::Light => {
+            world.insert_area_light(shape(AnyMaterial::DiffuseLight(c.into())))
+        }
+    };
+    SRT_OK
+}
+
+/// a new scene under the default sky, seen from 10 units down -z. free it with `srt_scene_free`
+#[no_mangle]
+pub extern "C" fn srt_scene_new() -> *mut Scene {
+    panic::catch_unwind(|| Box::into_raw(Box::new(SceneBuilder::new().build())))
+        .unwrap_or(ptr::null_mut())
+}
+
+/// frees a scene made by `srt_scene_new`
+///
+/// # Safety
+/// `scene` must come from `srt_scene_new` and not be freed already, or be null
+#[no_mangle]
+pub unsafe extern "C" fn srt_scene_free(scene: *mut Scene) {
+    if !scene.is_null() {
+        guard(|| {
+            drop(Box::from_raw(scene));
+            SRT_OK
+        });
+    }
+}
+
+/// adds a sphere
+///
+/// # Safety
+/// `scene` must come from `srt_scene_new`, and `center` point to 3 doubles
+#[no_mangle]
+pub unsafe extern "C" fn srt_add_sphere(
+    scene: *mut Scene,
+    center: *const f64,
+    radius: f64,
+    material: SrtMaterial,
+) -> i32 {
+    guard(|| {
+        let (Some(scene), false) = (scene.as_mut(), center.is_null()) else {
+            return SRT_INVALID_ARGUMENT;
+        };
+        let center = vec3(*(center as *const [f64; 3]));
+        insert(&mut scene.world, &material, |m| {
+            Sphere::new(center, radius as Float, m)
+        })
+    })
+}
+
+/// adds a triangle mesh, shaded flat
+///
+/// # Safety
+/// `scene` must come from `srt_scene_new`, `positions` point to 3 doubles for each of the
+/// `vertices`, and `indices` to 3 for each of the `triangles`, counter-clockwise seen from the
+/// front
+#[no_mangle]
+pub unsafe extern "C" fn srt_add_mesh(
+    scene: *mut Scene,
+    positions: *const f64,
+    vertices: usize,
+    indices: *const u32,
+    triangles: usize,
+    material: SrtMaterial,
+) -> i32 {
+    guard(|| {
+        let Some(scene) = scene.as_mut() else {
+            return SRT_INVALID_ARGUMENT;
+        };
+        if positions.is_null() || indices.is_null() {
+            return SRT_INVALID_ARGUMENT;
+        }
+        let positions: Vec<Vec3> = slice::from_raw_parts(positions as *const [f64; 3], vertices)
+            .iter()
+            .map(|&p| vec3(p))
+            .collect();
+        let triangles: Vec<[usize; 3]> =
+            slice::from_raw_parts(indices as *const [u32; 3], triangles)
+                .iter()
+                .map(|triangle| triangle.map(|i| i as usize))
+                .collect();
+        if triangles.iter().flatten().any(|&i| i >= vertices) {
+            return SRT_INVALID_ARGUMENT;
+        }
+        insert(&mut scene.world, &material, |m| {
+            Mesh::new(positions, triangles, m)
+        })
+    })
+}
+
+/// sets a solid background color in place of the sky, black for scenes lit only by their
+/// lights
+///
+/// # Safety
+/// `scene` must come from `srt_scene_new`, and `rgb` point to 3 doubles
+#[no_mangle]
+pub unsafe extern "C" fn srt_set_background(scene: *mut Scene, rgb: *const f64) -> i32 {
+    guard(|| {
+        let (Some(scene), false) = (scene.as_mut(), rgb.is_null()) else {
+            return SRT_INVALID_ARGUMENT;
+        };
+        let background = SolidBackground::from(color(*(rgb as *const [f64; 3])));
+        scene.world.set_background(background);
+        SRT_OK
+    })
+}
+
+/// points the camera from `eye` at `look_at`, which is in focus, with a vertical field of view
+/// of `vfov` degrees and a lens `aperture` across, 0 for a pinhole
+///
+/// # Safety
+/// `scene` must come from `srt_scene_new`, and `eye` and `look_at` point to 3 doubles each
+#[no_mangle]
+pub unsafe extern "C" fn srt_set_camera(
+    scene: *mut Scene,
+    eye: *const f64,
+    look_at: *const f64,
+    vfov: f64,
+    aperture: f64,
+) -> i32 {
+    guard(|| {
+        let Some(scene) = scene.as_mut() else {
+            return SRT_INVALID_ARGUMENT;
+        };
+        if eye.is_null() || look_at.is_null() || !(vfov > 0. && vfov < 180.) {
+            return SRT_INVALID_ARGUMENT;
+        }
+        scene.view = View {
+            eye: vec3(*(eye as *const [f64; 3])),
+            look_at: vec3(*(look_at as *const [f64; 3])),
+            vfov: vfov as Float,
+            aperture: aperture as Float,
+            ..scene.view
+        };
+        SRT_OK
+    })
+}
+
+/// path traces the scene with `spp` samples a pixel and up to `max_depth` bounces, then tone
+/// maps it into `pixels` as 8 bit rgba, row by row from the top. `progress`, if not null, is
+/// called after every pass of a few samples
+///
+/// # Safety
+/// `scene` must come from `srt_scene_new`, and `pixels` have room for `width * height * 4`
+/// bytes
+#[no_mangle]
+pub unsafe extern "C" fn srt_render(
+    scene: *const Scene,
+    width: u32,
+    height: u32,
+    spp: u32,
+    max_depth: u32,
+    pixels: *mut u8,
+    progress: SrtProgress,
+    user_data: *mut c_void,
+) -> i32 {
+    guard(|| {
+        /// samples taken between calls to `progress`
+        const PASS_SAMPLES: u32 = 4;
+        let Some(scene) = scene.as_ref() else {
+            return SRT_INVALID_ARGUMENT;
+        };
+        if pixels.is_null() || width < 2 || height < 2 {
+            return SRT_INVALID_ARGUMENT;
+        }
+        let aspect_ratio = width as Float / height as Float;
+        let camera = scene
+            .view
+            .autofocus(&scene.world, aspect_ratio)
+            .camera(aspect_ratio);
+        let integrator = PathTracer::new(max_depth);
+        let mut film = Film::new(width, height, false);
+        while film.samples < spp {
+            let samples = PASS_SAMPLES.min(spp - film.samples);
+            film.render_pass(&camera, &scene.world, &integrator, samples);
+            if let Some(progress) = progress {
+                if !progress(film.samples, spp, user_data) {
+                    return SRT_CANCELLED;
+                }
+            }
+        }
+        let image = Pipeline::standard(Exposure::default(), ToneMap::Aces).develop(&film.color());
+        let rgba = display_rgba8(&image);
+        slice::from_raw_parts_mut(pixels, rgba.len()).copy_from_slice(&rgba);
+        SRT_OK
+    })
+}
+
+#[test]
+fn render_through_c_api() {
+    extern "C" fn stop_after(samples: u32, _spp: u32, user_data: *mut c_void) -> bool {
+        let calls = unsafe { &mut *(user_data as *mut u32) };
+        *calls += 1;
+        samples < 8
+    }
+    let material = |kind: SrtMaterialKind, color, param| SrtMaterial {
+        kind: kind as u32,
+        color,
+        param,
+    };
+    unsafe {
+        let scene = srt_scene_new();
+        let gray = material(SrtMaterialKind::Diffuse, [0.5; 3], 0.);
+        assert_eq!(
+            srt_add_sphere(scene, [0., -101., 0.].as_ptr(), 100., gray),
+            SRT_OK
+        );
+        let light = material(SrtMaterialKind::Light, [4.; 3], 0.);
+        assert_eq!(
+            srt_add_sphere(scene, [0., 0., 0.].as_ptr(), 1., light),
+            SRT_OK
+        );
+        // a triangle facing the camera, off to the side
+        let positions = [2., 0., 0., 3., 0., 0., 2., 1., 0.];
+        let mirror = material(SrtMaterialKind::Metal, [0.9; 3], 0.);
+        let added = srt_add_mesh(scene, positions.as_ptr(), 3, [0, 2, 1].as_ptr(), 1, mirror);
+        assert_eq!(added, SRT_OK);
+        assert_eq!((*scene).world.len(), 3);
+        assert_eq!((*scene).world.lights.len(), 1);
+        assert_eq!(srt_set_background(scene, [0.; 3].as_ptr()), SRT_OK);
+        let (eye, look_at) = ([0., 0., -5.], [0., 0., 0.]);
+        assert_eq!(
+            srt_set_camera(scene, eye.as_ptr(), look_at.as_ptr(), 40., 0.),
+            SRT_OK
+        );
+
+        let mut pixels = vec![0u8; 16 * 8 * 4];
+        let rendered = srt_render(
+            scene,
+            16,
+            8,
+            4,
+            8,
+            pixels.as_mut_ptr(),
+            None,
+            std::ptr::null_mut(),
+        );
+        assert_eq!(rendered, SRT_OK);
+        // the light glows in the middle, against the black background in the corner
+        let at = |x: usize, y: usize| &pixels[(y * 16 + x) * 4..][..4];
+        assert!(at(8, 4)[0] > 200 && at(8, 4)[3] == 255);
+        assert_eq!(at(0, 0), [0, 0, 0, 255]);
+
+        let mut calls = 0u32;
+        let user_data = &mut calls as *mut u32 as *mut c_void;
+        let progress: SrtProgress = Some(stop_after);
+        let stopped = srt_render(
+            scene,
+            16,
+            8,
+            100,
+            8,
+            pixels.as_mut_ptr(),
+            progress,
+            user_data,
+        );
+        assert_eq!((stopped, calls), (SRT_CANCELLED, 2));
+
+        let null = std::ptr::null_mut();
+        assert_eq!(
+            srt_add_sphere(null, eye.as_ptr(), 1., gray),
+            SRT_INVALID_ARGUMENT
+        );
+        let out_of_range = srt_add_mesh(scene, positions.as_ptr(), 3, [0, 1, 3].as_ptr(), 1, gray);
+        assert_eq!(out_of_range, SRT_INVALID_ARGUMENT);
+        let unknown = SrtMaterial { kind: 7, ..gray };
+        assert_eq!(
+            srt_add_sphere(scene, eye.as_ptr(), 1., unknown),
+            SRT_INVALID_ARGUMENT
+        );
+        assert_eq!(
+            srt_set_camera(scene, eye.as_ptr(), look_at.as_ptr(), 0., 0.),
+            SRT_INVALID_ARGUMENT
+        );
+        assert_eq!((*scene).world.len(), 3);
+        srt_scene_free(scene);
+    }
+}
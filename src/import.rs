@@ -0,0 +1,92 @@
+//! meshes read from the files modelling programs save, along with the materials they come with.
+//! each format reads into a [`Model`], cached in [`Assets`] like images are
+
+use crate::assets::Assets;
+use crate::math::{Float, Normalize, Vec3};
+use crate::rt::{AnyMaterial, Mesh, MeshData};
+use std::{ffi::OsStr, fmt, io, path::Path, sync::Arc};
+
+#[cfg(feature = "gltf")]
+pub mod gltf;
+pub mod obj;
+pub mod ply;
+pub mod stl;
+
+/// a mesh read from a file, and the materials the file gives its groups of triangles
+#[derive(Clone)]
+pub struct Model {
+    pub mesh: Arc<MeshData>,
+    /// one for each group, or `None` for groups the file gives no material
+    pub materials: Vec<Option<AnyMaterial>>,
+}
+
+impl Model {
+    /// the mesh made of the model's own materials, and of `fallback` where it has none
+    pub fn into_mesh(self, fallback: AnyMaterial) -> Mesh {
+        let mut materials: Vec<AnyMaterial> = self
+            .materials
+            .into_iter()
+            .map(|material| material.unwrap_or_else(|| fallback.clone()))
+            .collect();
+        if materials.is_empty() {
+            materials.push(fallback);
+        }
+        Mesh::with_materials(self.mesh, materials)
+    }
+}
+
+/// gives the mesh the normals and surface coordinates read for each triangle's corners, if
+/// any triangle has them. the triangles missing what others have get their own flat normal, and
+/// coordinates of zero
+fn set_corners(
+    mesh: &mut MeshData,
+    normals: Vec<Option<[Vec3; 3]>>,
+    uvs: Vec<Option<[(Float, Float); 3]>>,
+) {
+    if uvs.iter().any(Option::is_some) {
+        mesh.uvs = uvs
+            .into_iter()
+            .map(|uvs| uvs.unwrap_or([(0., 0.); 3]))
+            .collect();
+    }
+    if normals.iter().any(Option::is_some) {
+        mesh.normals = normals
+            .into_iter()
+            .zip(&mesh.triangles)
+            .map(|(normals, &[a, b, c])| {
+                normals.unwrap_or_else(|| {
+                    let (a, b, c) = (mesh.positions[a], mesh.positions[b], mesh.positions[c]);
+                    [(b - a).cross(c - a).normalize(); 3]
+                })
+            })
+            .collect();
+    }
+}
+
+fn invalid(path: &Path, e: impl fmt::Display) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{}: {e}", path.display()),
+    )
+}
+
+/// reads the model at `path`, in the format its extension names
+pub fn open(path: &Path, assets: &Assets) -> io::Result<Model> {
+    let extension = path.extension().and_then(OsStr::to_str);
+    match extension.map(str::to_ascii_lowercase).as_deref() {
+        Some("obj") => obj::open(path, assets),
+        Some("ply") => ply::open(path, assets),
+        Some("stl") => stl::open(path, assets),
+        #[cfg(feature = "gltf")]
+        Some("gltf" | "glb") => gltf::open(path, assets),
+        #[cfg(not(feature = "gltf"))]
+        Some("gltf" | "glb") => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "gltf models need the gltf feature",
+        )),
+        _ => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("{}: not a model format that can be read", path.display()),
+        )),
+    }
+}
@@ -0,0 +1,506 @@
+//! gltf models, as .gltf files with their buffers and images beside them or embedded in data
+//! uris, or packed into one .glb. the meshes of the default scene are moved to where its nodes
+//! place them and merged into one, grouped by material, and each material becomes a `Pbr`:
+//!
+//! - the base color, metallic and roughness factors, scaling the base color and
+//!   metallic-roughness textures
+//! - the normal texture, through `NormalMap`, by its scale
+//! - the emissive factor and texture, brightened by `KHR_materials_emissive_strength`
+//! - the alpha modes `MASK` and `BLEND`, cutting through the surface by the alpha of the base
+//!   color, and single sided materials, leaving out their back faces
+//!
+//! textures repeat, whatever their samplers say, and only the first set of surface coordinates
+//! is read. the primitives without a material are made of the object's own
+
+use super::{invalid, set_corners, Model};
+use crate::assets::{Asset, Assets};
+use crate::math::{Float, Mat4, Normalize, Vec3};
+use crate::rt::*;
+use base64::Engine;
+use gltf::{image::Source, material::AlphaMode, mesh::Mode};
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+type Tex = Arc<dyn Texture + Send + Sync + 'static>;
+
+/// a loaded model, and the bytes it takes up besides the image files it shares with others
+struct Gltf(Model, usize);
+
+impl Asset for Gltf {
+    fn memory(&self) -> usize {
+        self.1
+    }
+}
+
+/// a texture scaled by a color, as gltf scales textures by their factors
+struct Tinted(Tex, Color);
+
+impl Texture for Tinted {
+    fn value(&self, u: Float, v: Float, point: Vec3) -> Color {
+        self.1 * self.0.value(u, v, point)
+    }
+
+    fn at(&self, contact: &RayContact) -> Color {
+        self.1 * self.0.at(contact)
+    }
+}
+
+/// how much of a surface is there: its alpha, if it isn't opaque, cut off at `cutoff` if it's
+/// masked. none of the back is there if it's single sided
+struct Coverage {
+    alpha: Option<Tex>,
+    cutoff: Option<Float>,
+    double_sided: bool,
+}
+
+impl Coverage {
+    fn cut(&self, alpha: Color) -> Color {
+        match self.cutoff {
+            Some(cutoff) if alpha.r >= cutoff => Color::WHITE,
+            Some(_) => Color::BLACK,
+            None => alpha,
+        }
+    }
+}
+
+impl Texture for Coverage {
+    fn value(&self, u: Float, v: Float, point: Vec3) -> Color {
+        let alpha = self.alpha.as_ref();
+        alpha.map_or(Color::WHITE, |alpha| self.cut(alpha.value(u, v, point)))
+    }
+
+    fn at(&self, contact: &RayContact) -> Color {
+        if !self.double_sided && !contact.front_face {
+            return Color::BLACK;
+        }
+        let alpha = self.alpha.as_ref();
+        alpha.map_or(Color::WHITE, |alpha| self.cut(alpha.at(contact)))
+    }
+}
+
+/// the bytes a uri points to, decoded from a data uri or read from a file beside the model
+fn read_uri(dir: &Path, uri: &str) -> io::Result<Vec<u8>> {
+    match uri.strip_prefix("data:") {
+        Some(data) => {
+            let (_, data) = data.split_once(";base64,").ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, "data uris must be base64")
+            })?;
+            base64::engine::general_purpose::STANDARD
+                .decode(data)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        }
+        None => fs::read(uri_path(dir, uri)),
+    }
+}
+
+/// the file a relative uri names, with its escapes like `%20` undone
+fn uri_path(dir: &Path, uri: &str) -> PathBuf {
+    let mut bytes = vec![];
+    let mut rest = uri.as_bytes();
+    while let Some((&byte, after)) = rest.split_first() {
+        let escaped = after
+            .get(..2)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(escaped) if byte == b'%' => {
+                bytes.push(escaped);
+                rest = &after[2..];
+            }
+            _ => {
+                bytes.push(byte);
+                rest = after;
+            }
+        }
+    }
+    dir.join(String::from_utf8_lossy(&bytes).as_ref())
+}
+
+/// a gltf matrix, whose columns come first, as a `Mat4`
+fn matrix(columns: [[f32; 4]; 4]) -> Mat4 {
+    Mat4::new(std::array::from_fn(|row| {
+        std::array::from_fn(|column| columns[column][row] as Float)
+    }))
+}
+
+fn vec3([x, y, z]: [f32; 3]) -> Vec3 {
+    Vec3::new(x as Float, y as Float, z as Float)
+}
+
+/// the meshes of every node merged into one
+#[derive(Default)]
+struct Merged {
+    mesh: MeshData,
+    normals: Vec<Option<[Vec3; 3]>>,
+    uvs: Vec<Option<[(Float, Float); 3]>>,
+    /// the index of the material of each group, `None` for primitives without one
+    materials: Vec<Option<usize>>,
+}
+
+impl Merged {
+    fn node(&mut self, node: gltf::Node, parent: Mat4, buffers: &[Vec<u8>]) -> Result<(), String> {
+        let transform = parent * matrix(node.transform().matrix());
+        if let Some(mesh) = node.mesh() {
+            for primitive in mesh.primitives() {
+                self.primitive(&primitive, transform, buffers)?;
+            }
+        }
+        for child in node.children() {
+            self.node(child, transform, buffers)?;
+        }
+        Ok(())
+    }
+
+    fn primitive(
+        &mut self,
+        primitive: &gltf::Primitive,
+        transform: Mat4,
+        buffers: &[Vec<u8>],
+    ) -> Result<(), String> {
+        // points and lines have no surface to render
+        if primitive.mode() != Mode::Triangles {
+            return Ok(());
+        }
+        let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(Vec::as_slice));
+        let Some(positions) = reader.read_positions() else {
+            return Ok(());
+        };
+        let start = self.mesh.positions.len();
+        let positions = positions.map(|p| transform.transform_point(vec3(p)));
+        self.mesh.positions.extend(positions);
+        let count = self.mesh.positions.len() - start;
+        let indices: Vec<usize> = match reader.read_indices() {
+            Some(indices) => indices.into_u32().map(|i| i as usize).collect(),
+            None => (0..count).collect(),
+        };
+        if let Some(i) = indices.iter().find(|&&i| i >= count) {
+            return Err(format!(
+                "index {i} is past the {count} vertices of its primitive"
+            ));
+        }
+
+        let normal_transform = transform.inverse().unwrap_or(transform).transpose();
+        let normals: Option<Vec<Vec3>> = reader.read_normals().map(|normals| {
+            let normals = normals.map(|n| normal_transform.transform_vector(vec3(n)));
+            normals.map(|n| n.normalize()).collect()
+        });
+        let normals = normals.filter(|normals| normals.len() == count);
+        // gltf images start at the top, and the tracer's at the bottom
+        let uvs: Option<Vec<(Float, Float)>> = reader.read_tex_coords(0).map(|uvs| {
+            let uvs = uvs.into_f32();
+            uvs.map(|[u, v]| (u as Float, 1. - v as Float)).collect()
+        });
+        let uvs = uvs.filter(|uvs| uvs.len() == count);
+        // mirroring turns the triangles inside out, so they're turned back
+        let axes = [Vec3::X, Vec3::Y, Vec3::Z].map(|axis| transform.transform_vector(axis));
+        let mirrored = axes[0].cross(axes[1]).dot(axes[2]) < 0.;
+
+        let material = primitive.material().index();
+        let group = match self.materials.iter().position(|&other| other == material) {
+            Some(group) => group,
+            None => {
+                self.materials.push(material);
+                self.materials.len() - 1
+            }
+        };
+        for triangle in indices.chunks_exact(3) {
+            let mut triangle = [triangle[0], triangle[1], triangle[2]];
+            if mirrored {
+                triangle.swap(1, 2);
+            }
+            self.mesh.triangles.push(triangle.map(|i| start + i));
+            let normals = normals.as_ref();
+            self.normals
+                .push(normals.map(|normals| triangle.map(|i| normals[i])));
+            self.uvs
+                .push(uvs.as_ref().map(|uvs| triangle.map(|i| uvs[i])));
+            self.mesh.groups.push(group);
+        }
+        Ok(())
+    }
+}
+
+/// the images of a model, loaded as its materials ask for them
+struct Images<'a> {
+    dir: &'a Path,
+    assets: &'a Assets,
+    buffers: &'a [Vec<u8>],
+    /// images packed into the model, by index and what they're read for. images in files of
+    /// their own are shared through the assets instead
+    packed: HashMap<(usize, Channel), ImageTexture>,
+}
+
+/// what a texture's image is read for
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Channel {
+    /// colors in srgb, as base colors and emission are, or data as the other maps are
+    Color(ColorSpace),
+    Alpha,
+}
+
+impl Images<'_> {
+    fn unpack(&mut self, image: &gltf::Image, channel: Channel) -> io::Result<&ImageTexture> {
+        let key = (image.index(), channel);
+        if !self.packed.contains_key(&key) {
+            let bytes = match image.source() {
+                Source::View { view, .. } => {
+                    let buffer = &self.buffers[view.buffer().index()];
+                    buffer
+                        .get(view.offset()..view.offset() + view.length())
+                        .ok_or_else(|| {
+                            io::Error::new(io::ErrorKind::InvalidData, "image past its buffer")
+                        })?
+                        .to_vec()
+                }
+                Source::Uri { uri, .. } => read_uri(self.dir, uri)?,
+            };
+            let rgba = image::load_from_memory(&bytes)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+                .into_rgba8();
+            let unpacked = match channel {
+                Channel::Color(space) => {
+                    let color = image::DynamicImage::ImageRgba8(rgba).into_rgb8();
+                    ImageTexture::new(color, space)
+                }
+                Channel::Alpha => {
+                    let alpha = image::RgbImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+                        image::Rgb([rgba.get_pixel(x, y)[3]; 3])
+                    });
+                    ImageTexture::new(alpha, ColorSpace::Linear)
+                }
+            };
+            self.packed.insert(key, unpacked);
+        }
+        Ok(&self.packed[&key])
+    }
+
+    /// the colors of a texture, or its alpha
+    fn texture(&mut self, texture: gltf::Texture, channel: Channel) -> io::Result<Tex> {
+        let image = texture.source();
+        let texture = match image.source() {
+            Source::Uri { uri, .. } if !uri.starts_with("data:") => {
+                let path = uri_path(self.dir, uri);
+                match channel {
+                    Channel::Color(space) => self.assets.image(&path, space)?,
+                    Channel::Alpha => self.assets.alpha(&path)?,
+                }
+            }
+            _ => self.unpack(&image, channel)?.clone(),
+        };
+        Ok(Arc::new(UvTransform::new(texture)))
+    }
+
+    fn material(&mut self, source: gltf::Material) -> io::Result<AnyMaterial> {
+        let pbr = source.pbr_metallic_roughness();
+        let [r, g, b, a] = pbr.base_color_factor().map(|c| c as Float);
+        let factor = Color::new(r, g, b);
+        let base_color: Tex = match pbr.base_color_texture() {
+            Some(info) => {
+                let texture = self.texture(info.texture(), Channel::Color(ColorSpace::Srgb))?;
+                Arc::new(Tinted(texture, factor))
+            }
+            None => Arc::new(SolidColor::from(factor)),
+        };
+        let metallic = pbr.metallic_factor() as Float;
+        let roughness = pbr.roughness_factor() as Float;
+        let mut surface = Pbr::new(base_color, metallic, roughness);
+        if let Some(info) = pbr.metallic_roughness_texture() {
+            let texture = self.texture(info.texture(), Channel::Color(ColorSpace::Linear))?;
+            surface = surface.with_metallic_roughness(texture);
+        }
+        let strength = source.emissive_strength().unwrap_or(1.) as Float;
+        let emission = strength * Color::from(vec3(source.emissive_factor()));
+        if !emission.is_black() {
+            surface = surface.with_emission(match source.emissive_texture() {
+                Some(info) => {
+                    let texture = self.texture(info.texture(), Channel::Color(ColorSpace::Srgb))?;
+                    Arc::new(Tinted(texture, emission))
+                }
+                None => Arc::new(SolidColor::from(emission)) as Tex,
+            });
+        }
+
+        let mut material = AnyMaterial::new(surface);
+        if let Some(normals) = source.normal_texture() {
+            let texture = self.texture(normals.texture(), Channel::Color(ColorSpace::Linear))?;
+            let map = NormalMap::new(material, texture, normals.scale() as Float);
+            material = AnyMaterial::new(map);
+        }
+        let alpha: Option<Tex> = match source.alpha_mode() {
+            AlphaMode::Opaque => None,
+            _ => Some(match pbr.base_color_texture() {
+                Some(info) => {
+                    let texture = self.texture(info.texture(), Channel::Alpha)?;
+                    Arc::new(Tinted(texture, Color::gray(a)))
+                }
+                None => Arc::new(SolidColor::from(Color::gray(a))),
+            }),
+        };
+        let cutoff = (source.alpha_mode() == AlphaMode::Mask)
+            .then(|| source.alpha_cutoff().unwrap_or(0.5) as Float);
+        if alpha.is_some() || !source.double_sided() {
+            let coverage = Coverage {
+                alpha,
+                cutoff,
+                double_sided: source.double_sided(),
+            };
+            material = AnyMaterial::new(Cutout::new(material, coverage));
+        }
+        Ok(material)
+    }
+}
+
+fn load(path: &Path, assets: &Assets) -> io::Result<Gltf> {
+    let gltf::Gltf { document, blob } = gltf::Gltf::open(path).map_err(|e| invalid(path, e))?;
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let buffers = document
+        .buffers()
+        .map(|buffer| match buffer.source() {
+            gltf::buffer::Source::Bin => blob
+                .clone()
+                .ok_or_else(|| invalid(path, "the binary chunk is missing")),
+            gltf::buffer::Source::Uri(uri) => read_uri(dir, uri),
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+
+    let scene = document
+        .default_scene()
+        .or_else(|| document.scenes().next());
+    let scene = scene.ok_or_else(|| invalid(path, "there's no scene"))?;
+    let mut merged = Merged::default();
+    for node in scene.nodes() {
+        merged
+            .node(node, Mat4::IDENTITY, &buffers)
+            .map_err(|e| invalid(path, e))?;
+    }
+    let Merged {
+        mut mesh,
+        normals,
+        uvs,
+        materials,
+    } = merged;
+    set_corners(&mut mesh, normals, uvs);
+
+    let mut images = Images {
+        dir,
+        assets,
+        buffers: &buffers,
+        packed: HashMap::new(),
+    };
+    let materials = materials
+        .into_iter()
+        .map(|index| {
+            let material = index.and_then(|index| document.materials().nth(index));
+            material
+                .map(|material| images.material(material))
+                .transpose()
+        })
+        .collect::<io::Result<_>>()?;
+    let memory = mesh.memory()
+        + images
+            .packed
+            .values()
+            .map(ImageTexture::memory)
+            .sum::<usize>();
+    let model = Model {
+        mesh: Arc::new(mesh),
+        materials,
+    };
+    Ok(Gltf(model, memory))
+}
+
+/// reads the gltf or glb file at `path`
+pub fn open(path: &Path, assets: &Assets) -> io::Result<Model> {
+    let gltf = assets.load(path, |path| load(path, assets))?;
+    Ok(gltf.0.clone())
+}
+
+#[test]
+fn gltf_materials() {
+    let dir = std::env::temp_dir().join("saraytracer-gltf");
+    fs::create_dir_all(&dir).unwrap();
+    // a half orange, half clear image, to mask by
+    image::RgbaImage::from_fn(2, 1, |x, _| image::Rgba([255, 128, 0, 255 * (1 - x as u8)]))
+        .save(dir.join("half.png"))
+        .unwrap();
+
+    // a triangle, its positions, surface coordinates and indices packed one after the other
+    let mut bytes = vec![];
+    for value in [0., 0., 0., 1., 0., 0., 0., 1., 0., 0., 1., 1., 1., 0., 0.] {
+        bytes.extend((value as f32).to_le_bytes());
+    }
+    bytes.extend([0u16, 1, 2, 0].iter().flat_map(|i| i.to_le_bytes()));
+    let buffer = base64::engine::general_purpose::STANDARD.encode(&bytes);
+    let gltf = format!(
+        r#"{{
+            "asset": {{"version": "2.0"}},
+            "scene": 0,
+            "scenes": [{{"nodes": [0]}}],
+            "nodes": [{{"mesh": 0, "translation": [0, 0, 5]}}],
+            "meshes": [{{"primitives": [{{
+                "attributes": {{"POSITION": 0, "TEXCOORD_0": 1}},
+                "indices": 2,
+                "material": 0
+            }}]}}],
+            "materials": [{{
+                "pbrMetallicRoughness": {{
+                    "baseColorFactor": [1, 0.5, 1, 1],
+                    "baseColorTexture": {{"index": 0}},
+                    "metallicFactor": 0
+                }},
+                "emissiveFactor": [0, 0, 1],
+                "alphaMode": "MASK"
+            }}],
+            "textures": [{{"source": 0}}],
+            "images": [{{"uri": "half.png"}}],
+            "buffers": [{{"byteLength": {}, "uri": "data:application/octet-stream;base64,{buffer}"}}],
+            "bufferViews": [
+                {{"buffer": 0, "byteOffset": 0, "byteLength": 36}},
+                {{"buffer": 0, "byteOffset": 36, "byteLength": 24}},
+                {{"buffer": 0, "byteOffset": 60, "byteLength": 6}}
+            ],
+            "accessors": [
+                {{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                  "min": [0, 0, 0], "max": [1, 1, 0]}},
+                {{"bufferView": 1, "componentType": 5126, "count": 3, "type": "VEC2"}},
+                {{"bufferView": 2, "componentType": 5123, "count": 3, "type": "SCALAR"}}
+            ]
+        }}"#,
+        bytes.len()
+    );
+    fs::write(dir.join("triangle.gltf"), gltf).unwrap();
+
+    let assets = Assets::new();
+    let model = super::open(&dir.join("triangle.gltf"), &assets).unwrap();
+    assert_eq!(model.mesh.positions[1], Vec3::new(1., 0., 5.));
+    // flipped to start at the bottom
+    assert_eq!(model.mesh.uvs[0][0], (0., 0.));
+    assert_eq!(model.mesh.uvs[0][1], (1., 0.));
+    let mesh = model.into_mesh(AnyMaterial::new(Diffuse::from(Color::WHITE)));
+
+    // near the first corner, where the image is orange and opaque, decoded from srgb and
+    // tinted by the factor
+    let ray = Ray::new(Vec3::new(0.1, 0.1, 10.), -Vec3::Z);
+    let front = mesh.hit(ray, 0.001..Float::INFINITY).unwrap();
+    assert!(front.front_face);
+    let albedo = front.material.albedo(&front);
+    assert!(albedo.r == 1. && (albedo.g - 0.216 * 0.5).abs() < 1e-3 && albedo.b == 0.);
+    assert!((front.material.emitted(&front) - Color::BLUE).is_black());
+    assert_eq!(front.material.opacity(&front), 1.);
+    // single sided, so there's no back
+    let ray = Ray::new(Vec3::new(0.1, 0.1, 0.), Vec3::Z);
+    let back = mesh.hit(ray, 0.001..Float::INFINITY).unwrap();
+    assert_eq!(back.material.opacity(&back), 0.);
+    // and masked out where the image is clear
+    let ray = Ray::new(Vec3::new(0.8, 0.1, 10.), -Vec3::Z);
+    let clear = mesh.hit(ray, 0.001..Float::INFINITY).unwrap();
+    assert_eq!(clear.material.opacity(&clear), 0.);
+
+    // the model and its image
+    assert_eq!(assets.len(), 3);
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,431 @@
+//! wavefront obj files, and the materials of the mtl libraries they name. faces of any number of
+//! corners are split into triangles, keeping whatever surface coordinates and normals they're
+//! given, and `usemtl` groups them by material. of each mtl material:
+//!
+//! - `Kd`, or the image `map_Kd` in its place, is the color of a diffuse surface
+//! - a `Ks` that isn't black makes it shiny, a `Pbr` rougher the lower `Ns` is. it's a metal of
+//!   the `Ks` color if `Kd` is black
+//! - `d` under 1 (or `Tr` over 0) with `illum` 4, 6, 7 or 9 makes it glass refracting by `Ni`.
+//!   otherwise `d` under 1, or the alpha of the image `map_d`, lets some light through
+//! - a `Ke` that isn't black makes it glow
+//!
+//! anything else in either file, like smoothing groups or the other maps, is skipped
+
+use super::{invalid, set_corners, Model};
+use crate::assets::{Asset, Assets};
+use crate::math::{Float, Normalize, Vec3};
+use crate::rt::*;
+use std::{
+    collections::HashMap,
+    fs, io,
+    mem::size_of,
+    path::Path,
+    str::{FromStr, SplitWhitespace},
+    sync::Arc,
+};
+
+type Tex = Arc<dyn Texture + Send + Sync + 'static>;
+
+/// the contents of an obj file
+pub struct Obj {
+    /// with a group for each material
+    pub mesh: Arc<MeshData>,
+    /// the name of the material each group is made of, empty for faces before any `usemtl`
+    pub groups: Vec<String>,
+    /// mtl files named by `mtllib`, relative to the obj file
+    pub libraries: Vec<String>,
+}
+
+impl Asset for Obj {
+    fn memory(&self) -> usize {
+        self.mesh.memory()
+    }
+}
+
+fn numbers<T: FromStr>(words: SplitWhitespace, min: usize) -> Result<Vec<T>, String> {
+    let numbers = words
+        .map(|word| word.parse().map_err(|_| format!("`{word}` isn't a number")))
+        .collect::<Result<Vec<T>, _>>()?;
+    if numbers.len() < min {
+        return Err(format!("expected {min} numbers, found {}", numbers.len()));
+    }
+    Ok(numbers)
+}
+
+/// an index counting from 1, or back from the end of the list if negative
+fn index(word: &str, count: usize) -> Result<usize, String> {
+    let i: isize = word
+        .parse()
+        .map_err(|_| format!("`{word}` isn't an index"))?;
+    let resolved = match i {
+        1.. => Some(i as usize - 1),
+        0 => None,
+        _ => count.checked_add_signed(i),
+    };
+    resolved
+        .filter(|&i| i < count)
+        .ok_or_else(|| format!("index {i} is out of range"))
+}
+
+/// the rest of a line, for names that may have spaces in them
+fn rest(words: SplitWhitespace) -> String {
+    words.collect::<Vec<_>>().join(" ")
+}
+
+/// the image file a map statement names, after any options. windows paths work too
+fn map_path(words: SplitWhitespace) -> Result<String, String> {
+    let path = words.last().ok_or("expected an image file")?;
+    Ok(path.replace('\\', "/"))
+}
+
+/// a face's corner, as indices of its position, surface coordinates and normal
+type Corner = (usize, Option<usize>, Option<usize>);
+
+#[derive(Default)]
+struct ObjParser {
+    positions: Vec<Vec3>,
+    uvs: Vec<(Float, Float)>,
+    normals: Vec<Vec3>,
+    triangles: Vec<[Corner; 3]>,
+    groups: Vec<usize>,
+    names: Vec<String>,
+    group: Option<usize>,
+    libraries: Vec<String>,
+}
+
+impl ObjParser {
+    /// the group for the material `name`, added if it's new
+    fn group(&mut self, name: String) -> usize {
+        if let Some(group) = self.names.iter().position(|other| *other == name) {
+            return group;
+        }
+        self.names.push(name);
+        self.names.len() - 1
+    }
+
+    fn corner(&self, word: &str) -> Result<Corner, String> {
+        let mut parts = word.split('/');
+        let position = index(parts.next().unwrap_or(""), self.positions.len())?;
+        let mut optional = |count| match parts.next() {
+            Some(part) if !part.is_empty() => index(part, count).map(Some),
+            _ => Ok(None),
+        };
+        let uv = optional(self.uvs.len())?;
+        let normal = optional(self.normals.len())?;
+        Ok((position, uv, normal))
+    }
+
+    fn line(&mut self, line: &str) -> Result<(), String> {
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("v") => {
+                let p = numbers(words, 3)?;
+                self.positions.push(Vec3::new(p[0], p[1], p[2]));
+            }
+            Some("vt") => {
+                let uv = numbers(words, 1)?;
+                self.uvs.push((uv[0], uv.get(1).copied().unwrap_or(0.)));
+            }
+            Some("vn") => {
+                let n = numbers(words, 3)?;
+                self.normals.push(Vec3::new(n[0], n[1], n[2]).normalize());
+            }
+            Some("f") => {
+                let corners = words
+                    .map(|word| self.corner(word))
+                    .collect::<Result<Vec<_>, _>>()?;
+                if corners.len() < 3 {
+                    return Err("a face needs at least three corners".into());
+                }
+                let group = match self.group {
+                    Some(group) => group,
+                    None => self.group(String::new()),
+                };
+                self.group = Some(group);
+                for pair in corners[1..].windows(2) {
+                    self.triangles.push([corners[0], pair[0], pair[1]]);
+                    self.groups.push(group);
+                }
+            }
+            Some("usemtl") => self.group = Some(self.group(rest(words))),
+            Some("mtllib") => self.libraries.extend(words.map(String::from)),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Obj {
+        let positions = self.positions;
+        let triangles: Vec<[usize; 3]> = self
+            .triangles
+            .iter()
+            .map(|triangle| triangle.map(|(position, _, _)| position))
+            .collect();
+        let mut mesh = MeshData::new(positions, triangles);
+        // triangles with a corner missing a normal or coordinates go without
+        let normals = self
+            .triangles
+            .iter()
+            .map(|triangle| {
+                let [a, b, c] = triangle.map(|(_, _, normal)| normal.map(|i| self.normals[i]));
+                Some([a?, b?, c?])
+            })
+            .collect();
+        let uvs = self
+            .triangles
+            .iter()
+            .map(|triangle| {
+                let [a, b, c] = triangle.map(|(_, uv, _)| uv.map(|i| self.uvs[i]));
+                Some([a?, b?, c?])
+            })
+            .collect();
+        set_corners(&mut mesh, normals, uvs);
+        mesh.groups = self.groups;
+        Obj {
+            mesh: Arc::new(mesh),
+            groups: self.names,
+            libraries: self.libraries,
+        }
+    }
+}
+
+impl Obj {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut parser = ObjParser::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("");
+            parser
+                .line(line)
+                .map_err(|e| format!("line {}: {e}", number + 1))?;
+        }
+        Ok(parser.finish())
+    }
+}
+
+/// a material of an mtl file, with the defaults of the format for anything left out
+#[derive(Clone, Debug, PartialEq)]
+pub struct MtlMaterial {
+    /// `Kd`
+    pub diffuse: Color,
+    /// `Ks`
+    pub specular: Color,
+    /// `Ns`, the phong exponent, from 0 up to about 1000
+    pub shininess: Float,
+    /// `d`, how opaque it is
+    pub dissolve: Float,
+    /// `Ni`
+    pub refraction_index: Float,
+    /// `Ke`
+    pub emission: Color,
+    /// `illum`, the lighting model
+    pub illum: u32,
+    /// `map_Kd`, relative to the mtl file
+    pub diffuse_map: Option<String>,
+    /// `map_d`, relative to the mtl file
+    pub alpha_map: Option<String>,
+}
+
+impl Default for MtlMaterial {
+    fn default() -> Self {
+        Self {
+            diffuse: Color::gray(0.8),
+            specular: Color::BLACK,
+            shininess: 0.,
+            dissolve: 1.,
+            refraction_index: 1.5,
+            emission: Color::BLACK,
+            illum: 2,
+            diffuse_map: None,
+            alpha_map: None,
+        }
+    }
+}
+
+/// a color, or a gray level written as one number
+fn color(words: SplitWhitespace) -> Result<Color, String> {
+    let c = numbers(words, 1)?;
+    Ok(match c[..] {
+        [r, g, b, ..] => Color::new(r, g, b),
+        [gray, ..] => Color::gray(gray),
+        _ => unreachable!("there's at least one number"),
+    })
+}
+
+impl MtlMaterial {
+    fn line(&mut self, keyword: &str, words: SplitWhitespace) -> Result<(), String> {
+        match keyword {
+            "Kd" => self.diffuse = color(words)?,
+            "Ks" => self.specular = color(words)?,
+            "Ke" => self.emission = color(words)?,
+            "Ns" => self.shininess = numbers(words, 1)?[0],
+            "d" => self.dissolve = numbers(words, 1)?[0],
+            "Tr" => self.dissolve = 1. - numbers::<Float>(words, 1)?[0],
+            "Ni" => self.refraction_index = numbers(words, 1)?[0],
+            "illum" => self.illum = numbers(words, 1)?[0],
+            "map_Kd" => self.diffuse_map = Some(map_path(words)?),
+            "map_d" => self.alpha_map = Some(map_path(words)?),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// the closest material the tracer has, loading any images relative to `dir`
+    pub fn build(&self, dir: &Path, assets: &Assets) -> io::Result<AnyMaterial> {
+        if matches!(self.illum, 4 | 6 | 7 | 9) && self.dissolve < 1. {
+            return Ok(AnyMaterial::new(Dielectric::new(self.refraction_index)));
+        }
+        let color: Tex = match &self.diffuse_map {
+            Some(map) => Arc::new(assets.image(&dir.join(map), ColorSpace::Srgb)?),
+            None => Arc::new(SolidColor::from(self.diffuse)),
+        };
+        // the roughness whose highlight is about as wide as a phong lobe this sharp
+        let roughness = (2. / (self.shininess.max(0.) + 2.)).powf(0.25);
+        let shiny = self.illum >= 2 && !self.specular.is_black();
+        let material = if !self.emission.is_black() {
+            AnyMaterial::new(DiffuseLight::from(self.emission))
+        } else if shiny && self.diffuse.is_black() && self.diffuse_map.is_none() {
+            AnyMaterial::new(Pbr::new(SolidColor::from(self.specular), 1., roughness))
+        } else if shiny {
+            AnyMaterial::new(Pbr::new(color, 0., roughness))
+        } else {
+            AnyMaterial::new(Diffuse::new(color))
+        };
+        let alpha: Option<Tex> = match &self.alpha_map {
+            Some(map) => Some(Arc::new(assets.alpha(&dir.join(map))?)),
+            None if self.dissolve < 1. => {
+                Some(Arc::new(SolidColor::from(Color::gray(self.dissolve))))
+            }
+            None => None,
+        };
+        Ok(match alpha {
+            Some(alpha) => AnyMaterial::new(Cutout::new(material, alpha)),
+            None => material,
+        })
+    }
+}
+
+/// the materials of an mtl file, by name
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Mtl {
+    pub materials: HashMap<String, MtlMaterial>,
+}
+
+impl Asset for Mtl {
+    fn memory(&self) -> usize {
+        self.materials.len() * size_of::<(String, MtlMaterial)>()
+    }
+}
+
+impl Mtl {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut materials = HashMap::new();
+        let mut current: Option<(String, MtlMaterial)> = None;
+        for (number, line) in text.lines().enumerate() {
+            let mut words = line.split('#').next().unwrap_or("").split_whitespace();
+            let Some(keyword) = words.next() else {
+                continue;
+            };
+            if keyword == "newmtl" {
+                materials.extend(current.take());
+                current = Some((rest(words), MtlMaterial::default()));
+                continue;
+            }
+            let at = |e| format!("line {}: {e}", number + 1);
+            let Some((_, material)) = &mut current else {
+                return Err(at(format!("`{keyword}` before any `newmtl`")));
+            };
+            material.line(keyword, words).map_err(at)?;
+        }
+        materials.extend(current);
+        Ok(Self { materials })
+    }
+}
+
+/// reads the obj file at `path`, with the materials of its mtl libraries. groups whose
+/// material isn't in any of them are left without one
+pub fn open(path: &Path, assets: &Assets) -> io::Result<Model> {
+    let obj = assets.load(path, |path| {
+        Obj::parse(&fs::read_to_string(path)?).map_err(|e| invalid(path, e))
+    })?;
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let mut libraries = vec![];
+    for library in &obj.libraries {
+        let path = dir.join(library);
+        let mtl = assets.load(&path, |path| {
+            Mtl::parse(&fs::read_to_string(path)?).map_err(|e| invalid(path, e))
+        })?;
+        libraries.push((path, mtl));
+    }
+    let materials = obj
+        .groups
+        .iter()
+        .map(|name| {
+            let found = libraries.iter().find_map(|(path, mtl)| {
+                let dir = path.parent().unwrap_or(Path::new(""));
+                mtl.materials.get(name).map(|material| (dir, material))
+            });
+            found
+                .map(|(dir, material)| material.build(dir, assets))
+                .transpose()
+        })
+        .collect::<io::Result<_>>()?;
+    Ok(Model {
+        mesh: obj.mesh.clone(),
+        materials,
+    })
+}
+
+#[test]
+fn obj_with_materials() {
+    let dir = std::env::temp_dir().join("saraytracer-obj");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+        dir.join("box.obj"),
+        "# a square of two materials\n\
+         mtllib box.mtl\n\
+         v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nv 2 0 0\nv 2 1 0\n\
+         vt 0 0\nvt 1 0\nvt 1 1\nvt 0 1\n\
+         usemtl red\n\
+         f 1/1 2/2 3/3 4/4\n\
+         usemtl shiny\n\
+         f -5 -2 -1\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("box.mtl"),
+        "newmtl red\nKd 1 0 0\n\n\
+         newmtl shiny\nKd 0 0 0\nKs 0.9 0.8 0.7\nNs 900\n",
+    )
+    .unwrap();
+
+    let assets = Assets::new();
+    let model = super::open(&dir.join("box.obj"), &assets).unwrap();
+    // the quad is split in two
+    assert_eq!(model.mesh.triangles, [[0, 1, 2], [0, 2, 3], [1, 4, 5]]);
+    assert_eq!(model.mesh.groups, [0, 0, 1]);
+    // the face without coordinates gets the first
+    assert_eq!(model.mesh.uvs[2], [(0., 0.); 3]);
+    assert!(model.mesh.normals.is_empty());
+    assert!(matches!(model.materials[0], Some(AnyMaterial::Diffuse(_))));
+    assert!(matches!(model.materials[1], Some(AnyMaterial::Pbr(_))));
+    assert_eq!(assets.len(), 2);
+
+    let mesh = model.into_mesh(AnyMaterial::new(Diffuse::from(Color::WHITE)));
+    let contact = |x| {
+        let ray = Ray::new(Vec3::new(x, 0.25, -1.), Vec3::Z);
+        mesh.hit(ray, 0.001..Float::INFINITY).unwrap()
+    };
+    let red = contact(0.75);
+    let (u, v) = red.uv;
+    assert!((u - 0.75).abs() < 1e-6 && (v - 0.25).abs() < 1e-6);
+    assert!((red.material.albedo(&red) - Color::RED).is_black());
+    let shiny = contact(1.5);
+    assert!(matches!(shiny.material, AnyMaterial::Pbr(_)));
+
+    let error = |obj: &str| Obj::parse(obj).err().unwrap();
+    assert_eq!(error("v 0 0 0\nf 1 2 3"), "line 2: index 2 is out of range");
+    assert_eq!(error("v 0 zero 0"), "line 1: `zero` isn't a number");
+    let mtl = Mtl::parse("Kd 1 1 1").err().unwrap();
+    assert_eq!(mtl, "line 1: `Kd` before any `newmtl`");
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,347 @@
+//! ply files, as scanned models like the stanford bunny come in, written out in ascii or packed
+//! in binary of either byte order. of the vertices, the positions are read, along with normals
+//! (`nx`, `ny`, `nz`) and colors (`red`, `green`, `blue`) if they have them, and faces of any
+//! number of corners are split into triangles. everything else in the file is skipped.
+//!
+//! a model with colors is made of a diffuse material showing them. without, it's made of the
+//! object's own
+
+use super::{invalid, Model};
+use crate::assets::Assets;
+use crate::math::{Float, Normalize, Vec3};
+use crate::rt::*;
+use std::{fs, io, path::Path, str::SplitAsciiWhitespace};
+
+/// the type of a property's values
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Kind {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Kind {
+    fn parse(name: &str) -> Result<Self, String> {
+        Ok(match name {
+            "char" | "int8" => Kind::I8,
+            "uchar" | "uint8" => Kind::U8,
+            "short" | "int16" => Kind::I16,
+            "ushort" | "uint16" => Kind::U16,
+            "int" | "int32" => Kind::I32,
+            "uint" | "uint32" => Kind::U32,
+            "float" | "float32" => Kind::F32,
+            "double" | "float64" => Kind::F64,
+            _ => return Err(format!("unknown type `{name}`")),
+        })
+    }
+
+    /// the largest value, by which integer colors are scaled into [0, 1]. 1 for decimals
+    fn max(self) -> f64 {
+        match self {
+            Kind::I8 => i8::MAX as f64,
+            Kind::U8 => u8::MAX as f64,
+            Kind::I16 => i16::MAX as f64,
+            Kind::U16 => u16::MAX as f64,
+            Kind::I32 => i32::MAX as f64,
+            Kind::U32 => u32::MAX as f64,
+            Kind::F32 | Kind::F64 => 1.,
+        }
+    }
+}
+
+struct Property {
+    name: String,
+    kind: Kind,
+    /// the type of the count before the values, for properties that are lists
+    count: Option<Kind>,
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Ascii,
+    LittleEndian,
+    BigEndian,
+}
+
+/// the elements the header describes, and where the data after it starts
+fn header(bytes: &[u8]) -> Result<(Format, Vec<Element>, usize), String> {
+    const END: &[u8] = b"end_header";
+    let end = bytes
+        .windows(END.len())
+        .position(|window| window == END)
+        .ok_or("the header never ends")?;
+    let text = std::str::from_utf8(&bytes[..end]).map_err(|_| "the header isn't text")?;
+    // the data starts on the line after
+    let start = bytes[end..]
+        .iter()
+        .position(|&byte| byte == b'\n')
+        .map_or(bytes.len(), |newline| end + newline + 1);
+
+    let mut lines = text.lines();
+    if lines.next().map(str::trim) != Some("ply") {
+        return Err("not a ply file".into());
+    }
+    let mut format = None;
+    let mut elements: Vec<Element> = vec![];
+    for line in lines {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words[..] {
+            ["format", name, _] => {
+                format = Some(match name {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::LittleEndian,
+                    "binary_big_endian" => Format::BigEndian,
+                    _ => return Err(format!("unknown format `{name}`")),
+                })
+            }
+            ["element", name, count] => elements.push(Element {
+                name: name.into(),
+                count: count
+                    .parse()
+                    .map_err(|_| format!("`{count}` isn't a count"))?,
+                properties: vec![],
+            }),
+            ["property", ref rest @ ..] => {
+                let (count, kind, name) = match *rest {
+                    ["list", count, kind, name] => (Some(Kind::parse(count)?), kind, name),
+                    [kind, name] => (None, kind, name),
+                    _ => return Err(format!("can't read `{line}`")),
+                };
+                let element = elements
+                    .last_mut()
+                    .ok_or_else(|| format!("property `{name}` outside of any element"))?;
+                element.properties.push(Property {
+                    name: name.into(),
+                    kind: Kind::parse(kind)?,
+                    count,
+                });
+            }
+            _ => {}
+        }
+    }
+    let format = format.ok_or("the header has no format")?;
+    Ok((format, elements, start))
+}
+
+/// the values after the header, one after another
+enum Data<'a> {
+    Ascii(SplitAsciiWhitespace<'a>),
+    Binary(&'a [u8], Format),
+}
+
+impl Data<'_> {
+    fn read(&mut self, kind: Kind) -> Result<f64, String> {
+        let (bytes, format) = match self {
+            Data::Ascii(words) => {
+                let word = words.next().ok_or("the file ends early")?;
+                return word.parse().map_err(|_| format!("`{word}` isn't a number"));
+            }
+            Data::Binary(bytes, format) => (bytes, *format),
+        };
+        macro_rules! take {
+            ($ty:ty) => {{
+                const SIZE: usize = std::mem::size_of::<$ty>();
+                let (value, rest) = bytes
+                    .split_first_chunk::<SIZE>()
+                    .ok_or("the file ends early")?;
+                *bytes = rest;
+                match format {
+                    Format::BigEndian => <$ty>::from_be_bytes(*value),
+                    _ => <$ty>::from_le_bytes(*value),
+                }
+            }};
+        }
+        Ok(match kind {
+            Kind::I8 => take!(i8) as f64,
+            Kind::U8 => take!(u8) as f64,
+            Kind::I16 => take!(i16) as f64,
+            Kind::U16 => take!(u16) as f64,
+            Kind::I32 => take!(i32) as f64,
+            Kind::U32 => take!(u32) as f64,
+            Kind::F32 => take!(f32) as f64,
+            Kind::F64 => take!(f64),
+        })
+    }
+
+    /// the values of a property of one element, several if it's a list
+    fn property(&mut self, property: &Property) -> Result<Vec<f64>, String> {
+        let count = match property.count {
+            Some(kind) => self.read(kind)? as usize,
+            None => 1,
+        };
+        (0..count).map(|_| self.read(property.kind)).collect()
+    }
+}
+
+/// reads a ply file into a mesh
+pub fn parse(bytes: &[u8]) -> Result<MeshData, String> {
+    let (format, elements, start) = header(bytes)?;
+    let mut data = match format {
+        Format::Ascii => Data::Ascii(
+            std::str::from_utf8(&bytes[start..])
+                .map_err(|_| "the data isn't text")?
+                .split_ascii_whitespace(),
+        ),
+        binary => Data::Binary(&bytes[start..], binary),
+    };
+
+    let mut positions = vec![];
+    let mut normals = vec![];
+    let mut colors = vec![];
+    let mut faces: Vec<Vec<usize>> = vec![];
+    for element in &elements {
+        let find = |name: &str| element.properties.iter().position(|p| p.name == name);
+        let [x, y, z, nx, ny, nz, red, green, blue] =
+            ["x", "y", "z", "nx", "ny", "nz", "red", "green", "blue"].map(find);
+        let indices = find("vertex_indices").or_else(|| find("vertex_index"));
+        for _ in 0..element.count {
+            let values = element
+                .properties
+                .iter()
+                .map(|property| data.property(property))
+                .collect::<Result<Vec<_>, _>>()?;
+            let vector = |x: Option<usize>, y: Option<usize>, z: Option<usize>| {
+                let [x, y, z] = [x?, y?, z?].map(|i| values[i].first().copied().unwrap_or(0.));
+                Some(Vec3::new(x as Float, y as Float, z as Float))
+            };
+            match element.name.as_str() {
+                "vertex" => {
+                    positions.push(vector(x, y, z).ok_or("vertices need x, y and z")?);
+                    normals.extend(vector(nx, ny, nz).map(|n| n.normalize()));
+                    colors.extend(vector(red, green, blue).map(|c| {
+                        let max = element.properties[red.unwrap()].kind.max() as Float;
+                        Color::from(c / max)
+                    }));
+                }
+                "face" => {
+                    let indices = indices.ok_or("faces need vertex indices")?;
+                    let face = values[indices].iter().map(|&i| {
+                        if i >= 0. && i.fract() == 0. {
+                            Ok(i as usize)
+                        } else {
+                            Err(format!("`{i}` isn't a vertex index"))
+                        }
+                    });
+                    faces.push(face.collect::<Result<_, _>>()?);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let mut triangles = vec![];
+    for face in faces {
+        if let Some(&i) = face.iter().find(|&&i| i >= positions.len()) {
+            return Err(format!(
+                "index {i} is past the {} vertices",
+                positions.len()
+            ));
+        }
+        for pair in face.get(1..).unwrap_or_default().windows(2) {
+            triangles.push([face[0], pair[0], pair[1]]);
+        }
+    }
+    let mut mesh = MeshData::new(positions, triangles);
+    if normals.len() == mesh.positions.len() {
+        mesh = mesh.with_vertex_normals(&normals)?;
+    }
+    if colors.len() == mesh.positions.len() {
+        mesh.colors = mesh
+            .triangles
+            .iter()
+            .map(|triangle| triangle.map(|i| colors[i]))
+            .collect();
+    }
+    Ok(mesh)
+}
+
+/// reads the ply file at `path`
+pub fn open(path: &Path, assets: &Assets) -> io::Result<Model> {
+    let mesh = assets.load(path, |path| {
+        parse(&fs::read(path)?).map_err(|e| invalid(path, e))
+    })?;
+    let materials = match mesh.colors.is_empty() {
+        true => vec![],
+        false => vec![Some(AnyMaterial::new(Diffuse::new(VertexColors)))],
+    };
+    Ok(Model { mesh, materials })
+}
+
+#[test]
+fn ply_formats() {
+    let header = |format: &str| {
+        format!(
+            "ply\nformat {format} 1.0\ncomment a colored square\n\
+             element vertex 4\n\
+             property float x\nproperty float y\nproperty float z\n\
+             property uchar red\nproperty uchar green\nproperty uchar blue\n\
+             element face 1\nproperty list uchar int vertex_indices\nend_header\n"
+        )
+    };
+    let corners = [(0., 0.), (1., 0.), (1., 1.), (0., 1.)];
+    let ascii = header("ascii")
+        + "0 0 0 255 0 0\n1 0 0 0 255 0\n1 1 0 0 0 255\n0 1 0 255 255 255\n4 0 1 2 3\n";
+    let mut little = header("binary_little_endian").into_bytes();
+    let mut big = header("binary_big_endian").into_bytes();
+    for (x, y) in corners {
+        for value in [x, y, 0.] {
+            little.extend((value as f32).to_le_bytes());
+            big.extend((value as f32).to_be_bytes());
+        }
+        for bytes in [&mut little, &mut big] {
+            bytes.extend([255, 0, 0]);
+        }
+    }
+    for bytes in [&mut little, &mut big] {
+        bytes.push(4);
+    }
+    for i in 0..4i32 {
+        little.extend(i.to_le_bytes());
+        big.extend(i.to_be_bytes());
+    }
+
+    for bytes in [ascii.as_bytes(), &little, &big] {
+        let mesh = parse(bytes).unwrap();
+        assert_eq!(mesh.positions[2], Vec3::new(1., 1., 0.));
+        assert_eq!(mesh.triangles, [[0, 1, 2], [0, 2, 3]]);
+        assert_eq!(mesh.colors[0][0], Color::RED);
+        assert!(mesh.normals.is_empty());
+    }
+
+    // the colors are blended across the surface, for the material to show
+    let model = Model {
+        mesh: std::sync::Arc::new(parse(ascii.as_bytes()).unwrap()),
+        materials: vec![Some(AnyMaterial::new(Diffuse::new(VertexColors)))],
+    };
+    let mesh = model.into_mesh(AnyMaterial::new(Diffuse::from(Color::WHITE)));
+    let ray = Ray::new(Vec3::new(0.6, 0.3, -1.), Vec3::Z);
+    let contact = mesh.hit(ray, 0.001..Float::INFINITY).unwrap();
+    let albedo = contact.material.albedo(&contact);
+    assert!(
+        (albedo - Color::new(0.4, 0.3, 0.3)).is_black(),
+        "{albedo:?}"
+    );
+
+    for index in ["-1", "1.5"] {
+        let face = format!("\n4 0 1 2 {index}\n");
+        let bad = ascii.replace("\n4 0 1 2 3\n", &face);
+        assert_eq!(
+            parse(bad.as_bytes()).err().unwrap(),
+            format!("`{index}` isn't a vertex index")
+        );
+    }
+    let truncated = &little[..little.len() - 2];
+    assert_eq!(parse(truncated).err().unwrap(), "the file ends early");
+    assert_eq!(parse(b"solid cube").err().unwrap(), "the header never ends");
+}
@@ -0,0 +1,181 @@
+//! stl files, as models for 3d printing are saved, in binary or ascii. stl only has loose
+//! triangles with a normal each, so corners in the same place are joined back into shared
+//! vertices, and the mesh is shaded smoothly across edges meeting at less than
+//! `SMOOTH_ANGLE`, curved surfaces blending while the sharp edges of printed parts stay sharp.
+//! triangles wound against the normal the file gives them are turned around.
+//!
+//! stl has no materials, so the model is made of the object's own. models are usually in
+//! millimeters, and may want scaling down
+
+use super::{invalid, Model};
+use crate::assets::Assets;
+use crate::math::{Float, Vec3};
+use crate::rt::*;
+use std::{collections::HashMap, fs, io, path::Path};
+
+/// degrees between faces past which their edge is left sharp
+pub const SMOOTH_ANGLE: Float = 30.;
+
+/// a triangle, with the normal the file gives it
+type Facet = (Vec3, [Vec3; 3]);
+
+fn vec3(bytes: &[u8]) -> Vec3 {
+    let [x, y, z] = [0, 4, 8].map(|i| {
+        let value = f32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+        value as Float
+    });
+    Vec3::new(x, y, z)
+}
+
+/// an 80 byte header, a count, and 50 bytes for each triangle: the normal, the three corners,
+/// and two bytes of attributes
+fn binary(bytes: &[u8]) -> Option<Vec<Facet>> {
+    let count = bytes.get(80..84)?;
+    let count = u32::from_le_bytes([count[0], count[1], count[2], count[3]]) as usize;
+    let facets = bytes.get(84..)?;
+    if facets.len() != count.checked_mul(50)? {
+        return None;
+    }
+    let facets = facets.chunks_exact(50).map(|facet| {
+        let normal = vec3(&facet[0..12]);
+        (normal, [12, 24, 36].map(|i| vec3(&facet[i..i + 12])))
+    });
+    Some(facets.collect())
+}
+
+fn ascii(text: &str) -> Result<Vec<Facet>, String> {
+    let mut words = text.split_whitespace();
+    let vector = |words: &mut std::str::SplitWhitespace| -> Result<Vec3, String> {
+        let mut number = || {
+            let word = words.next().ok_or("the file ends early")?;
+            word.parse::<Float>()
+                .map_err(|_| format!("`{word}` isn't a number"))
+        };
+        Ok(Vec3::new(number()?, number()?, number()?))
+    };
+    let mut facets = vec![];
+    let (mut normal, mut corners) = (Vec3::ZERO, vec![]);
+    while let Some(word) = words.next() {
+        match word {
+            "normal" => normal = vector(&mut words)?,
+            "vertex" => corners.push(vector(&mut words)?),
+            "endfacet" => {
+                // facets of more than three corners are split
+                for pair in corners.get(1..).unwrap_or_default().windows(2) {
+                    facets.push((normal, [corners[0], pair[0], pair[1]]));
+                }
+                (normal, corners) = (Vec3::ZERO, vec![]);
+            }
+            _ => {}
+        }
+    }
+    Ok(facets)
+}
+
+/// reads an stl file into a mesh, with its corners joined but not yet smoothed
+pub fn parse(bytes: &[u8]) -> Result<MeshData, String> {
+    // binary files may start with `solid` too, so they're told apart by their length
+    let facets = match binary(bytes) {
+        Some(facets) => facets,
+        None if bytes.trim_ascii_start().starts_with(b"solid") => {
+            ascii(std::str::from_utf8(bytes).map_err(|_| "the file isn't text")?)?
+        }
+        None => return Err("neither a binary nor an ascii stl file".into()),
+    };
+
+    let mut positions = vec![];
+    // corners by their exact coordinates
+    let mut vertices = HashMap::new();
+    let mut vertex = |p: Vec3| {
+        *vertices
+            .entry([p.x, p.y, p.z].map(Float::to_bits))
+            .or_insert_with(|| {
+                positions.push(p);
+                positions.len() - 1
+            })
+    };
+    let mut triangles = vec![];
+    for (normal, [a, b, c]) in facets {
+        let winding = (b - a).cross(c - a);
+        let (b, c) = if winding.dot(normal) < 0. {
+            (c, b)
+        } else {
+            (b, c)
+        };
+        triangles.push([vertex(a), vertex(b), vertex(c)]);
+    }
+    Ok(MeshData::new(positions, triangles))
+}
+
+/// reads the stl file at `path`, shaded smoothly
+pub fn open(path: &Path, assets: &Assets) -> io::Result<Model> {
+    let mesh = assets.load(path, |path| {
+        let mesh = parse(&fs::read(path)?).map_err(|e| invalid(path, e))?;
+        Ok(mesh.smooth(SMOOTH_ANGLE))
+    })?;
+    Ok(Model {
+        mesh,
+        materials: vec![],
+    })
+}
+
+#[test]
+fn stl_formats() {
+    // a tetrahedron, with one face wound inside out, and a facet without a normal
+    let corners = [Vec3::ZERO, Vec3::X, Vec3::Y, Vec3::Z];
+    let facets = [
+        (-Vec3::Z, [0, 2, 1]),
+        (-Vec3::Y, [0, 3, 1]),
+        (-Vec3::X, [0, 3, 2]),
+        (Vec3::ZERO, [1, 2, 3]),
+    ];
+    let mut text = "solid tetrahedron\n".to_string();
+    let mut bytes = b"solid but binary".to_vec();
+    bytes.resize(80, 0);
+    bytes.extend((facets.len() as u32).to_le_bytes());
+    for (normal, triangle) in facets {
+        text += &format!(
+            "facet normal {} {} {}\nouter loop\n",
+            normal.x, normal.y, normal.z
+        );
+        for p in [normal].into_iter().chain(triangle.map(|i| corners[i])) {
+            bytes.extend(
+                [p.x, p.y, p.z]
+                    .iter()
+                    .flat_map(|&x| (x as f32).to_le_bytes()),
+            );
+        }
+        for p in triangle.map(|i| corners[i]) {
+            text += &format!("vertex {} {} {}\n", p.x, p.y, p.z);
+        }
+        text += "endloop\nendfacet\n";
+        bytes.extend([0, 0]);
+    }
+    text += "endsolid tetrahedron\n";
+
+    for bytes in [text.as_bytes(), &bytes] {
+        let mesh = parse(bytes).unwrap();
+        // the corners are shared again
+        assert_eq!(mesh.positions.len(), corners.len());
+        assert!(corners.iter().all(|p| mesh.positions.contains(p)));
+        // every face now faces out
+        let center = Vec3::ONE / 4.;
+        for &[a, b, c] in &mesh.triangles {
+            let [a, b, c] = [a, b, c].map(|i| mesh.positions[i]);
+            assert!((b - a).cross(c - a).dot(a - center) > 0.);
+        }
+    }
+
+    // no two faces of a tetrahedron are within the angle, so it stays sharp
+    let mesh = parse(text.as_bytes()).unwrap().smooth(SMOOTH_ANGLE);
+    let mesh = Mesh::shared(std::sync::Arc::new(mesh), Diffuse::from(Color::WHITE));
+    let ray = Ray::new(Vec3::new(0.1, 0.1, -1.), Vec3::Z);
+    let contact = mesh.hit(ray, 0.001..Float::INFINITY).unwrap();
+    assert!((contact.normal + Vec3::Z).is_zero());
+
+    assert_eq!(
+        parse(b"solid\nfacet normal 0 0 x").err().unwrap(),
+        "`x` isn't a number"
+    );
+    assert!(parse(&bytes[..100]).is_err());
+}
@@ -0,0 +1,498 @@
+//! integrators, which find the light arriving along each ray a camera casts: the path tracer
+//! renders with, and debug views of the geometry it's tracing through
+
+use crate::math::{random, Float, Vec3};
+use crate::rt::{
+    Color, InteriorStack, Light, LightSample, Material, Ray, RayContact, Shape, World,
+    MAX_PASS_THROUGHS,
+};
+use crate::stats::{count, thread_count, Counter};
+use std::str::FromStr;
+
+/// multiple importance sampling weight for a sample drawn with density `f`,
+/// against another strategy that could have drawn it with density `g`
+fn power_heuristic(f: Float, g: Float) -> Float {
+    if f.is_infinite() {
+        return 1.;
+    }
+    let (f2, g2) = (f * f, g * g);
+    if f2 + g2 == 0. {
+        0.
+    } else {
+        f2 / (f2 + g2)
+    }
+}
+
+/// the integrators to pick from, by name
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum IntegratorKind {
+    /// a `PathTracer`
+    #[default]
+    Path,
+    /// photons traced out from the lights as well, with `Sppm`, for caustics
+    Sppm,
+    /// `Normals`
+    Normals,
+    /// `Depth`, fading out at the given distance
+    Depth(Float),
+    /// `Uvs`
+    Uv,
+    /// a `Heatmap` of a `PathTracer`, white at the given number of intersection tests
+    Heat(Float),
+}
+
+impl IntegratorKind {
+    /// whether it shows the geometry rather than the light
+    pub fn is_debug(self) -> bool {
+        matches!(
+            self,
+            IntegratorKind::Normals
+                | IntegratorKind::Depth(_)
+                | IntegratorKind::Uv
+                | IntegratorKind::Heat(_)
+        )
+    }
+}
+
+impl FromStr for IntegratorKind {
+    type Err = String;
+
+    /// path, sppm, normals, depth, depth:<far>, uv, heat or heat:<tests>
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.starts_with("heat") && !cfg!(feature = "stats") {
+            return Err("heat counts intersection tests, which needs the stats feature".into());
+        }
+        match s.split_once(':') {
+            Some(("depth", far)) => far
+                .parse()
+                .map(IntegratorKind::Depth)
+                .map_err(|e| format!("bad depth distance `{far}`: {e}")),
+            Some(("heat", tests)) => tests
+                .parse()
+                .map(IntegratorKind::Heat)
+                .map_err(|e| format!("bad intersection test count `{tests}`: {e}")),
+            _ => match s {
+                "path" => Ok(IntegratorKind::Path),
+                "sppm" => Ok(IntegratorKind::Sppm),
+                "normals" => Ok(IntegratorKind::Normals),
+                "depth" => Ok(IntegratorKind::Depth(Depth::default().far)),
+                "uv" => Ok(IntegratorKind::Uv),
+                "heat" => Ok(IntegratorKind::Heat(Heatmap::DEFAULT_MAX)),
+                _ => Err(format!(
+                    "unknown integrator `{s}`, expected path, sppm, normals, depth, uv or heat"
+                )),
+            },
+        }
+    }
+}
+
+/// finds the light arriving along a ray, for the pixel it was cast through
+pub trait Integrator {
+    fn color(&self, ray: Ray, world: &World) -> Color;
+}
+
+/// integrators picked at runtime are integrators too
+impl<I: Integrator + ?Sized> Integrator for Box<I> {
+    fn color(&self, ray: Ray, world: &World) -> Color {
+        (**self).color(ray, world)
+    }
+}
+
+/// limits on the light a path can carry, to trade a little bias for fewer fireflies:
+/// the lone bright pixels left by rare paths that find a light through glass or off metal
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Clamp {
+    /// highest luminance any bounce after the first can add. lights seen directly are left alone
+    pub bounce: Option<Float>,
+    /// highest luminance of a whole sample
+    pub sample: Option<Float>,
+}
+
+/// scales a color down to the given luminance if it's brighter, keeping its hue
+fn clamp_luminance(color: Color, max: Option<Float>) -> Color {
+    match max {
+        Some(max) if color.luminance() > max => color * (max / color.luminance()),
+        _ => color,
+    }
+}
+
+/// follows paths of light back from the camera, bouncing off what they hit, and samples the
+/// lights directly at every bounce
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PathTracer {
+    /// bounces per path
+    pub max_depth: u32,
+    /// limits on the light of each path
+    pub clamp: Clamp,
+    /// lights drawn at random at each bounce, to resample down to a single shadow ray. none
+    /// sends one to every light, which gets slow with hundreds of them
+    pub light_candidates: Option<u32>,
+}
+
+impl Default for PathTracer {
+    fn default() -> Self {
+        Self::new(50)
+    }
+}
+
+impl PathTracer {
+    /// constructor for paths of up to `max_depth` bounces
+    pub fn new(max_depth: u32) -> Self {
+        Self {
+            max_depth,
+            clamp: Clamp::default(),
+            light_candidates: None,
+        }
+    }
+}
+
+impl Integrator for PathTracer {
+    fn color(&self, ray: Ray, world: &World) -> Color {
+        let mut color = Color::BLACK;
+        // fraction of the light found at the current bounce that makes it back to the camera
+        let mut throughput = Color::WHITE;
+        let mut ray = ray;
+        // set when the previous bounce also sampled the world's lights directly, holding the density
+        // it scattered this ray with, to weigh any light it hits against that
+        let mut scatter_pdf: Option<Float> = None;
+        let mut interiors = InteriorStack::default();
+
+        count(Counter::Paths, 1);
+        let (mut bounce, mut passes) = (0, 0);
+        while bounce < self.max_depth {
+            count(Counter::Bounces, 1);
+            // light this bounce adds, clamped past the first
+            let add = |light: Color| match bounce {
+                0 => light,
+                _ => clamp_luminance(light, self.clamp.bounce),
+            };
+            let Some(mut contact) = world.hit(ray, 0.0..Float::INFINITY) else {
+                let mut background = world.background.color(ray);
+                if let (Some(bsdf_pdf), Some(light)) = (scatter_pdf, world.environment) {
+                    let light_pdf = world.lights[light].pdf(ray.origin, ray.direction);
+                    background = power_heuristic(bsdf_pdf, light_pdf) * background;
+                }
+                color += add(throughput * background);
+                break;
+            };
+            // surfaces inside a higher priority volume are passed through without a bounce
+            if !interiors.hit(ray, &mut contact) {
+                passes += 1;
+                if passes > MAX_PASS_THROUGHS {
+                    break;
+                }
+                ray = contact.spawn(ray, ray.direction);
+                continue;
+            }
+
+            let mut emitted = contact.material.emitted(&contact);
+            if let (Some(bsdf_pdf), Some(light)) = (scatter_pdf, contact.light) {
+                let light_pdf = world.lights[light].pdf(ray.origin, ray.direction);
+                emitted = power_heuristic(bsdf_pdf, light_pdf) * emitted;
+            }
+            color += add(throughput
+                * (emitted + direct_light(ray, world, &contact, true, self.light_candidates)));
+
+            let Some(scatter) = contact.material.scatter(ray, &contact) else {
+                break;
+            };
+            interiors.scattered(&contact, scatter.ray);
+            throughput *= scatter.attenuation;
+            ray = scatter.ray;
+            // specular scatters aren't competing with light sampling, so hit lights in full
+            scatter_pdf = scatter.pdf.filter(|&pdf| pdf > 0.);
+            bounce += 1;
+        }
+        clamp_luminance(color, self.clamp.sample)
+    }
+}
+
+/// the shading normal of the first surface hit, facing the camera, with each axis mapped from
+/// [-1, 1] to a channel in [0, 1]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Normals;
+
+impl Integrator for Normals {
+    fn color(&self, ray: Ray, world: &World) -> Color {
+        match world.hit(ray, 0.0..Float::INFINITY) {
+            Some(contact) => Color::from((contact.normal + Vec3::ONE) / 2.),
+            None => Color::BLACK,
+        }
+    }
+}
+
+/// distance to the first surface hit, white up close fading to black at `far` and beyond
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Depth {
+    pub far: Float,
+}
+
+impl Default for Depth {
+    fn default() -> Self {
+        Self { far: 10. }
+    }
+}
+
+impl Integrator for Depth {
+    fn color(&self, ray: Ray, world: &World) -> Color {
+        match world.hit(ray, 0.0..Float::INFINITY) {
+            Some(contact) => {
+                let distance = contact.t * ray.direction.length();
+                Color::gray((1. - distance / self.far).max(0.))
+            }
+            None => Color::BLACK,
+        }
+    }
+}
+
+/// surface coordinates of the first surface hit, u in red and v in green. shapes without any
+/// come out black
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Uvs;
+
+impl Integrator for Uvs {
+    fn color(&self, ray: Ray, world: &World) -> Color {
+        match world.hit(ray, 0.0..Float::INFINITY) {
+            Some(RayContact { uv: (u, v), .. }) => Color::new(u, v, 0.),
+            None => Color::BLACK,
+        }
+    }
+}
+
+/// the intersection tests the path tracer runs for each path, shadow rays included, to see where
+/// the world is slow to trace. black for none, through red and yellow to white at `max` and
+/// beyond. needs the `stats` feature to count them, and is black without
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Heatmap {
+    pub tracer: PathTracer,
+    pub max: Float,
+}
+
+impl Heatmap {
+    pub const DEFAULT_MAX: Float = 1000.;
+}
+
+impl Integrator for Heatmap {
+    fn color(&self, ray: Ray, world: &World) -> Color {
+        let before = thread_count(Counter::IntersectionTests);
+        self.tracer.color(ray, world);
+        let tests = thread_count(Counter::IntersectionTests) - before;
+        let heat = 3. * tests as Float / self.max;
+        Color::new(
+            heat.min(1.),
+            (heat - 1.).clamp(0., 1.),
+            (heat - 2.).clamp(0., 1.),
+        )
+    }
+}
+
+/// light reaching the contact straight from the world's lights, with a shadow ray for each, or
+/// for just one picked out of `candidates` lights drawn at random if there's a number of them.
+/// `weighted` leaves out what a scattered ray could also have found, for paths that go on to
+/// weigh the lights they hit against it
+pub(crate) fn direct_light(
+    ray: Ray,
+    world: &World,
+    contact: &RayContact,
+    weighted: bool,
+    candidates: Option<u32>,
+) -> Color {
+    // the light a sample would bring if nothing's in the way
+    let unshadowed = |light: &(dyn Light + Send + Sync)| {
+        let sample = light.sample(contact.point)?;
+        let cos = sample.direction.dot(contact.normal);
+        if cos <= 0. {
+            return None;
+        }
+        let brdf = contact.material.brdf(ray, contact, sample.direction);
+        if brdf.is_black() {
+            return None;
+        }
+        let weight = if weighted {
+            let bsdf_pdf = contact
+                .material
+                .scattering_pdf(ray, contact, sample.direction);
+            power_heuristic(sample.pdf, bsdf_pdf)
+        } else {
+            1.
+        };
+        Some((sample, weight * brdf * cos * sample.radiance))
+    };
+    let visible = |sample: &LightSample| {
+        count(Counter::ShadowRays, 1);
+        let (shadow, bounds) = contact.shadow_ray(ray, sample.direction, sample.distance);
+        !world.occluded(shadow, bounds)
+    };
+
+    let Some(candidates) = candidates else {
+        return world
+            .lights
+            .iter()
+            .filter_map(|light| unshadowed(light.as_ref()))
+            .filter(|(sample, _)| visible(sample))
+            .fold(Color::BLACK, |sum, (_, light)| sum + light);
+    };
+    let lights = world.lights.len();
+    if lights == 0 || candidates == 0 {
+        return Color::BLACK;
+    }
+    // resampled importance sampling: candidates are kept in proportion to how bright they'd be,
+    // and the one picked is weighed by how much brighter than that the candidates were overall
+    let mut reservoir = Reservoir::default();
+    for _ in 0..candidates {
+        let light = &world.lights[((random::<Float>() * lights as Float) as usize).min(lights - 1)];
+        // over the chance of drawing the light
+        let candidate =
+            unshadowed(light.as_ref()).map(|(sample, light)| (sample, lights as Float * light));
+        let weight = candidate.map_or(0., |(_, light)| light.luminance().max(0.));
+        reservoir.add(candidate, weight);
+    }
+    match reservoir.picked {
+        Some((sample, light)) if visible(&sample) => {
+            light / light.luminance() * (reservoir.weight / candidates as Float)
+        }
+        _ => Color::BLACK,
+    }
+}
+
+/// one of a stream of candidates, picked as they come with odds in proportion to their weights,
+/// without keeping the rest
+struct Reservoir<T> {
+    picked: Option<T>,
+    /// of every candidate so far
+    weight: Float,
+}
+
+impl<T> Default for Reservoir<T> {
+    fn default() -> Self {
+        Self {
+            picked: None,
+            weight: 0.,
+        }
+    }
+}
+
+impl<T> Reservoir<T> {
+    /// offers the next candidate. none is one that can never be picked
+    fn add(&mut self, candidate: Option<T>, weight: Float) {
+        self.weight += weight;
+        if weight > 0. && random::<Float>() * self.weight < weight {
+            self.picked = candidate;
+        }
+    }
+}
+
+#[test]
+fn clamped_samples() {
+    use crate::rt::*;
+    let scene = SceneBuilder::new()
+        .look_at(Vec3::new(0., 0., -4.), Vec3::ZERO)
+        .background(SolidBackground::from(Color::BLACK))
+        .sphere(Vec3::ZERO, 1.)
+        .emissive(Color::new(50., 50., 50.))
+        .build();
+    let ray = Ray::new(scene.view.eye, Vec3::Z);
+    let clamp = |bounce, sample| PathTracer {
+        clamp: Clamp { bounce, sample },
+        ..PathTracer::new(10)
+    };
+
+    let light = PathTracer::new(10).color(ray, &scene.world);
+    assert!((light.luminance() - 50.).abs() < 1e-9);
+    // the light is seen directly, so only the per sample clamp applies
+    assert_eq!(clamp(Some(2.), None).color(ray, &scene.world), light);
+    let clamped = clamp(None, Some(2.)).color(ray, &scene.world);
+    assert!((clamped.luminance() - 2.).abs() < 1e-9);
+}
+
+#[test]
+fn resampled_lights() {
+    use crate::rt::*;
+    let mut builder = SceneBuilder::new()
+        .background(SolidBackground::from(Color::BLACK))
+        .sphere(Vec3::new(0., -100., 0.), 100.)
+        .diffuse(Color::gray(0.5));
+    for i in 0..100 {
+        let (x, z) = ((i % 10) as Float - 4.5, (i / 10) as Float - 4.5);
+        let color = Color::new(1. + x.abs(), 1., 1. + z.abs());
+        builder = builder.light(PointLight::new(Vec3::new(x, 2., z), color));
+    }
+    let world = builder.build().world;
+    let ray = Ray::new(Vec3::new(0.5, 3., -1.), Vec3::new(0., -1., 0.3));
+
+    // one bounce leaves just the direct light, which a shadow ray to every light finds exactly
+    let exact = PathTracer::new(1).color(ray, &world);
+    let resampling = PathTracer {
+        light_candidates: Some(8),
+        ..PathTracer::new(1)
+    };
+    let n = 5000;
+    let resampled =
+        (0..n).fold(Color::BLACK, |sum, _| sum + resampling.color(ray, &world)) / n as Float;
+    let error = (resampled - exact) / exact;
+    assert!(error.r.abs() < 0.03 && error.g.abs() < 0.03 && error.b.abs() < 0.03);
+}
+
+#[test]
+fn debug_views() {
+    use crate::rt::*;
+    let world = SceneBuilder::new()
+        .quad(
+            Vec3::new(-1., -1., 2.),
+            Vec3::new(2., 0., 0.),
+            Vec3::new(0., 2., 0.),
+        )
+        .build()
+        .world;
+    let ray = Ray::new(Vec3::new(0.5, 0., 0.), Vec3::Z);
+    let miss = Ray::new(Vec3::ZERO, -Vec3::Z);
+
+    // the quad faces away from the ray, so its normal is turned around to face it
+    assert_eq!(Normals.color(ray, &world), Color::new(0.5, 0.5, 0.));
+    assert_eq!(Depth { far: 4. }.color(ray, &world), Color::gray(0.5));
+    assert_eq!(Uvs.color(ray, &world), Color::new(0.75, 0.5, 0.));
+    for color in [
+        Normals.color(miss, &world),
+        Depth::default().color(miss, &world),
+        Uvs.color(miss, &world),
+    ] {
+        assert_eq!(color, Color::BLACK);
+    }
+    assert_eq!("depth:20".parse(), Ok(IntegratorKind::Depth(20.)));
+    assert!("depth:far".parse::<IntegratorKind>().is_err());
+
+    // a miss tests the world's one shape and goes no further
+    #[cfg(feature = "stats")]
+    {
+        let heat = |max| {
+            let tracer = PathTracer::new(4);
+            Heatmap { tracer, max }.color(miss, &world)
+        };
+        assert_eq!(heat(2.), Color::new(1., 0.5, 0.));
+        assert_eq!(heat(1.), Color::WHITE);
+        assert_eq!("heat:100".parse(), Ok(IntegratorKind::Heat(100.)));
+    }
+    #[cfg(not(feature = "stats"))]
+    assert!("heat".parse::<IntegratorKind>().is_err());
+}
+
+#[test]
+fn pass_throughs_take_no_bounces() {
+    use crate::rt::*;
+    // a ball of glass as clear as air, filling rings of water that it takes priority over
+    let mut builder = SceneBuilder::new()
+        .background(SolidBackground::from(Color::WHITE))
+        .sphere(Vec3::ZERO, 10.)
+        .material(Dielectric::new(1.).with_priority(2));
+    for radius in 1..=4 {
+        builder = builder
+            .sphere(Vec3::ZERO, radius as Float)
+            .material(Dielectric::new(1.33).with_priority(1));
+    }
+    let world = builder.build().world;
+    let ray = Ray::new(Vec3::new(0., 0., -20.), Vec3::Z);
+
+    // in and out of the glass is two bounces, however many water surfaces are passed on the way
+    assert_eq!(PathTracer::new(3).color(ray, &world), Color::WHITE);
+    assert_eq!(PathTracer::new(2).color(ray, &world), Color::BLACK);
+}
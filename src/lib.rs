@@ -0,0 +1,65 @@
+//! a path tracer, rendering scenes of shapes, materials and lights to images.
+//!
+//! - [`math`] has the vector and matrix types everything is built from
+//! - [`rt`] has the scene itself: the [`World`](rt::World) of shapes and lights, cameras,
+//!   materials, textures and backgrounds, plus [`SceneBuilder`](rt::SceneBuilder) to put them
+//!   together
+//! - [`render`] traces a world into a [`Film`](render::Film), pass by pass, with the path tracer
+//!   or debug views of [`integrator`], and [`sppm`] renders it by photon mapping instead, for
+//!   caustics
+//! - [`post`] develops what's been rendered for display, exposing and tone mapping it,
+//!   [`output`] saves it, [`video`] encodes animations, and [`denoise`] cleans renders up
+//! - [`scene`] reads scenes described in ron files, sharing the images they load through
+//!   [`assets`], and [`anim`] keyframes them over time. [`import`] reads the models they use
+//! - [`distributed`] splits a render between machines, [`server`] takes renders over http, and
+//!   [`ffi`] embeds the tracer in programs written in c and other languages
+//! - [`stats`] counts the rays a render traces, with the `stats` feature
+//!
+//! ```no_run
+//! use raytracer::{integrator::PathTracer, math::Vec3, output::*, post::*, render::Film, rt::*};
+//!
+//! let scene = SceneBuilder::new()
+//!     .look_at(Vec3::new(0., 1., -6.), Vec3::new(0., 1., 0.))
+//!     .sphere(Vec3::new(0., -1000., 0.), 1000.)
+//!     .diffuse(Color::new(0.5, 0.5, 0.5))
+//!     .sphere(Vec3::new(0., 1., 0.), 1.)
+//!     .metal(Color::new(0.8, 0.6, 0.2), 0.1)
+//!     .build();
+//!
+//! let mut film = Film::new(400, 225, false);
+//! let camera = scene.view.camera(16. / 9.);
+//! film.render_pass(&camera, &scene.world, &PathTracer::new(50), 50);
+//! let image = Pipeline::standard(Exposure::default(), ToneMap::Aces).develop(&film.color());
+//! save_display("sphere.png", &image, BitDepth::Eight).unwrap();
+//! ```
+
+// casts between `Float` and the f32 and f64 of images and files do nothing in one precision
+#![allow(clippy::unnecessary_cast)]
+
+pub mod anim;
+pub mod assets;
+pub mod denoise;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod distributed;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod ffi;
+pub mod import;
+pub mod integrator;
+pub mod math;
+#[cfg(feature = "oidn")]
+pub mod oidn;
+pub mod output;
+pub mod post;
+#[cfg(feature = "preview")]
+pub mod preview;
+pub mod render;
+pub mod rt;
+pub mod scene;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod server;
+pub mod sppm;
+pub mod stats;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod video;
+#[cfg(target_arch = "wasm32")]
+pub mod web;
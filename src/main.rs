@@ -1,154 +1,769 @@
-use std::sync::{Arc, RwLock};
+use std::error::Error;
+use std::fs;
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 use std::time::Instant;
 
-use image;
-use rand::prelude::*;
-use rayon::prelude::*;
+use clap::builder::PossibleValuesParser;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use image::Rgb32FImage;
 
-mod math;
-mod rt;
-use math::*;
-use rt::*;
+use raytracer::anim::frame_path;
+use raytracer::distributed::{self, Job};
+use raytracer::server::RenderServer;
+use raytracer::sppm::Sppm;
+use raytracer::stats::Stats;
+use raytracer::video::Video;
+use raytracer::{
+    denoise::*, integrator::*, math::*, output::*, post::*, render::*, rt::*, scene::*,
+};
 
-fn ray_color(ray: Ray, world: &World, max_depth: u32) -> Color {
-    if max_depth <= 0 {
-        return Color::BLACK;
+fn parse_vec3(s: &str) -> Result<Vec3, String> {
+    let parts: Vec<Float> = s
+        .split(',')
+        .map(|p| p.trim().parse::<Float>())
+        .collect::<Result<_, _>>()
+        .map_err(|e| format!("`{s}` is not a vector: {e}"))?;
+    match parts[..] {
+        [x, y, z] => Ok(Vec3::new(x, y, z)),
+        _ => Err(format!(
+            "`{s}` is not a vector of three comma-separated numbers"
+        )),
     }
+}
 
-    if let Some(contact) = world.hit(ray, 0.001..f64::INFINITY) {
-        return match contact.material.scatter(ray, &contact) {
-            Some(RayScatter { ray, attenuation }) => {
-                attenuation * ray_color(ray, world, max_depth - 1)
-            }
-            None => Color::BLACK,
-        };
+fn parse_interval(s: &str) -> Result<(Float, Float), String> {
+    match s
+        .split_once(',')
+        .map(|(a, b)| (a.trim().parse(), b.trim().parse()))
+    {
+        Some((Ok(open), Ok(close))) if open <= close => Ok((open, close)),
+        _ => Err(format!("`{s}` is not an interval, expected <open>,<close>")),
     }
-    // background
-    let dir = ray.direction.normalize();
-    let t = (dir.y + 1.) / 2.;
-    (1. - t) * Color::WHITE + t * Color::new(0.5, 0.7, 1.0)
 }
 
-fn create_scene(world: &mut World) {
-    let ground: Diffuse = Color::new(0.8, 0.5, 0.9).into();
+fn parse_pixel(s: &str) -> Result<(u32, u32), String> {
+    match s
+        .split_once(',')
+        .map(|(x, y)| (x.trim().parse(), y.trim().parse()))
+    {
+        Some((Ok(x), Ok(y))) => Ok((x, y)),
+        _ => Err(format!("`{s}` is not a pixel, expected <x>,<y>")),
+    }
+}
 
-    world.insert(Sphere::new(Vec3::new(0., -1000., -1.), 1000., ground));
+fn parse_fraction(s: &str) -> Result<Float, String> {
+    let error = |e| format!("`{s}` is not a number or fraction: {e}");
+    match s.split_once('/') {
+        Some((a, b)) => {
+            Ok(a.trim().parse::<Float>().map_err(error)?
+                / b.trim().parse::<Float>().map_err(error)?)
+        }
+        None => s.trim().parse().map_err(error),
+    }
+}
 
-    let mut rng = thread_rng();
-    for x in -8..8 {
-        for z in -8..8 {
-            let pos = Vec3 {
-                x: (rng.gen::<f64>() * 0.9) + (x as f64),
-                y: 0.2,
-                z: (rng.gen::<f64>() * 0.9) + (z as f64),
-            };
+/// a width or height. the sampler spreads pixels over `size - 1`, so images need two a side
+fn parse_size(s: &str) -> Result<u32, String> {
+    match s.trim().parse::<u32>() {
+        Ok(size) if size >= 2 => Ok(size),
+        Ok(_) => Err(format!(
+            "`{s}` is too small, images are at least 2 pixels a side"
+        )),
+        Err(e) => Err(format!("`{s}` is not a size: {e}")),
+    }
+}
 
-            if (pos - Vec3::new(4., 0.2, 0.)).length() > 0.9 {
-                let choose_mat: f64 = rng.gen();
-
-                if choose_mat < 0.8 {
-                    // diffuse
-                    let mat: Diffuse = Color::random().into();
-                    world.insert(Sphere::new(pos, 0.2, mat));
-                } else if choose_mat < 0.95 {
-                    // metal
-                    let mat = Metal {
-                        color: Color::random() * 0.5 + Color::GRAY,
-                        fuzz: rng.gen::<f64>() * 0.3,
-                    };
-                    world.insert(Sphere::new(pos, 0.2, mat));
-                } else {
-                    // dielectric
-                    let mat = Dielectric {
-                        refraction_index: 1.5,
-                    };
-                    world.insert(Sphere::new(pos, 0.2, mat));
-                }
-            }
+fn parse_bit_depth(s: &str) -> Result<BitDepth, String> {
+    match s {
+        "8" => Ok(BitDepth::Eight),
+        "16" => Ok(BitDepth::Sixteen),
+        _ => Err(format!("unsupported bit depth {s}, expected 8 or 16")),
+    }
+}
+
+#[derive(Parser)]
+#[command(
+    name = "saraytracer",
+    version,
+    about = "Ray tracing a scene to an image"
+)]
+#[command(args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// threads to render with. defaults to one per core
+    #[arg(long, global = true)]
+    threads: Option<usize>,
+    #[command(flatten)]
+    render: RenderArgs,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// renders a scene to an image. the default when no command is given
+    Render(Box<RenderArgs>),
+    /// renders tiles for a render started elsewhere with --listen, until it's done
+    Worker {
+        /// address the render is listening on, as host:port
+        coordinator: String,
+    },
+    /// takes renders over an http api: post a scene to /renders, poll /renders/<id> for progress,
+    /// and fetch /renders/<id>/image.png or image.exr
+    Serve {
+        /// address to listen on, as host:port
+        #[arg(default_value = "127.0.0.1:8080")]
+        addr: String,
+        /// directory the paths in posted scenes are relative to
+        #[arg(long, default_value = ".")]
+        dir: PathBuf,
+    },
+}
+
+/// bundles of resolution, samples and bounces
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum Quality {
+    Draft,
+    Final,
+}
+
+#[derive(Args)]
+struct RenderArgs {
+    /// scene file in ron. renders the built in demo scene if left out
+    scene: Option<PathBuf>,
+    /// a built in scene to render instead of a file: spheres, the demo scene, cornell,
+    /// glass-checker, materials or furnace
+    #[arg(
+        long = "scene",
+        id = "preset",
+        value_name = "NAME",
+        conflicts_with = "scene",
+        value_parser = PossibleValuesParser::new(scenes::NAMES)
+    )]
+    preset: Option<String>,
+    /// draft for a quick look, 400 wide at 16 samples a pixel and 8 bounces, or final for a
+    /// 1920 wide render at 1024 samples and 50 bounces. --width, --spp and --max-depth still
+    /// take precedence
+    #[arg(long)]
+    quality: Option<Quality>,
+    #[arg(
+        long,
+        default_value_t = 400,
+        default_value_ifs = [("quality", "final", "1920")],
+        value_parser = parse_size
+    )]
+    width: u32,
+    /// defaults to a 16:9 image
+    #[arg(long, value_parser = parse_size)]
+    height: Option<u32>,
+    /// samples per pixel
+    #[arg(
+        long,
+        default_value_t = 50,
+        default_value_ifs = [("quality", "draft", "16"), ("quality", "final", "1024")]
+    )]
+    spp: u32,
+    /// bounces per path
+    #[arg(long, default_value_t = 50, default_value_ifs = [("quality", "draft", "8")])]
+    max_depth: u32,
+    /// highest luminance of a sample, to keep fireflies out at the cost of darkening highlights
+    #[arg(long)]
+    clamp: Option<Float>,
+    /// highest luminance any bounce after the first adds to a sample. less biased than --clamp,
+    /// since lights and highlights seen directly stay as bright
+    #[arg(long)]
+    clamp_bounce: Option<Float>,
+    /// stratified, halton, sobol or blue-noise. the low discrepancy sequences converge faster,
+    /// most of all over the first few hundred samples, and blue noise leaves finer grained noise
+    #[arg(long, default_value = "stratified")]
+    sampler: Sampler,
+    /// follows a single wavelength of light per sample, so glass with an abbe number splits
+    /// white light into rainbows. colors take more samples to settle
+    #[arg(long)]
+    spectral: bool,
+    /// path, or sppm for stochastic progressive photon mapping, which follows light out from the
+    /// scene's lights too, to find the caustics glass and mirrors cast. --spp counts its
+    /// iterations, each a sample of every pixel. normals, depth:<far> and uv show the first
+    /// surface seen, for debugging geometry, and heat:<tests> how many intersection tests each
+    /// path runs, with the stats feature. they're saved without tone mapping
+    #[arg(long, default_value = "path")]
+    integrator: IntegratorKind,
+    /// photons sent out from the lights each iteration of --integrator sppm
+    #[arg(long, default_value_t = 100000)]
+    photons: u32,
+    /// draws this many of the scene's lights at random at every bounce and resamples them down
+    /// to the one that matters most, for a single shadow ray instead of one per light. for
+    /// scenes with hundreds of lights, where 8 to 32 is plenty
+    #[arg(long)]
+    light_candidates: Option<u32>,
+    /// renders with the same seed and settings come out identical
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+    /// format picked by the extension: .png, .ppm, or anything else the image crate can write
+    #[arg(short, long, default_value = "output.png")]
+    output: String,
+    /// render every frame of the scene's animation. a run of # in the output paths is replaced
+    /// by the frame number, as in -o frames/frame_####.png, or _0001 and so on is added
+    #[arg(long)]
+    animate: bool,
+    /// encode the frames of --animate into a video here instead, through ffmpeg, in the format
+    /// given by the extension, such as .mp4 or .webm
+    #[arg(long, requires = "animate")]
+    video: Option<String>,
+    /// bits per channel of the output, 8 or 16
+    #[arg(long, default_value = "8", value_parser = parse_bit_depth)]
+    bit_depth: BitDepth,
+    /// clamp, reinhard, reinhard:<white> or aces. this, the white balance, bloom, srgb and
+    /// dither settings give way to the scene's own post processing if it has any
+    #[arg(long, default_value = "aces")]
+    tone_map: ToneMap,
+    /// in stops. added to the physical or automatic exposure if there is one
+    #[arg(long, default_value_t = 0.)]
+    exposure: Float,
+    /// picks the exposure from the render itself, bringing its log-average luminance to middle
+    /// gray, for scenes lit with physical intensities that would otherwise come out black or
+    /// blown out
+    #[arg(long, conflicts_with_all = ["iso", "f_stop", "shutter_speed"])]
+    auto_exposure: bool,
+    /// color temperature in kelvin of the light to balance for, which comes out as daylight
+    /// would: around 2700 for household bulbs, 3200 for studio tungsten, 6500 for daylight
+    #[arg(long)]
+    white_balance: Option<Float>,
+    /// green-magenta shift of the light to balance for, off the black body colors
+    /// --white-balance follows, in cie 1960 uv. positive for greenish light such as from
+    /// fluorescent tubes, around 0.01 for a strong cast
+    #[arg(long, allow_hyphen_values = true)]
+    tint: Option<Float>,
+    /// makes highlights glow into their surroundings, with this share of the light above
+    /// --bloom-threshold, 0.2 if left out
+    #[arg(long, num_args = 0..=1, default_missing_value = "0.2")]
+    bloom: Option<Float>,
+    /// luminance, once exposed, above which light blooms. 1 is the white of the display
+    #[arg(long, default_value_t = 1., requires = "bloom")]
+    bloom_threshold: Float,
+    /// spread of the bloom, as a fraction of the image's width
+    #[arg(long, default_value_t = 0.01, requires = "bloom")]
+    bloom_radius: Float,
+    /// leaves the saved image's values linear, rather than encoding them with the srgb curve
+    /// image files are expected to have
+    #[arg(long)]
+    no_srgb: bool,
+    /// leaves out the faint noise added before the saved image is rounded to its bit depth,
+    /// which keeps smooth gradients from showing bands
+    #[arg(long)]
+    no_dither: bool,
+    /// sensor sensitivity, for exposing the scene's radiance in cd/m² the way a camera would.
+    /// any of --iso, --f-stop and --shutter-speed turns that on, the rest default to sunny 16:
+    /// iso 100, f/16 and 1/100
+    #[arg(long)]
+    iso: Option<Float>,
+    /// f-number of the aperture, for the physical exposure only
+    #[arg(long)]
+    f_stop: Option<Float>,
+    /// in seconds, or as a fraction like 1/250, for the physical exposure only
+    #[arg(long, value_parser = parse_fraction)]
+    shutter_speed: Option<Float>,
+    /// camera position, as x,y,z
+    #[arg(long, value_parser = parse_vec3, allow_hyphen_values = true)]
+    eye: Option<Vec3>,
+    /// point the camera looks at and focuses on, as x,y,z
+    #[arg(long, value_parser = parse_vec3, allow_hyphen_values = true)]
+    look_at: Option<Vec3>,
+    /// vertical field of view, in degrees
+    #[arg(long)]
+    vfov: Option<Float>,
+    /// lens diameter, 0 for a pinhole camera
+    #[arg(long)]
+    aperture: Option<Float>,
+    /// shape of the lens opening, which out of focus highlights take: round, or the number of
+    /// blades of an iris, as in 6 for hexagonal bokeh, optionally turned as <blades>:<degrees>
+    #[arg(long)]
+    aperture_shape: Option<ApertureShape>,
+    /// distance in front of the camera that's in focus, instead of the look at point
+    #[arg(long, conflicts_with = "autofocus")]
+    focus_dist: Option<Float>,
+    /// focus on whatever is seen through a pixel, as x,y from the top left of the image,
+    /// or through its center if left out
+    #[arg(long, value_parser = parse_pixel, num_args = 0..=1)]
+    autofocus: Option<Option<(u32, u32)>>,
+    /// interval the shutter is open for, as open,close. shapes moving while it's open are blurred.
+    /// animation frames open it this long after the moment they show
+    #[arg(long, value_parser = parse_interval)]
+    shutter: Option<(Float, Float)>,
+    /// perspective, fisheye, with --vfov across the height of the image, equirectangular,
+    /// a 360° panorama best rendered at 2:1, or stereo:<ipd>, a panorama for each eye, left above
+    /// right, with the eyes ipd apart in scene units. plain stereo puts them 0.064 apart
+    #[arg(long)]
+    projection: Option<Projection>,
+    /// samples are taken over the whole image in passes of this many per pixel.
+    /// each pass spreads its samples over a grid in the pixel, which square numbers fill evenly
+    #[arg(long, default_value_t = 4)]
+    pass_samples: u32,
+    /// seconds between saving the estimate so far to the output, to preview long renders
+    #[arg(long, default_value_t = 10.)]
+    preview_interval: f64,
+    /// saved after every pass, and resumed from if it already exists. renders carry on from it
+    /// until they have --spp samples, so raising that refines a finished render further
+    #[arg(long, conflicts_with = "listen")]
+    checkpoint: Option<String>,
+    /// split the render into tiles of rows for `saraytracer worker` processes, on this machine
+    /// or others, that connect to this address, as host:port. workers can join at any time
+    #[arg(long)]
+    listen: Option<String>,
+    /// rows of the image in each tile handed to a worker
+    #[arg(long, default_value_t = 16)]
+    tile_rows: u32,
+    /// also write the raw radiance here, as .exr or .hdr
+    #[arg(long)]
+    hdr_output: Option<String>,
+    /// also write albedo, normal, depth and object id passes to <prefix>_albedo.exr and so on,
+    /// with cryptomatte's ranked ids and coverage in <prefix>_crypto00.exr to _crypto02.exr and
+    /// the names behind the ids in <prefix>_manifest.json
+    #[arg(long)]
+    aov_output: Option<String>,
+    /// also write a heatmap of how noisy each pixel still is: its standard error relative to its
+    /// brightness, on a log scale from 0.1% in blue to 100% in red. every pixel takes the same
+    /// samples, so it shows where more of them would still pay off
+    #[arg(long)]
+    noise_output: Option<String>,
+    /// also write a copy denoised by the built in à-trous filter, guided by the normals and depth
+    #[arg(long)]
+    atrous_output: Option<String>,
+    /// also write a copy denoised by intel open image denoise, guided by the albedo and normals
+    #[cfg(feature = "oidn")]
+    #[arg(long)]
+    denoised_output: Option<String>,
+    /// frame the shot in a preview window first
+    #[cfg(feature = "preview")]
+    #[arg(long, conflicts_with = "animate")]
+    preview: bool,
+}
+
+impl RenderArgs {
+    fn height(&self) -> u32 {
+        self.height
+            .unwrap_or(((self.width as f64 / (16.0 / 9.0)) as u32).max(2))
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    if let Some(threads) = cli.threads {
+        if let Err(e) = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+        {
+            eprintln!("error: could not start {threads} threads: {e}");
+            return ExitCode::FAILURE;
+        }
+    }
+    let result = match cli.command {
+        Some(Command::Render(args)) => render(*args),
+        Some(Command::Worker { coordinator }) => work(&coordinator),
+        Some(Command::Serve { addr, dir }) => serve(&addr, dir),
+        None => render(cli.render),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn render(args: RenderArgs) -> Result<(), Box<dyn Error>> {
+    let scene = match &args.scene {
+        Some(path) => Some(
+            SceneFile::open(path).map_err(|e| format!("could not read {}: {e}", path.display()))?,
+        ),
+        None => None,
+    };
+    if !args.animate {
+        render_frame(&args, scene.as_ref(), 0., &|path| path.to_string(), None)?;
+        report_assets(scene.as_ref());
+        return Ok(());
+    }
+    let animation = scene.as_ref().map(|s| s.animation).unwrap_or_default();
+    let mut video = match &args.video {
+        Some(path) => Some(Video::new(path, args.width, args.height(), animation.fps)?),
+        None => None,
+    };
+    let frames = animation.frames();
+    for frame in 0..frames {
+        println!("frame {} / {frames}", frame + 1);
+        let time = animation.time(frame);
+        let output = |path: &str| frame_path(path, frame + 1);
+        render_frame(&args, scene.as_ref(), time, &output, video.as_mut())?;
+    }
+    if let Some(video) = video {
+        video.finish()?;
+    }
+    report_assets(scene.as_ref());
+    Ok(())
+}
+
+/// builds the scene file, or the preset scene if there's none, as it is `time` seconds into its
+/// animation. the random numbers scenes are built with, like noise tables, are drawn from the
+/// seed, so the same seed builds the same scene
+fn build_scene(
+    args: &RenderArgs,
+    scene: Option<&SceneFile>,
+    dir: &Path,
+    time: Float,
+) -> Result<Scene, Box<dyn Error>> {
+    seed_rng(args.seed);
+    Ok(match scene {
+        Some(scene) => scene.scene_at(dir, time)?,
+        None => {
+            let name = args.preset.as_deref().unwrap_or("spheres");
+            scenes::by_name(name).ok_or(format!("no preset scene {name}"))?
+        }
+    })
+}
+
+/// prints how much memory the images and meshes the scene loaded take up, if it loaded any
+fn report_assets(scene: Option<&SceneFile>) {
+    if let Some(assets) = scene.map(SceneFile::assets).filter(|a| !a.is_empty()) {
+        println!("loaded {assets}");
+    }
+}
+
+/// renders the scene as it is `time` seconds into its animation, saving to the paths `output`
+/// makes of the ones given. the tone mapped image goes to `video` instead if there is one
+fn render_frame(
+    args: &RenderArgs,
+    scene: Option<&SceneFile>,
+    time: Float,
+    output: &dyn Fn(&str) -> String,
+    video: Option<&mut Video>,
+) -> Result<(), Box<dyn Error>> {
+    let (width, height) = (args.width, args.height());
+    let aspect_ratio = width as Float / height as Float;
+    let bit_depth = args.bit_depth;
+    let base_exposure =
+        if args.iso.is_some() || args.f_stop.is_some() || args.shutter_speed.is_some() {
+            let default = PhysicalExposure::default();
+            let physical = PhysicalExposure {
+                iso: args.iso.unwrap_or(default.iso),
+                f_number: args.f_stop.unwrap_or(default.f_number),
+                shutter_speed: args.shutter_speed.unwrap_or(default.shutter_speed),
+            };
+            physical.stops() + args.exposure
+        } else {
+            args.exposure
+        };
+    let exposure = Exposure {
+        stops: base_exposure,
+        auto: args.auto_exposure,
+    };
+    // from the rendered radiance to the image saved. debug views are saved as they are
+    let pipeline = if args.integrator.is_debug() {
+        Pipeline::new().then(ToneMap::Clamp)
+    } else if let Some(post) = scene.and_then(SceneFile::pipeline) {
+        Pipeline::new().then(exposure).then(post)
+    } else {
+        let mut pipeline = Pipeline::new().then(exposure);
+        if args.white_balance.is_some() || args.tint.is_some() {
+            pipeline = pipeline.then(WhiteBalance::new(
+                args.white_balance.unwrap_or(WhiteBalance::DAYLIGHT),
+                args.tint.unwrap_or(0.),
+            ));
         }
+        if let Some(strength) = args.bloom {
+            pipeline = pipeline.then(Bloom {
+                threshold: args.bloom_threshold,
+                radius: args.bloom_radius,
+                strength,
+            });
+        }
+        pipeline = pipeline.then(args.tone_map);
+        if !args.no_srgb {
+            pipeline = pipeline.then(Srgb);
+        }
+        if !args.no_dither {
+            pipeline = pipeline.then(Dither::new(bit_depth));
+        }
+        pipeline
+    };
+
+    let render_aovs = args.aov_output.is_some() || args.atrous_output.is_some();
+    #[cfg(feature = "oidn")]
+    let render_aovs = render_aovs || args.denoised_output.is_some();
+    if args.integrator == IntegratorKind::Sppm
+        && (render_aovs
+            || args.spectral
+            || args.checkpoint.is_some()
+            || args.listen.is_some()
+            || args.noise_output.is_some())
+    {
+        return Err(
+            "sppm renders can't have aovs or noise heatmaps, be spectral, checkpointed or distributed"
+                .into(),
+        );
+    }
+    if args.integrator.is_debug() && args.listen.is_some() {
+        return Err("only path traced renders can be distributed".into());
     }
 
-    world.insert(Sphere::new(
-        Vec3::new(0., 1., 0.),
-        1.0,
-        Dielectric {
-            refraction_index: 1.5,
+    // world and camera
+    let dir = args
+        .scene
+        .as_deref()
+        .and_then(Path::parent)
+        .unwrap_or(Path::new("."));
+    let Scene { world, view } = build_scene(args, scene, dir, time)?;
+    let view = View {
+        eye: args.eye.unwrap_or(view.eye),
+        look_at: args.look_at.unwrap_or(view.look_at),
+        vfov: args.vfov.unwrap_or(view.vfov),
+        aperture: args.aperture.unwrap_or(view.aperture),
+        aperture_shape: args.aperture_shape.unwrap_or(view.aperture_shape),
+        projection: args.projection.unwrap_or(view.projection),
+        shutter: {
+            let (open, close) = args.shutter.unwrap_or(view.shutter);
+            (time + open, time + close)
         },
-    ));
-
-    world.insert(Sphere::new(
-        Vec3::new(4., 1., 0.),
-        1.0,
-        Metal {
-            color: Color::new(0.8, 0.8, 0.8),
-            fuzz: 0.0,
+        focus: match (args.focus_dist, args.autofocus) {
+            (Some(distance), _) => Focus::Distance(distance),
+            (None, Some(Some((x, y)))) => Focus::Auto(
+                (x as Float + 0.5) / width as Float,
+                1. - (y as Float + 0.5) / height as Float,
+            ),
+            (None, Some(None)) => Focus::Auto(0.5, 0.5),
+            (None, None) => view.focus,
         },
-    ));
+        ..view
+    };
 
-    world.insert(Sphere::new(
-        Vec3::new(-4., 1., 0.),
-        1.0,
-        Diffuse {
-            color: Color::new(0.8, 0.5, 0.2),
-        },
-    ));
-}
-
-fn main() {
-    const ASPECT_RATIO: f64 = 16.0 / 9.0; // width / height
-    const IMAGE_WIDTH: u32 = 400;
-    const IMAGE_HEIGHT: u32 = (IMAGE_WIDTH as f64 / ASPECT_RATIO) as u32;
-    const IMAGE_PIXELS: u32 = IMAGE_WIDTH * IMAGE_HEIGHT;
-    const SAMPLES_PER_PIXEL: u32 = 50;
-
-    // camera
-    let eye = Vec3::new(13., 2., 3.);
-    let camera = FixedCamera::new(
-        eye,
-        Vec3::ZERO,
-        Vec3::Y,
-        ASPECT_RATIO,
-        20.,
-        0.01,
-        eye.length(),
-    );
-
-    // image storage
-    let mut imgbuf = image::RgbImage::new(IMAGE_WIDTH, IMAGE_HEIGHT);
-
-    // world
-    let world = Arc::new(RwLock::new(World::new()));
-    create_scene(&mut world.write().unwrap());
+    // frame the shot interactively first
+    #[cfg(feature = "preview")]
+    let view = if args.preview {
+        raytracer::preview::preview(view, &world, width, height, &pipeline)
+    } else {
+        view
+    };
+    let view = view.autofocus(&world, aspect_ratio);
+    let camera = view.camera(aspect_ratio);
 
+    // leave out the rays autofocus and the preview traced
+    Stats::take();
     let now = Instant::now();
-    let px: Vec<(u32, u32, Color)> = (0..IMAGE_PIXELS)
-        .into_par_iter()
-        .map_with(world, |world, i| {
-            let mut rng = thread_rng();
-            let x = i % IMAGE_WIDTH;
-            let y = i / IMAGE_WIDTH;
-            let mut pixel_color = Color::WHITE;
-            for _ in 0..SAMPLES_PER_PIXEL {
-                let (px, py) = (x as f64, y as f64);
-                // 			vv random sampling
-                let rx: f64 = rng.gen();
-                let ry: f64 = rng.gen();
-                let dx = (px + rx) / ((IMAGE_WIDTH - 1) as f64);
-                let dy = (py + ry) / ((IMAGE_HEIGHT - 1) as f64);
-                let r = camera.get_screen_ray(dx, dy);
-                pixel_color += ray_color(r, &world.read().unwrap(), 50);
+    let mut last_preview = now;
+    if args.integrator == IntegratorKind::Sppm {
+        let mut sppm = Sppm::new(width, height, args.photons);
+        sppm.seed = args.seed;
+        while sppm.iterations < args.spp {
+            sppm.iterate(&camera, &world, args.max_depth);
+            if sppm.iterations < args.spp
+                && video.is_none()
+                && last_preview.elapsed().as_secs_f64() >= args.preview_interval
+            {
+                let color = sppm.color();
+                let preview = pipeline.develop(&color);
+                save_display(&output(&args.output), &preview, bit_depth)?;
+                println!("{} / {} iterations", sppm.iterations, args.spp);
+                last_preview = Instant::now();
+            }
+        }
+        println!("Raytracer computed in {:.2}s", now.elapsed().as_secs_f64());
+        #[cfg(feature = "stats")]
+        println!("{}", Stats::take());
+        let color = sppm.color();
+        return save_color(args, &color, &pipeline.develop(&color), output, video);
+    }
+    let mut film = if let Some(addr) = &args.listen {
+        let job = Job {
+            scene: args.scene.as_deref().map(fs::read_to_string).transpose()?,
+            preset: args.preset.clone(),
+            dir: dir.to_path_buf(),
+            camera: view.into(),
+            time,
+            width,
+            height,
+            spp: args.spp,
+            pass_samples: args.pass_samples,
+            max_depth: args.max_depth,
+            seed: args.seed,
+            clamp_bounce: args.clamp_bounce,
+            clamp_sample: args.clamp,
+            sampler: args.sampler,
+            spectral: args.spectral,
+            light_candidates: args.light_candidates,
+            aovs: render_aovs,
+        };
+        let listener = TcpListener::bind(addr)?;
+        println!("waiting for workers on {}", listener.local_addr()?);
+        distributed::coordinate(&listener, &job, args.tile_rows, |event| match event {
+            distributed::Event::Joined(addr) => println!("worker {addr} joined"),
+            distributed::Event::Left(addr, e) => println!("worker {addr} left: {e}"),
+            distributed::Event::Rendered { rows, left } => {
+                println!("rows {}..{} done, {left} tiles left", rows.start, rows.end)
             }
-            let color = pixel_color / SAMPLES_PER_PIXEL as f64;
-            (x, y, color)
-        })
-        .collect();
+        })?
+    } else {
+        match args
+            .checkpoint
+            .as_deref()
+            .map(|path| Film::open_checkpoint(&output(path)))
+        {
+            Some(Ok(film))
+                if (film.width, film.height, &film.rows) == (width, height, &(0..height)) =>
+            {
+                println!("resuming from {} samples", film.samples);
+                film
+            }
+            Some(Ok(_)) => return Err("checkpoint was rendered at a different resolution".into()),
+            Some(Err(e)) if e.kind() != std::io::ErrorKind::NotFound => {
+                return Err(format!("could not read checkpoint: {e}").into())
+            }
+            _ => {
+                let mut film = Film::new(width, height, render_aovs);
+                film.seed = args.seed;
+                film
+            }
+        }
+    };
+    film.sampler = args.sampler;
+    film.spectral = args.spectral;
+    let tracer = PathTracer {
+        max_depth: args.max_depth,
+        clamp: Clamp {
+            bounce: args.clamp_bounce,
+            sample: args.clamp,
+        },
+        light_candidates: args.light_candidates,
+    };
+    let integrator: Box<dyn Integrator + Sync> = match args.integrator {
+        IntegratorKind::Normals => Box::new(Normals),
+        IntegratorKind::Depth(far) => Box::new(Depth { far }),
+        IntegratorKind::Uv => Box::new(Uvs),
+        IntegratorKind::Heat(max) => Box::new(Heatmap { tracer, max }),
+        _ => Box::new(tracer),
+    };
+    // aovs can't be added to a checkpoint that was rendered without them
+    if render_aovs && !film.aovs {
+        return Err("checkpoint was rendered without aov passes".into());
+    }
+    while film.samples < args.spp {
+        let samples = args.pass_samples.max(1).min(args.spp - film.samples);
+        film.render_pass(&camera, &world, &integrator, samples);
+        if let Some(path) = &args.checkpoint {
+            film.save_checkpoint(&output(path))?;
+        }
+        if film.samples < args.spp
+            && video.is_none()
+            && last_preview.elapsed().as_secs_f64() >= args.preview_interval
+        {
+            let color = film.color();
+            let preview = pipeline.develop(&color);
+            save_display(&output(&args.output), &preview, bit_depth)?;
+            println!("{} / {} samples", film.samples, args.spp);
+            last_preview = Instant::now();
+        }
+    }
     let elapsed = now.elapsed();
     println!("Raytracer computed in {:.2}s", elapsed.as_secs_f64());
+    #[cfg(feature = "stats")]
+    println!("{}", Stats::take());
+
+    let hdrbuf = film.color();
+    save_color(args, &hdrbuf, &pipeline.develop(&hdrbuf), output, video)?;
+    if let Some(prefix) = &args.aov_output {
+        let prefix = output(prefix);
+        save_linear(&format!("{prefix}_albedo.exr"), &film.albedo())?;
+        save_linear(&format!("{prefix}_normal.exr"), &film.normal())?;
+        save_linear(&format!("{prefix}_depth.exr"), &film.depth())?;
+        save_linear(&format!("{prefix}_id.exr"), &film.object_ids())?;
+        for layer in 0..3 {
+            film.cryptomatte(layer)
+                .save(format!("{prefix}_crypto{layer:02}.exr"))?;
+        }
+        let manifest: serde_json::Map<_, _> = world
+            .objects()
+            .map(|object| (object.label(), format!("{:08x}", object.id()).into()))
+            .collect();
+        fs::write(
+            format!("{prefix}_manifest.json"),
+            serde_json::Value::Object(manifest).to_string(),
+        )?;
+    }
+    if let Some(path) = &args.noise_output {
+        save_display(&output(path), &heatmap(&film.noise(), 0.001, 1.), bit_depth)?;
+    }
+    if let Some(path) = &args.atrous_output {
+        let denoised = Atrous::default().denoise(&hdrbuf, &film.normal(), &film.depth());
+        save_display(&output(path), &pipeline.develop(&denoised), bit_depth)?;
+    }
+    #[cfg(feature = "oidn")]
+    if let Some(path) = &args.denoised_output {
+        let denoised = raytracer::oidn::denoise(&hdrbuf, &film.albedo(), &film.normal())?;
+        save_display(&output(path), &pipeline.develop(&denoised), bit_depth)?;
+    }
+    Ok(())
+}
 
-    for (x, y, color) in px.into_iter() {
-        let px = &mut imgbuf.get_pixel_mut(x, IMAGE_HEIGHT - y - 1).0;
-        *px = color.into_rgb8_array();
+/// saves the image developed from the rendered radiance to the output, or `video` if there is
+/// one, and the radiance as it is to the hdr output if asked for
+fn save_color(
+    args: &RenderArgs,
+    hdrbuf: &Rgb32FImage,
+    imgbuf: &Rgb32FImage,
+    output: &dyn Fn(&str) -> String,
+    video: Option<&mut Video>,
+) -> Result<(), Box<dyn Error>> {
+    match video {
+        Some(video) => video.push(imgbuf)?,
+        None => save_display(&output(&args.output), imgbuf, args.bit_depth)?,
+    }
+    if let Some(path) = &args.hdr_output {
+        save_linear(&output(path), hdrbuf)?;
     }
+    Ok(())
+}
+
+fn work(coordinator: &str) -> Result<(), Box<dyn Error>> {
+    let stream = TcpStream::connect(coordinator)
+        .map_err(|e| format!("could not connect to {coordinator}: {e}"))?;
+    println!("rendering tiles for {coordinator}");
+    distributed::work(stream)?;
+    println!("render finished");
+    Ok(())
+}
 
-    imgbuf.save("output.png").unwrap();
+fn serve(addr: &str, dir: PathBuf) -> Result<(), Box<dyn Error>> {
+    let server =
+        RenderServer::new(addr, dir).map_err(|e| format!("could not listen on {addr}: {e}"))?;
+    println!("serving renders on http://{addr}/renders");
+    server.run();
+    Ok(())
+}
+
+#[test]
+fn seeded_scenes_repeat() {
+    let args = Cli::parse_from(["saraytracer", "scenes/worn.ron", "--seed", "3"]).render;
+    let file = SceneFile::open("scenes/worn.ron").unwrap();
+    // the worn scene's noise is drawn when it's built
+    let render = || {
+        let Scene { world, view } =
+            build_scene(&args, Some(&file), Path::new("scenes"), 0.).unwrap();
+        let mut film = Film::new(8, 6, false);
+        film.render_pass(&view.camera(4. / 3.), &world, &PathTracer::new(4), 1);
+        film.color()
+    };
+    assert_eq!(render(), render());
+}
+
+#[test]
+fn image_sizes() {
+    let args = |size: &[&str]| {
+        let args = ["saraytracer"].iter().chain(size);
+        Cli::try_parse_from(args).map(|cli| (cli.render.width, cli.render.height()))
+    };
+    assert_eq!(args(&[]).unwrap(), (400, 225));
+    assert_eq!(args(&["--width", "2"]).unwrap(), (2, 2));
+    for size in [["--width", "0"], ["--width", "1"], ["--height", "0"]] {
+        assert!(args(&size).is_err());
+    }
 }
@@ -1,5 +1,34 @@
+mod bluenoise;
+mod mat4;
+mod onb;
+mod perlin;
+mod random;
+mod roots;
+mod sampler;
 mod vec3;
+mod worley;
+pub use bluenoise::*;
+pub use mat4::*;
+pub use onb::*;
+pub use perlin::*;
+pub use random::*;
+pub use roots::*;
+pub use sampler::*;
 pub use vec3::*;
+pub use worley::*;
+
+/// the floating point type everything is computed in. f64 unless the `f32` feature is on,
+/// which trades precision for speed
+#[cfg(not(feature = "f32"))]
+pub type Float = f64;
+#[cfg(feature = "f32")]
+pub type Float = f32;
+
+#[cfg(feature = "f32")]
+pub use std::f32::consts;
+/// constants like pi, in `Float`
+#[cfg(not(feature = "f32"))]
+pub use std::f64::consts;
 
 pub trait Normalize {
     fn normalize(&self) -> Self;
@@ -0,0 +1,113 @@
+use rand::prelude::*;
+use std::sync::OnceLock;
+
+/// width and height of the blue noise tile, which repeats across the image
+pub const BLUE_NOISE_SIZE: usize = 64;
+
+/// spread of the gaussian the void-and-cluster energy is measured with
+const SIGMA: f64 = 1.5;
+
+/// a tile of blue noise: values spread so evenly that pixels below any threshold are spaced
+/// apart, with no clumps or gaps. each value is a fraction of 2^32
+fn blue_noise_tile() -> &'static [u32] {
+    static TILE: OnceLock<Vec<u32>> = OnceLock::new();
+    TILE.get_or_init(void_and_cluster)
+}
+
+/// the blue noise value at a pixel, tiled over the image, as a fraction of 2^32
+pub fn blue_noise(x: u32, y: u32) -> u32 {
+    let n = BLUE_NOISE_SIZE as u32;
+    blue_noise_tile()[((y % n) * n + x % n) as usize]
+}
+
+/// ulichney's void-and-cluster method. pixels are ranked in the order they're switched on,
+/// always filling the biggest void left between the pixels already on
+fn void_and_cluster() -> Vec<u32> {
+    const N: usize = BLUE_NOISE_SIZE;
+    // gaussian falloff for every offset on the wrapping tile
+    let kernel: Vec<f64> = (0..N * N)
+        .map(|i| {
+            let wrap = |d: usize| d.min(N - d) as f64;
+            let (dx, dy) = (wrap(i % N), wrap(i / N));
+            (-(dx * dx + dy * dy) / (2. * SIGMA * SIGMA)).exp()
+        })
+        .collect();
+    let mut energy = vec![0.; N * N];
+    let mut on = vec![false; N * N];
+    let toggle = |on: &mut [bool], energy: &mut [f64], p: usize| {
+        on[p] = !on[p];
+        let sign = if on[p] { 1. } else { -1. };
+        let (px, py) = (p % N, p / N);
+        for (i, e) in energy.iter_mut().enumerate() {
+            let (dx, dy) = ((i % N + N - px) % N, (i / N + N - py) % N);
+            *e += sign * kernel[dy * N + dx];
+        }
+    };
+    let tightest_cluster = |on: &[bool], energy: &[f64]| {
+        (0..N * N)
+            .filter(|&i| on[i])
+            .max_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+            .unwrap()
+    };
+    let largest_void = |on: &[bool], energy: &[f64]| {
+        (0..N * N)
+            .filter(|&i| !on[i])
+            .min_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+            .unwrap()
+    };
+
+    // a random tenth of the pixels, evened out by moving the most crowded into the emptiest
+    // space until that stops changing anything
+    let initial = N * N / 10;
+    let mut rng = SmallRng::seed_from_u64(0);
+    for p in rand::seq::index::sample(&mut rng, N * N, initial) {
+        toggle(&mut on, &mut energy, p);
+    }
+    loop {
+        let cluster = tightest_cluster(&on, &energy);
+        toggle(&mut on, &mut energy, cluster);
+        let void = largest_void(&on, &energy);
+        toggle(&mut on, &mut energy, void);
+        if void == cluster {
+            break;
+        }
+    }
+
+    let mut rank = vec![0; N * N];
+    // the initial pixels rank below the rest, the most crowded highest
+    let (mut prototype_on, mut prototype_energy) = (on.clone(), energy.clone());
+    for r in (0..initial).rev() {
+        let cluster = tightest_cluster(&prototype_on, &prototype_energy);
+        toggle(&mut prototype_on, &mut prototype_energy, cluster);
+        rank[cluster] = r;
+    }
+    for r in initial..N * N {
+        let void = largest_void(&on, &energy);
+        toggle(&mut on, &mut energy, void);
+        rank[void] = r;
+    }
+    let scale = (1u64 << 32) as f64 / (N * N) as f64;
+    rank.into_iter()
+        .map(|r| (r as f64 * scale) as u32)
+        .collect()
+}
+
+#[test]
+fn blue_noise_is_evenly_spread() {
+    const N: usize = BLUE_NOISE_SIZE;
+    let tile = blue_noise_tile();
+    let mut sorted = tile.to_vec();
+    sorted.sort();
+    sorted.dedup();
+    assert_eq!(sorted.len(), N * N);
+
+    // the lowest tenth are spaced out, where white noise would have neighbours
+    let low: Vec<_> = (0..N * N).filter(|&i| tile[i] < u32::MAX / 10).collect();
+    for (n, &a) in low.iter().enumerate() {
+        for &b in &low[..n] {
+            let wrap = |d: usize| d.min(N - d);
+            let (dx, dy) = ((a % N).abs_diff(b % N), (a / N).abs_diff(b / N));
+            assert!(wrap(dx) + wrap(dy) > 1, "{a} and {b} are adjacent");
+        }
+    }
+}
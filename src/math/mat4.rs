@@ -0,0 +1,167 @@
+use super::{Float, Normalize, Vec3};
+use std::ops::Mul;
+
+/// row-major 4x4 affine transformation matrix, acting on column vectors
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mat4 {
+    pub m: [[Float; 4]; 4],
+}
+
+impl Default for Mat4 {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl Mat4 {
+    pub const IDENTITY: Self = Self::new([
+        [1., 0., 0., 0.],
+        [0., 1., 0., 0.],
+        [0., 0., 1., 0.],
+        [0., 0., 0., 1.],
+    ]);
+
+    #[inline(always)]
+    pub const fn new(m: [[Float; 4]; 4]) -> Self {
+        Self { m }
+    }
+
+    /// translation by `offset`
+    pub fn translate(offset: Vec3) -> Self {
+        Self::new([
+            [1., 0., 0., offset.x],
+            [0., 1., 0., offset.y],
+            [0., 0., 1., offset.z],
+            [0., 0., 0., 1.],
+        ])
+    }
+
+    /// per-axis scale
+    pub fn scale(factor: Vec3) -> Self {
+        Self::new([
+            [factor.x, 0., 0., 0.],
+            [0., factor.y, 0., 0.],
+            [0., 0., factor.z, 0.],
+            [0., 0., 0., 1.],
+        ])
+    }
+
+    /// counter-clockwise rotation of `degrees` around `axis`
+    pub fn rotate(axis: Vec3, degrees: Float) -> Self {
+        let Vec3 { x, y, z } = axis.normalize();
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let t = 1. - cos;
+        Self::new([
+            [
+                t * x * x + cos,
+                t * x * y - sin * z,
+                t * x * z + sin * y,
+                0.,
+            ],
+            [
+                t * x * y + sin * z,
+                t * y * y + cos,
+                t * y * z - sin * x,
+                0.,
+            ],
+            [
+                t * x * z - sin * y,
+                t * y * z + sin * x,
+                t * z * z + cos,
+                0.,
+            ],
+            [0., 0., 0., 1.],
+        ])
+    }
+
+    pub fn transpose(&self) -> Self {
+        let mut out = Self::IDENTITY;
+        for (r, row) in out.m.iter_mut().enumerate() {
+            for (c, value) in row.iter_mut().enumerate() {
+                *value = self.m[c][r];
+            }
+        }
+        out
+    }
+
+    /// inverse through gauss-jordan elimination, or None if the matrix is singular
+    pub fn inverse(&self) -> Option<Self> {
+        let mut a = self.m;
+        let mut inv = Self::IDENTITY.m;
+        for col in 0..4 {
+            // partial pivoting
+            let pivot = (col..4).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+            if a[pivot][col].abs() < 1e-12 {
+                return None;
+            }
+            a.swap(col, pivot);
+            inv.swap(col, pivot);
+
+            let p = a[col][col];
+            for c in 0..4 {
+                a[col][c] /= p;
+                inv[col][c] /= p;
+            }
+            for row in 0..4 {
+                if row != col {
+                    let f = a[row][col];
+                    for c in 0..4 {
+                        a[row][c] -= f * a[col][c];
+                        inv[row][c] -= f * inv[col][c];
+                    }
+                }
+            }
+        }
+        Some(Self::new(inv))
+    }
+
+    /// transforms a position, applying translation
+    pub fn transform_point(&self, p: Vec3) -> Vec3 {
+        self.transform_vector(p) + Vec3::new(self.m[0][3], self.m[1][3], self.m[2][3])
+    }
+
+    /// transforms a direction, ignoring translation
+    pub fn transform_vector(&self, v: Vec3) -> Vec3 {
+        let m = &self.m;
+        Vec3 {
+            x: m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+            y: m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+            z: m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z,
+        }
+    }
+}
+
+impl Mul for Mat4 {
+    type Output = Self;
+    /// composes transforms, `rhs` being applied first
+    fn mul(self, rhs: Self) -> Self::Output {
+        let mut out = [[0.; 4]; 4];
+        for (r, row) in out.iter_mut().enumerate() {
+            for (c, value) in row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.m[r][k] * rhs.m[k][c]).sum();
+            }
+        }
+        Self::new(out)
+    }
+}
+
+#[test]
+fn transform_and_inverse() {
+    let m = Mat4::translate(Vec3::new(1., 2., 3.))
+        * Mat4::rotate(Vec3::Y, 90.)
+        * Mat4::scale(Vec3::new(2., 2., 2.));
+    // scaled to (2, 0, 0), rotated onto -z, then translated
+    let p = m.transform_point(Vec3::X);
+    assert!((p - Vec3::new(1., 2., 1.)).is_zero());
+
+    let inv = m.inverse().unwrap();
+    assert!((inv.transform_point(p) - Vec3::X).is_zero());
+    let id = m * inv;
+    for r in 0..4 {
+        for c in 0..4 {
+            assert!((id.m[r][c] - Mat4::IDENTITY.m[r][c]).abs() < 1e-12);
+        }
+    }
+
+    assert!(Mat4::scale(Vec3::new(1., 0., 1.)).inverse().is_none());
+}
@@ -0,0 +1,75 @@
+use super::{Float, Normalize, Vec3};
+
+/// an orthonormal basis: three perpendicular unit vectors, with `w` usually a surface normal.
+/// it's right handed, so u × v = w
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Onb {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+impl Onb {
+    /// a basis around the unit vector `w`, turned arbitrarily about it
+    pub fn new(w: Vec3) -> Self {
+        let helper = if w.x.abs() > 0.9 { Vec3::Y } else { Vec3::X };
+        let u = w.cross(helper).normalize();
+        Self {
+            u,
+            v: w.cross(u),
+            w,
+        }
+    }
+
+    /// a basis around the unit vector `w` with `u` along `tangent`, once it's made
+    /// perpendicular to `w`. a tangent along `w` leaves `u` arbitrary
+    pub fn with_tangent(w: Vec3, tangent: Vec3) -> Self {
+        let u = tangent - tangent.dot(w) * w;
+        if u.length_squared() < 1e-12 {
+            return Self::new(w);
+        }
+        let u = u.normalize();
+        Self {
+            u,
+            v: w.cross(u),
+            w,
+        }
+    }
+
+    /// the vector a·u + b·v + c·w, from coordinates in the basis
+    pub fn local(&self, a: Float, b: Float, c: Float) -> Vec3 {
+        a * self.u + b * self.v + c * self.w
+    }
+
+    /// the coordinates of `v` in the basis, as (a, b, c) for `local`
+    pub fn to_local(&self, v: Vec3) -> Vec3 {
+        Vec3::new(v.dot(self.u), v.dot(self.v), v.dot(self.w))
+    }
+}
+
+#[test]
+fn orthonormal_bases() {
+    let close = |a: Float, b: Float| (a - b).abs() < 1e-6;
+    for w in [
+        Vec3::X,
+        -Vec3::Y,
+        Vec3::Z,
+        Vec3::new(0.95, 0.1, -0.3).normalize(),
+        Vec3::new(-1., 2., 3.).normalize(),
+    ] {
+        let onb = Onb::new(w);
+        assert!(close(onb.u.length(), 1.) && close(onb.v.length(), 1.));
+        assert!(close(onb.u.dot(onb.v), 0.) && close(onb.u.dot(w), 0.) && close(onb.v.dot(w), 0.));
+        assert!((onb.u.cross(onb.v) - w).is_zero());
+
+        let p = Vec3::new(0.3, -2., 0.7);
+        assert!((onb.local(p.x, p.y, p.z) - (0.3 * onb.u - 2. * onb.v + 0.7 * w)).is_zero());
+        let back = onb.to_local(onb.local(p.x, p.y, p.z));
+        assert!((back - p).is_zero());
+    }
+
+    let onb = Onb::with_tangent(Vec3::Y, Vec3::new(2., 1., 0.));
+    assert!((onb.u - Vec3::X).is_zero());
+    assert!((onb.v - -Vec3::Z).is_zero());
+    assert_eq!(Onb::with_tangent(Vec3::Y, Vec3::Y), Onb::new(Vec3::Y));
+}
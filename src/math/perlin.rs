@@ -0,0 +1,131 @@
+use super::{with_rng, Float, Vec3};
+use rand::seq::SliceRandom;
+
+pub(super) const POINT_COUNT: usize = 256;
+
+/// shuffled tables hashing the integer lattice points of space, for noises that place
+/// something random at each of them
+#[derive(Clone, Debug)]
+pub(crate) struct Lattice {
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+impl Lattice {
+    /// constructor with freshly shuffled permutation tables
+    pub fn new() -> Self {
+        let perm = || {
+            let mut p: Vec<usize> = (0..POINT_COUNT).collect();
+            with_rng(|rng| p.shuffle(rng));
+            p
+        };
+        Self {
+            perm_x: perm(),
+            perm_y: perm(),
+            perm_z: perm(),
+        }
+    }
+
+    /// an index below `POINT_COUNT` for the lattice point, repeating every `POINT_COUNT` along
+    /// each axis
+    pub fn hash(&self, i: i64, j: i64, k: i64) -> usize {
+        let wrap = |n: i64| n.rem_euclid(POINT_COUNT as i64) as usize;
+        self.perm_x[wrap(i)] ^ self.perm_y[wrap(j)] ^ self.perm_z[wrap(k)]
+    }
+}
+
+/// gradient noise over 3d space, in the style of ken perlin's improved noise
+#[derive(Clone, Debug)]
+pub struct Perlin {
+    gradients: Vec<Vec3>,
+    lattice: Lattice,
+}
+
+impl Default for Perlin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Perlin {
+    /// constructor with freshly shuffled permutation tables
+    pub fn new() -> Self {
+        Self {
+            gradients: (0..POINT_COUNT).map(|_| Vec3::random_unit()).collect(),
+            lattice: Lattice::new(),
+        }
+    }
+
+    /// noise value in [-1, 1]. zero at every integer lattice point
+    pub fn noise(&self, p: Vec3) -> Float {
+        let (fx, fy, fz) = (p.x.floor(), p.y.floor(), p.z.floor());
+        let (u, v, w) = (p.x - fx, p.y - fy, p.z - fz);
+        let (i, j, k) = (fx as i64, fy as i64, fz as i64);
+
+        // gradients at the 8 corners of the surrounding lattice cell
+        let mut c = [[[Vec3::ZERO; 2]; 2]; 2];
+        for (di, plane) in c.iter_mut().enumerate() {
+            for (dj, row) in plane.iter_mut().enumerate() {
+                for (dk, corner) in row.iter_mut().enumerate() {
+                    let index = self
+                        .lattice
+                        .hash(i + di as i64, j + dj as i64, k + dk as i64);
+                    *corner = self.gradients[index];
+                }
+            }
+        }
+
+        Self::trilinear(&c, u, v, w)
+    }
+
+    /// trilinear interpolation of corner gradients, with hermite smoothing of the weights
+    fn trilinear(c: &[[[Vec3; 2]; 2]; 2], u: Float, v: Float, w: Float) -> Float {
+        let smooth = |t: Float| t * t * (3. - 2. * t);
+        let (uu, vv, ww) = (smooth(u), smooth(v), smooth(w));
+        let mut accum = 0.;
+        for (i, plane) in c.iter().enumerate() {
+            for (j, row) in plane.iter().enumerate() {
+                for (k, gradient) in row.iter().enumerate() {
+                    let (fi, fj, fk) = (i as Float, j as Float, k as Float);
+                    let weight = Vec3::new(u - fi, v - fj, w - fk);
+                    accum += (fi * uu + (1. - fi) * (1. - uu))
+                        * (fj * vv + (1. - fj) * (1. - vv))
+                        * (fk * ww + (1. - fk) * (1. - ww))
+                        * gradient.dot(weight);
+                }
+            }
+        }
+        accum
+    }
+
+    /// fractal brownian motion: `octaves` layers of noise, each at double the frequency and half the amplitude
+    pub fn fbm(&self, p: Vec3, octaves: u32) -> Float {
+        let mut accum = 0.;
+        let mut p = p;
+        let mut weight = 1.;
+        for _ in 0..octaves {
+            accum += weight * self.noise(p);
+            weight *= 0.5;
+            p *= 2.;
+        }
+        accum
+    }
+
+    /// absolute value of fbm, which folds the noise into sharp creases
+    pub fn turbulence(&self, p: Vec3, octaves: u32) -> Float {
+        self.fbm(p, octaves).abs()
+    }
+}
+
+#[test]
+fn perlin_range_and_lattice() {
+    let perlin = Perlin::new();
+    for _ in 0..10000 {
+        let p = 20. * Vec3::random() - Vec3::new(10., 10., 10.);
+        let n = perlin.noise(p);
+        assert!((-1.0..=1.0).contains(&n));
+    }
+    // gradient noise vanishes on the lattice
+    assert!(perlin.noise(Vec3::new(3., -7., 12.)).abs() < 1e-12);
+}
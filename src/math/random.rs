@@ -0,0 +1,140 @@
+use super::{blue_noise, Sampler};
+use rand::distributions::{Distribution, Standard};
+use rand::prelude::*;
+use std::cell::RefCell;
+
+/// the point of a sampler's sequence a sample of a pixel is drawing its numbers from
+#[derive(Clone, Copy, Debug)]
+struct Sequence {
+    sampler: Sampler,
+    index: u64,
+    pixel: (u32, u32),
+    /// numbers drawn from the point so far
+    dimension: usize,
+    scramble: u64,
+}
+
+/// a thread's random numbers: a seeded generator, or a sampler's sequence for the dimensions
+/// it covers while a sample of a pixel is being taken
+pub struct SampleRng {
+    /// small rng is a lot quicker than std rng, at the cost of sequences that can differ
+    /// between platforms and rand versions
+    rng: SmallRng,
+    sequence: Option<Sequence>,
+}
+
+impl RngCore for SampleRng {
+    fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let random = self.rng.next_u64();
+        match &mut self.sequence {
+            Some(seq) if seq.dimension < seq.sampler.dimensions() => {
+                let scramble = match seq.sampler {
+                    // every dimension reads the tile from a different place, so they don't line up
+                    Sampler::BlueNoise => {
+                        let d = seq.dimension as u32;
+                        blue_noise(seq.pixel.0 + d * 23, seq.pixel.1 + d * 41)
+                    }
+                    _ => mix_seed(seq.scramble, seq.dimension as u64) as u32,
+                };
+                let x = seq.sampler.sample(seq.index, seq.dimension, scramble);
+                seq.dimension += 1;
+                // random low bits, so the point isn't cut off at 32 bits
+                (x as u64) << 32 | random >> 32
+            }
+            _ => random,
+        }
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.rng.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.rng.try_fill_bytes(dest)
+    }
+}
+
+thread_local! {
+    /// seeded from the os until a render reseeds it
+    static RNG: RefCell<SampleRng> = RefCell::new(SampleRng {
+        rng: SmallRng::from_entropy(),
+        sequence: None,
+    });
+}
+
+/// runs `f` with this thread's random number generator, which all sampling draws from
+pub fn with_rng<T>(f: impl FnOnce(&mut SampleRng) -> T) -> T {
+    RNG.with(|rng| f(&mut rng.borrow_mut()))
+}
+
+/// a random value from this thread's generator
+pub fn random<T>() -> T
+where
+    Standard: Distribution<T>,
+{
+    with_rng(|rng| rng.gen())
+}
+
+/// restarts this thread's generator from `seed`, so everything drawn after it is reproducible
+pub fn seed_rng(seed: u64) {
+    with_rng(|rng| rng.rng = SmallRng::seed_from_u64(seed));
+}
+
+/// makes the numbers drawn on this thread follow point `index` of `sampler`'s sequence for a
+/// sample of `pixel`, randomized by `scramble`, until `end_sequence`. numbers past the
+/// dimensions the sequence covers come from the generator as usual
+pub fn start_sequence(sampler: Sampler, index: u64, pixel: (u32, u32), scramble: u64) {
+    let sequence = Sequence {
+        sampler,
+        index,
+        pixel,
+        dimension: 0,
+        scramble,
+    };
+    with_rng(|rng| rng.sequence = Some(sequence));
+}
+
+/// goes back to drawing every number from the generator
+pub fn end_sequence() {
+    with_rng(|rng| rng.sequence = None);
+}
+
+/// derives a seed for one part of a render, like a pixel, from the seed of the whole.
+/// nearby values give unrelated seeds (splitmix64's finalizer)
+pub fn mix_seed(seed: u64, value: u64) -> u64 {
+    let mut z = seed
+        ^ value
+            .wrapping_add(0x9e37_79b9_7f4a_7c15)
+            .wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[test]
+fn seeded_sequences_repeat() {
+    let draw = |seed| {
+        seed_rng(seed);
+        [random::<f64>(), random(), random()]
+    };
+    assert_eq!(draw(7), draw(7));
+    assert_ne!(draw(7), draw(8));
+    assert_ne!(mix_seed(0, 1), mix_seed(0, 2));
+    assert_ne!(mix_seed(1, 0), mix_seed(2, 0));
+}
+
+#[test]
+fn sequences_drive_random_numbers() {
+    start_sequence(Sampler::Sobol, 1, (0, 0), 0);
+    let (x, y): (f64, f64) = (random(), random());
+    end_sequence();
+    // point 1 of sobol is (0.5, 0.5), scrambled by the seeds mixed from 0 for each dimension
+    let scrambled =
+        |dimension| (0x8000_0000 ^ mix_seed(0, dimension) as u32) as f64 / 2f64.powi(32);
+    assert!((x - scrambled(0)).abs() < 1e-9);
+    assert!((y - scrambled(1)).abs() < 1e-9);
+}
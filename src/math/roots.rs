@@ -0,0 +1,151 @@
+//! closed-form polynomial root finding, after Jochen Schwarze's solvers in Graphics Gems I.
+//! all functions return the real roots in ascending order
+
+use super::{consts::PI, Float};
+
+const E: Float = 1e-9;
+
+fn is_zero(x: Float) -> bool {
+    x.abs() < E
+}
+
+fn sorted(mut roots: Vec<Float>) -> Vec<Float> {
+    roots.sort_by(|a, b| a.total_cmp(b));
+    roots
+}
+
+/// real roots of a*x^2 + b*x + c
+pub fn solve_quadratic(a: Float, b: Float, c: Float) -> Vec<Float> {
+    if is_zero(a) {
+        return if is_zero(b) { vec![] } else { vec![-c / b] };
+    }
+    // normal form x^2 + p*x + q
+    let p = b / (2. * a);
+    let q = c / a;
+    let d = p * p - q;
+    if is_zero(d) {
+        vec![-p]
+    } else if d < 0. {
+        vec![]
+    } else {
+        let sqrt_d = d.sqrt();
+        vec![-p - sqrt_d, -p + sqrt_d]
+    }
+}
+
+/// real roots of a*x^3 + b*x^2 + c*x + d
+pub fn solve_cubic(a: Float, b: Float, c: Float, d: Float) -> Vec<Float> {
+    if is_zero(a) {
+        return solve_quadratic(b, c, d);
+    }
+    // normal form x^3 + A*x^2 + B*x + C
+    let (a, b, c) = (b / a, c / a, d / a);
+
+    // substitute x = y - A/3 to eliminate the quadratic term: y^3 + 3*p*y + 2*q
+    let sq_a = a * a;
+    let p = (-sq_a / 3. + b) / 3.;
+    let q = (2. / 27. * a * sq_a - a * b / 3. + c) / 2.;
+
+    let cb_p = p * p * p;
+    let d = q * q + cb_p;
+
+    let roots = if is_zero(d) {
+        if is_zero(q) {
+            // one triple root
+            vec![0.]
+        } else {
+            // one single and one double root
+            let u = (-q).cbrt();
+            vec![2. * u, -u]
+        }
+    } else if d < 0. {
+        // three real roots
+        let phi = (-q / (-cb_p).sqrt()).clamp(-1., 1.).acos() / 3.;
+        let t = 2. * (-p).sqrt();
+        vec![
+            t * phi.cos(),
+            -t * (phi + PI / 3.).cos(),
+            -t * (phi - PI / 3.).cos(),
+        ]
+    } else {
+        // one real root
+        let sqrt_d = d.sqrt();
+        vec![(sqrt_d - q).cbrt() - (sqrt_d + q).cbrt()]
+    };
+
+    sorted(roots.into_iter().map(|y| y - a / 3.).collect())
+}
+
+/// real roots of a*x^4 + b*x^3 + c*x^2 + d*x + e
+pub fn solve_quartic(a: Float, b: Float, c: Float, d: Float, e: Float) -> Vec<Float> {
+    if is_zero(a) {
+        return solve_cubic(b, c, d, e);
+    }
+    // normal form x^4 + A*x^3 + B*x^2 + C*x + D
+    let (a, b, c, d) = (b / a, c / a, d / a, e / a);
+
+    // substitute x = y - A/4 to eliminate the cubic term: y^4 + p*y^2 + q*y + r
+    let sq_a = a * a;
+    let p = -3. / 8. * sq_a + b;
+    let q = sq_a * a / 8. - a * b / 2. + c;
+    let r = -3. / 256. * sq_a * sq_a + sq_a * b / 16. - a * c / 4. + d;
+
+    let roots = if is_zero(r) {
+        // no absolute term: y * (y^3 + p*y + q) = 0
+        let mut roots = solve_cubic(1., 0., p, q);
+        roots.push(0.);
+        roots
+    } else {
+        // solve the resolvent cubic and take one real root...
+        let z = solve_cubic(1., -p / 2., -r, r * p / 2. - q * q / 8.)[0];
+
+        // ...to build two quadratic equations
+        let u = z * z - r;
+        let v = 2. * z - p;
+        let u = if is_zero(u) {
+            0.
+        } else if u > 0. {
+            u.sqrt()
+        } else {
+            return vec![];
+        };
+        let v = if is_zero(v) {
+            0.
+        } else if v > 0. {
+            v.sqrt()
+        } else {
+            return vec![];
+        };
+
+        let v = if q < 0. { -v } else { v };
+        let mut roots = solve_quadratic(1., v, z - u);
+        roots.extend(solve_quadratic(1., -v, z + u));
+        roots
+    };
+
+    sorted(roots.into_iter().map(|y| y - a / 4.).collect())
+}
+
+#[test]
+fn polynomial_roots() {
+    fn assert_roots(actual: Vec<Float>, expected: &[Float]) {
+        assert_eq!(actual.len(), expected.len(), "{actual:?} != {expected:?}");
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < 1e-6, "{actual:?} != {expected:?}");
+        }
+    }
+
+    // (x - 1)(x + 2)
+    assert_roots(solve_quadratic(1., 1., -2.), &[-2., 1.]);
+    assert_roots(solve_quadratic(1., 0., 1.), &[]);
+    // (x - 1)(x - 2)(x - 3)
+    assert_roots(solve_cubic(2., -12., 22., -12.), &[1., 2., 3.]);
+    // (x - 1)(x^2 + 1)
+    assert_roots(solve_cubic(1., -1., 1., -1.), &[1.]);
+    // (x - 1)(x - 2)(x - 3)(x - 4)
+    assert_roots(solve_quartic(1., -10., 35., -50., 24.), &[1., 2., 3., 4.]);
+    // (x^2 - 4)(x^2 + 1)
+    assert_roots(solve_quartic(1., 0., -3., 0., -4.), &[-2., 2.]);
+    // x^4 + 1
+    assert_roots(solve_quartic(1., 0., 0., 0., 1.), &[]);
+}
@@ -0,0 +1,155 @@
+use serde::{Deserialize, Serialize};
+use std::{str::FromStr, sync::OnceLock};
+
+/// where the numbers driving each sample of a pixel come from
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Sampler {
+    /// independent random numbers, with the pixel offsets jittered over a grid
+    #[default]
+    Stratified,
+    /// the halton sequence, one prime base per dimension
+    Halton,
+    /// the sobol sequence, with joe and kuo's direction numbers
+    Sobol,
+    /// the sobol sequence, shifted around the unit interval by a tile of blue noise over the
+    /// image. the error left at low sample counts is spread out as fine grain instead of clumps
+    BlueNoise,
+}
+
+/// first primes, the bases of the halton sequence's dimensions
+const PRIMES: [u32; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131,
+];
+
+/// degree, coefficients and initial direction numbers of the primitive polynomial of each sobol
+/// dimension after the first, from new-joe-kuo-6.21201
+const SOBOL_POLYNOMIALS: [(u32, u32, &[u32]); 20] = [
+    (1, 0, &[1]),
+    (2, 1, &[1, 3]),
+    (3, 1, &[1, 3, 1]),
+    (3, 2, &[1, 1, 1]),
+    (4, 1, &[1, 1, 3, 3]),
+    (4, 4, &[1, 3, 5, 13]),
+    (5, 2, &[1, 1, 5, 5, 17]),
+    (5, 4, &[1, 1, 5, 5, 5]),
+    (5, 7, &[1, 1, 7, 11, 19]),
+    (5, 11, &[1, 1, 5, 1, 1]),
+    (5, 13, &[1, 1, 1, 3, 11]),
+    (5, 14, &[1, 3, 5, 5, 31]),
+    (6, 1, &[1, 3, 3, 9, 7, 49]),
+    (6, 13, &[1, 1, 1, 15, 21, 21]),
+    (6, 16, &[1, 3, 1, 13, 27, 49]),
+    (6, 19, &[1, 1, 1, 15, 7, 5]),
+    (6, 22, &[1, 3, 1, 15, 13, 25]),
+    (6, 25, &[1, 1, 5, 5, 19, 61]),
+    (7, 1, &[1, 3, 7, 11, 23, 15, 103]),
+    (7, 4, &[1, 3, 7, 13, 13, 15, 69]),
+];
+
+/// the direction numbers of every sobol dimension, as 32 bit fractions
+fn sobol_directions() -> &'static [[u32; 32]] {
+    static DIRECTIONS: OnceLock<Vec<[u32; 32]>> = OnceLock::new();
+    DIRECTIONS.get_or_init(|| {
+        let first = std::array::from_fn(|i| 1 << (31 - i));
+        let rest = SOBOL_POLYNOMIALS.iter().map(|&(s, a, m)| {
+            let s = s as usize;
+            let mut v = [0u32; 32];
+            for i in 0..32 {
+                v[i] = if i < s {
+                    m[i] << (31 - i)
+                } else {
+                    let mut x = v[i - s] ^ (v[i - s] >> s);
+                    for k in 1..s {
+                        if (a >> (s - 1 - k)) & 1 == 1 {
+                            x ^= v[i - k];
+                        }
+                    }
+                    x
+                };
+            }
+            v
+        });
+        std::iter::once(first).chain(rest).collect()
+    })
+}
+
+/// digits of `index` in `base`, mirrored around the point
+fn radical_inverse(mut index: u64, base: u32) -> f64 {
+    let base = base as u64;
+    let (mut reversed, mut scale) = (0., 1.);
+    while index > 0 {
+        scale /= base as f64;
+        reversed += (index % base) as f64 * scale;
+        index /= base;
+    }
+    reversed
+}
+
+impl Sampler {
+    /// dimensions the sequence has numbers for. later ones fall back to random numbers
+    pub fn dimensions(&self) -> usize {
+        match self {
+            Sampler::Stratified => 0,
+            Sampler::Halton => PRIMES.len(),
+            Sampler::Sobol | Sampler::BlueNoise => SOBOL_POLYNOMIALS.len() + 1,
+        }
+    }
+
+    /// coordinate `dimension` of point `index` of the sequence, as a fraction of 2^32.
+    /// `scramble` randomizes the sequence while keeping it evenly spread
+    pub fn sample(&self, index: u64, dimension: usize, scramble: u32) -> u32 {
+        match self {
+            Sampler::Stratified => scramble,
+            Sampler::Halton => {
+                // halton points can't be scrambled bitwise, so shift them around the unit interval
+                let x = radical_inverse(index, PRIMES[dimension]);
+                ((x * 2f64.powi(32)) as u32).wrapping_add(scramble)
+            }
+            Sampler::Sobol => {
+                let v = &sobol_directions()[dimension];
+                let bits = (0..32).filter(|bit| (index >> bit) & 1 == 1);
+                bits.fold(scramble, |x, bit| x ^ v[bit])
+            }
+            Sampler::BlueNoise => Sampler::Sobol
+                .sample(index, dimension, 0)
+                .wrapping_add(scramble),
+        }
+    }
+}
+
+impl FromStr for Sampler {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "stratified" => Ok(Sampler::Stratified),
+            "halton" => Ok(Sampler::Halton),
+            "sobol" => Ok(Sampler::Sobol),
+            "blue-noise" => Ok(Sampler::BlueNoise),
+            _ => Err(format!(
+                "unknown sampler `{s}`, expected stratified, halton, sobol or blue-noise"
+            )),
+        }
+    }
+}
+
+#[test]
+fn sequences_stratify_every_dimension() {
+    // the first n points land in n different intervals of [0, 1)
+    let halton = PRIMES.iter().map(|&p| (Sampler::Halton, p as u64));
+    let sobol = [(Sampler::Sobol, 64); SOBOL_POLYNOMIALS.len() + 1];
+    for (dimension, (sampler, n)) in halton.enumerate().chain(sobol.into_iter().enumerate()) {
+        let mut intervals: Vec<_> = (0..n)
+            .map(|i| {
+                let x = sampler.sample(i, dimension, 0) as f64 / 2f64.powi(32);
+                // halton points are cut off a hair under multiples of 1/n
+                (x * n as f64 + 1e-6) as u64
+            })
+            .collect();
+        intervals.sort();
+        assert_eq!(intervals, (0..n).collect::<Vec<_>>());
+    }
+    let sobol: Vec<_> = (0..4).map(|i| Sampler::Sobol.sample(i, 1, 0)).collect();
+    assert_eq!(sobol, [0, 1 << 31, 3 << 30, 1 << 30]);
+}
@@ -1,12 +1,13 @@
-use super::Normalize;
-use rand::prelude::*;
-use std::ops::{Add, AddAssign, Div, Mul, MulAssign, Neg, Sub};
+use super::{consts::PI, random, Float, Normalize, Onb};
+use std::ops::{
+    Add, AddAssign, Div, DivAssign, Index, IndexMut, Mul, MulAssign, Neg, Sub, SubAssign,
+};
 
 #[derive(Debug, Default, Clone, Copy, PartialEq)]
 pub struct Vec3 {
-    pub x: f64,
-    pub y: f64,
-    pub z: f64,
+    pub x: Float,
+    pub y: Float,
+    pub z: Float,
 }
 
 impl Vec3 {
@@ -17,45 +18,44 @@ impl Vec3 {
     pub const ONE: Self = Self::new(1.0, 1.0, 1.0);
 
     #[inline(always)]
-    pub const fn new(x: f64, y: f64, z: f64) -> Self {
+    pub const fn new(x: Float, y: Float, z: Float) -> Self {
         Self { x, y, z }
     }
 
     /// vector magnitude
-    pub fn length(&self) -> f64 {
+    pub fn length(&self) -> Float {
         self.length_squared().sqrt()
     }
 
     /// vector magnitude, squared
-    pub fn length_squared(&self) -> f64 {
+    pub fn length_squared(&self) -> Float {
         self.x * self.x + self.y * self.y + self.z * self.z
     }
 
     /// vector with components randomized between [0, 1]
     pub fn random() -> Vec3 {
-        let mut rng = thread_rng();
         Vec3 {
-            x: rng.gen(),
-            y: rng.gen(),
-            z: rng.gen(),
+            x: random(),
+            y: random(),
+            z: random(),
         }
     }
 
     /// random point in a unit sphere
     pub fn random_unit_sphere() -> Vec3 {
-        Vec3::random_unit() * random::<f64>()
+        Vec3::random_unit() * random::<Float>()
     }
 
-    /// random unit vector
+    /// random unit vector, uniformly distributed over the sphere
     pub fn random_unit() -> Vec3 {
-        let mut rng = thread_rng();
-        let mut r = || 2f64 * rng.gen::<f64>() - 1.;
+        let z = 2. * random::<Float>() - 1.;
+        let phi = 2. * PI * random::<Float>();
+        let r = (1. - z * z).sqrt();
         Vec3 {
-            x: r(),
-            y: r(),
-            z: r(),
+            x: r * phi.cos(),
+            y: r * phi.sin(),
+            z,
         }
-        .normalize()
     }
 
     /// random point in a hemisphere around the given normal
@@ -69,11 +69,29 @@ impl Vec3 {
         }
     }
 
-    /// random point in a disk in the xy plane
+    /// random unit vector in the hemisphere around the given unit normal,
+    /// with a density proportional to the cosine of its angle to the normal
+    pub fn random_cosine_direction(normal: Vec3) -> Vec3 {
+        // points spread evenly over the disk, lifted up onto the hemisphere
+        let disk = Vec3::random_in_xy_unit_disk();
+        let z = (1. - disk.length_squared()).max(0.).sqrt();
+        Onb::new(normal).local(disk.x, disk.y, z)
+    }
+
+    /// random point in the unit disk in the xy plane, spread evenly over it. uses shirley and
+    /// chiu's concentric mapping of the square, so stratified numbers give stratified points
     pub fn random_in_xy_unit_disk() -> Vec3 {
-        let mut v = Vec3::random_unit();
-        v.z = 0.;
-        v
+        let (a, b) = (2. * random::<Float>() - 1., 2. * random::<Float>() - 1.);
+        if a == 0. && b == 0. {
+            return Vec3::ZERO;
+        }
+        // squares around the center map to circles, each side of them to a quarter of the circle
+        let (r, theta) = if a.abs() > b.abs() {
+            (a, PI / 4. * (b / a))
+        } else {
+            (b, PI / 2. - PI / 4. * (a / b))
+        };
+        Vec3::new(r * theta.cos(), r * theta.sin(), 0.)
     }
 
     /// reflect against a surface with the given normal
@@ -82,11 +100,11 @@ impl Vec3 {
     }
 
     /// refract on a surface
-    pub fn refract(&self, normal: Vec3, etai_over_etat: f64) -> Vec3 {
+    pub fn refract(&self, normal: Vec3, etai_over_etat: Float) -> Vec3 {
         let uv = *self;
         let cos_theta = (-uv).dot(normal).min(1.0);
         let perp = etai_over_etat * (uv + cos_theta * normal);
-        let parallel = -1. * (1.0 - perp.length_squared()).abs().sqrt() * normal;
+        let parallel = -(1.0 - perp.length_squared()).abs().sqrt() * normal;
         perp + parallel
     }
 
@@ -99,8 +117,54 @@ impl Vec3 {
         }
     }
 
+    /// absolute value of x, y, and z
+    pub fn abs(self) -> Self {
+        Self {
+            x: self.x.abs(),
+            y: self.y.abs(),
+            z: self.z.abs(),
+        }
+    }
+
+    /// the largest of x, y and z
+    pub fn max_component(self) -> Float {
+        self.x.max(self.y).max(self.z)
+    }
+
+    /// the smaller of each component
+    pub fn min(self, other: Self) -> Self {
+        Self {
+            x: self.x.min(other.x),
+            y: self.y.min(other.y),
+            z: self.z.min(other.z),
+        }
+    }
+
+    /// the larger of each component
+    pub fn max(self, other: Self) -> Self {
+        Self {
+            x: self.x.max(other.x),
+            y: self.y.max(other.y),
+            z: self.z.max(other.z),
+        }
+    }
+
+    /// every component clamped to [min, max]
+    pub fn clamp(self, min: Float, max: Float) -> Self {
+        Self {
+            x: self.x.clamp(min, max),
+            y: self.y.clamp(min, max),
+            z: self.z.clamp(min, max),
+        }
+    }
+
+    /// `self` at `t` = 0 through to `other` at `t` = 1
+    pub fn lerp(self, other: Self, t: Float) -> Self {
+        (1. - t) * self + t * other
+    }
+
     /// dot product
-    pub fn dot(self, rhs: Self) -> f64 {
+    pub fn dot(self, rhs: Self) -> Float {
         self.x * rhs.x + self.y * rhs.y + self.z * rhs.z
     }
 
@@ -115,19 +179,10 @@ impl Vec3 {
 
     /// is this vec zero (or very close to it)
     pub fn is_zero(&self) -> bool {
-        const E: f64 = 1e-8;
+        // single precision rounds off far sooner
+        const E: Float = if cfg!(feature = "f32") { 1e-5 } else { 1e-8 };
         self.x.abs() < E && self.y.abs() < E && self.z.abs() < E
     }
-
-    /// maps [0.0, 1.0] to [0, 255]
-    #[inline]
-    pub fn into_rgb8_array(self) -> [u8; 3] {
-        [
-            (self.x * 255.99) as u8,
-            (self.y * 255.99) as u8,
-            (self.z * 255.99) as u8,
-        ]
-    }
 }
 
 #[test]
@@ -136,7 +191,7 @@ fn random_unit_distribution() {
     for _ in 0..100000 {
         vecs.push(Vec3::random_unit_sphere());
     }
-    let mut sum = 0f64;
+    let mut sum = 0 as Float;
     let mut vec_sum = Vec3::ZERO;
     for vec in vecs {
         sum += vec.length();
@@ -145,12 +200,64 @@ fn random_unit_distribution() {
     let average = sum / 100000.0;
     let vec_average = vec_sum / 100000.0;
     // average length should be 0.5
-    assert!(0.49 <= average && average <= 0.51);
+    assert!((0.49..=0.51).contains(&average));
     // average x, y, and z should be 0
     let e = 0.01;
-    assert!(-e <= vec_average.x && vec_average.x <= e);
-    assert!(-e <= vec_average.y && vec_average.y <= e);
-    assert!(-e <= vec_average.z && vec_average.z <= e);
+    assert!((-e..=e).contains(&vec_average.x));
+    assert!((-e..=e).contains(&vec_average.y));
+    assert!((-e..=e).contains(&vec_average.z));
+}
+
+#[test]
+fn random_disk_distribution() {
+    let n = 100000;
+    let points: Vec<_> = (0..n).map(|_| Vec3::random_in_xy_unit_disk()).collect();
+    assert!(points.iter().all(|p| p.length() <= 1. + 1e-6 && p.z == 0.));
+    // evenly spread, half the points lie within 1/sqrt(2) of the center
+    let inner = points.iter().filter(|p| p.length_squared() < 0.5).count();
+    assert!((inner as Float / n as Float - 0.5).abs() < 0.01);
+    let right = points.iter().filter(|p| p.x > 0.).count();
+    assert!((right as Float / n as Float - 0.5).abs() < 0.01);
+}
+
+#[test]
+fn component_operators() {
+    let a = Vec3::new(1., -2., 4.);
+    let b = Vec3::new(-3., 5., 2.);
+    assert_eq!(8. / a, Vec3::new(8., -4., 2.));
+    assert_eq!(a.min(b), Vec3::new(-3., -2., 2.));
+    assert_eq!(a.max(b), Vec3::new(1., 5., 4.));
+    assert_eq!(a.clamp(0., 3.), Vec3::new(1., 0., 3.));
+    assert_eq!(a.abs(), Vec3::new(1., 2., 4.));
+    // as a distance field finds how far outside a box a point is
+    let outside = (a.abs() - 1.5 * Vec3::ONE).max(Vec3::ZERO);
+    assert_eq!(outside, Vec3::new(0., 0.5, 2.5));
+    assert_eq!(a.lerp(b, 0.25), Vec3::new(0., -0.25, 3.5));
+
+    let mut c = a;
+    c -= b;
+    assert_eq!(c, a - b);
+    c /= 2.;
+    assert_eq!(c, (a - b) / 2.);
+    c[1] = 7.;
+    assert_eq!((c[0], c[1], c[2]), (2., 7., 1.));
+}
+
+#[test]
+fn random_cosine_distribution() {
+    let n = 100000;
+    let normal = Vec3::new(1., -2., 2.) / 3.;
+    let cosines: Vec<_> = (0..n)
+        .map(|_| Vec3::random_cosine_direction(normal))
+        .map(|d| {
+            assert!((d.length() - 1.).abs() < 1e-6);
+            d.dot(normal)
+        })
+        .collect();
+    assert!(cosines.iter().all(|&c| c >= 0.));
+    // with a density of cos / pi, the average cosine is 2/3
+    let average = cosines.iter().sum::<Float>() / n as Float;
+    assert!((average - 2. / 3.).abs() < 0.01);
 }
 
 impl Normalize for Vec3 {
@@ -194,7 +301,7 @@ impl Mul<Vec3> for Vec3 {
     }
 }
 
-impl Mul<Vec3> for f64 {
+impl Mul<Vec3> for Float {
     type Output = Vec3;
     fn mul(self, rhs: Vec3) -> Self::Output {
         Vec3 {
@@ -205,9 +312,9 @@ impl Mul<Vec3> for f64 {
     }
 }
 
-impl Mul<f64> for Vec3 {
+impl Mul<Float> for Vec3 {
     type Output = Self;
-    fn mul(self, rhs: f64) -> Self::Output {
+    fn mul(self, rhs: Float) -> Self::Output {
         Self {
             x: self.x * rhs,
             y: self.y * rhs,
@@ -216,9 +323,9 @@ impl Mul<f64> for Vec3 {
     }
 }
 
-impl Div<f64> for Vec3 {
+impl Div<Float> for Vec3 {
     type Output = Self;
-    fn div(self, rhs: f64) -> Self::Output {
+    fn div(self, rhs: Float) -> Self::Output {
         Self {
             x: self.x / rhs,
             y: self.y / rhs,
@@ -227,13 +334,13 @@ impl Div<f64> for Vec3 {
     }
 }
 
-impl Div<Vec3> for f64 {
+impl Div<Vec3> for Float {
     type Output = Vec3;
     fn div(self, rhs: Vec3) -> Self::Output {
         Vec3 {
-            x: rhs.x / self,
-            y: rhs.y / self,
-            z: rhs.z / self,
+            x: self / rhs.x,
+            y: self / rhs.y,
+            z: self / rhs.z,
         }
     }
 }
@@ -253,10 +360,50 @@ impl AddAssign for Vec3 {
     }
 }
 
-impl MulAssign<f64> for Vec3 {
-    fn mul_assign(&mut self, rhs: f64) {
+impl SubAssign for Vec3 {
+    fn sub_assign(&mut self, rhs: Self) {
+        self.x -= rhs.x;
+        self.y -= rhs.y;
+        self.z -= rhs.z;
+    }
+}
+
+impl MulAssign<Float> for Vec3 {
+    fn mul_assign(&mut self, rhs: Float) {
         self.x *= rhs;
         self.y *= rhs;
         self.z *= rhs;
     }
 }
+
+impl DivAssign<Float> for Vec3 {
+    fn div_assign(&mut self, rhs: Float) {
+        self.x /= rhs;
+        self.y /= rhs;
+        self.z /= rhs;
+    }
+}
+
+/// x, y and z by axis, 0, 1 and 2
+impl Index<usize> for Vec3 {
+    type Output = Float;
+    fn index(&self, axis: usize) -> &Float {
+        match axis {
+            0 => &self.x,
+            1 => &self.y,
+            2 => &self.z,
+            _ => panic!("no axis {axis} in a Vec3"),
+        }
+    }
+}
+
+impl IndexMut<usize> for Vec3 {
+    fn index_mut(&mut self, axis: usize) -> &mut Float {
+        match axis {
+            0 => &mut self.x,
+            1 => &mut self.y,
+            2 => &mut self.z,
+            _ => panic!("no axis {axis} in a Vec3"),
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use super::{perlin::POINT_COUNT, Float, Lattice, Vec3};
+
+/// cellular noise over 3d space, after steven worley: a random feature point in every cell of
+/// the integer lattice, and the distances from anywhere to the nearest of them
+#[derive(Clone, Debug)]
+pub struct Worley {
+    /// where in its cell each feature point lies, picked by the cell's hash
+    points: Vec<Vec3>,
+    lattice: Lattice,
+}
+
+impl Default for Worley {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Worley {
+    /// constructor with freshly placed feature points
+    pub fn new() -> Self {
+        Self {
+            points: (0..POINT_COUNT).map(|_| Vec3::random()).collect(),
+            lattice: Lattice::new(),
+        }
+    }
+
+    /// distances to the nearest feature point and the second nearest, f1 and f2
+    pub fn distances(&self, p: Vec3) -> (Float, Float) {
+        let (i, j, k) = (p.x.floor() as i64, p.y.floor() as i64, p.z.floor() as i64);
+        let (mut f1, mut f2) = (Float::INFINITY, Float::INFINITY);
+        // feature points stay in their cells, so the two nearest are in the neighboring ones
+        for di in -1..=1 {
+            for dj in -1..=1 {
+                for dk in -1..=1 {
+                    let (ci, cj, ck) = (i + di, j + dj, k + dk);
+                    let corner = Vec3::new(ci as Float, cj as Float, ck as Float);
+                    let point = corner + self.points[self.lattice.hash(ci, cj, ck)];
+                    let distance = (point - p).length();
+                    if distance < f1 {
+                        (f1, f2) = (distance, f1);
+                    } else if distance < f2 {
+                        f2 = distance;
+                    }
+                }
+            }
+        }
+        (f1, f2)
+    }
+}
+
+#[test]
+fn worley_distances() {
+    let worley = Worley::new();
+    for _ in 0..1000 {
+        let p = 20. * Vec3::random() - Vec3::new(10., 10., 10.);
+        let (f1, f2) = worley.distances(p);
+        assert!(f1 <= f2);
+        // a feature point's own cell is never further away than its far corner
+        assert!(f1 < (3.0 as Float).sqrt());
+        // and the nearest point is the same one from right next to it
+        let (near, _) = worley.distances(p + Vec3::new(1e-4, 0., 0.));
+        assert!((near - f1).abs() <= 1.1e-4);
+    }
+}
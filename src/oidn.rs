@@ -0,0 +1,47 @@
+use crate::output::{open_pfm, save_pfm};
+use image::Rgb32FImage;
+use std::{env, io, process::Command};
+
+/// runs intel open image denoise over a linear render, guided by its albedo and normal passes.
+/// shells out to the `oidnDenoise` tool shipped with oidn, or to `$OIDN_DENOISE` if set
+pub fn denoise(
+    color: &Rgb32FImage,
+    albedo: &Rgb32FImage,
+    normal: &Rgb32FImage,
+) -> io::Result<Rgb32FImage> {
+    let dir = env::temp_dir();
+    let path = |name: &str| {
+        dir.join(format!("raytracer_{}_{name}.pfm", std::process::id()))
+            .to_string_lossy()
+            .into_owned()
+    };
+    let (color_path, albedo_path, normal_path, output_path) = (
+        path("color"),
+        path("albedo"),
+        path("normal"),
+        path("denoised"),
+    );
+    save_pfm(&color_path, color)?;
+    save_pfm(&albedo_path, albedo)?;
+    save_pfm(&normal_path, normal)?;
+
+    let program = env::var("OIDN_DENOISE").unwrap_or_else(|_| "oidnDenoise".to_string());
+    let status = Command::new(program)
+        .args(["--hdr", &color_path])
+        .args(["--alb", &albedo_path])
+        .args(["--nrm", &normal_path])
+        .args(["-o", &output_path])
+        .status();
+    let denoised = match status {
+        Ok(status) if status.success() => open_pfm(&output_path),
+        Ok(status) => Err(io::Error::other(format!(
+            "oidnDenoise failed with {status}"
+        ))),
+        Err(e) => Err(e),
+    };
+
+    for path in [color_path, albedo_path, normal_path, output_path] {
+        let _ = std::fs::remove_file(path);
+    }
+    denoised
+}
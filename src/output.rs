@@ -0,0 +1,237 @@
+use crate::math::Float;
+use image::{DynamicImage, ImageOutputFormat, ImageResult, Rgb, Rgb32FImage};
+use std::{
+    fs::File,
+    io::{self, BufWriter, Cursor, Read, Write},
+};
+
+/// bits per channel of a saved display image
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BitDepth {
+    #[default]
+    Eight,
+    Sixteen,
+}
+
+/// false colors for an image of values between `low` and `high`, taken from its first channel
+/// on a log scale, from blue through cyan, green and yellow to red
+pub fn heatmap(image: &Rgb32FImage, low: Float, high: Float) -> Rgb32FImage {
+    const STOPS: [[f32; 3]; 5] = [
+        [0., 0., 1.],
+        [0., 1., 1.],
+        [0., 1., 0.],
+        [1., 1., 0.],
+        [1., 0., 0.],
+    ];
+    let mut out = image.clone();
+    for pixel in out.pixels_mut() {
+        let value = (pixel.0[0] as Float).max(low);
+        let t = ((value / low).ln() / (high / low).ln()).clamp(0., 1.) as f32;
+        let at = t * (STOPS.len() - 1) as f32;
+        let i = (at as usize).min(STOPS.len() - 2);
+        let f = at - i as f32;
+        *pixel = Rgb([0, 1, 2].map(|c| STOPS[i][c] * (1. - f) + STOPS[i + 1][c] * f));
+    }
+    out
+}
+
+/// writes a tone mapped image, with channels in [0, 1], in the format given by the extension.
+/// `.ppm` paths are written as plain (ascii) ppm
+pub fn save_display(path: &str, image: &Rgb32FImage, depth: BitDepth) -> ImageResult<()> {
+    if path.ends_with(".ppm") {
+        return save_plain_ppm(path, image, depth);
+    }
+    let image = DynamicImage::ImageRgb32F(image.clone());
+    match depth {
+        BitDepth::Eight => image.into_rgb8().save(path),
+        BitDepth::Sixteen => image.into_rgb16().save(path),
+    }
+}
+
+/// encodes a tone mapped image in memory, for when there's no file system to save it to
+pub fn encode_display(
+    image: &Rgb32FImage,
+    format: ImageOutputFormat,
+    depth: BitDepth,
+) -> ImageResult<Vec<u8>> {
+    let image = DynamicImage::ImageRgb32F(image.clone());
+    let image = match depth {
+        BitDepth::Eight => DynamicImage::ImageRgb8(image.into_rgb8()),
+        BitDepth::Sixteen => DynamicImage::ImageRgb16(image.into_rgb16()),
+    };
+    let mut bytes = Cursor::new(vec![]);
+    image.write_to(&mut bytes, format)?;
+    Ok(bytes.into_inner())
+}
+
+/// a tone mapped image as 8 bit rgba, row by row from the top, the way canvases take pixels
+pub fn display_rgba8(image: &Rgb32FImage) -> Vec<u8> {
+    DynamicImage::ImageRgb32F(image.clone())
+        .into_rgba8()
+        .into_raw()
+}
+
+/// writes the linear radiance of a render, untouched by tone mapping.
+/// `.hdr` paths are saved as radiance rgbe, anything else by extension, such as `.exr`
+pub fn save_linear(path: &str, image: &Rgb32FImage) -> ImageResult<()> {
+    if path.ends_with(".hdr") {
+        let file = BufWriter::new(File::create(path)?);
+        let pixels: Vec<_> = image.pixels().copied().collect();
+        image::codecs::hdr::HdrEncoder::new(file).encode(
+            &pixels,
+            image.width() as usize,
+            image.height() as usize,
+        )
+    } else {
+        image.save(path)
+    }
+}
+
+/// encodes the linear radiance of a render as openexr in memory
+pub fn encode_linear(image: &Rgb32FImage) -> ImageResult<Vec<u8>> {
+    let mut bytes = Cursor::new(vec![]);
+    image.write_to(&mut bytes, ImageOutputFormat::OpenExr)?;
+    Ok(bytes.into_inner())
+}
+
+/// writes a little-endian portable float map, which denoisers commonly read and write
+pub fn save_pfm(path: &str, image: &Rgb32FImage) -> io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    write!(file, "PF\n{} {}\n-1.0\n", image.width(), image.height())?;
+    // rows run from the bottom up
+    for y in (0..image.height()).rev() {
+        for x in 0..image.width() {
+            for c in image.get_pixel(x, y).0 {
+                file.write_all(&c.to_le_bytes())?;
+            }
+        }
+    }
+    file.flush()
+}
+
+/// reads a portable float map written by `save_pfm`, or any other color one
+pub fn open_pfm(path: &str) -> io::Result<Rgb32FImage> {
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+    let mut bytes = vec![];
+    File::open(path)?.read_to_end(&mut bytes)?;
+
+    // three whitespace separated header lines: magic, dimensions and scale
+    let mut header = vec![];
+    let mut start = 0;
+    while header.len() < 4 {
+        let end = start
+            + bytes[start..]
+                .iter()
+                .position(|b| b.is_ascii_whitespace())
+                .ok_or_else(|| invalid("truncated pfm header"))?;
+        if end > start {
+            header.push(String::from_utf8_lossy(&bytes[start..end]).into_owned());
+        }
+        start = end + 1;
+    }
+    if header[0] != "PF" {
+        return Err(invalid("not a color pfm"));
+    }
+    let parse = |s: &str| s.parse::<Float>().map_err(|_| invalid("bad pfm header"));
+    let (width, height, scale) = (parse(&header[1])?, parse(&header[2])?, parse(&header[3])?);
+    let (width, height) = (width as u32, height as u32);
+
+    let data = &bytes[start..];
+    if data.len() < (width * height * 12) as usize {
+        return Err(invalid("truncated pfm data"));
+    }
+    let mut floats = data.chunks_exact(4).map(|b| {
+        let b = [b[0], b[1], b[2], b[3]];
+        if scale < 0. {
+            f32::from_le_bytes(b)
+        } else {
+            f32::from_be_bytes(b)
+        }
+    });
+    let mut image = Rgb32FImage::new(width, height);
+    for y in (0..height).rev() {
+        for x in 0..width {
+            let mut next = || floats.next().unwrap_or(0.);
+            image.put_pixel(x, y, Rgb([next(), next(), next()]));
+        }
+    }
+    Ok(image)
+}
+
+fn save_plain_ppm(path: &str, image: &Rgb32FImage, depth: BitDepth) -> ImageResult<()> {
+    let max = match depth {
+        BitDepth::Eight => u8::MAX as u32,
+        BitDepth::Sixteen => u16::MAX as u32,
+    };
+    let mut file = BufWriter::new(File::create(path)?);
+    writeln!(file, "P3\n{} {}\n{}", image.width(), image.height(), max)?;
+    for row in image.rows() {
+        let line: Vec<String> = row
+            .flat_map(|pixel| pixel.0)
+            .map(|c| ((c.clamp(0., 1.) * max as f32).round() as u32).to_string())
+            .collect();
+        writeln!(file, "{}", line.join(" "))?;
+    }
+    file.flush()?;
+    Ok(())
+}
+
+#[test]
+fn plain_ppm_output() {
+    let mut image = Rgb32FImage::new(2, 1);
+    image.put_pixel(0, 0, image::Rgb([1., 0.5, 0.]));
+    image.put_pixel(1, 0, image::Rgb([0., 2., -1.]));
+    let path = std::env::temp_dir().join("raytracer_plain_ppm_output.ppm");
+    let path = path.to_str().unwrap();
+
+    save_display(path, &image, BitDepth::Eight).unwrap();
+    let text = std::fs::read_to_string(path).unwrap();
+    assert_eq!(text, "P3\n2 1\n255\n255 128 0 0 255 0\n");
+
+    save_display(path, &image, BitDepth::Sixteen).unwrap();
+    let text = std::fs::read_to_string(path).unwrap();
+    assert!(text.starts_with("P3\n2 1\n65535\n65535 32768 0"));
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn pfm_round_trip() {
+    let mut image = Rgb32FImage::new(3, 2);
+    image.put_pixel(0, 0, Rgb([1., 2., 3.]));
+    image.put_pixel(2, 1, Rgb([-0.5, 100., 0.25]));
+    let path = std::env::temp_dir().join("raytracer_pfm_round_trip.pfm");
+    let path = path.to_str().unwrap();
+    save_pfm(path, &image).unwrap();
+    assert_eq!(open_pfm(path).unwrap(), image);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn in_memory_output() {
+    let mut image = Rgb32FImage::new(2, 1);
+    image.put_pixel(0, 0, Rgb([1., 0.5, 0.]));
+    assert_eq!(display_rgba8(&image), [255, 128, 0, 255, 0, 0, 0, 255]);
+
+    let png = encode_display(&image, ImageOutputFormat::Png, BitDepth::Eight).unwrap();
+    let decoded = image::load_from_memory(&png).unwrap().into_rgb8();
+    assert_eq!(decoded.into_raw(), [255, 128, 0, 0, 0, 0]);
+
+    let exr = encode_linear(&image).unwrap();
+    let decoded = image::load_from_memory(&exr).unwrap().into_rgb32f();
+    assert_eq!(decoded, image);
+}
+
+#[test]
+fn heatmap_colors() {
+    let values = Rgb32FImage::from_fn(4, 1, |x, _| Rgb([[0., 0.001, 0.01, 1.][x as usize]; 3]));
+    let colors = heatmap(&values, 0.001, 0.1);
+    let near = |x, color: [f32; 3]| {
+        let pixel = colors.get_pixel(x, 0).0;
+        (0..3).all(|c| (pixel[c] - color[c]).abs() < 1e-4)
+    };
+    assert!(near(0, [0., 0., 1.]));
+    assert!(near(1, [0., 0., 1.]));
+    // halfway between on the log scale, and clamped either side
+    assert!(near(2, [0., 1., 0.]));
+    assert!(near(3, [1., 0., 0.]));
+}
@@ -0,0 +1,447 @@
+//! post processing, developing the linear radiance a render accumulates into the image that's
+//! shown. each step is a [`PostEffect`], and a [`Pipeline`] runs them in order, usually
+//! exposure, white balance, bloom, tone mapping, the srgb encoding and dithering
+
+use crate::math::Float;
+use crate::output::BitDepth;
+use crate::rt::{Color, ToneMap, WhiteBalance};
+use image::{Rgb, Rgb32FImage};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// a step of developing an image
+pub trait PostEffect: fmt::Debug + Send + Sync {
+    /// applies the effect to the whole image in place
+    fn apply(&self, image: &mut Rgb32FImage);
+}
+
+/// applies `f` to every pixel of an image
+fn map_pixels(image: &mut Rgb32FImage, f: impl Fn(Color) -> Color) {
+    for pixel in image.pixels_mut() {
+        *pixel = Rgb(f(Color::from(pixel.0)).to_array());
+    }
+}
+
+/// effects applied one after another
+#[derive(Debug, Default)]
+pub struct Pipeline {
+    effects: Vec<Box<dyn PostEffect>>,
+}
+
+impl Pipeline {
+    /// constructor. develops nothing until given effects
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// exposes, tone maps, encodes and dithers for an 8 bit display, which is all most renders
+    /// need
+    pub fn standard(exposure: Exposure, tone_map: ToneMap) -> Self {
+        Self::new()
+            .then(exposure)
+            .then(tone_map)
+            .then(Srgb)
+            .then(Dither::default())
+    }
+
+    /// adds an effect to the end of the pipeline
+    pub fn then<E: PostEffect + 'static>(mut self, effect: E) -> Self {
+        self.effects.push(Box::new(effect));
+        self
+    }
+
+    /// a developed copy of an image of linear radiance
+    pub fn develop(&self, image: &Rgb32FImage) -> Rgb32FImage {
+        let mut out = image.clone();
+        self.apply(&mut out);
+        out
+    }
+}
+
+impl PostEffect for Pipeline {
+    fn apply(&self, image: &mut Rgb32FImage) {
+        for effect in &self.effects {
+            effect.apply(image);
+        }
+    }
+}
+
+impl FromIterator<Box<dyn PostEffect>> for Pipeline {
+    fn from_iter<I: IntoIterator<Item = Box<dyn PostEffect>>>(effects: I) -> Self {
+        Self {
+            effects: effects.into_iter().collect(),
+        }
+    }
+}
+
+impl PostEffect for Box<dyn PostEffect> {
+    fn apply(&self, image: &mut Rgb32FImage) {
+        (**self).apply(image)
+    }
+}
+
+/// scales the image's brightness
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Exposure {
+    /// in stops, each doubling the brightness
+    pub stops: Float,
+    /// adds the exposure that brings the image to middle gray, see `auto_exposure`
+    pub auto: bool,
+}
+
+impl PostEffect for Exposure {
+    fn apply(&self, image: &mut Rgb32FImage) {
+        let stops = match self.auto {
+            true => self.stops + auto_exposure(image),
+            false => self.stops,
+        };
+        let scale = stops.exp2();
+        map_pixels(image, |color| color * scale);
+    }
+}
+
+/// the exposure in stops that brings an image to middle gray on average. the average is the
+/// log-average luminance, a geometric mean that a few bright highlights don't throw off, after
+/// reinhard et al., "photographic tone reproduction for digital images" (2002)
+pub fn auto_exposure(image: &Rgb32FImage) -> Float {
+    const MIDDLE_GRAY: Float = 0.18;
+    // keeps nearly black pixels from dragging the log-average down to nothing
+    const DELTA: Float = 1e-4;
+    // pixels are averaged in blocks first, so the black ones of a noisy render taken with
+    // few samples don't drag the average down. blocks that are black through and through, as
+    // where nothing's lit at all, are left out, as no exposure could bring them up
+    const BLOCK: u32 = 8;
+    let (width, height) = image.dimensions();
+    let (columns, rows) = (width.div_ceil(BLOCK), height.div_ceil(BLOCK));
+    let mut blocks = vec![(0., 0); (columns * rows) as usize];
+    for (x, y, pixel) in image.enumerate_pixels() {
+        let (sum, n) = &mut blocks[(y / BLOCK * columns + x / BLOCK) as usize];
+        *sum += Color::from(pixel.0).luminance().max(0.);
+        *n += 1;
+    }
+    let lit: Vec<_> = blocks.iter().filter(|&&(sum, _)| sum > 0.).collect();
+    if lit.is_empty() {
+        return 0.;
+    }
+    let log_sum: Float = lit
+        .iter()
+        .map(|&&(sum, n)| (DELTA + sum / n as Float).ln())
+        .sum();
+    let average = (log_sum / lit.len() as Float).exp();
+    (MIDDLE_GRAY / average).log2()
+}
+
+impl PostEffect for WhiteBalance {
+    fn apply(&self, image: &mut Rgb32FImage) {
+        map_pixels(image, |color| WhiteBalance::apply(self, color));
+    }
+}
+
+/// light scattering around bright highlights in the lens and the eye, so they glow into their
+/// surroundings. the light above the threshold is blurred by a gaussian, across the rows and
+/// then down the columns
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Bloom {
+    /// luminance above which light blooms. 1 is the white of the display, once exposed
+    pub threshold: Float,
+    /// spread of the glow, as a fraction of the image's width
+    pub radius: Float,
+    /// how much of the light above the threshold goes into the glow
+    pub strength: Float,
+}
+
+impl Default for Bloom {
+    fn default() -> Self {
+        Self {
+            threshold: 1.,
+            radius: 0.01,
+            strength: 0.2,
+        }
+    }
+}
+
+impl PostEffect for Bloom {
+    fn apply(&self, image: &mut Rgb32FImage) {
+        let (width, height) = image.dimensions();
+        if width == 0 || height == 0 {
+            return;
+        }
+        let bright: Vec<Color> = image
+            .pixels()
+            .map(|pixel| {
+                let color = Color::from(pixel.0);
+                let luminance = color.luminance();
+                if luminance > self.threshold {
+                    color * ((luminance - self.threshold) / luminance)
+                } else {
+                    Color::BLACK
+                }
+            })
+            .collect();
+
+        let sigma = (self.radius * width as Float).max(0.5);
+        let reach = (3. * sigma).ceil() as i64;
+        let kernel: Vec<Float> = (-reach..=reach)
+            .map(|i| (-0.5 * (i as Float / sigma).powi(2)).exp())
+            .collect();
+        // weights falling outside the image are left out, so the edges don't darken
+        let blur = |colors: &[Color], stride: usize, n: usize| -> Vec<Color> {
+            let mut out = vec![Color::BLACK; colors.len()];
+            for line in 0..colors.len() / n {
+                let at = |i: usize| {
+                    if stride == 1 {
+                        line * n + i
+                    } else {
+                        i * stride + line
+                    }
+                };
+                for i in 0..n {
+                    let (mut sum, mut weights) = (Color::BLACK, 0.);
+                    for (k, weight) in kernel.iter().enumerate() {
+                        let j = i as i64 + k as i64 - reach;
+                        if (0..n as i64).contains(&j) {
+                            sum += colors[at(j as usize)] * *weight;
+                            weights += weight;
+                        }
+                    }
+                    out[at(i)] = sum / weights;
+                }
+            }
+            out
+        };
+        let rows = blur(&bright, 1, width as usize);
+        let glow = blur(&rows, width as usize, height as usize);
+
+        for (pixel, glow) in image.pixels_mut().zip(glow) {
+            *pixel = Rgb((Color::from(pixel.0) + glow * self.strength).to_array());
+        }
+    }
+}
+
+impl PostEffect for ToneMap {
+    fn apply(&self, image: &mut Rgb32FImage) {
+        map_pixels(image, |color| ToneMap::apply(self, color, 0.));
+    }
+}
+
+/// encodes linear values with the srgb transfer function, as image files and displays expect,
+/// clamping them to [0, 1]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Srgb;
+
+impl Srgb {
+    /// a single channel
+    pub fn encode(linear: Float) -> Float {
+        let linear = linear.clamp(0., 1.);
+        if linear <= 0.0031308 {
+            12.92 * linear
+        } else {
+            1.055 * linear.powf(1. / 2.4) - 0.055
+        }
+    }
+
+    /// a single channel back to linear, undoing `encode`
+    pub fn decode(encoded: Float) -> Float {
+        let encoded = encoded.clamp(0., 1.);
+        if encoded <= 0.04045 {
+            encoded / 12.92
+        } else {
+            ((encoded + 0.055) / 1.055).powf(2.4)
+        }
+    }
+}
+
+impl PostEffect for Srgb {
+    fn apply(&self, image: &mut Rgb32FImage) {
+        map_pixels(image, |c| {
+            Color::new(Srgb::encode(c.r), Srgb::encode(c.g), Srgb::encode(c.b))
+        });
+    }
+}
+
+/// adds noise of about a step of the image's bit depth before it's saved, so smooth gradients
+/// such as skies don't show bands where they're rounded to the steps either side. the noise is
+/// triangular, as the sum of two uniform ones, which keeps its strength the same everywhere
+/// between steps. pure black and white are left alone
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Dither {
+    /// bits per channel of the image saved
+    pub bits: u32,
+}
+
+impl Dither {
+    /// dithering for images saved at `depth`
+    pub fn new(depth: BitDepth) -> Self {
+        let bits = match depth {
+            BitDepth::Eight => 8,
+            BitDepth::Sixteen => 16,
+        };
+        Self { bits }
+    }
+}
+
+impl Default for Dither {
+    fn default() -> Self {
+        Self::new(BitDepth::Eight)
+    }
+}
+
+/// a uniform number in [0, 1) from a pixel's coordinates and channel, the same every time so
+/// renders and the frames of animations keep the same grain
+fn pixel_noise(x: u32, y: u32, channel: u32) -> Float {
+    // the lowbias32 integer hash of chris wellons
+    let mut h = x.wrapping_mul(0x9e3779b9) ^ y.wrapping_mul(0x85ebca6b) ^ channel << 29;
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x7feb352d);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x846ca68b);
+    h ^= h >> 16;
+    h as Float / 2f64.powi(32) as Float
+}
+
+impl PostEffect for Dither {
+    fn apply(&self, image: &mut Rgb32FImage) {
+        let step = 1. / ((1u64 << self.bits) - 1) as Float;
+        for (x, y, pixel) in image.enumerate_pixels_mut() {
+            for (channel, c) in pixel.0.iter_mut().enumerate() {
+                let value = *c as Float;
+                if value <= 0. || value >= 1. {
+                    continue;
+                }
+                let channel = channel as u32;
+                let noise = pixel_noise(x, y, channel) + pixel_noise(x, y, channel + 3) - 1.;
+                *c = (value + noise * step).clamp(0., 1.) as f32;
+            }
+        }
+    }
+}
+
+#[test]
+fn pipeline_in_order() {
+    let image = Rgb32FImage::from_pixel(2, 2, Rgb([0.25; 3]));
+    let at = |pipeline: Pipeline| pipeline.develop(&image).get_pixel(0, 0).0[0];
+    assert_eq!(at(Pipeline::new()), 0.25);
+    let exposure = |stops| Exposure { stops, auto: false };
+    assert_eq!(at(Pipeline::new().then(exposure(2.))), 1.);
+    // clamped once encoded, so exposing after can't bring it back
+    assert!((at(Pipeline::new().then(exposure(3.)).then(Srgb)) - 1.).abs() < 1e-6);
+    let encoded_first = at(Pipeline::new().then(Srgb).then(exposure(3.)));
+    assert!((encoded_first - 8. * 0.537099).abs() < 1e-4);
+    let standard = at(Pipeline::standard(exposure(0.), ToneMap::Clamp));
+    // give or take the dithering
+    assert!((standard - 0.537099).abs() < 1. / 255.);
+    // pipelines nest, and can be put together from any effects
+    let effects: Vec<Box<dyn PostEffect>> = vec![Box::new(exposure(-1.)), Box::new(Srgb)];
+    let nested = Pipeline::new()
+        .then(exposure(1.))
+        .then(effects.into_iter().collect::<Pipeline>());
+    assert!((at(nested) - 0.537099).abs() < 1e-5);
+
+    // the srgb curve is continuous where its two pieces meet
+    let (below, above) = (Srgb::encode(0.0031308), Srgb::encode(0.0031309));
+    assert!((above - below).abs() < 1e-4);
+    assert_eq!(
+        (Srgb::encode(-1.), Srgb::encode(2.)),
+        (0., Srgb::encode(1.))
+    );
+}
+
+#[test]
+fn auto_exposure_finds_middle_gray() {
+    let gray = |level: f32| Rgb32FImage::from_pixel(64, 64, Rgb([level; 3]));
+    assert!((auto_exposure(&gray(0.18))).abs() < 1e-3);
+    // scenes lit a thousand times brighter come out the same
+    let bright = auto_exposure(&gray(180.));
+    assert!((bright + (1000. as Float).log2()).abs() < 1e-3);
+    // a single highlight hardly moves it
+    let mut image = gray(0.18);
+    image.put_pixel(3, 3, Rgb([1e4; 3]));
+    assert!(auto_exposure(&image) > -0.5);
+    // nor do unlit surroundings, or the noise of a render with few samples
+    let framed = Rgb32FImage::from_fn(32, 32, |x, y| match (x / 16, (x + y) % 2) {
+        (0, _) => Rgb([0.; 3]),
+        (_, 0) => Rgb([0.36; 3]),
+        _ => Rgb([0.; 3]),
+    });
+    assert!(auto_exposure(&framed).abs() < 1e-3);
+    assert_eq!(auto_exposure(&Rgb32FImage::new(0, 0)), 0.);
+
+    let auto = Exposure {
+        stops: 1.,
+        auto: true,
+    };
+    let developed = Pipeline::new().then(auto).develop(&gray(180.));
+    assert!((developed.get_pixel(0, 0).0[0] - 0.36).abs() < 1e-3);
+}
+
+#[test]
+fn bloom_spreads_highlights() {
+    // a single bright pixel in the middle of a dim image
+    let mut image = Rgb32FImage::from_pixel(64, 64, Rgb([0.1; 3]));
+    image.put_pixel(32, 32, Rgb([100.; 3]));
+    let bloom = Bloom {
+        radius: 0.05,
+        ..Bloom::default()
+    };
+    let bloomed = Pipeline::new().then(bloom).develop(&image);
+    let at = |x, y| bloomed.get_pixel(x, y).0[0];
+    // glows into its neighbors, fading with distance, and equally in every direction
+    assert!(at(33, 32) > at(36, 32) && at(36, 32) > at(40, 32) && at(40, 32) > 0.1);
+    assert!((at(36, 32) - at(32, 36)).abs() < 1e-4);
+    assert_eq!(at(0, 0), 0.1);
+    // the glow carries the strength's share of the light above the threshold
+    let total: Float = bloomed
+        .pixels()
+        .map(|p| (p.0[0] - 0.1) as Float)
+        .sum::<Float>()
+        - (100. - 0.1);
+    assert!((total - 0.2 * 99.).abs() < 0.5, "{total}");
+    // nothing blooms below the threshold, though exposing it up first can bring it over
+    let dim = Rgb32FImage::from_pixel(8, 8, Rgb([0.5; 3]));
+    assert_eq!(Pipeline::new().then(bloom).develop(&dim), dim);
+    let exposed = Pipeline::new().then(Exposure {
+        stops: 2.,
+        auto: false,
+    });
+    let glowing = exposed.then(bloom).develop(&dim);
+    assert!(glowing.get_pixel(0, 0).0[0] > 2.);
+}
+
+#[test]
+fn dithering_hides_banding() {
+    // a shade between two steps of 8 bits rounds to the nearer of them everywhere, but
+    // dithered, the steps around it average out to it
+    let shade = 100.3 / 255.;
+    let image = Rgb32FImage::from_pixel(64, 64, Rgb([shade as f32; 3]));
+    let dithered = Pipeline::new().then(Dither::default()).develop(&image);
+    let steps: Vec<Float> = dithered
+        .pixels()
+        .flat_map(|p| p.0)
+        .map(|c| (c * 255.).round() as Float)
+        .collect();
+    let average = steps.iter().sum::<Float>() / steps.len() as Float;
+    assert!((average - 100.3).abs() < 0.02, "{average}");
+    // never more than a step off
+    assert!(steps.iter().all(|&s| (99. ..=102.).contains(&s)));
+    // the same every time
+    assert_eq!(
+        Pipeline::new().then(Dither::default()).develop(&image),
+        dithered
+    );
+
+    let mut edges = Rgb32FImage::new(8, 8);
+    edges.put_pixel(1, 1, Rgb([1.; 3]));
+    assert_eq!(
+        Pipeline::new().then(Dither::default()).develop(&edges),
+        edges
+    );
+    let sixteen = Pipeline::new()
+        .then(Dither::new(BitDepth::Sixteen))
+        .develop(&image);
+    assert!(sixteen
+        .pixels()
+        .flat_map(|p| p.0)
+        .all(|c| (c - shade as f32).abs() < 1e-4));
+}
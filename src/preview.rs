@@ -0,0 +1,137 @@
+use crate::integrator::PathTracer;
+use crate::math::{Float, Mat4, Normalize};
+use crate::post::Pipeline;
+use crate::render::Film;
+use crate::rt::{View, World};
+use minifb::{Key, KeyRepeat, MouseButton, MouseMode, Window, WindowOptions};
+use std::time::Instant;
+
+/// bounces traced while previewing, to keep each pass quick
+const PREVIEW_DEPTH: u32 = 8;
+/// degrees the view orbits per pixel the mouse is dragged
+const ORBIT_SPEED: Float = 0.3;
+/// fraction of the distance to the look-at point moved per second
+const MOVE_SPEED: Float = 0.5;
+
+/// opens a window that renders the world progressively from `view`, refining it while the
+/// camera stays put. wasd moves, q and e sink and rise, dragging the mouse orbits the look-at
+/// point and scrolling zooms towards it. enter or escape closes the window and returns the view.
+/// each pass is developed by `pipeline` to be shown
+pub fn preview(view: View, world: &World, width: u32, height: u32, pipeline: &Pipeline) -> View {
+    let mut window = match Window::new(
+        "saraytracer preview - wasd/qe move, drag to orbit, scroll to zoom, enter to render",
+        width as usize,
+        height as usize,
+        WindowOptions::default(),
+    ) {
+        Ok(window) => window,
+        Err(e) => {
+            eprintln!("could not open the preview window, rendering as is: {e}");
+            return view;
+        }
+    };
+    window.set_target_fps(60);
+
+    let aspect_ratio = width as Float / height as Float;
+    let mut view = view;
+    let mut film = Film::new(width, height, false);
+    let mut buffer = vec![0u32; (width * height) as usize];
+    let mut last_mouse: Option<(f32, f32)> = None;
+    let mut last_frame = Instant::now();
+
+    while window.is_open()
+        && !window.is_key_pressed(Key::Enter, KeyRepeat::No)
+        && !window.is_key_down(Key::Escape)
+    {
+        let elapsed = last_frame.elapsed().as_secs_f64() as Float;
+        last_frame = Instant::now();
+        let moved = navigate(&window, &mut view, &mut last_mouse, elapsed);
+        if moved {
+            film = Film::new(width, height, false);
+        }
+
+        let camera = view.autofocus(world, aspect_ratio).camera(aspect_ratio);
+        film.render_pass(&camera, world, &PathTracer::new(PREVIEW_DEPTH), 1);
+        let image = pipeline.develop(&film.color());
+        for (out, pixel) in buffer.iter_mut().zip(image.pixels()) {
+            let [r, g, b] = pixel.0.map(|c| (c.clamp(0., 1.) * 255.) as u32);
+            *out = (r << 16) | (g << 8) | b;
+        }
+        if window
+            .update_with_buffer(&buffer, width as usize, height as usize)
+            .is_err()
+        {
+            break;
+        }
+    }
+
+    println!(
+        "view: eye {:?}, look at {:?}, vfov {}",
+        view.eye, view.look_at, view.vfov
+    );
+    view
+}
+
+/// moves the view by the keys and mouse, returning whether it changed
+fn navigate(
+    window: &Window,
+    view: &mut View,
+    last_mouse: &mut Option<(f32, f32)>,
+    elapsed: Float,
+) -> bool {
+    let mut moved = false;
+    let offset = view.eye - view.look_at;
+    let distance = offset.length();
+    let forward = -offset.normalize();
+    let right = forward.cross(view.up).normalize();
+
+    // fly both the eye and the point it looks at
+    let step = MOVE_SPEED * distance * elapsed;
+    let keys = [
+        (Key::W, forward),
+        (Key::S, -forward),
+        (Key::D, right),
+        (Key::A, -right),
+        (Key::E, view.up),
+        (Key::Q, -view.up),
+    ];
+    for (key, direction) in keys {
+        if window.is_key_down(key) {
+            view.eye += step * direction;
+            view.look_at += step * direction;
+            moved = true;
+        }
+    }
+
+    // orbit around the look-at point
+    let mouse = window.get_mouse_pos(MouseMode::Discard);
+    if window.get_mouse_down(MouseButton::Left) {
+        if let (Some((x, y)), Some((last_x, last_y))) = (mouse, *last_mouse) {
+            let (dx, dy) = ((x - last_x) as Float, (y - last_y) as Float);
+            if dx != 0. || dy != 0. {
+                let yaw = Mat4::rotate(view.up, -dx * ORBIT_SPEED);
+                let offset = yaw.transform_vector(view.eye - view.look_at);
+                let pitched = Mat4::rotate(right, -dy * ORBIT_SPEED).transform_vector(offset);
+                // stop short of flipping over the top or bottom
+                let offset = if pitched.normalize().dot(view.up).abs() < 0.99 {
+                    pitched
+                } else {
+                    offset
+                };
+                view.eye = view.look_at + offset;
+                moved = true;
+            }
+        }
+    }
+    *last_mouse = mouse;
+
+    // zoom towards the look-at point
+    if let Some((_, scroll)) = window.get_scroll_wheel() {
+        if scroll != 0. {
+            let scale = (1. - 0.1 * scroll.signum() as Float).max(0.1);
+            view.eye = view.look_at + scale * (view.eye - view.look_at);
+            moved = true;
+        }
+    }
+    moved
+}
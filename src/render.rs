@@ -0,0 +1,669 @@
+use crate::integrator::Integrator;
+use crate::math::{
+    end_sequence, mix_seed, random, seed_rng, start_sequence, with_rng, Float, Sampler, Vec3,
+};
+use crate::rt::{
+    sample_wavelength, wavelength_color, Camera, Color, Footprint, Material, Object, Ray, Shape,
+    World,
+};
+use image::{Rgb, Rgb32FImage, Rgba, Rgba32FImage};
+#[cfg(not(target_arch = "wasm32"))]
+use rayon::prelude::*;
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Write},
+    ops::Range,
+};
+
+/// first bytes of a checkpoint file, with its format version
+const CHECKPOINT_MAGIC: &[u8; 8] = b"SRTCKPT5";
+/// bytes of the sums a checkpoint holds for each pixel: three colors' worth of f64s for the
+/// color, albedo and normal, then the depth and the sum of squares
+const CHECKPOINT_PIXEL_BYTES: u64 = 11 * 8;
+
+/// sub-pixel offsets in [0, 1) for `samples` samples of a pixel, jittered within the cells of
+/// an n×n grid over it so they spread out evenly. every sample lands in a different cell, picked
+/// at random when there are more cells than samples, so square numbers of samples fill the grid
+pub fn stratified_offsets(samples: u32) -> impl Iterator<Item = (Float, Float)> {
+    let n = (samples as Float).sqrt().ceil() as usize;
+    let cells = with_rng(|rng| rand::seq::index::sample(rng, n * n, samples as usize));
+    cells.into_iter().map(move |cell| {
+        let (cx, cy) = (cell % n, cell / n);
+        let (rx, ry): (Float, Float) = (random(), random());
+        (
+            (cx as Float + rx) / n as Float,
+            (cy as Float + ry) / n as Float,
+        )
+    })
+}
+
+/// first-hit surface properties, rendered alongside the image for denoisers and compositing
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Aov {
+    pub albedo: Color,
+    /// shading normal, facing the camera
+    pub normal: Vec3,
+    /// distance from the camera. infinite where nothing was hit
+    pub depth: Float,
+    /// `Object::id` of the object hit
+    pub object: Option<u32>,
+}
+
+impl Aov {
+    /// properties of the first surface the ray hits
+    pub fn trace(ray: Ray, world: &World) -> Self {
+        match world.hit(ray, 0.0..Float::INFINITY) {
+            Some(contact) => Self {
+                albedo: contact.material.albedo(&contact),
+                normal: contact.normal,
+                depth: contact.t * ray.direction.length(),
+                object: contact.object.and_then(|o| world.get(o)).map(Object::id),
+            },
+            None => Self {
+                albedo: world.background.color(ray),
+                normal: Vec3::ZERO,
+                depth: Float::INFINITY,
+                object: None,
+            },
+        }
+    }
+}
+
+/// running sums of every sample taken of each pixel, so a render can be refined pass by pass
+/// and looked at in between
+pub struct Film {
+    pub width: u32,
+    pub height: u32,
+    /// rows of the image the film covers, from the top. all of them,
+    /// unless it's a tile of a render split up between machines
+    pub rows: Range<u32>,
+    /// samples taken of every pixel so far
+    pub samples: u32,
+    /// whether passes also trace the first-hit aovs
+    pub aovs: bool,
+    /// every pixel's random numbers are derived from this, so the same film, scene and passes
+    /// always render the same image
+    pub seed: u64,
+    /// where the random numbers of each sample come from
+    pub sampler: Sampler,
+    /// whether each sample follows a single wavelength of light, so dispersive glass can
+    /// split it into colors
+    pub spectral: bool,
+    /// per pixel sums, row by row from the top of the image
+    color: Vec<Color>,
+    albedo: Vec<Color>,
+    normal: Vec<Vec3>,
+    /// the nearest any sample of each pixel found, infinite until one hits something
+    depth: Vec<Float>,
+    /// per pixel sums of each sample's luminance squared, for how much the samples vary
+    squares: Vec<Float>,
+    /// ids of the objects each pixel's samples hit first, with how many samples hit each
+    objects: Vec<Vec<(u32, u32)>>,
+}
+
+impl Film {
+    /// constructor for an empty film
+    pub fn new(width: u32, height: u32, aovs: bool) -> Self {
+        Self::tile(width, height, 0..height, aovs)
+    }
+
+    /// constructor for an empty film of just some rows of the image. its pixels
+    /// come out the same as those rows of a whole film rendered with the same settings
+    pub fn tile(width: u32, height: u32, rows: Range<u32>, aovs: bool) -> Self {
+        let pixels = (width * rows.len() as u32) as usize;
+        Self {
+            width,
+            height,
+            rows,
+            samples: 0,
+            aovs,
+            seed: 0,
+            sampler: Sampler::default(),
+            spectral: false,
+            color: vec![Color::BLACK; pixels],
+            albedo: vec![Color::BLACK; pixels],
+            normal: vec![Vec3::ZERO; pixels],
+            depth: vec![Float::INFINITY; pixels],
+            squares: vec![0.; pixels],
+            objects: vec![vec![]; pixels],
+        }
+    }
+
+    /// takes `samples` more samples of every pixel, each the color `integrator` finds
+    pub fn render_pass<C, I>(&mut self, camera: &C, world: &World, integrator: &I, samples: u32)
+    where
+        C: Camera + Sync,
+        I: Integrator + Sync,
+    {
+        let (width, height) = (self.width, self.height);
+        let offset = (self.rows.start * width) as usize;
+        let (aovs, sampler, spectral) = (self.aovs, self.sampler, self.spectral);
+        let (first, seed) = (self.samples, mix_seed(self.seed, self.samples as u64));
+        // scrambles each pixel's sequence the same way every pass, so passes carry on along it
+        let scramble = !self.seed;
+        let footprint = Footprint::spreading(camera.pixel_spread(height));
+        let sample_pixel = |i: usize,
+                            color: &mut Color,
+                            albedo: &mut Color,
+                            normal: &mut Vec3,
+                            depth: &mut Float,
+                            square: &mut Float,
+                            objects: &mut Vec<(u32, u32)>| {
+            // a pass draws the same numbers whichever thread it lands on
+            seed_rng(mix_seed(seed, i as u64));
+            let x = i as u32 % width;
+            // screen space runs from the bottom up
+            let y = height - 1 - i as u32 / width;
+            let mut offsets = stratified_offsets(samples);
+            for n in first..first + samples {
+                start_sequence(sampler, n as u64, (x, y), mix_seed(scramble, i as u64));
+                let (rx, ry) = match sampler {
+                    Sampler::Stratified => offsets.next().unwrap(),
+                    // the sequences spread out their first two dimensions by themselves
+                    _ => (random(), random()),
+                };
+                let (px, py) = (x as Float, y as Float);
+                let dx = (px + rx) / ((width - 1) as Float);
+                let dy = (py + ry) / ((height - 1) as Float);
+                let mut r = Ray {
+                    footprint,
+                    ..camera.get_screen_ray(dx, dy)
+                };
+                let sample = if spectral {
+                    let wavelength = sample_wavelength();
+                    r.wavelength = Some(wavelength);
+                    wavelength_color(wavelength) * integrator.color(r, world)
+                } else {
+                    integrator.color(r, world)
+                };
+                *color += sample;
+                *square += sample.luminance() * sample.luminance();
+                if aovs {
+                    let hit = Aov::trace(r, world);
+                    *albedo += hit.albedo;
+                    *normal += hit.normal;
+                    *depth = depth.min(hit.depth);
+                    if let Some(id) = hit.object {
+                        match objects.iter_mut().find(|(seen, _)| *seen == id) {
+                            Some((_, hits)) => *hits += 1,
+                            None => objects.push((id, 1)),
+                        }
+                    }
+                }
+            }
+            end_sequence();
+        };
+        // a task per row rather than per pixel, so threads spend their time tracing rather
+        // than being handed work. there are no threads to spread rows over in the browser
+        let row = width as usize;
+        #[cfg(not(target_arch = "wasm32"))]
+        let rows = self
+            .color
+            .par_chunks_mut(row)
+            .zip(self.albedo.par_chunks_mut(row))
+            .zip(self.normal.par_chunks_mut(row))
+            .zip(self.depth.par_chunks_mut(row))
+            .zip(self.squares.par_chunks_mut(row))
+            .zip(self.objects.par_chunks_mut(row));
+        #[cfg(target_arch = "wasm32")]
+        let rows = self
+            .color
+            .chunks_mut(row)
+            .zip(self.albedo.chunks_mut(row))
+            .zip(self.normal.chunks_mut(row))
+            .zip(self.depth.chunks_mut(row))
+            .zip(self.squares.chunks_mut(row))
+            .zip(self.objects.chunks_mut(row));
+        rows.enumerate().for_each(
+            |(y, (((((color, albedo), normal), depth), squares), objects))| {
+                let start = offset + y * row;
+                let pixels = color
+                    .iter_mut()
+                    .zip(albedo.iter_mut())
+                    .zip(normal.iter_mut())
+                    .zip(depth.iter_mut())
+                    .zip(squares.iter_mut())
+                    .zip(objects.iter_mut());
+                for (x, (((((color, albedo), normal), depth), square), objects)) in
+                    pixels.enumerate()
+                {
+                    sample_pixel(start + x, color, albedo, normal, depth, square, objects);
+                }
+            },
+        );
+        self.samples += samples;
+    }
+
+    /// writes the sums and sample count to `path`, for `open_checkpoint` to resume from.
+    /// the file is written next to it and moved into place, so an interrupted save keeps the old one
+    pub fn save_checkpoint(&self, path: &str) -> io::Result<()> {
+        let partial = format!("{path}.partial");
+        let mut file = BufWriter::new(File::create(&partial)?);
+        self.write_checkpoint(&mut file)?;
+        file.flush()?;
+        drop(file);
+        fs::rename(partial, path)
+    }
+
+    /// writes the film in the format of `save_checkpoint`.
+    /// sums are stored as f64 whatever precision they were rendered in
+    pub fn write_checkpoint(&self, mut file: impl Write) -> io::Result<()> {
+        file.write_all(CHECKPOINT_MAGIC)?;
+        let (width, height, rows) = (self.width, self.height, &self.rows);
+        for n in [
+            width,
+            height,
+            rows.start,
+            rows.end,
+            self.samples,
+            self.aovs as u32,
+        ] {
+            file.write_all(&n.to_le_bytes())?;
+        }
+        file.write_all(&self.seed.to_le_bytes())?;
+        let colors = self
+            .color
+            .iter()
+            .chain(&self.albedo)
+            .map(|&c| Vec3::from(c));
+        for v in colors.chain(self.normal.iter().copied()) {
+            for c in [v.x, v.y, v.z] {
+                file.write_all(&(c as f64).to_le_bytes())?;
+            }
+        }
+        for d in self.depth.iter().chain(&self.squares) {
+            file.write_all(&(*d as f64).to_le_bytes())?;
+        }
+        for objects in &self.objects {
+            file.write_all(&(objects.len() as u32).to_le_bytes())?;
+            for &(id, hits) in objects {
+                file.write_all(&id.to_le_bytes())?;
+                file.write_all(&hits.to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// reads a film saved by `save_checkpoint`, to carry on adding samples to it.
+    /// passes of the same size pick up the random numbers where it left off,
+    /// so the result matches an uninterrupted render
+    pub fn open_checkpoint(path: &str) -> io::Result<Self> {
+        Self::read_checkpoint(BufReader::new(File::open(path)?))
+    }
+
+    /// reads a film written by `write_checkpoint`
+    pub fn read_checkpoint(file: impl Read) -> io::Result<Self> {
+        Self::read_checkpoint_of(file, None)
+    }
+
+    /// reads a checkpoint of the tile of `rows` of a `width` by `height` image, failing on one
+    /// of anything else before reading its sums
+    pub fn read_tile(
+        file: impl Read,
+        width: u32,
+        height: u32,
+        rows: Range<u32>,
+    ) -> io::Result<Self> {
+        Self::read_checkpoint_of(file, Some((width, height, rows)))
+    }
+
+    /// reads a checkpoint, of the given width, height and rows if there are any
+    fn read_checkpoint_of(
+        mut file: impl Read,
+        tile: Option<(u32, u32, Range<u32>)>,
+    ) -> io::Result<Self> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+        let mut magic = [0; 8];
+        file.read_exact(&mut magic)?;
+        if &magic != CHECKPOINT_MAGIC {
+            return Err(invalid("not a render checkpoint"));
+        }
+        let mut u32s = [0u32; 6];
+        for n in &mut u32s {
+            let mut bytes = [0; 4];
+            file.read_exact(&mut bytes)?;
+            *n = u32::from_le_bytes(bytes);
+        }
+        let [width, height, start, end, samples, aovs] = u32s;
+        if start > end || end > height {
+            return Err(invalid("checkpoint rows are out of the image"));
+        }
+        if tile.is_some_and(|tile| tile != (width, height, start..end)) {
+            return Err(invalid("checkpoint is of a different tile"));
+        }
+        let mut seed = [0; 8];
+        file.read_exact(&mut seed)?;
+
+        // the sums are read before the film's made for them, so a header that claims more
+        // pixels than the file holds fails rather than allocating for them
+        let size = (width as u64 * (end - start) as u64)
+            .checked_mul(CHECKPOINT_PIXEL_BYTES)
+            .filter(|&size| usize::try_from(size).is_ok())
+            .ok_or_else(|| invalid("checkpoint is too large"))?;
+        let mut sums = vec![];
+        file.by_ref().take(size).read_to_end(&mut sums)?;
+        if sums.len() as u64 != size {
+            return Err(invalid("checkpoint is cut short"));
+        }
+        let mut sums = sums
+            .chunks_exact(8)
+            .map(|bytes| f64::from_le_bytes(bytes.try_into().unwrap()) as Float);
+        let mut next = || sums.next().unwrap();
+
+        let mut film = Film::tile(width, height, start..end, aovs != 0);
+        film.samples = samples;
+        film.seed = u64::from_le_bytes(seed);
+        for c in film.color.iter_mut().chain(&mut film.albedo) {
+            *c = Color::new(next(), next(), next());
+        }
+        for v in &mut film.normal {
+            *v = Vec3::new(next(), next(), next());
+        }
+        for d in film.depth.iter_mut().chain(&mut film.squares) {
+            *d = next();
+        }
+        let mut next = || -> io::Result<u32> {
+            let mut bytes = [0; 4];
+            file.read_exact(&mut bytes)?;
+            Ok(u32::from_le_bytes(bytes))
+        };
+        for objects in &mut film.objects {
+            for _ in 0..next()? {
+                objects.push((next()?, next()?));
+            }
+        }
+        Ok(film)
+    }
+
+    /// copies the sums of a tile of the same image into this film, along with its sample count.
+    /// a render split into tiles is whole once every tile's been inserted
+    pub fn insert(&mut self, tile: &Film) -> io::Result<()> {
+        let fits = (tile.width, tile.height) == (self.width, self.height)
+            && tile.rows.start >= self.rows.start
+            && tile.rows.end <= self.rows.end
+            && (tile.aovs || !self.aovs);
+        if !fits {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "tile doesn't fit in the film",
+            ));
+        }
+        let start = ((tile.rows.start - self.rows.start) * self.width) as usize;
+        let pixels = start..start + tile.color.len();
+        self.color[pixels.clone()].copy_from_slice(&tile.color);
+        self.squares[pixels.clone()].copy_from_slice(&tile.squares);
+        if self.aovs {
+            self.albedo[pixels.clone()].copy_from_slice(&tile.albedo);
+            self.normal[pixels.clone()].copy_from_slice(&tile.normal);
+            self.depth[pixels.clone()].copy_from_slice(&tile.depth);
+            self.objects[pixels].clone_from_slice(&tile.objects);
+        }
+        self.samples = tile.samples;
+        Ok(())
+    }
+
+    /// the average of a per pixel sum, as an image of the rows the film covers
+    fn average<T: Copy>(&self, sums: &[T], rgb: impl Fn(T) -> [Float; 3]) -> Rgb32FImage {
+        let n = self.samples.max(1) as Float;
+        Rgb32FImage::from_fn(self.width, self.rows.len() as u32, |x, y| {
+            let sum = rgb(sums[(y * self.width + x) as usize]);
+            Rgb(sum.map(|c| (c / n) as f32))
+        })
+    }
+
+    /// current estimate of the linear radiance reaching each pixel
+    pub fn color(&self) -> Rgb32FImage {
+        self.average(&self.color, |c| [c.r, c.g, c.b])
+    }
+
+    pub fn albedo(&self) -> Rgb32FImage {
+        self.average(&self.albedo, |c| [c.r, c.g, c.b])
+    }
+
+    pub fn normal(&self) -> Rgb32FImage {
+        self.average(&self.normal, |n| [n.x, n.y, n.z])
+    }
+
+    /// distance to the nearest surface seen through each pixel, infinite where every sample
+    /// missed
+    pub fn depth(&self) -> Rgb32FImage {
+        Rgb32FImage::from_fn(self.width, self.rows.len() as u32, |x, y| {
+            Rgb([self.depth[(y * self.width + x) as usize] as f32; 3])
+        })
+    }
+
+    /// the standard error of each pixel's luminance, relative to the luminance itself, in every
+    /// channel: how far off the estimate is likely to be, as a fraction of it. more samples
+    /// bring it down by the square root of their number. it's 0 for black pixels, where there's
+    /// nothing to be off by, and before there are two samples to compare
+    pub fn noise(&self) -> Rgb32FImage {
+        let n = self.samples as Float;
+        Rgb32FImage::from_fn(self.width, self.rows.len() as u32, |x, y| {
+            let i = (y * self.width + x) as usize;
+            let mean = self.color[i].luminance() / n;
+            if self.samples < 2 || mean <= 0. {
+                return Rgb([0.; 3]);
+            }
+            let variance = ((self.squares[i] - n * mean * mean) / (n - 1.)).max(0.);
+            Rgb([((variance / n).sqrt() / mean) as f32; 3])
+        })
+    }
+
+    /// the objects a pixel's samples hit, with the fraction of them that hit each, most first
+    fn coverage(&self, x: u32, y: u32) -> Vec<(u32, Float)> {
+        let n = self.samples.max(1) as Float;
+        let mut objects = self.objects[(y * self.width + x) as usize].clone();
+        objects.sort_by_key(|&(id, hits)| (std::cmp::Reverse(hits), id));
+        objects
+            .into_iter()
+            .map(|(id, hits)| (id, hits as Float / n))
+            .collect()
+    }
+
+    /// each pixel in a color of its own for the object covering most of it, and black where
+    /// there's none, for telling objects apart by eye
+    pub fn object_ids(&self) -> Rgb32FImage {
+        Rgb32FImage::from_fn(self.width, self.rows.len() as u32, |x, y| {
+            let id = self.coverage(x, y).first().map_or(0, |&(id, _)| id);
+            Rgb([id >> 16, id >> 8, id].map(|byte| (byte & 0xff) as f32 / 255.))
+        })
+    }
+
+    /// a layer of cryptomatte ranks: the ids, as floats of the same bits, and coverage of the
+    /// two objects ranked `2 * layer` and `2 * layer + 1` by how much of each pixel they cover.
+    /// compositors matte an object out by adding up its coverage over the layers
+    pub fn cryptomatte(&self, layer: usize) -> Rgba32FImage {
+        Rgba32FImage::from_fn(self.width, self.rows.len() as u32, |x, y| {
+            let coverage = self.coverage(x, y);
+            let rank = |rank: usize| {
+                coverage
+                    .get(rank)
+                    .map_or([0.; 2], |&(id, part)| [f32::from_bits(id), part as f32])
+            };
+            let ([id0, part0], [id1, part1]) = (rank(2 * layer), rank(2 * layer + 1));
+            Rgba([id0, part0, id1, part1])
+        })
+    }
+}
+
+#[test]
+fn checkpoint_round_trip() {
+    let mut film = Film::new(3, 2, true);
+    film.samples = 7;
+    film.seed = 42;
+    film.color[1] = Color::new(1., 2., 3.);
+    film.normal[4] = Vec3::new(-1., 0.5, 0.25);
+    film.depth[5] = Float::INFINITY;
+    film.squares[3] = 12.5;
+    film.objects[2] = vec![(9, 4), (3, 2)];
+    let path = std::env::temp_dir().join("raytracer_checkpoint_round_trip.ckpt");
+    let path = path.to_str().unwrap();
+
+    film.save_checkpoint(path).unwrap();
+    let resumed = Film::open_checkpoint(path).unwrap();
+    fs::remove_file(path).unwrap();
+    assert_eq!((resumed.width, resumed.height), (3, 2));
+    assert_eq!((resumed.samples, resumed.aovs, resumed.seed), (7, true, 42));
+    assert_eq!(resumed.color, film.color);
+    assert_eq!(resumed.albedo, film.albedo);
+    assert_eq!(resumed.normal, film.normal);
+    assert_eq!(resumed.depth, film.depth);
+    assert_eq!(resumed.squares, film.squares);
+    assert_eq!(resumed.objects, film.objects);
+}
+
+#[test]
+fn object_passes() {
+    use crate::integrator::Normals;
+    use crate::rt::{Diffuse, FixedCamera, Sphere};
+    let mut world = World::new();
+    for (name, x) in [("left", -1.), ("right", 1.)] {
+        world.insert_named(
+            name,
+            Sphere::new(Vec3::new(x, 0., 0.), 1., Diffuse::from(Color::WHITE)),
+        );
+    }
+    let camera = FixedCamera::new(Vec3::new(0., 0., -5.), Vec3::ZERO, Vec3::Y, 2., 40., 0., 5.);
+    let mut film = Film::new(16, 8, true);
+    film.render_pass(&camera, &world, &Normals, 16);
+
+    let id = |name| world.find(name).unwrap().id();
+    let ids = film.cryptomatte(0);
+    // the middle column straddles both spheres, the corners see neither
+    let middle = ids.get_pixel(7, 4).0;
+    assert!(middle[1] > 0. && middle[3] > 0. && middle[1] + middle[3] <= 1.);
+    let mut pair = [middle[0].to_bits(), middle[2].to_bits()];
+    pair.sort();
+    let mut expected = [id("left"), id("right")];
+    expected.sort();
+    assert_eq!(pair, expected);
+    assert_eq!(ids.get_pixel(0, 0).0, [0.; 4]);
+    assert_eq!(film.cryptomatte(1).get_pixel(7, 4).0, [0.; 4]);
+    // depth is the nearest any sample found, so pixels only partly covered aren't pushed away
+    let depth = film.depth();
+    assert!(depth.get_pixel(0, 0).0[0].is_infinite());
+    let mut edges = 0;
+    for (x, y) in (0..16).flat_map(|x| (0..8).map(move |y| (x, y))) {
+        let [_, a, _, b] = ids.get_pixel(x, y).0;
+        if a + b > 0. && a + b < 1. {
+            let d = depth.get_pixel(x, y).0[0];
+            assert!(d > 3. && d < 6., "{d}");
+            edges += 1;
+        }
+    }
+    assert!(edges > 0);
+    // the left of the image sees the sphere on the right, as the camera looks down +z
+    let covered = ids.get_pixel(4, 4).0;
+    assert_eq!((covered[0].to_bits(), covered[1]), (id("right"), 1.));
+    assert_ne!(
+        film.object_ids().get_pixel(4, 4),
+        film.object_ids().get_pixel(12, 4)
+    );
+}
+
+#[test]
+fn seeded_renders_match() {
+    use crate::integrator::PathTracer;
+    use crate::rt::*;
+    let scene = SceneBuilder::new()
+        .look_at(Vec3::new(0., 0., -4.), Vec3::ZERO)
+        .sphere(Vec3::ZERO, 1.)
+        .material(Dielectric::rough(1.5, 0.2))
+        .sphere(Vec3::new(0., -101., 0.), 100.)
+        .diffuse(Color::new(0.5, 0.5, 0.5))
+        .build();
+    let camera = scene.view.camera(1.);
+    let render = |seed, passes: &[u32]| {
+        let mut film = Film::new(8, 8, true);
+        film.seed = seed;
+        for &samples in passes {
+            film.render_pass(&camera, &scene.world, &PathTracer::new(10), samples);
+        }
+        film
+    };
+
+    let film = render(3, &[2, 2]);
+    let again = render(3, &[2, 2]);
+    assert_eq!(film.color, again.color);
+    assert_eq!(film.depth, again.depth);
+    assert_ne!(film.color, render(4, &[2, 2]).color);
+
+    // tiles rendered apart and sent over as checkpoints come out the same as the whole film
+    let mut tiled = Film::new(8, 8, true);
+    for rows in [0..3, 3..8] {
+        let mut tile = Film::tile(8, 8, rows.clone(), true);
+        tile.seed = 3;
+        for samples in [2, 2] {
+            tile.render_pass(&camera, &scene.world, &PathTracer::new(10), samples);
+        }
+        let mut bytes = vec![];
+        tile.write_checkpoint(&mut bytes).unwrap();
+        tiled
+            .insert(&Film::read_tile(&bytes[..], 8, 8, rows).unwrap())
+            .unwrap();
+        assert!(Film::read_tile(&bytes[..], 8, 8, 0..8).is_err());
+    }
+    assert_eq!(tiled.samples, 4);
+    assert_eq!(tiled.color, film.color);
+    assert_eq!(tiled.normal, film.normal);
+
+    // a header claiming more pixels than follow it is caught before they're allocated for
+    let mut bytes = vec![];
+    film.write_checkpoint(&mut bytes).unwrap();
+    let cut = Film::read_checkpoint(&bytes[..bytes.len() / 2]);
+    assert_eq!(cut.err().unwrap().kind(), io::ErrorKind::InvalidData);
+    bytes[8..12].copy_from_slice(&u32::MAX.to_le_bytes());
+    bytes[12..16].copy_from_slice(&u32::MAX.to_le_bytes());
+    bytes[20..24].copy_from_slice(&u32::MAX.to_le_bytes());
+    let huge = Film::read_checkpoint(&bytes[..]);
+    assert_eq!(huge.err().unwrap().kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn stratified_offsets_fill_the_grid() {
+    let mut cells: Vec<_> = stratified_offsets(9)
+        .map(|(x, y)| ((x * 3.) as u32, (y * 3.) as u32))
+        .collect();
+    cells.sort();
+    let grid: Vec<_> = (0..3).flat_map(|x| (0..3).map(move |y| (x, y))).collect();
+    assert_eq!(cells, grid);
+
+    let cells: Vec<_> = stratified_offsets(5)
+        .map(|(x, y)| ((x * 3.) as u32, (y * 3.) as u32))
+        .collect();
+    assert_eq!(cells.len(), 5);
+    assert!(cells
+        .iter()
+        .enumerate()
+        .all(|(i, c)| !cells[..i].contains(c)));
+}
+
+#[test]
+fn noise_estimate() {
+    use crate::integrator::PathTracer;
+    use crate::rt::*;
+    // samples of 0, 2, 0 and 2 average to 1, with a variance of 4 / 3 between them
+    let mut film = Film::new(2, 1, false);
+    film.samples = 4;
+    film.color[0] = Color::WHITE * 4.;
+    film.squares[0] = 8.;
+    let noise = film.noise();
+    assert!((noise.get_pixel(0, 0).0[0] as Float - (1. / 3. as Float).sqrt()).abs() < 1e-6);
+    assert_eq!(noise.get_pixel(1, 0).0, [0.; 3]);
+
+    // sixteen times the samples bring it down to about a quarter
+    let scene = SceneBuilder::new()
+        .look_at(Vec3::new(0., 0., -4.), Vec3::ZERO)
+        .sphere(Vec3::ZERO, 1.)
+        .diffuse(Color::new(0.5, 0.5, 0.5))
+        .build();
+    let camera = scene.view.camera(1.);
+    let average = |samples| {
+        let mut film = Film::new(8, 8, false);
+        film.render_pass(&camera, &scene.world, &PathTracer::new(10), samples);
+        let noise = film.noise();
+        noise.pixels().map(|p| p.0[0] as Float).sum::<Float>() / 64.
+    };
+    let ratio = average(64) / average(4);
+    assert!((0.15..0.35).contains(&ratio), "{ratio}");
+}
@@ -1,39 +1,114 @@
-use std::ops::Range;
+use std::{any::Any, ops::Range, sync::Arc};
 
-use crate::math::{Normalize, Vec3};
+use crate::math::{Float, Normalize, Vec3};
+use crate::stats::{count, Counter};
 
+mod background;
+mod builder;
 mod camera;
+mod color;
+mod csg;
+mod expr;
+mod instance;
+mod light;
 mod material;
+mod medium;
+mod mesh;
+pub mod scenes;
+mod sdf;
 mod shape;
+mod spectrum;
+mod sun;
+mod texture;
+mod tonemap;
 
+pub use background::*;
+pub use builder::*;
 pub use camera::*;
+pub use color::*;
+pub use csg::*;
+pub use expr::*;
+pub use instance::*;
+pub use light::*;
 pub use material::*;
+pub use medium::*;
+pub use mesh::*;
+pub use sdf::*;
 pub use shape::*;
-
-pub type Color = Vec3;
-impl Color {
-    pub const WHITE: Self = Self::ONE;
-    pub const BLACK: Self = Self::ZERO;
-    pub const GRAY: Self = Self::new(0.5, 0.5, 0.5);
-    pub const RED: Self = Self::X;
-    pub const GREEN: Self = Self::Y;
-    pub const BLUE: Self = Self::Z;
-}
+pub use spectrum::*;
+pub use sun::*;
+pub use texture::*;
+pub use tonemap::*;
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Ray {
     pub origin: Vec3,
     pub direction: Vec3,
+    /// moment the ray was cast at, for animated shapes
+    pub time: Float,
+    /// in nanometers, when rendering spectrally. none carries red, green and blue together
+    pub wavelength: Option<Float>,
+    /// how wide the beam of light the ray stands for is, for filtering textures over
+    pub footprint: Footprint,
+}
+
+/// the beam around a ray, as a cone widening along it: a cheaper stand in for a pair of ray
+/// differentials, carried along a path to filter textures over the area of a pixel rather than
+/// point sampling them, which shimmers wherever a pixel takes in many texels
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Footprint {
+    /// across the beam where the ray starts
+    pub width: Float,
+    /// how much wider it gets per unit of distance along the ray, the angle it spreads at
+    pub spread: Float,
+}
+
+impl Footprint {
+    /// a beam starting at a point, as from a pinhole, spreading at `spread` radians
+    pub fn spreading(spread: Float) -> Self {
+        Self { width: 0., spread }
+    }
+
+    /// width of the beam `distance` along it
+    pub fn width_at(&self, distance: Float) -> Float {
+        self.width + self.spread * distance
+    }
 }
 
 impl Ray {
     /// constructor
     pub fn new(origin: Vec3, direction: Vec3) -> Ray {
-        Ray { origin, direction }
+        Ray::timed(origin, direction, 0.)
+    }
+
+    /// constructor for a ray cast at the given time
+    pub fn timed(origin: Vec3, direction: Vec3, time: Float) -> Ray {
+        Ray {
+            origin,
+            direction,
+            time,
+            wavelength: None,
+            footprint: Footprint::default(),
+        }
+    }
+
+    /// a ray carrying on from this one, as scattered off a surface, at the same moment and
+    /// wavelength. its beam starts out as wide as this one's got by then, spreading as before
+    pub fn scattered(&self, origin: Vec3, direction: Vec3) -> Ray {
+        let width = self.footprint.width_at((origin - self.origin).length());
+        Ray {
+            origin,
+            direction,
+            footprint: Footprint {
+                width,
+                ..self.footprint
+            },
+            ..*self
+        }
     }
 
     /// computes the position after the ray travels t units in `direction` from `origin`
-    pub fn at(&self, t: f64) -> Vec3 {
+    pub fn at(&self, t: Float) -> Vec3 {
         self.origin + t * self.direction
     }
 }
@@ -42,42 +117,410 @@ impl Normalize for Ray {
     /// normalizes the ray direction. origin unaffected
     fn normalize(&self) -> Self {
         Ray {
-            origin: self.origin,
             direction: self.direction.normalize(),
+            ..*self
+        }
+    }
+}
+
+/// refers to an object in a world for as long as it's there. handles aren't reused, so one
+/// left over from a removed object finds nothing rather than whatever took its place
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Handle(u64);
+
+/// a shape in a world, and what it's known by
+pub struct Object {
+    pub shape: AnyShape,
+    /// for finding the object again with `World::find`
+    pub name: Option<String>,
+    handle: Handle,
+    /// index of the world light the shape is sampled as, if it's an area light
+    light: Option<usize>,
+}
+
+impl Object {
+    pub fn handle(&self) -> Handle {
+        self.handle
+    }
+
+    /// whether the object is also sampled directly as a light
+    pub fn is_light(&self) -> bool {
+        self.light.is_some()
+    }
+
+    /// what the object goes by in compositing: its name, or `object<n>` for the nth object
+    /// inserted into the world if it has none
+    pub fn label(&self) -> String {
+        match &self.name {
+            Some(name) => name.clone(),
+            None => format!("object{}", self.handle.0),
+        }
+    }
+
+    /// an id for picking the object out in compositing, the same from render to render. it's
+    /// the hash of the label cryptomatte takes, so its bits also make a float that's neither
+    /// infinite, nan nor denormal
+    pub fn id(&self) -> u32 {
+        let hash = murmur3(self.label().as_bytes(), 0);
+        let exponent = (hash >> 23) & 0xff;
+        if exponent == 0 || exponent == 0xff {
+            hash ^ (1 << 23)
+        } else {
+            hash
         }
     }
 }
 
-#[derive(Default)]
+/// the 32 bit murmurhash3 of `bytes`
+fn murmur3(bytes: &[u8], seed: u32) -> u32 {
+    const C1: u32 = 0xcc9e2d51;
+    const C2: u32 = 0x1b873593;
+    let scramble = |k: u32| k.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+    let mut hash = seed;
+    let mut blocks = bytes.chunks_exact(4);
+    for block in &mut blocks {
+        hash ^= scramble(u32::from_le_bytes([block[0], block[1], block[2], block[3]]));
+        hash = hash
+            .rotate_left(13)
+            .wrapping_mul(5)
+            .wrapping_add(0xe6546b64);
+    }
+    let tail = blocks.remainder();
+    if !tail.is_empty() {
+        hash ^= scramble(tail.iter().rev().fold(0, |k, &b| (k << 8) | b as u32));
+    }
+    hash ^= bytes.len() as u32;
+    hash ^= hash >> 16;
+    hash = hash.wrapping_mul(0x85ebca6b);
+    hash ^= hash >> 13;
+    hash = hash.wrapping_mul(0xc2b2ae35);
+    hash ^ (hash >> 16)
+}
+
 pub struct World {
-    pub shapes: Vec<Box<dyn Shape + Send + Sync + 'static>>,
+    /// in the order they were inserted
+    objects: Vec<Object>,
+    next_handle: u64,
+    pub background: Box<dyn Background + Send + Sync + 'static>,
+    /// lights that are sampled directly at every bounce
+    pub lights: Vec<Box<dyn Light + Send + Sync + 'static>>,
+    /// the light in `lights` sampling the background, if it's an environment map, to weigh
+    /// the rays that escape against it
+    pub(crate) environment: Option<usize>,
+}
+
+impl Default for World {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl World {
     pub fn new() -> Self {
-        Self { shapes: vec![] }
+        Self {
+            objects: vec![],
+            next_handle: 0,
+            background: Box::new(GradientBackground::default()),
+            lights: vec![],
+            environment: None,
+        }
+    }
+
+    pub fn insert<T: Shape + Send + Sync + 'static>(&mut self, shape: T) -> Handle {
+        self.push(AnyShape::new(shape), None)
+    }
+
+    /// inserts a shape that can be found again by `name`
+    pub fn insert_named<T>(&mut self, name: &str, shape: T) -> Handle
+    where
+        T: Shape + Send + Sync + 'static,
+    {
+        let handle = self.insert(shape);
+        self.objects.last_mut().unwrap().name = Some(name.to_string());
+        handle
+    }
+
+    fn push(&mut self, shape: AnyShape, light: Option<usize>) -> Handle {
+        let handle = Handle(self.next_handle);
+        self.next_handle += 1;
+        self.objects.push(Object {
+            shape,
+            name: None,
+            handle,
+            light,
+        });
+        handle
+    }
+
+    fn index(&self, handle: Handle) -> Option<usize> {
+        // handles only ever go up, so the objects stay sorted by them
+        self.objects
+            .binary_search_by_key(&handle, |object| object.handle)
+            .ok()
+    }
+
+    pub fn get(&self, handle: Handle) -> Option<&Object> {
+        self.objects.get(self.index(handle)?)
+    }
+
+    /// the object, to move or rename. an area light keeps sampling the shape it was inserted
+    /// with, so remove and insert those again instead of replacing their shape
+    pub fn get_mut(&mut self, handle: Handle) -> Option<&mut Object> {
+        let index = self.index(handle)?;
+        self.objects.get_mut(index)
+    }
+
+    /// takes an object out of the world, along with the light it's sampled as
+    pub fn remove(&mut self, handle: Handle) -> Option<Object> {
+        let object = self.objects.remove(self.index(handle)?);
+        if let Some(light) = object.light {
+            self.remove_light(light);
+        }
+        Some(object)
+    }
+
+    /// removes a sampled light, keeping track of the others as they move down
+    fn remove_light(&mut self, light: usize) {
+        self.lights.remove(light);
+        let indices = self.objects.iter_mut().map(|other| &mut other.light);
+        for index in indices.chain([&mut self.environment]) {
+            if let Some(index) = index.as_mut().filter(|index| **index > light) {
+                *index -= 1;
+            }
+        }
+    }
+
+    /// the first object with the given name
+    pub fn find(&self, name: &str) -> Option<&Object> {
+        self.objects
+            .iter()
+            .find(|object| object.name.as_deref() == Some(name))
+    }
+
+    /// every object, in the order they were inserted
+    pub fn objects(&self) -> impl Iterator<Item = &Object> {
+        self.objects.iter()
+    }
+
+    pub fn objects_mut(&mut self) -> impl Iterator<Item = &mut Object> {
+        self.objects.iter_mut()
+    }
+
+    /// number of objects
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    pub fn add_light<T: Light + Send + Sync + 'static>(&mut self, light: T) {
+        self.lights.push(Box::new(light));
+    }
+
+    /// inserts an emissive shape that is also sampled directly as a light.
+    /// the shape must implement `sample_point` and `pdf_value`
+    pub fn insert_area_light<T: Shape + Send + Sync + 'static>(&mut self, shape: T) -> Handle {
+        let shape = Arc::new(shape);
+        self.add_light(AreaLight {
+            shape: shape.clone(),
+        });
+        self.push(AnyShape::new(shape), Some(self.lights.len() - 1))
+    }
+
+    /// replaces the background, along with any environment map lighting the world
+    pub fn set_background<T: Background + Send + Sync + 'static>(&mut self, background: T) {
+        if let Some(light) = self.environment.take() {
+            self.remove_light(light);
+        }
+        self.background = Box::new(background);
+    }
+
+    /// surrounds the world with an environment map that's also sampled directly as a light,
+    /// in proportion to how bright it is, so a small bright sun in it doesn't take thousands
+    /// of samples to resolve
+    pub fn set_environment(&mut self, map: EnvironmentMap) {
+        let map = Arc::new(map);
+        self.set_background(map.clone());
+        self.add_light(EnvironmentLight::new(map));
+        self.environment = Some(self.lights.len() - 1);
+    }
+
+    /// `insert`, for chaining
+    pub fn with<T: Shape + Send + Sync + 'static>(mut self, shape: T) -> Self {
+        self.insert(shape);
+        self
+    }
+
+    /// `add_light`, for chaining
+    pub fn with_light<T: Light + Send + Sync + 'static>(mut self, light: T) -> Self {
+        self.add_light(light);
+        self
     }
 
-    pub fn insert<T: Shape + Send + Sync + 'static>(&mut self, shape: T) {
-        self.shapes.push(Box::new(shape));
+    /// `insert_area_light`, for chaining
+    pub fn with_area_light<T: Shape + Send + Sync + 'static>(mut self, shape: T) -> Self {
+        self.insert_area_light(shape);
+        self
+    }
+
+    /// `set_background`, for chaining
+    pub fn with_background<T: Background + Send + Sync + 'static>(mut self, background: T) -> Self {
+        self.set_background(background);
+        self
     }
 }
 
+/// `value` as a `U`, if that's the type it already is. lets the enums of built in shapes and
+/// materials pick out their variants from whatever they're handed
+pub(crate) fn downcast<T: 'static, U: 'static>(value: T) -> Result<U, T> {
+    let mut value = Some(value);
+    match (&mut value as &mut dyn Any).downcast_mut::<Option<U>>() {
+        Some(value) => Ok(value.take().unwrap()),
+        None => Err(value.unwrap()),
+    }
+}
+
+/// a world and the view to render it from
+pub struct Scene {
+    pub world: World,
+    pub view: View,
+}
+
 impl Shape for World {
-    fn hit(&self, ray: Ray, bounds: Range<f64>) -> Option<RayContact> {
-        self.shapes
-            .iter()
-            .map(|shape| shape.hit(ray, bounds.clone()))
-            .filter_map(|contact| contact)
-            .fold(None, |acc, contact| match acc {
-                None => Some(contact),
-                Some(min) => {
-                    if contact < min {
-                        Some(contact)
-                    } else {
-                        Some(min)
+    /// the nearest surface, passing through cutouts where their opacity lets the ray through
+    fn hit(&self, ray: Ray, bounds: Range<Float>) -> Option<RayContact<'_>> {
+        count(Counter::Rays, 1);
+        let nearest = |bounds: Range<Float>| {
+            count(Counter::IntersectionTests, self.objects.len() as u64);
+            self.objects
+                .iter()
+                .filter_map(|object| {
+                    let contact = object.shape.hit(ray, bounds.clone())?;
+                    Some(RayContact {
+                        light: object.light,
+                        object: Some(object.handle),
+                        ..contact
+                    })
+                })
+                .fold(None, |acc, contact| match acc {
+                    None => Some(contact),
+                    Some(min) => {
+                        if contact < min {
+                            Some(contact)
+                        } else {
+                            Some(min)
+                        }
                     }
-                }
-            })
+                })
+        };
+        hit_opaque(nearest, bounds)
+    }
+
+    fn occluded(&self, ray: Ray, bounds: Range<Float>) -> bool {
+        count(Counter::Rays, 1);
+        let tested = self
+            .objects
+            .iter()
+            .position(|object| object.shape.occluded(ray, bounds.clone()));
+        count(
+            Counter::IntersectionTests,
+            tested.map_or(self.objects.len(), |i| i + 1) as u64,
+        );
+        tested.is_some()
     }
 }
+
+#[test]
+fn object_ids() {
+    assert_eq!(murmur3(b"", 0), 0);
+    assert_eq!(murmur3(b"", 1), 0x514e28b7);
+    assert_eq!(murmur3(b"hello", 0), 0x248bfa47);
+    assert_eq!(
+        murmur3(b"The quick brown fox jumps over the lazy dog", 0x9747b28c),
+        0x2fa826cd
+    );
+
+    let mut world = World::new();
+    let ball = || Sphere::new(Vec3::ZERO, 1., Diffuse::from(Color::WHITE));
+    let unnamed = world.insert(ball());
+    world.insert_named("ball", ball());
+    let object = world.get(unnamed).unwrap();
+    assert_eq!(object.label(), "object0");
+    // ids come from labels alone, so renaming an object changes it and nothing else does
+    let ball = world.find("ball").unwrap().id();
+    assert!(f32::from_bits(ball).is_normal());
+    assert_ne!(ball, object.id());
+    let mut other = World::new();
+    other.insert_named(
+        "ball",
+        Sphere::new(Vec3::ONE, 2., Diffuse::from(Color::BLACK)),
+    );
+    assert_eq!(other.find("ball").unwrap().id(), ball);
+}
+
+#[test]
+fn editing_the_world() {
+    let light = |y| {
+        Quad::new(
+            Vec3::new(0., y, 0.),
+            Vec3::X,
+            Vec3::Z,
+            DiffuseLight::from(Color::WHITE),
+        )
+    };
+    let mut world = World::new();
+    let first = world.insert_area_light(light(0.));
+    let ball = world.insert_named(
+        "ball",
+        Sphere::new(Vec3::ZERO, 1., Diffuse::from(Color::GRAY)),
+    );
+    let second = world.insert_area_light(light(5.));
+    assert_eq!(world.len(), 3);
+    assert_eq!(world.lights.len(), 2);
+    assert_eq!(world.find("ball").map(Object::handle), Some(ball));
+
+    // removing a light takes its sampled light along, and the others still find theirs
+    assert!(world.remove(first).unwrap().is_light());
+    assert!(world.get(first).is_none() && world.remove(first).is_none());
+    assert_eq!(world.lights.len(), 1);
+    let down = Ray::new(Vec3::new(0.5, 10., 0.5), -Vec3::Y);
+    let contact = world.hit(down, 0.001..Float::INFINITY).unwrap();
+    assert_eq!(contact.light, Some(0));
+    assert!(world.get(second).unwrap().is_light());
+
+    world.get_mut(ball).unwrap().name = Some("moon".to_string());
+    assert!(world.find("ball").is_none());
+    let names: Vec<_> = world.objects().filter_map(|o| o.name.as_deref()).collect();
+    assert_eq!(names, ["moon"]);
+}
+
+#[test]
+fn cutouts_let_rays_through() {
+    fn quad(y: Float, opacity: Float) -> Quad {
+        let alpha = SolidColor::from(Color::gray(opacity));
+        let material = Cutout::new(Diffuse::from(Color::WHITE), alpha);
+        Quad::new(Vec3::new(-1., y, -1.), 2. * Vec3::Z, 2. * Vec3::X, material)
+    }
+    let mut world = World::new();
+    let half = world.insert(quad(1., 0.5));
+    world.insert(quad(0., 1.));
+    let ray = Ray::new(Vec3::new(0., 2., 0.), -Vec3::Y);
+
+    let n = 10000;
+    let rate = |pass: &dyn Fn() -> bool| (0..n).filter(|_| pass()).count() as Float / n as Float;
+    let through = rate(&|| world.hit(ray, 0.0..Float::INFINITY).unwrap().t > 1.5);
+    assert!((through - 0.5).abs() < 0.03);
+    let blocked = rate(&|| world.occluded(ray, 0.0..1.5));
+    assert!((blocked - 0.5).abs() < 0.03);
+
+    // fully cut out, rays only ever find what's behind
+    world.remove(half);
+    world.insert(quad(1., 0.));
+    assert_eq!(rate(&|| world.occluded(ray, 0.0..1.5)), 0.);
+    assert_eq!(
+        rate(&|| world.hit(ray, 0.0..Float::INFINITY).unwrap().t == 2.),
+        1.
+    );
+}
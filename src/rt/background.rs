@@ -0,0 +1,248 @@
+use super::{Color, Ray, Texture};
+use crate::math::{consts::PI, Float, Normalize, Vec3};
+use std::sync::Arc;
+
+/// the light arriving along rays that escape the scene
+pub trait Background {
+    fn color(&self, ray: Ray) -> Color;
+}
+
+impl<B: Background + ?Sized> Background for Arc<B> {
+    fn color(&self, ray: Ray) -> Color {
+        self.as_ref().color(ray)
+    }
+}
+
+/// the same color in every direction
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SolidBackground {
+    pub color: Color,
+}
+
+impl SolidBackground {
+    /// no light at all, for scenes lit only by their own light sources
+    pub const BLACK: Self = Self {
+        color: Color::BLACK,
+    };
+}
+
+impl Background for SolidBackground {
+    fn color(&self, _ray: Ray) -> Color {
+        self.color
+    }
+}
+
+impl From<Color> for SolidBackground {
+    fn from(color: Color) -> Self {
+        Self { color }
+    }
+}
+
+/// blends from `bottom` straight down to `top` straight up
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GradientBackground {
+    pub bottom: Color,
+    pub top: Color,
+}
+
+impl Default for GradientBackground {
+    /// the default daylight sky
+    fn default() -> Self {
+        Self {
+            bottom: Color::WHITE,
+            top: Color::new(0.5, 0.7, 1.0),
+        }
+    }
+}
+
+impl Background for GradientBackground {
+    fn color(&self, ray: Ray) -> Color {
+        let dir = ray.direction.normalize();
+        let t = (dir.y + 1.) / 2.;
+        self.bottom.lerp(self.top, t)
+    }
+}
+
+/// a texture wrapped around the scene in latitude/longitude layout, as with a sphere's surface
+#[derive(Clone)]
+pub struct EnvironmentMap {
+    pub texture: Arc<dyn Texture + Send + Sync + 'static>,
+    /// columns and rows of the grid the map is sampled over when lighting the world with it.
+    /// an image's own size finds every pixel of a small sun
+    pub resolution: (u32, u32),
+}
+
+impl EnvironmentMap {
+    /// constructor
+    pub fn new<Tex>(texture: Tex) -> Self
+    where
+        Tex: Texture + Send + Sync + 'static,
+    {
+        Self {
+            texture: Arc::new(texture),
+            resolution: (1024, 512),
+        }
+    }
+
+    pub fn with_resolution(self, columns: u32, rows: u32) -> Self {
+        Self {
+            resolution: (columns, rows),
+            ..self
+        }
+    }
+
+    /// the map's coordinates in a unit direction, v running from straight down to straight up
+    pub fn uv(dir: Vec3) -> (Float, Float) {
+        let u = ((-dir.z).atan2(dir.x) + PI) / (2. * PI);
+        let v = (-dir.y).clamp(-1., 1.).acos() / PI;
+        (u, v)
+    }
+
+    /// the unit direction at the map's coordinates, undoing `uv`
+    pub fn direction(u: Float, v: Float) -> Vec3 {
+        let (theta, phi) = (v * PI, u * 2. * PI - PI);
+        let sin = theta.sin();
+        Vec3::new(sin * phi.cos(), -theta.cos(), -sin * phi.sin())
+    }
+}
+
+impl Background for EnvironmentMap {
+    fn color(&self, ray: Ray) -> Color {
+        let dir = ray.direction.normalize();
+        let (u, v) = Self::uv(dir);
+        self.texture.value(u, v, dir)
+    }
+}
+
+/// analytic daylight sky after preetham, shirley and smits, "a practical analytic model for daylight" (1999),
+/// with a visible sun disk. +y is up, and the model only holds while the sun is above the horizon
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PreethamSky {
+    sun_direction: Vec3,
+    turbidity: Float,
+    /// sky luminance, chromaticity x and chromaticity y at the zenith
+    zenith: [Float; 3],
+    /// perez distribution coefficients A..E for each of the zenith values
+    perez: [[Float; 5]; 3],
+    sun_color: Color,
+    /// scale from the model's kcd/m^2 to scene units
+    pub intensity: Float,
+    /// apparent angular radius of the sun disk, in degrees
+    pub sun_radius: Float,
+    /// radiance of the sun disk, relative to the sky
+    pub sun_intensity: Float,
+}
+
+impl PreethamSky {
+    /// constructor. `sun_direction` points towards the sun. `turbidity` ranges from 2 for a
+    /// very clear sky to around 10 for thick haze
+    pub fn new(sun_direction: Vec3, turbidity: Float) -> Self {
+        let sun_direction = sun_direction.normalize();
+        let t = turbidity;
+        let theta_s = sun_direction.y.clamp(-1., 1.).acos();
+        let (t2, th2, th3) = (t * t, theta_s * theta_s, theta_s * theta_s * theta_s);
+
+        let chi = (4. / 9. - t / 120.) * (PI - 2. * theta_s);
+        let zenith_y = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let zenith_x = t2 * (0.00166 * th3 - 0.00375 * th2 + 0.00209 * theta_s)
+            + t * (-0.02903 * th3 + 0.06377 * th2 - 0.03202 * theta_s + 0.00394)
+            + (0.11693 * th3 - 0.21196 * th2 + 0.06052 * theta_s + 0.25886);
+        let zenith_yc = t2 * (0.00275 * th3 - 0.00610 * th2 + 0.00317 * theta_s)
+            + t * (-0.04214 * th3 + 0.08970 * th2 - 0.04153 * theta_s + 0.00516)
+            + (0.15346 * th3 - 0.26756 * th2 + 0.06670 * theta_s + 0.26688);
+
+        let perez = [
+            [
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ],
+            [
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ],
+            [
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ],
+        ];
+
+        // sunlight extinction through the atmosphere, from rayleigh scattering and angstrom's haze
+        // formula, at the rough wavelengths of red, green and blue
+        let elevation = 90. - theta_s.to_degrees().min(90.);
+        let air_mass = 1. / (theta_s.cos().max(0.) + 0.50572 * (elevation + 6.07995).powf(-1.6364));
+        let beta = 0.04608 * t - 0.04586;
+        let extinction = |micrometers: Float| {
+            let rayleigh = 0.008735 * micrometers.powf(-4.08);
+            let haze = beta * micrometers.powf(-1.3);
+            (-air_mass * (rayleigh + haze)).exp()
+        };
+
+        Self {
+            sun_direction,
+            turbidity,
+            zenith: [zenith_y, zenith_x, zenith_yc],
+            perez,
+            sun_color: Color::new(extinction(0.68), extinction(0.55), extinction(0.44)),
+            intensity: 1. / 15.,
+            sun_radius: 0.27,
+            sun_intensity: 1000.,
+        }
+    }
+
+    pub fn sun_direction(&self) -> Vec3 {
+        self.sun_direction
+    }
+
+    pub fn turbidity(&self) -> Float {
+        self.turbidity
+    }
+
+    /// color of the sunlight after passing through the atmosphere, at full strength
+    pub fn sun_color(&self) -> Color {
+        self.sun_color
+    }
+
+    /// sky color in a unit direction, without the sun disk
+    pub fn sky(&self, dir: Vec3) -> Color {
+        // the model is only defined above the horizon, so extend the horizon color downwards
+        let cos_theta = dir.y.max(0.01);
+        let cos_gamma = dir.dot(self.sun_direction).clamp(-1., 1.);
+        let gamma = cos_gamma.acos();
+        let theta_s = self.sun_direction.y.clamp(-1., 1.).acos();
+
+        let perez =
+            |[a, b, c, d, e]: [Float; 5], cos_theta: Float, gamma: Float, cos_gamma: Float| {
+                (1. + a * (b / cos_theta).exp())
+                    * (1. + c * (d * gamma).exp() + e * cos_gamma * cos_gamma)
+            };
+        let [lum, x, y] = [0, 1, 2].map(|i| {
+            self.zenith[i] * perez(self.perez[i], cos_theta, gamma, cos_gamma)
+                / perez(self.perez[i], 1., theta_s, theta_s.cos())
+        });
+
+        // xyY to XYZ to linear sRGB
+        let big_x = x / y * lum;
+        let big_z = (1. - x - y) / y * lum;
+        self.intensity * Color::from_xyz(big_x, lum, big_z).max(Color::BLACK)
+    }
+}
+
+impl Background for PreethamSky {
+    fn color(&self, ray: Ray) -> Color {
+        let dir = ray.direction.normalize();
+        if dir.dot(self.sun_direction) >= self.sun_radius.to_radians().cos() {
+            self.sun_intensity * self.sun_color
+        } else {
+            self.sky(dir)
+        }
+    }
+}
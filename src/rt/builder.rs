@@ -0,0 +1,213 @@
+use super::*;
+use crate::math::{Float, Vec3};
+
+/// a shape waiting for the material that comes after it
+type PendingShape = Box<dyn FnOnce(&mut World, AnyMaterial, bool)>;
+
+/// builds a scene one shape at a time. each shape takes the material given right after it:
+///
+/// ```
+/// # use raytracer::{math::Vec3, rt::*};
+/// let scene = SceneBuilder::new()
+///     .look_at(Vec3::new(0., 1., -5.), Vec3::ZERO)
+///     .sphere(Vec3::new(0., -1000., 0.), 1000.).diffuse(Color::GRAY)
+///     .sphere(Vec3::new(0., 1., 0.), 1.).metal(Color::WHITE, 0.1)
+///     .build();
+/// ```
+///
+/// shapes left without a material are plain gray diffuse
+pub struct SceneBuilder {
+    world: World,
+    view: View,
+    pending: Option<PendingShape>,
+}
+
+impl Default for SceneBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// a builder adding to a world that's already got things in it
+impl From<World> for SceneBuilder {
+    fn from(world: World) -> Self {
+        Self {
+            world,
+            ..Self::new()
+        }
+    }
+}
+
+impl SceneBuilder {
+    /// an empty world under the default sky, seen from 10 units down -z
+    pub fn new() -> Self {
+        Self {
+            world: World::new(),
+            view: View {
+                eye: Vec3::new(0., 0., -10.),
+                look_at: Vec3::ZERO,
+                up: Vec3::Y,
+                vfov: 40.,
+                aperture: 0.,
+                aperture_shape: ApertureShape::Round,
+                projection: Projection::Perspective,
+                shutter: (0., 0.),
+                focus: Focus::LookAt,
+            },
+            pending: None,
+        }
+    }
+
+    pub fn camera(mut self, view: View) -> Self {
+        self.view = view;
+        self
+    }
+
+    /// points the camera from `eye` at `target`, keeping its other settings
+    pub fn look_at(mut self, eye: Vec3, target: Vec3) -> Self {
+        self.view.eye = eye;
+        self.view.look_at = target;
+        self
+    }
+
+    pub fn background<B: Background + Send + Sync + 'static>(mut self, background: B) -> Self {
+        self.world.set_background(background);
+        self
+    }
+
+    /// an environment map around the scene, also lighting it directly
+    pub fn environment(mut self, map: EnvironmentMap) -> Self {
+        self.world.set_environment(map);
+        self
+    }
+
+    pub fn light<L: Light + Send + Sync + 'static>(mut self, light: L) -> Self {
+        self.world.add_light(light);
+        self
+    }
+
+    /// starts a shape built by `shape` from the next material
+    pub fn shape<S, F>(mut self, shape: F) -> Self
+    where
+        S: Shape + Send + Sync + 'static,
+        F: FnOnce(AnyMaterial) -> S + 'static,
+    {
+        self.flush();
+        self.pending = Some(Box::new(move |world, material, emissive| {
+            if emissive {
+                world.insert_area_light(shape(material));
+            } else {
+                world.insert(shape(material));
+            }
+        }));
+        self
+    }
+
+    pub fn sphere(self, center: Vec3, radius: Float) -> Self {
+        self.shape(move |m| Sphere::new(center, radius, m))
+    }
+
+    /// a sphere moving from `start` at `time.0` to `end` at `time.1`
+    pub fn moving_sphere(
+        self,
+        start: Vec3,
+        end: Vec3,
+        time: (Float, Float),
+        radius: Float,
+    ) -> Self {
+        self.shape(move |m| MovingSphere::new((start, end), time, radius, m))
+    }
+
+    pub fn cylinder(self, base: Vec3, top: Vec3, radius: Float) -> Self {
+        self.shape(move |m| Cylinder::new(base, top, radius, true, m))
+    }
+
+    pub fn cone(self, base: Vec3, apex: Vec3, radius: Float) -> Self {
+        self.shape(move |m| Cone::new(base, apex, radius, true, m))
+    }
+
+    pub fn torus(self, center: Vec3, axis: Vec3, major_radius: Float, minor_radius: Float) -> Self {
+        self.shape(move |m| Torus::new(center, axis, major_radius, minor_radius, m))
+    }
+
+    pub fn quad(self, corner: Vec3, u: Vec3, v: Vec3) -> Self {
+        self.shape(move |m| Quad::new(corner, u, v, m))
+    }
+
+    /// gives the last shape a material
+    pub fn material<M: Material + Send + Sync + 'static>(mut self, material: M) -> Self {
+        self.finish(AnyMaterial::new(material), false);
+        self
+    }
+
+    pub fn diffuse(self, color: Color) -> Self {
+        self.material(Diffuse::from(color))
+    }
+
+    pub fn metal(self, color: Color, fuzz: Float) -> Self {
+        self.material(Metal::new(SolidColor::from(color), fuzz))
+    }
+
+    pub fn pbr(self, color: Color, metallic: Float, roughness: Float) -> Self {
+        self.material(Pbr::new(SolidColor::from(color), metallic, roughness))
+    }
+
+    pub fn glass(self, refraction_index: Float) -> Self {
+        self.material(Dielectric::new(refraction_index))
+    }
+
+    /// makes the last shape glow, and samples it directly as an area light
+    pub fn emissive(mut self, color: Color) -> Self {
+        self.finish(AnyMaterial::DiffuseLight(color.into()), true);
+        self
+    }
+
+    /// makes the last shape glow with a texture, scaled by `intensity`, and samples it
+    /// directly as an area light
+    pub fn emissive_texture<Tex>(mut self, texture: Tex, intensity: Float) -> Self
+    where
+        Tex: Texture + Send + Sync + 'static,
+    {
+        let light = DiffuseLight::new(texture, intensity);
+        self.finish(AnyMaterial::DiffuseLight(light), true);
+        self
+    }
+
+    fn finish(&mut self, material: AnyMaterial, emissive: bool) {
+        if let Some(pending) = self.pending.take() {
+            pending(&mut self.world, material, emissive);
+        }
+    }
+
+    /// gives a shape left without a material the default one
+    fn flush(&mut self) {
+        self.finish(AnyMaterial::Diffuse(Color::GRAY.into()), false);
+    }
+
+    pub fn build(mut self) -> Scene {
+        self.flush();
+        Scene {
+            world: self.world,
+            view: self.view,
+        }
+    }
+}
+
+#[test]
+fn builder_assigns_materials_in_order() {
+    let scene = SceneBuilder::new()
+        .look_at(Vec3::new(0., 0., -5.), Vec3::ZERO)
+        .sphere(Vec3::ZERO, 1.)
+        .emissive(Color::WHITE)
+        .sphere(Vec3::new(0., 0., 5.), 1.)
+        .sphere(Vec3::new(0., 0., 10.), 1.)
+        .metal(Color::WHITE, 0.)
+        .build();
+    assert_eq!(scene.world.len(), 3);
+    assert_eq!(scene.world.lights.len(), 1);
+
+    let ray = Ray::new(scene.view.eye, Vec3::Z);
+    let contact = scene.world.hit(ray, 0.001..Float::INFINITY).unwrap();
+    assert_eq!(contact.light, Some(0));
+    assert!(!contact.material.emitted(&contact).is_black());
+}
@@ -1,5 +1,7 @@
-use crate::math::{Normalize, Vec3};
-use crate::rt::Ray;
+use crate::math::{consts::PI, random, Float, Normalize, Vec3};
+use crate::rt::{Footprint, Ray, Shape};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
 
 #[derive(Clone, Copy, PartialEq, Debug)]
 struct Screen {
@@ -10,7 +12,10 @@ struct Screen {
 
 pub struct FixedCamera {
     pub eye: Vec3,
-    lens_radius: f64,
+    /// interval the shutter is open for. rays are cast at random times within it
+    pub shutter: (Float, Float),
+    lens_radius: Float,
+    aperture_shape: ApertureShape,
     uvw: (Vec3, Vec3, Vec3),
     screen: Screen,
 }
@@ -20,14 +25,14 @@ impl FixedCamera {
         eye: Vec3,
         look_at: Vec3,
         up: Vec3,
-        aspect_ratio: f64,
-        vfov: f64,
-        aperture: f64,
-        focus_dist: f64,
+        aspect_ratio: Float,
+        vfov: Float,
+        aperture: Float,
+        focus_dist: Float,
     ) -> Self {
         let h = (vfov.to_radians() / 2.).tan();
-        let viewport_height: f64 = 2.0 * h;
-        let viewport_width: f64 = viewport_height * aspect_ratio;
+        let viewport_height: Float = 2.0 * h;
+        let viewport_width: Float = viewport_height * aspect_ratio;
 
         let w = (eye - look_at).normalize();
         let u = up.cross(w).normalize();
@@ -44,20 +49,332 @@ impl FixedCamera {
         };
         FixedCamera {
             eye,
+            shutter: (0., 0.),
             lens_radius: aperture / 2.,
+            aperture_shape: ApertureShape::Round,
             uvw: (u, v, w),
             screen,
         }
     }
+
+    /// opens the shutter from `open` to `close`, blurring shapes that move in between
+    pub fn with_shutter(self, open: Float, close: Float) -> Self {
+        Self {
+            shutter: (open, close),
+            ..self
+        }
+    }
+
+    /// shapes the lens opening, and so the out of focus highlights
+    pub fn with_aperture_shape(self, aperture_shape: ApertureShape) -> Self {
+        Self {
+            aperture_shape,
+            ..self
+        }
+    }
+}
+
+/// the shape of the lens opening, which the blur of out of focus highlights takes
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub enum ApertureShape {
+    #[default]
+    Round,
+    /// a regular polygon, as made by the blades of an iris, with a corner `rotation` degrees
+    /// counter-clockwise from the right
+    Polygon { blades: u32, rotation: Float },
+}
+
+impl ApertureShape {
+    /// a random point on the opening, spread evenly over it. it fits in the unit disk
+    pub fn sample(&self) -> Vec3 {
+        match *self {
+            ApertureShape::Round => Vec3::random_in_xy_unit_disk(),
+            ApertureShape::Polygon { blades, rotation } => {
+                // a point in a random one of the triangles between the center and each side
+                let blades = blades.max(3) as Float;
+                let (distance, along) = (random::<Float>().sqrt(), random::<Float>() * blades);
+                let corner = |i: Float| {
+                    let (sin, cos) = (rotation.to_radians() + 2. * PI * i / blades).sin_cos();
+                    Vec3::new(cos, sin, 0.)
+                };
+                let (a, b) = (corner(along.floor()), corner(along.floor() + 1.));
+                distance * (a + along.fract() * (b - a))
+            }
+        }
+    }
+}
+
+impl FromStr for ApertureShape {
+    type Err = String;
+
+    /// `round`, or the number of blades, optionally followed by the rotation as `<blades>:<degrees>`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("round") {
+            return Ok(ApertureShape::Round);
+        }
+        let (blades, rotation) = s.split_once(':').unwrap_or((s, "0"));
+        match (blades.parse(), rotation.parse()) {
+            (Ok(blades), Ok(rotation)) if blades >= 3 => {
+                Ok(ApertureShape::Polygon { blades, rotation })
+            }
+            _ => Err(format!(
+                "unknown aperture shape `{s}`, expected round, <blades> or <blades>:<degrees>, \
+                 with at least 3 blades"
+            )),
+        }
+    }
+}
+
+/// a fisheye lens with an equidistant projection: the angle from the view direction grows
+/// evenly with the distance from the center of the image
+pub struct FisheyeCamera {
+    pub eye: Vec3,
+    pub shutter: (Float, Float),
+    /// angle across the height of the image, in radians. the corners of a wide image see further
+    fov: Float,
+    aspect_ratio: Float,
+    uvw: (Vec3, Vec3, Vec3),
+}
+
+impl FisheyeCamera {
+    /// `fov` is in degrees, and can be up to 360
+    pub fn new(eye: Vec3, look_at: Vec3, up: Vec3, aspect_ratio: Float, fov: Float) -> Self {
+        Self {
+            eye,
+            shutter: (0., 0.),
+            fov: fov.to_radians(),
+            aspect_ratio,
+            uvw: basis(eye, look_at, up),
+        }
+    }
+}
+
+/// the whole sphere of directions around the eye, as longitude across the image and latitude
+/// up it. a 2:1 image of it can be used as an environment map or viewed in vr
+pub struct EquirectangularCamera {
+    pub eye: Vec3,
+    pub shutter: (Float, Float),
+    uvw: (Vec3, Vec3, Vec3),
+}
+
+impl EquirectangularCamera {
+    /// the center of the image looks from `eye` towards `look_at`
+    pub fn new(eye: Vec3, look_at: Vec3, up: Vec3) -> Self {
+        Self {
+            eye,
+            shutter: (0., 0.),
+            uvw: basis(eye, look_at, up),
+        }
+    }
+}
+
+/// omni-directional stereo: an equirectangular panorama for each eye, the left above the right.
+/// every column is seen from a pair of eyes turned to face it, so the whole panorama has depth
+/// when viewed in a vr headset
+pub struct StereoCamera {
+    pub eye: Vec3,
+    pub shutter: (Float, Float),
+    /// distance between the eyes
+    ipd: Float,
+    uvw: (Vec3, Vec3, Vec3),
+}
+
+impl StereoCamera {
+    /// `eye` is midway between the eyes, which are `ipd` apart
+    pub fn new(eye: Vec3, look_at: Vec3, up: Vec3, ipd: Float) -> Self {
+        Self {
+            eye,
+            shutter: (0., 0.),
+            ipd,
+            uvw: basis(eye, look_at, up),
+        }
+    }
+}
+
+/// right, up and backwards from a camera at `eye` looking at `look_at`
+fn basis(eye: Vec3, look_at: Vec3, up: Vec3) -> (Vec3, Vec3, Vec3) {
+    let w = (eye - look_at).normalize();
+    let u = up.cross(w).normalize();
+    (u, w.cross(u), w)
+}
+
+/// how a camera maps directions onto the image
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub enum Projection {
+    /// a lens that keeps straight lines straight
+    #[default]
+    Perspective,
+    /// an equidistant fisheye, with the field of view across the height of the image
+    Fisheye,
+    /// all directions, as a 360° panorama
+    Equirectangular,
+    /// a 360° panorama for each eye, the left on top, with the eyes `ipd` apart.
+    /// best rendered at 1:1
+    Stereo { ipd: Float },
+}
+
+impl Projection {
+    /// the average distance between people's eyes, in meters
+    pub const DEFAULT_IPD: Float = 0.064;
+}
+
+impl FromStr for Projection {
+    type Err = String;
+
+    /// one of `perspective`, `fisheye`, `equirectangular`, `stereo` or `stereo:<ipd>`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "perspective" => Ok(Projection::Perspective),
+            "fisheye" => Ok(Projection::Fisheye),
+            "equirectangular" => Ok(Projection::Equirectangular),
+            "stereo" => Ok(Projection::Stereo {
+                ipd: Projection::DEFAULT_IPD,
+            }),
+            other => match other.strip_prefix("stereo:").map(str::parse) {
+                Some(Ok(ipd)) => Ok(Projection::Stereo { ipd }),
+                _ => Err(format!(
+                    "unknown projection `{s}`, expected perspective, fisheye, equirectangular, \
+                     stereo or stereo:<ipd>"
+                )),
+            },
+        }
+    }
+}
+
+/// the distance a perspective camera with an aperture is sharpest at
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub enum Focus {
+    /// on the point the camera looks at
+    #[default]
+    LookAt,
+    /// this far in front of the camera
+    Distance(Float),
+    /// on whatever is seen through a point on the image, from (0, 0) at the bottom left to
+    /// (1, 1) at the top right, once `View::autofocus` has found it
+    Auto(Float, Float),
+}
+
+/// where a camera sits and what it looks at, kept around so it can be moved and rebuilt
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct View {
+    pub eye: Vec3,
+    pub look_at: Vec3,
+    pub up: Vec3,
+    /// vertical field of view, in degrees
+    pub vfov: Float,
+    /// lens diameter, for perspective cameras
+    pub aperture: Float,
+    pub aperture_shape: ApertureShape,
+    pub projection: Projection,
+    /// interval the shutter is open for. shapes that move while it's open are blurred
+    pub shutter: (Float, Float),
+    pub focus: Focus,
+}
+
+impl View {
+    /// the distance to the plane in focus. autofocus not yet done falls back to the look at point
+    pub fn focus_dist(&self) -> Float {
+        match self.focus {
+            Focus::Distance(distance) => distance,
+            Focus::LookAt | Focus::Auto(..) => (self.eye - self.look_at).length(),
+        }
+    }
+
+    /// the view focused on the distance to what `world` shows at the autofocus point, as seen
+    /// with the shutter opening. when nothing is there, it focuses on the look at point instead
+    pub fn autofocus(&self, world: &dyn Shape, aspect_ratio: Float) -> View {
+        let Focus::Auto(dx, dy) = self.focus else {
+            return *self;
+        };
+        // through a pinhole with the screen a unit in front, t is the depth along the view
+        let pinhole = FixedCamera::new(
+            self.eye,
+            self.look_at,
+            self.up,
+            aspect_ratio,
+            self.vfov,
+            0.,
+            1.,
+        );
+        let ray = Ray {
+            time: self.shutter.0,
+            ..pinhole.get_screen_ray(dx, dy)
+        };
+        let focus = match world.hit(ray, 0.0..Float::INFINITY) {
+            Some(contact) => Focus::Distance(contact.t),
+            None => Focus::LookAt,
+        };
+        View { focus, ..*self }
+    }
+
+    /// camera for the view, focused as `focus` says
+    pub fn camera(&self, aspect_ratio: Float) -> Box<dyn Camera + Send + Sync> {
+        let shutter = self.shutter;
+        match self.projection {
+            Projection::Perspective => Box::new(
+                FixedCamera::new(
+                    self.eye,
+                    self.look_at,
+                    self.up,
+                    aspect_ratio,
+                    self.vfov,
+                    self.aperture,
+                    self.focus_dist(),
+                )
+                .with_aperture_shape(self.aperture_shape)
+                .with_shutter(shutter.0, shutter.1),
+            ),
+            Projection::Fisheye => Box::new(FisheyeCamera {
+                shutter,
+                ..FisheyeCamera::new(self.eye, self.look_at, self.up, aspect_ratio, self.vfov)
+            }),
+            Projection::Equirectangular => Box::new(EquirectangularCamera {
+                shutter,
+                ..EquirectangularCamera::new(self.eye, self.look_at, self.up)
+            }),
+            Projection::Stereo { ipd } => Box::new(StereoCamera {
+                shutter,
+                ..StereoCamera::new(self.eye, self.look_at, self.up, ipd)
+            }),
+        }
+    }
 }
 
 pub trait Camera {
-    fn get_screen_ray(&self, dx: f64, dy: f64) -> Ray;
+    /// the ray through a point on the image, from (0, 0) at the bottom left to (1, 1) at the
+    /// top right
+    fn get_screen_ray(&self, dx: Float, dy: Float) -> Ray;
+
+    /// the angle between the rays through neighboring pixels of an image `height` pixels tall,
+    /// for the beam each pixel's rays stand for. zero leaves textures point sampled
+    fn pixel_spread(&self, _height: u32) -> Float {
+        0.
+    }
+}
+
+impl<C: Camera + ?Sized> Camera for Box<C> {
+    fn get_screen_ray(&self, dx: Float, dy: Float) -> Ray {
+        (**self).get_screen_ray(dx, dy)
+    }
+
+    fn pixel_spread(&self, height: u32) -> Float {
+        (**self).pixel_spread(height)
+    }
+}
+
+/// the angle of `angle` radians split over the steps between `height` pixels
+fn per_pixel(angle: Float, height: u32) -> Float {
+    angle / (height.max(2) - 1) as Float
+}
+
+/// a random moment the shutter is open
+fn shutter_time((open, close): (Float, Float)) -> Float {
+    open + random::<Float>() * (close - open)
 }
 
 impl Camera for FixedCamera {
-    fn get_screen_ray(&self, dx: f64, dy: f64) -> Ray {
-        let rd = self.lens_radius * Vec3::random_in_xy_unit_disk();
+    fn get_screen_ray(&self, dx: Float, dy: Float) -> Ray {
+        let rd = self.lens_radius * self.aperture_shape.sample();
         let (u, v, _) = self.uvw;
         let offset = u * rd.x + v * rd.y;
 
@@ -68,6 +385,191 @@ impl Camera for FixedCamera {
                 + (dy * self.screen.vertical)
                 - self.eye
                 - offset,
+            time: shutter_time(self.shutter),
+            wavelength: None,
+            footprint: Footprint::default(),
         }
     }
+
+    /// as seen at the middle of the image
+    fn pixel_spread(&self, height: u32) -> Float {
+        let center = self.screen.origin + (self.screen.horizontal + self.screen.vertical) / 2.;
+        let distance = (center - self.eye).length();
+        per_pixel(self.screen.vertical.length() / distance, height)
+    }
+}
+
+impl Camera for FisheyeCamera {
+    fn get_screen_ray(&self, dx: Float, dy: Float) -> Ray {
+        let (u, v, w) = self.uvw;
+        // from the center, with the height of the image spanning -1 to 1
+        let (x, y) = ((2. * dx - 1.) * self.aspect_ratio, 2. * dy - 1.);
+        let r = x.hypot(y);
+        let theta = r * self.fov / 2.;
+        let sideways = if r > 0. { (x * u + y * v) / r } else { u };
+        let direction = theta.sin() * sideways - theta.cos() * w;
+        Ray::timed(self.eye, direction, shutter_time(self.shutter))
+    }
+
+    fn pixel_spread(&self, height: u32) -> Float {
+        per_pixel(self.fov, height)
+    }
+}
+
+/// longitude and latitude of a point on an equirectangular image, in radians
+fn panorama_angles(dx: Float, dy: Float) -> (Float, Float) {
+    ((dx - 0.5) * 2. * PI, (dy - 0.5) * PI)
+}
+
+/// the direction at a longitude and latitude around a camera with basis `uvw`
+fn panorama_direction((u, v, w): (Vec3, Vec3, Vec3), longitude: Float, latitude: Float) -> Vec3 {
+    let around = longitude.sin() * u - longitude.cos() * w;
+    latitude.cos() * around + latitude.sin() * v
+}
+
+impl Camera for EquirectangularCamera {
+    fn get_screen_ray(&self, dx: Float, dy: Float) -> Ray {
+        let (longitude, latitude) = panorama_angles(dx, dy);
+        let direction = panorama_direction(self.uvw, longitude, latitude);
+        Ray::timed(self.eye, direction, shutter_time(self.shutter))
+    }
+
+    /// along the equator, where the pixels see the most
+    fn pixel_spread(&self, height: u32) -> Float {
+        per_pixel(PI, height)
+    }
+}
+
+impl Camera for StereoCamera {
+    fn get_screen_ray(&self, dx: Float, dy: Float) -> Ray {
+        let (u, _, w) = self.uvw;
+        // the left eye's panorama is the top half of the image
+        let (side, dy) = if dy >= 0.5 {
+            (-1., dy * 2. - 1.)
+        } else {
+            (1., dy * 2.)
+        };
+        let (longitude, latitude) = panorama_angles(dx, dy);
+        let direction = panorama_direction(self.uvw, longitude, latitude);
+        // the eyes turn to face each column, so they sit to either side of it. they close in
+        // towards the poles, where looking straight up or down there's no telling which way
+        // they'd face
+        let right = longitude.cos() * u + longitude.sin() * w;
+        let offset = side * self.ipd / 2. * latitude.cos() * right;
+        Ray::timed(self.eye + offset, direction, shutter_time(self.shutter))
+    }
+
+    /// each eye's panorama takes up half the height
+    fn pixel_spread(&self, height: u32) -> Float {
+        per_pixel(PI, height / 2)
+    }
+}
+
+#[test]
+fn shutter_times_rays() {
+    for projection in [Projection::Perspective, Projection::Equirectangular] {
+        let view = View {
+            eye: Vec3::ZERO,
+            look_at: Vec3::Z,
+            up: Vec3::Y,
+            vfov: 40.,
+            aperture: 0.,
+            aperture_shape: ApertureShape::Round,
+            projection,
+            shutter: (1., 1.5),
+            focus: Focus::LookAt,
+        };
+        let camera = view.camera(1.);
+        let times: Vec<_> = (0..1000)
+            .map(|_| camera.get_screen_ray(0.5, 0.5).time)
+            .collect();
+        assert!(times.iter().all(|t| (1.0..=1.5).contains(t)));
+        assert!(times.iter().any(|&t| t < 1.1) && times.iter().any(|&t| t > 1.4));
+    }
+}
+
+#[test]
+fn aperture_shapes() {
+    let hexagon = ApertureShape::Polygon {
+        blades: 6,
+        rotation: 30.,
+    };
+    assert_eq!("6:30".parse(), Ok(hexagon));
+    assert_eq!("round".parse(), Ok(ApertureShape::Round));
+    assert!("2".parse::<ApertureShape>().is_err());
+
+    // with a corner at the top, the sides are flat on the left and right
+    let points: Vec<_> = (0..10000).map(|_| hexagon.sample()).collect();
+    let apothem = (PI / 6.).cos();
+    assert!(points.iter().all(|p| p.x.abs() <= apothem + 1e-6));
+    assert!(points.iter().any(|p| p.y > 0.95));
+    assert!(points.iter().all(|p| p.length() <= 1. + 1e-6));
+}
+
+#[test]
+fn panoramic_cameras() {
+    let (eye, look_at) = (Vec3::new(1., 2., 3.), Vec3::new(1., 2., 10.));
+    let close = |a: Vec3, b: Vec3| (a.normalize() - b).is_zero();
+
+    let panorama = EquirectangularCamera::new(eye, look_at, Vec3::Y);
+    assert!(close(panorama.get_screen_ray(0.5, 0.5).direction, Vec3::Z));
+    assert!(close(panorama.get_screen_ray(0.5, 1.).direction, Vec3::Y));
+    assert!(close(panorama.get_screen_ray(0., 0.5).direction, -Vec3::Z));
+    // the right of the image turns right, which is -x looking down +z with y up
+    assert!(close(
+        panorama.get_screen_ray(0.75, 0.5).direction,
+        -Vec3::X
+    ));
+
+    let fisheye = FisheyeCamera::new(eye, look_at, Vec3::Y, 2., 180.);
+    assert!(close(fisheye.get_screen_ray(0.5, 0.5).direction, Vec3::Z));
+    assert!(close(fisheye.get_screen_ray(0.5, 1.).direction, Vec3::Y));
+    assert!(close(
+        fisheye.get_screen_ray(0.5, 0.75).direction,
+        Vec3::new(0., 1., 1.).normalize()
+    ));
+    assert_eq!(fisheye.get_screen_ray(0.5, 0.5).origin, eye);
+
+    // both eyes look the same way, from either side of the eye, the left eye above
+    let stereo = StereoCamera::new(eye, look_at, Vec3::Y, 0.1);
+    let (left, right) = (
+        stereo.get_screen_ray(0.5, 0.75),
+        stereo.get_screen_ray(0.5, 0.25),
+    );
+    assert!(close(left.direction, Vec3::Z) && close(right.direction, Vec3::Z));
+    assert!((left.origin - (eye + Vec3::new(0.05, 0., 0.))).is_zero());
+    assert!((right.origin - (eye - Vec3::new(0.05, 0., 0.))).is_zero());
+    // turned to face -x, the left eye is towards +z
+    let left = stereo.get_screen_ray(0.75, 0.75);
+    assert!(close(left.direction, -Vec3::X));
+    assert!((left.origin - (eye + Vec3::new(0., 0., 0.05))).is_zero());
+    assert_eq!(
+        "stereo:0.07".parse::<Projection>(),
+        Ok(Projection::Stereo { ipd: 0.07 })
+    );
+}
+
+#[test]
+fn autofocus() {
+    use crate::rt::{Color, SceneBuilder};
+
+    let scene = SceneBuilder::new()
+        .look_at(Vec3::ZERO, Vec3::Z)
+        .sphere(Vec3::new(0., 0., 5.), 1.)
+        .diffuse(Color::GRAY)
+        .build();
+    let view = View {
+        focus: Focus::Auto(0.5, 0.5),
+        ..scene.view
+    };
+    let focused = view.autofocus(&scene.world, 1.);
+    assert_eq!(focused.focus, Focus::Distance(4.));
+    assert_eq!(focused.focus_dist(), 4.);
+
+    // nothing at the corner, so it falls back on the look at point
+    let view = View {
+        focus: Focus::Auto(0., 1.),
+        ..view
+    };
+    assert_eq!(view.autofocus(&scene.world, 1.).focus, Focus::LookAt);
 }
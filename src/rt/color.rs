@@ -0,0 +1,255 @@
+use crate::math::{Float, Vec3};
+use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Sub};
+use std::str::FromStr;
+
+/// linear rgb radiance or reflectance. unbounded, as lights can be any brightness
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Color {
+    pub r: Float,
+    pub g: Float,
+    pub b: Float,
+}
+
+impl Color {
+    pub const WHITE: Self = Self::new(1., 1., 1.);
+    pub const BLACK: Self = Self::new(0., 0., 0.);
+    pub const GRAY: Self = Self::new(0.5, 0.5, 0.5);
+    pub const RED: Self = Self::new(1., 0., 0.);
+    pub const GREEN: Self = Self::new(0., 1., 0.);
+    pub const BLUE: Self = Self::new(0., 0., 1.);
+
+    #[inline(always)]
+    pub const fn new(r: Float, g: Float, b: Float) -> Self {
+        Self { r, g, b }
+    }
+
+    /// the same value in every channel
+    pub const fn gray(value: Float) -> Self {
+        Self::new(value, value, value)
+    }
+
+    /// a random color with channels in [0, 1]
+    pub fn random() -> Self {
+        Vec3::random().into()
+    }
+
+    /// parses `#rrggbb`, `#rgb`, or either without the `#`. channels are taken as they are,
+    /// scaled to [0, 1], without decoding a gamma
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        let digits = hex.strip_prefix('#').unwrap_or(hex);
+        let channel = |i: usize, width: usize| {
+            let value = u8::from_str_radix(digits.get(i * width..(i + 1) * width)?, 16).ok()?;
+            // a single digit stands for itself repeated, as in css
+            let value = if width == 1 { value * 17 } else { value };
+            Some(value as Float / 255.)
+        };
+        let width = match digits.len() {
+            6 => 2,
+            3 => 1,
+            _ => return None,
+        };
+        Some(Self::new(
+            channel(0, width)?,
+            channel(1, width)?,
+            channel(2, width)?,
+        ))
+    }
+
+    /// linear srgb from cie 1931 xyz, under the d65 white point
+    pub fn from_xyz(x: Float, y: Float, z: Float) -> Self {
+        Self::new(
+            3.2406 * x - 1.5372 * y - 0.4986 * z,
+            -0.9689 * x + 1.8758 * y + 0.0415 * z,
+            0.0557 * x - 0.2040 * y + 1.0570 * z,
+        )
+    }
+
+    /// cie 1931 xyz from linear srgb, under the d65 white point, undoing `from_xyz`
+    pub fn to_xyz(&self) -> (Float, Float, Float) {
+        (
+            0.4124 * self.r + 0.3576 * self.g + 0.1805 * self.b,
+            0.2126 * self.r + 0.7152 * self.g + 0.0722 * self.b,
+            0.0193 * self.r + 0.1192 * self.g + 0.9505 * self.b,
+        )
+    }
+
+    /// perceived brightness, with rec. 709 weights
+    pub fn luminance(&self) -> Float {
+        0.2126 * self.r + 0.7152 * self.g + 0.0722 * self.b
+    }
+
+    /// every channel raised to `gamma`. 1 / 2.2 roughly encodes linear values for display, and
+    /// 2.2 decodes them again
+    pub fn gamma(self, gamma: Float) -> Self {
+        Self::new(self.r.powf(gamma), self.g.powf(gamma), self.b.powf(gamma))
+    }
+
+    /// every channel clamped to [min, max]
+    pub fn clamp(self, min: Float, max: Float) -> Self {
+        Self::new(
+            self.r.clamp(min, max),
+            self.g.clamp(min, max),
+            self.b.clamp(min, max),
+        )
+    }
+
+    /// the larger of each channel
+    pub fn max(self, other: Self) -> Self {
+        Self::new(
+            self.r.max(other.r),
+            self.g.max(other.g),
+            self.b.max(other.b),
+        )
+    }
+
+    /// `self` at `t` = 0 through to `other` at `t` = 1
+    pub fn lerp(self, other: Self, t: Float) -> Self {
+        (1. - t) * self + t * other
+    }
+
+    /// whether every channel is zero, or very close to it
+    pub fn is_black(&self) -> bool {
+        Vec3::from(*self).is_zero()
+    }
+
+    /// the channels in [0, 1] mapped to [0, 255]
+    pub fn to_rgb8(self) -> [u8; 3] {
+        [self.r, self.g, self.b].map(|c| (c.clamp(0., 1.) * 255.99) as u8)
+    }
+
+    pub fn to_array(self) -> [f32; 3] {
+        [self.r as f32, self.g as f32, self.b as f32]
+    }
+}
+
+impl FromStr for Color {
+    type Err = String;
+
+    /// a hex color, as `#rrggbb` or `#rgb`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Color::from_hex(s).ok_or_else(|| format!("`{s}` is not a color, expected #rrggbb"))
+    }
+}
+
+/// red, green and blue from x, y and z, for colors stored or computed as vectors, like the
+/// directions normal maps encode
+impl From<Vec3> for Color {
+    fn from(v: Vec3) -> Self {
+        Self::new(v.x, v.y, v.z)
+    }
+}
+
+impl From<Color> for Vec3 {
+    fn from(c: Color) -> Self {
+        Vec3::new(c.r, c.g, c.b)
+    }
+}
+
+impl From<[f32; 3]> for Color {
+    fn from([r, g, b]: [f32; 3]) -> Self {
+        Self::new(r as Float, g as Float, b as Float)
+    }
+}
+
+impl Add for Color {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self {
+        Self::new(self.r + rhs.r, self.g + rhs.g, self.b + rhs.b)
+    }
+}
+
+impl Sub for Color {
+    type Output = Self;
+    fn sub(self, rhs: Self) -> Self {
+        Self::new(self.r - rhs.r, self.g - rhs.g, self.b - rhs.b)
+    }
+}
+
+/// filters one color by another, channel by channel
+impl Mul for Color {
+    type Output = Self;
+    fn mul(self, rhs: Self) -> Self {
+        Self::new(self.r * rhs.r, self.g * rhs.g, self.b * rhs.b)
+    }
+}
+
+impl Mul<Float> for Color {
+    type Output = Self;
+    fn mul(self, rhs: Float) -> Self {
+        Self::new(self.r * rhs, self.g * rhs, self.b * rhs)
+    }
+}
+
+impl Mul<Color> for Float {
+    type Output = Color;
+    fn mul(self, rhs: Color) -> Color {
+        rhs * self
+    }
+}
+
+impl Div for Color {
+    type Output = Self;
+    fn div(self, rhs: Self) -> Self {
+        Self::new(self.r / rhs.r, self.g / rhs.g, self.b / rhs.b)
+    }
+}
+
+impl Div<Float> for Color {
+    type Output = Self;
+    fn div(self, rhs: Float) -> Self {
+        Self::new(self.r / rhs, self.g / rhs, self.b / rhs)
+    }
+}
+
+impl AddAssign for Color {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+impl MulAssign for Color {
+    fn mul_assign(&mut self, rhs: Self) {
+        *self = *self * rhs;
+    }
+}
+
+impl MulAssign<Float> for Color {
+    fn mul_assign(&mut self, rhs: Float) {
+        *self = *self * rhs;
+    }
+}
+
+impl DivAssign<Float> for Color {
+    fn div_assign(&mut self, rhs: Float) {
+        *self = *self / rhs;
+    }
+}
+
+#[test]
+fn colors() {
+    assert_eq!(
+        Color::from_hex("#ff8000"),
+        Some(Color::new(1., 128. / 255., 0.))
+    );
+    assert_eq!("0f0".parse(), Ok(Color::GREEN));
+    assert_eq!(Color::from_hex("#12345"), None);
+    assert_eq!(Color::from_hex("#gg0000"), None);
+    assert!("red".parse::<Color>().is_err());
+
+    assert!((Color::WHITE.luminance() - 1.).abs() < 1e-9);
+    assert!((Color::GREEN.luminance() - 0.7152).abs() < 1e-9);
+    assert_eq!(Color::new(0.25, 1., 4.).gamma(0.5), Color::new(0.5, 1., 2.));
+    assert_eq!(
+        Color::new(-1., 0.5, 2.).clamp(0., 1.),
+        Color::new(0., 0.5, 1.)
+    );
+    assert_eq!(Color::new(2., 0.5, 1.).to_rgb8(), [255, 127, 255]);
+    assert_eq!(
+        Color::BLACK.lerp(Color::new(2., 4., 8.), 0.25),
+        Color::new(0.5, 1., 2.)
+    );
+    assert_eq!(
+        Color::new(1., 2., 3.) * Color::gray(2.),
+        Color::new(2., 4., 6.)
+    );
+}
@@ -0,0 +1,155 @@
+use super::{Ray, RayContact, Shape};
+use crate::math::Float;
+use std::ops::Range;
+
+/// how the two children of a `Csg` are combined
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CsgOp {
+    /// inside either child
+    Union,
+    /// inside both children
+    Intersection,
+    /// inside the first child but not the second
+    Difference,
+}
+
+impl CsgOp {
+    fn inside(self, in_a: bool, in_b: bool) -> bool {
+        match self {
+            CsgOp::Union => in_a || in_b,
+            CsgOp::Intersection => in_a && in_b,
+            CsgOp::Difference => in_a && !in_b,
+        }
+    }
+}
+
+/// a boolean combination of two closed shapes
+pub struct Csg {
+    pub op: CsgOp,
+    pub a: Box<dyn Shape + Send + Sync + 'static>,
+    pub b: Box<dyn Shape + Send + Sync + 'static>,
+}
+
+impl Csg {
+    /// constructor
+    pub fn new<A, B>(op: CsgOp, a: A, b: B) -> Self
+    where
+        A: Shape + Send + Sync + 'static,
+        B: Shape + Send + Sync + 'static,
+    {
+        Self {
+            op,
+            a: Box::new(a),
+            b: Box::new(b),
+        }
+    }
+
+    pub fn union<A, B>(a: A, b: B) -> Self
+    where
+        A: Shape + Send + Sync + 'static,
+        B: Shape + Send + Sync + 'static,
+    {
+        Self::new(CsgOp::Union, a, b)
+    }
+
+    pub fn intersection<A, B>(a: A, b: B) -> Self
+    where
+        A: Shape + Send + Sync + 'static,
+        B: Shape + Send + Sync + 'static,
+    {
+        Self::new(CsgOp::Intersection, a, b)
+    }
+
+    /// carves `b` out of `a`
+    pub fn difference<A, B>(a: A, b: B) -> Self
+    where
+        A: Shape + Send + Sync + 'static,
+        B: Shape + Send + Sync + 'static,
+    {
+        Self::new(CsgOp::Difference, a, b)
+    }
+}
+
+impl Shape for Csg {
+    /// walks the entry/exit events of both children in order along the ray,
+    /// returning the first one where the combined inside/outside state flips
+    fn hit(&self, ray: Ray, bounds: Range<Float>) -> Option<RayContact<'_>> {
+        // step past a surface once it has been consumed
+        const STEP: Float = 1e-6;
+
+        let mut hit_a = self.a.hit(ray, bounds.clone());
+        let mut hit_b = self.b.hit(ray, bounds.clone());
+        // a ray that first leaves a shape started out inside of it
+        let mut in_a = hit_a.as_ref().is_some_and(|c| !c.front_face);
+        let mut in_b = hit_b.as_ref().is_some_and(|c| !c.front_face);
+
+        loop {
+            let from_a = match (&hit_a, &hit_b) {
+                (None, None) => return None,
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+                (Some(a), Some(b)) => a.t <= b.t,
+            };
+            let was_inside = self.op.inside(in_a, in_b);
+            let contact = if from_a {
+                in_a = !in_a;
+                hit_a.take()
+            } else {
+                in_b = !in_b;
+                hit_b.take()
+            }?;
+            let is_inside = self.op.inside(in_a, in_b);
+
+            if was_inside != is_inside {
+                // contact normals already oppose the ray, only the side needs fixing up:
+                // entering the combined solid is its front face, whichever child was hit
+                return Some(RayContact {
+                    front_face: is_inside,
+                    ..contact
+                });
+            }
+
+            let next = contact.t + STEP..bounds.end;
+            if from_a {
+                hit_a = self.a.hit(ray, next);
+            } else {
+                hit_b = self.b.hit(ray, next);
+            }
+        }
+    }
+}
+
+#[test]
+fn csg_difference_carves_hole() {
+    use crate::math::Vec3;
+    use crate::rt::{Color, Diffuse, Sphere};
+    let mat = Diffuse::from(Color::WHITE);
+    // unit sphere with a bite taken out of its -x side
+    let csg = Csg::difference(
+        Sphere::new(Vec3::ZERO, 1., mat.clone()),
+        Sphere::new(Vec3::new(-1., 0., 0.), 0.5, mat.clone()),
+    );
+
+    // straight through the bite: enters the cavity wall at x = -0.5
+    let ray = Ray::new(Vec3::new(-5., 0., 0.), Vec3::X);
+    let contact = csg.hit(ray, 0.001..Float::INFINITY).unwrap();
+    assert!((contact.t - 4.5).abs() < 1e-9);
+    assert!(contact.front_face);
+    assert!((contact.normal - -Vec3::X).is_zero());
+
+    // from inside the remaining solid, leaving through the bite
+    let ray = Ray::new(Vec3::ZERO, -Vec3::X);
+    let contact = csg.hit(ray, 0.001..Float::INFINITY).unwrap();
+    assert!((contact.t - 0.5).abs() < 1e-9);
+    assert!(!contact.front_face);
+    assert!((contact.normal - Vec3::X).is_zero());
+
+    // intersection of the two is only the lens between x = -1 and x = -0.5
+    let lens = Csg::intersection(
+        Sphere::new(Vec3::ZERO, 1., mat.clone()),
+        Sphere::new(Vec3::new(-1., 0., 0.), 0.5, mat),
+    );
+    let ray = Ray::new(Vec3::new(-5., 0., 0.), Vec3::X);
+    let contact = lens.hit(ray, 0.001..Float::INFINITY).unwrap();
+    assert!((contact.t - 4.).abs() < 1e-9);
+}
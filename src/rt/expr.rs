@@ -0,0 +1,413 @@
+//! textures written as little formulas, such as `sin(p.x * 10) * noise(p * 4)`, parsed once
+//! when they're loaded and evaluated at every point they're looked up at
+//!
+//! every value is three numbers, red, green and blue, and plain numbers stand for all three
+//! alike, so arithmetic works on colors and points channel by channel. the variables are the
+//! point in space `p`, the surface coordinates `u` and `v`, and the normal `n`, and `.x`, `.y`
+//! and `.z` take one of the three. the functions are:
+//!
+//! - `sin`, `cos`, `abs`, `floor`, `fract` and `sqrt` of each channel
+//! - `min(a, b)`, `max(a, b)`, `pow(a, b)`, `step(edge, a)`, `clamp(a, low, high)`, and
+//!   `mix(a, b, t)` blending from `a` to `b`
+//! - `length(a)`, and `dot(a, b)`
+//! - `noise(p)`, perlin noise in [-1, 1], `fbm(p)` adding up 5 octaves of it, and
+//!   `turbulence(p)`, the absolute value of that
+//! - `rgb(r, g, b)`, a color from the first channel of each
+
+use super::{Color, RayContact, Texture};
+use crate::math::{Float, Perlin, Vec3};
+
+/// octaves of noise in `fbm` and `turbulence`
+const OCTAVES: u32 = 5;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Var {
+    Point,
+    U,
+    V,
+    Normal,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Func {
+    Sin,
+    Cos,
+    Abs,
+    Floor,
+    Fract,
+    Sqrt,
+    Min,
+    Max,
+    Pow,
+    Step,
+    Clamp,
+    Mix,
+    Length,
+    Dot,
+    Noise,
+    Fbm,
+    Turbulence,
+    Rgb,
+}
+
+/// the functions by name, and how many arguments they take
+const FUNCTIONS: [(&str, Func, usize); 18] = [
+    ("sin", Func::Sin, 1),
+    ("cos", Func::Cos, 1),
+    ("abs", Func::Abs, 1),
+    ("floor", Func::Floor, 1),
+    ("fract", Func::Fract, 1),
+    ("sqrt", Func::Sqrt, 1),
+    ("min", Func::Min, 2),
+    ("max", Func::Max, 2),
+    ("pow", Func::Pow, 2),
+    ("step", Func::Step, 2),
+    ("clamp", Func::Clamp, 3),
+    ("mix", Func::Mix, 3),
+    ("length", Func::Length, 1),
+    ("dot", Func::Dot, 2),
+    ("noise", Func::Noise, 1),
+    ("fbm", Func::Fbm, 1),
+    ("turbulence", Func::Turbulence, 1),
+    ("rgb", Func::Rgb, 3),
+];
+
+#[derive(Clone, Debug, PartialEq)]
+enum Expr {
+    Number(Float),
+    Var(Var),
+    /// one channel of a value, spread over all three
+    Channel(Box<Expr>, usize),
+    Neg(Box<Expr>),
+    Binary(Op, Box<Expr>, Box<Expr>),
+    Call(Func, Vec<Expr>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(Float),
+    Name(String),
+    Symbol(char),
+    End,
+}
+
+/// splits the source into tokens, each with the byte it starts at
+fn tokenize(source: &str) -> Result<Vec<(usize, Token)>, String> {
+    let mut tokens = vec![];
+    let mut chars = source.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_digit() || c == '.' && tokens.last().is_none_or(|t| !ends_value(t)) {
+            let mut end = start;
+            while let Some(&(i, c)) = chars
+                .peek()
+                .filter(|(_, c)| c.is_ascii_digit() || *c == '.')
+            {
+                end = i + c.len_utf8();
+                chars.next();
+            }
+            let number = source[start..end]
+                .parse()
+                .map_err(|_| format!("`{}` at {start} isn't a number", &source[start..end]))?;
+            tokens.push((start, Token::Number(number)));
+        } else if c.is_alphabetic() || c == '_' {
+            let mut end = start;
+            while let Some(&(i, c)) = chars
+                .peek()
+                .filter(|(_, c)| c.is_alphanumeric() || *c == '_')
+            {
+                end = i + c.len_utf8();
+                chars.next();
+            }
+            tokens.push((start, Token::Name(source[start..end].to_string())));
+        } else if "+-*/(),.".contains(c) {
+            tokens.push((start, Token::Symbol(c)));
+            chars.next();
+        } else {
+            return Err(format!("unexpected `{c}` at {start}"));
+        }
+    }
+    tokens.push((source.len(), Token::End));
+    Ok(tokens)
+}
+
+/// whether a `.` after the token picks a channel, rather than starting a number like `.5`
+fn ends_value(token: &(usize, Token)) -> bool {
+    matches!(
+        token.1,
+        Token::Number(_) | Token::Name(_) | Token::Symbol(')')
+    )
+}
+
+/// recursive descent over the tokens, with `*` and `/` binding tighter than `+` and `-`
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    next: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.next].1
+    }
+
+    fn advance(&mut self) -> (usize, Token) {
+        let token = self.tokens[self.next].clone();
+        if token.1 != Token::End {
+            self.next += 1;
+        }
+        token
+    }
+
+    fn expect(&mut self, symbol: char) -> Result<(), String> {
+        match self.advance() {
+            (_, Token::Symbol(c)) if c == symbol => Ok(()),
+            (at, _) => Err(format!("expected `{symbol}` at {at}")),
+        }
+    }
+
+    fn sum(&mut self) -> Result<Expr, String> {
+        let mut left = self.product()?;
+        loop {
+            let op = match self.peek() {
+                Token::Symbol('+') => Op::Add,
+                Token::Symbol('-') => Op::Sub,
+                _ => return Ok(left),
+            };
+            self.advance();
+            left = Expr::Binary(op, Box::new(left), Box::new(self.product()?));
+        }
+    }
+
+    fn product(&mut self) -> Result<Expr, String> {
+        let mut left = self.unary()?;
+        loop {
+            let op = match self.peek() {
+                Token::Symbol('*') => Op::Mul,
+                Token::Symbol('/') => Op::Div,
+                _ => return Ok(left),
+            };
+            self.advance();
+            left = Expr::Binary(op, Box::new(left), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if *self.peek() == Token::Symbol('-') {
+            self.advance();
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+        let mut expr = self.primary()?;
+        while *self.peek() == Token::Symbol('.') {
+            self.advance();
+            let channel = match self.advance() {
+                (_, Token::Name(name)) if name == "x" => 0,
+                (_, Token::Name(name)) if name == "y" => 1,
+                (_, Token::Name(name)) if name == "z" => 2,
+                (at, _) => return Err(format!("expected `x`, `y` or `z` at {at}")),
+            };
+            expr = Expr::Channel(Box::new(expr), channel);
+        }
+        Ok(expr)
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        match self.advance() {
+            (_, Token::Number(number)) => Ok(Expr::Number(number)),
+            (_, Token::Symbol('(')) => {
+                let expr = self.sum()?;
+                self.expect(')')?;
+                Ok(expr)
+            }
+            (at, Token::Name(name)) if *self.peek() == Token::Symbol('(') => {
+                let Some(&(_, func, arity)) = FUNCTIONS.iter().find(|(n, ..)| *n == name) else {
+                    return Err(format!("no function named `{name}` at {at}"));
+                };
+                self.advance();
+                let mut args = vec![];
+                if *self.peek() != Token::Symbol(')') {
+                    args.push(self.sum()?);
+                    while *self.peek() == Token::Symbol(',') {
+                        self.advance();
+                        args.push(self.sum()?);
+                    }
+                }
+                self.expect(')')?;
+                if args.len() != arity {
+                    return Err(format!(
+                        "`{name}` at {at} takes {arity} arguments, not {}",
+                        args.len()
+                    ));
+                }
+                Ok(Expr::Call(func, args))
+            }
+            (at, Token::Name(name)) => match name.as_str() {
+                "p" => Ok(Expr::Var(Var::Point)),
+                "u" => Ok(Expr::Var(Var::U)),
+                "v" => Ok(Expr::Var(Var::V)),
+                "n" => Ok(Expr::Var(Var::Normal)),
+                _ => Err(format!("no variable named `{name}` at {at}")),
+            },
+            (at, Token::End) => Err(format!("expected a value at {at}, the end")),
+            (at, Token::Symbol(c)) => Err(format!("expected a value at {at}, not `{c}`")),
+        }
+    }
+}
+
+/// where an expression is looked up
+struct Inputs {
+    point: Vec3,
+    uv: (Float, Float),
+    normal: Vec3,
+}
+
+type Lanes = [Float; 3];
+
+fn lanes(v: Vec3) -> Lanes {
+    [v.x, v.y, v.z]
+}
+
+fn vec3([x, y, z]: Lanes) -> Vec3 {
+    Vec3::new(x, y, z)
+}
+
+fn zip(a: Lanes, b: Lanes, f: impl Fn(Float, Float) -> Float) -> Lanes {
+    [f(a[0], b[0]), f(a[1], b[1]), f(a[2], b[2])]
+}
+
+/// a texture computed from a formula
+#[derive(Clone, Debug)]
+pub struct Expression {
+    root: Expr,
+    perlin: Perlin,
+}
+
+impl Expression {
+    /// parses the formula, or says what's wrong with it and where
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut parser = Parser {
+            tokens: tokenize(source)?,
+            next: 0,
+        };
+        let root = parser.sum()?;
+        match parser.advance() {
+            (_, Token::End) => Ok(Self {
+                root,
+                perlin: Perlin::new(),
+            }),
+            (at, _) => Err(format!("expected the end at {at}")),
+        }
+    }
+
+    fn eval(&self, expr: &Expr, inputs: &Inputs) -> Lanes {
+        let eval = |expr| self.eval(expr, inputs);
+        match expr {
+            Expr::Number(number) => [*number; 3],
+            Expr::Var(Var::Point) => lanes(inputs.point),
+            Expr::Var(Var::U) => [inputs.uv.0; 3],
+            Expr::Var(Var::V) => [inputs.uv.1; 3],
+            Expr::Var(Var::Normal) => lanes(inputs.normal),
+            Expr::Channel(expr, channel) => [eval(expr)[*channel]; 3],
+            Expr::Neg(expr) => eval(expr).map(|a| -a),
+            Expr::Binary(op, a, b) => {
+                let (a, b) = (eval(a), eval(b));
+                match op {
+                    Op::Add => zip(a, b, |a, b| a + b),
+                    Op::Sub => zip(a, b, |a, b| a - b),
+                    Op::Mul => zip(a, b, |a, b| a * b),
+                    Op::Div => zip(a, b, |a, b| a / b),
+                }
+            }
+            Expr::Call(func, args) => {
+                let arg = |i: usize| eval(&args[i]);
+                match func {
+                    Func::Sin => arg(0).map(Float::sin),
+                    Func::Cos => arg(0).map(Float::cos),
+                    Func::Abs => arg(0).map(Float::abs),
+                    Func::Floor => arg(0).map(Float::floor),
+                    Func::Fract => arg(0).map(|a| a - a.floor()),
+                    Func::Sqrt => arg(0).map(|a| a.max(0.).sqrt()),
+                    Func::Min => zip(arg(0), arg(1), Float::min),
+                    Func::Max => zip(arg(0), arg(1), Float::max),
+                    Func::Pow => zip(arg(0), arg(1), Float::powf),
+                    Func::Step => zip(arg(0), arg(1), |edge, a| (a >= edge) as u8 as Float),
+                    Func::Clamp => {
+                        let low = zip(arg(0), arg(1), Float::max);
+                        zip(low, arg(2), Float::min)
+                    }
+                    Func::Mix => {
+                        let (a, b, t) = (arg(0), arg(1), arg(2));
+                        [0, 1, 2].map(|i| a[i] + (b[i] - a[i]) * t[i])
+                    }
+                    Func::Length => [vec3(arg(0)).length(); 3],
+                    Func::Dot => [vec3(arg(0)).dot(vec3(arg(1))); 3],
+                    Func::Noise => [self.perlin.noise(vec3(arg(0))); 3],
+                    Func::Fbm => [self.perlin.fbm(vec3(arg(0)), OCTAVES); 3],
+                    Func::Turbulence => [self.perlin.turbulence(vec3(arg(0)), OCTAVES); 3],
+                    Func::Rgb => [arg(0)[0], arg(1)[0], arg(2)[0]],
+                }
+            }
+        }
+    }
+
+    fn color(&self, inputs: &Inputs) -> Color {
+        let [r, g, b] = self.eval(&self.root, inputs);
+        Color::new(r, g, b)
+    }
+}
+
+impl Texture for Expression {
+    /// with the normal left at zero, as there's no surface to take it from
+    fn value(&self, u: Float, v: Float, point: Vec3) -> Color {
+        self.color(&Inputs {
+            point,
+            uv: (u, v),
+            normal: Vec3::ZERO,
+        })
+    }
+
+    fn at(&self, contact: &RayContact) -> Color {
+        self.color(&Inputs {
+            point: contact.point,
+            uv: contact.uv,
+            normal: contact.normal,
+        })
+    }
+}
+
+#[test]
+fn expressions() {
+    let at =
+        |source: &str, point: Vec3| Expression::parse(source).unwrap().value(0.25, 0.75, point);
+    let p = Vec3::new(1., 2., 3.);
+    assert_eq!(at("1 + 2 * 3 - 4 / 2", p), Color::gray(5.));
+    assert_eq!(at("-(1 + 2) * .5", p), Color::gray(-1.5));
+    assert_eq!(at("p * 2", p), Color::new(2., 4., 6.));
+    assert_eq!(at("p.y + u + v", p), Color::gray(3.));
+    assert_eq!(at("rgb(u, v, p.z)", p), Color::new(0.25, 0.75, 3.));
+    assert_eq!(at("clamp(p, 1.5, 2.5)", p), Color::new(1.5, 2., 2.5));
+    assert_eq!(at("mix(0, p, 0.5)", p), Color::new(0.5, 1., 1.5));
+    assert_eq!(at("step(2, p) * fract(2.5)", p), Color::new(0., 0.5, 0.5));
+    assert_eq!(at("dot(p, p) - length(p) * length(p)", p).r.round(), 0.);
+    assert_eq!(at("noise(floor(p))", p), Color::BLACK);
+    let stripes = at("sin(p.x * 10) * noise(p * 4)", Vec3::new(0.1, 0.2, 0.3));
+    assert!(stripes.r.abs() <= 1. && stripes.r == stripes.b);
+
+    let error = |source: &str| Expression::parse(source).unwrap_err();
+    assert_eq!(error("1 +"), "expected a value at 3, the end");
+    assert_eq!(error("(1"), "expected `)` at 2");
+    assert_eq!(error("1 2"), "expected the end at 2");
+    assert_eq!(error("q * 2"), "no variable named `q` at 0");
+    assert_eq!(error("tan(p)"), "no function named `tan` at 0");
+    assert_eq!(error("mix(1, 2)"), "`mix` at 0 takes 3 arguments, not 2");
+    assert_eq!(error("p.w"), "expected `x`, `y` or `z` at 2");
+    assert_eq!(error("1 % 2"), "unexpected `%` at 2");
+}
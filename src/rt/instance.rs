@@ -0,0 +1,123 @@
+use super::{rounding_error, Footprint, Ray, RayContact, Shape};
+use crate::math::{Float, Mat4, Normalize, Vec3};
+use std::ops::Range;
+
+/// places a shape authored in its own object space into the world
+pub struct Instance<S: Shape> {
+    pub shape: S,
+    /// object to world
+    transform: Mat4,
+    /// world to object
+    inverse: Mat4,
+    /// object to world, for normals
+    normal_matrix: Mat4,
+}
+
+impl<S: Shape> Instance<S> {
+    /// constructor. panics if `transform` is not invertible
+    pub fn new(shape: S, transform: Mat4) -> Self {
+        let inverse = transform
+            .inverse()
+            .expect("instance transform must be invertible");
+        Self {
+            shape,
+            transform,
+            inverse,
+            normal_matrix: inverse.transpose(),
+        }
+    }
+
+    pub fn transform(&self) -> Mat4 {
+        self.transform
+    }
+
+    /// applies `transform` after the current one
+    pub fn then(self, transform: Mat4) -> Self {
+        Self::new(self.shape, transform * self.transform)
+    }
+
+    pub fn translate(self, offset: Vec3) -> Self {
+        self.then(Mat4::translate(offset))
+    }
+
+    pub fn rotate(self, axis: Vec3, degrees: Float) -> Self {
+        self.then(Mat4::rotate(axis, degrees))
+    }
+
+    pub fn scale(self, factor: Vec3) -> Self {
+        self.then(Mat4::scale(factor))
+    }
+
+    /// the ray in the shape's own space. the direction is deliberately left unnormalized so t
+    /// means the same thing in both spaces
+    fn to_local(&self, ray: Ray) -> Ray {
+        let direction = self.inverse.transform_vector(ray.direction);
+        // scaled along with the distances along the ray
+        let scale = direction.length() / ray.direction.length();
+        Ray {
+            origin: self.inverse.transform_point(ray.origin),
+            direction,
+            footprint: Footprint {
+                width: scale * ray.footprint.width,
+                spread: scale * ray.footprint.spread,
+            },
+            ..ray
+        }
+    }
+}
+
+impl<S: Shape> Shape for Instance<S> {
+    fn hit(&self, ray: Ray, bounds: Range<Float>) -> Option<RayContact<'_>> {
+        let contact = self.shape.hit(self.to_local(ray), bounds)?;
+        let point = ray.at(contact.t);
+        // the point's error in the shape's space, stretched along with it
+        let error = self.transform.transform_vector(Vec3::ONE * contact.error);
+        Some(RayContact {
+            point,
+            normal: self
+                .normal_matrix
+                .transform_vector(contact.normal)
+                .normalize(),
+            geometric_normal: self
+                .normal_matrix
+                .transform_vector(contact.geometric_normal)
+                .normalize(),
+            error: error.length().max(rounding_error(point)),
+            tangent: self.transform.transform_vector(contact.tangent).normalize(),
+            bitangent: self
+                .transform
+                .transform_vector(contact.bitangent)
+                .normalize(),
+            ..contact
+        })
+    }
+
+    fn occluded(&self, ray: Ray, bounds: Range<Float>) -> bool {
+        self.shape.occluded(self.to_local(ray), bounds)
+    }
+}
+
+#[test]
+fn instance_transforms_hits() {
+    use crate::rt::{Color, Diffuse, Sphere};
+    // unit sphere squashed to half height, then moved up
+    let instance = Instance::new(
+        Sphere::new(Vec3::ZERO, 1., Diffuse::from(Color::WHITE)),
+        Mat4::IDENTITY,
+    )
+    .scale(Vec3::new(1., 0.5, 1.))
+    .translate(Vec3::new(0., 3., 0.));
+
+    let ray = Ray::new(Vec3::new(0., 10., 0.), -Vec3::Y);
+    let contact = instance.hit(ray, 0.001..Float::INFINITY).unwrap();
+    assert!((contact.t - 6.5).abs() < 1e-9);
+    assert!((contact.point - Vec3::new(0., 3.5, 0.)).is_zero());
+    assert!((contact.normal - Vec3::Y).is_zero());
+
+    // normals stay perpendicular to the squashed surface
+    let ray = Ray::new(Vec3::new(0.6, 10., 0.), -Vec3::Y);
+    let contact = instance.hit(ray, 0.001..Float::INFINITY).unwrap();
+    let p = contact.point - Vec3::new(0., 3., 0.);
+    let tangent = Vec3::new(p.y * 4., -p.x, 0.);
+    assert!(contact.normal.dot(tangent).abs() < 1e-9);
+}
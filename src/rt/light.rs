@@ -0,0 +1,468 @@
+use super::{rounding_error, Background, Color, EnvironmentMap, Material, Ray, Shape};
+use crate::math::{consts::PI, random, Float, Normalize, Onb, Vec3};
+use std::sync::Arc;
+
+/// light arriving at a point from a light source
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LightSample {
+    /// unit direction from the point towards the light
+    pub direction: Vec3,
+    /// distance to the light, for shadow rays
+    pub distance: Float,
+    /// incident radiance, already divided by the probability of picking this sample
+    pub radiance: Color,
+    /// probability density of picking this sample, per steradian.
+    /// infinite for lights that scattered rays can never hit, such as point lights
+    pub pdf: Float,
+}
+
+/// light leaving a light source, to follow forwards through the scene
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Photon {
+    pub ray: Ray,
+    /// radiant flux it carries, already divided by the probability of sending it out this way
+    pub power: Color,
+}
+
+/// a light source that is sampled directly with shadow rays rather than found by scattered rays
+pub trait Light {
+    /// samples the light arriving at `point`, if any can
+    fn sample(&self, point: Vec3) -> Option<LightSample>;
+
+    /// probability density, per steradian, of `sample` picking the light seen from `origin` along
+    /// `direction`. zero for lights that scattered rays can never hit
+    fn pdf(&self, _origin: Vec3, _direction: Vec3) -> Float {
+        0.
+    }
+
+    /// sends a photon out of the light, if it can. `bounds` is a sphere around the lit part of
+    /// the scene, as its center and radius, for lights infinitely far away to aim at
+    fn emit(&self, _bounds: (Vec3, Float)) -> Option<Photon> {
+        None
+    }
+}
+
+/// an infinitely small light shining equally in all directions
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PointLight {
+    pub position: Vec3,
+    /// radiant intensity. falls off with the square of the distance
+    pub intensity: Color,
+}
+
+impl PointLight {
+    /// constructor
+    pub fn new(position: Vec3, intensity: Color) -> Self {
+        Self {
+            position,
+            intensity,
+        }
+    }
+}
+
+impl Light for PointLight {
+    fn sample(&self, point: Vec3) -> Option<LightSample> {
+        let offset = self.position - point;
+        let distance_squared = offset.length_squared();
+        Some(LightSample {
+            direction: offset.normalize(),
+            distance: distance_squared.sqrt(),
+            radiance: self.intensity / distance_squared,
+            pdf: Float::INFINITY,
+        })
+    }
+
+    fn emit(&self, _bounds: (Vec3, Float)) -> Option<Photon> {
+        Some(Photon {
+            ray: Ray::new(self.position, Vec3::random_unit()),
+            power: 4. * PI * self.intensity,
+        })
+    }
+}
+
+/// a point light restricted to a cone, fading out between its inner and outer angles
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpotLight {
+    pub position: Vec3,
+    /// unit direction the spot shines in
+    pub direction: Vec3,
+    pub intensity: Color,
+    /// cosines of the half-angles of the fully lit cone and of the falloff edge
+    cos_inner: Float,
+    cos_outer: Float,
+}
+
+impl SpotLight {
+    /// constructor. angles are half-angles in degrees, measured from `direction`
+    pub fn new(
+        position: Vec3,
+        direction: Vec3,
+        intensity: Color,
+        inner_angle: Float,
+        outer_angle: Float,
+    ) -> Self {
+        Self {
+            position,
+            direction: direction.normalize(),
+            intensity,
+            cos_inner: inner_angle.to_radians().cos(),
+            cos_outer: outer_angle.max(inner_angle).to_radians().cos(),
+        }
+    }
+
+    /// 1 inside the inner cone, 0 outside the outer cone, and smooth in between
+    fn falloff(&self, cos_angle: Float) -> Float {
+        if cos_angle >= self.cos_inner {
+            1.
+        } else if cos_angle <= self.cos_outer {
+            0.
+        } else {
+            let t = (cos_angle - self.cos_outer) / (self.cos_inner - self.cos_outer);
+            t * t * (3. - 2. * t)
+        }
+    }
+}
+
+impl Light for SpotLight {
+    fn sample(&self, point: Vec3) -> Option<LightSample> {
+        let offset = self.position - point;
+        let distance_squared = offset.length_squared();
+        let direction = offset.normalize();
+        let falloff = self.falloff((-direction).dot(self.direction));
+        if falloff <= 0. {
+            return None;
+        }
+        Some(LightSample {
+            direction,
+            distance: distance_squared.sqrt(),
+            radiance: falloff * self.intensity / distance_squared,
+            pdf: Float::INFINITY,
+        })
+    }
+
+    /// picks directions evenly over the outer cone
+    fn emit(&self, _bounds: (Vec3, Float)) -> Option<Photon> {
+        let solid_angle = 2. * PI * (1. - self.cos_outer);
+        if solid_angle <= 0. {
+            return None;
+        }
+        let z = 1. + random::<Float>() * (self.cos_outer - 1.);
+        let phi = 2. * PI * random::<Float>();
+        let sin = (1. - z * z).sqrt();
+        let direction = Onb::new(self.direction).local(sin * phi.cos(), sin * phi.sin(), z);
+        Some(Photon {
+            ray: Ray::new(self.position, direction),
+            power: self.falloff(z) * solid_angle * self.intensity,
+        })
+    }
+}
+
+/// a light infinitely far away, such as the sun, shining along a single direction.
+/// a non-zero angular radius spreads it over a small disk in the sky, softening its shadows
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DirectionalLight {
+    /// unit direction the light travels in
+    pub direction: Vec3,
+    /// irradiance on a surface facing the light. does not fall off with distance
+    pub intensity: Color,
+    /// cosine of the angular radius of the disk
+    cos_radius: Float,
+}
+
+impl DirectionalLight {
+    /// constructor. the angular radius is in degrees, the sun's being about 0.27
+    pub fn new(direction: Vec3, intensity: Color, angular_radius: Float) -> Self {
+        Self {
+            direction: direction.normalize(),
+            intensity,
+            cos_radius: angular_radius.clamp(0., 90.).to_radians().cos(),
+        }
+    }
+
+    /// angular radius of the disk in degrees
+    pub fn angular_radius(&self) -> Float {
+        self.cos_radius.acos().to_degrees()
+    }
+}
+
+impl DirectionalLight {
+    /// a direction towards the light, uniform over the cone of directions the disk covers
+    fn sample_direction(&self) -> Vec3 {
+        let w = -self.direction;
+        if self.cos_radius >= 1. {
+            return w;
+        }
+        let z = 1. + random::<Float>() * (self.cos_radius - 1.);
+        let phi = 2. * PI * random::<Float>();
+        let sin = (1. - z * z).sqrt();
+        Onb::new(w).local(sin * phi.cos(), sin * phi.sin(), z)
+    }
+}
+
+impl Light for DirectionalLight {
+    fn sample(&self, _point: Vec3) -> Option<LightSample> {
+        Some(LightSample {
+            direction: self.sample_direction(),
+            distance: Float::INFINITY,
+            // radiance over the disk divided by the density of picking a direction on it
+            radiance: self.intensity,
+            // scattered rays never reach it, as it has no shape in the world
+            pdf: Float::INFINITY,
+        })
+    }
+
+    /// sends photons in from a disk across the bounds, facing the light. anything outside
+    /// them doesn't cast a shadow
+    fn emit(&self, (center, radius): (Vec3, Float)) -> Option<Photon> {
+        let direction = -self.sample_direction();
+        let (r, phi) = (
+            random::<Float>().sqrt() * radius,
+            2. * PI * random::<Float>(),
+        );
+        let offset = Onb::new(direction).local(r * phi.cos(), r * phi.sin(), -radius);
+        Some(Photon {
+            ray: Ray::new(center + offset, direction),
+            power: PI * radius * radius * self.intensity,
+        })
+    }
+}
+
+/// an emissive shape sampled as a light, by picking points on its surface
+#[derive(Clone)]
+pub struct AreaLight {
+    pub shape: Arc<dyn Shape + Send + Sync + 'static>,
+}
+
+impl Light for AreaLight {
+    fn sample(&self, point: Vec3) -> Option<LightSample> {
+        let target = self.shape.sample_point(point)?;
+        let direction = (target - point).normalize();
+        let pdf = self.shape.pdf_value(point, direction);
+        if pdf <= 0. {
+            return None;
+        }
+        // find the sampled point again, for the material that emits from it
+        let contact = self
+            .shape
+            .hit(Ray::new(point, direction), 0.0..Float::INFINITY)?;
+        Some(LightSample {
+            direction,
+            distance: contact.t,
+            radiance: contact.material.emitted(&contact) / pdf,
+            pdf,
+        })
+    }
+
+    fn pdf(&self, origin: Vec3, direction: Vec3) -> Float {
+        self.shape.pdf_value(origin, direction)
+    }
+
+    /// sends photons out of either side of the surface, cosine weighted
+    fn emit(&self, _bounds: (Vec3, Float)) -> Option<Photon> {
+        let surface = self.shape.sample_surface()?;
+        let side = if random::<bool>() {
+            surface.outward_normal
+        } else {
+            -surface.outward_normal
+        };
+        // find the point again from the side it leaves, for the light given off there
+        let error = rounding_error(surface.point);
+        let origin = surface.point + 2. * error * side;
+        let contact = self.shape.hit(Ray::new(origin, -side), 0.0..4. * error)?;
+        Some(Photon {
+            ray: contact.spawn(Ray::new(origin, side), Vec3::random_cosine_direction(side)),
+            // over the cosine weighted density, and the half chance of picking the side
+            power: 2. * PI * surface.area * contact.material.emitted(&contact),
+        })
+    }
+}
+
+/// picks among cells in proportion to their weights, spread as a density over [0, 1)
+#[derive(Clone, Debug)]
+struct Distribution {
+    /// running sums of the weights, from 0 up to 1
+    cdf: Vec<Float>,
+    /// sum of the weights, before they're scaled to 1
+    total: Float,
+}
+
+impl Distribution {
+    fn new(weights: impl IntoIterator<Item = Float>) -> Self {
+        let mut cdf = vec![0.];
+        let mut total = 0.;
+        for weight in weights {
+            total += weight.max(0.);
+            cdf.push(total);
+        }
+        if total > 0. {
+            cdf.iter_mut().for_each(|sum| *sum /= total);
+        }
+        Self { cdf, total }
+    }
+
+    fn len(&self) -> usize {
+        self.cdf.len() - 1
+    }
+
+    /// the cell `r` in [0, 1) lands in, and how far across it
+    fn sample(&self, r: Float) -> (usize, Float) {
+        let cell = self
+            .cdf
+            .partition_point(|&sum| sum <= r)
+            .saturating_sub(1)
+            .min(self.len() - 1);
+        let (low, high) = (self.cdf[cell], self.cdf[cell + 1]);
+        let across = if high > low {
+            (r - low) / (high - low)
+        } else {
+            0.5
+        };
+        (cell, across)
+    }
+
+    /// density of landing in the cell, over [0, 1)
+    fn pdf(&self, cell: usize) -> Float {
+        (self.cdf[cell + 1] - self.cdf[cell]) * self.len() as Float
+    }
+}
+
+/// an environment map sampled as a light, picking directions in proportion to how bright the
+/// map is there. a small sun in it is found by shadow rays straight away, rather than waiting
+/// for scattered rays to stumble on it
+#[derive(Clone)]
+pub struct EnvironmentLight {
+    pub map: Arc<EnvironmentMap>,
+    /// over the rows of the map's grid, bottom to top
+    rows: Distribution,
+    /// over the cells of each row, in the map's u
+    columns: Vec<Distribution>,
+}
+
+impl EnvironmentLight {
+    /// constructor. looks the map up once for each cell of its grid
+    pub fn new(map: Arc<EnvironmentMap>) -> Self {
+        let (width, height) = (map.resolution.0.max(1), map.resolution.1.max(1));
+        let columns: Vec<_> = (0..height)
+            .map(|row| {
+                let v = (row as Float + 0.5) / height as Float;
+                // rows near the poles cover less of the sky
+                let sin = (v * PI).sin();
+                Distribution::new((0..width).map(|column| {
+                    let u = (column as Float + 0.5) / width as Float;
+                    let ray = Ray::new(Vec3::ZERO, EnvironmentMap::direction(u, v));
+                    map.color(ray).luminance() * sin
+                }))
+            })
+            .collect();
+        Self {
+            map,
+            rows: Distribution::new(columns.iter().map(|row| row.total)),
+            columns,
+        }
+    }
+
+    /// density per steradian of picking the direction at `v` in the given cell
+    fn cell_pdf(&self, row: usize, column: usize, v: Float) -> Float {
+        let sin = (v * PI).sin();
+        if sin <= 0. {
+            return 0.;
+        }
+        // from the unit square the map's grid covers to the sphere of directions
+        self.rows.pdf(row) * self.columns[row].pdf(column) / (2. * PI * PI * sin)
+    }
+
+    /// a unit direction towards the map, with its density per steradian
+    fn sample_direction(&self) -> Option<(Vec3, Float)> {
+        if self.rows.total <= 0. {
+            return None;
+        }
+        let (row, across_row) = self.rows.sample(random());
+        let (column, across_column) = self.columns[row].sample(random());
+        let u = (column as Float + across_column) / self.columns[row].len() as Float;
+        let v = (row as Float + across_row) / self.rows.len() as Float;
+        let pdf = self.cell_pdf(row, column, v);
+        (pdf > 0.).then(|| (EnvironmentMap::direction(u, v), pdf))
+    }
+}
+
+impl Light for EnvironmentLight {
+    fn sample(&self, point: Vec3) -> Option<LightSample> {
+        let (direction, pdf) = self.sample_direction()?;
+        Some(LightSample {
+            direction,
+            distance: Float::INFINITY,
+            radiance: self.map.color(Ray::new(point, direction)) / pdf,
+            pdf,
+        })
+    }
+
+    fn pdf(&self, _origin: Vec3, direction: Vec3) -> Float {
+        if self.rows.total <= 0. {
+            return 0.;
+        }
+        let (u, v) = EnvironmentMap::uv(direction.normalize());
+        let cell = |t: Float, cells: usize| ((t * cells as Float) as usize).min(cells - 1);
+        let row = cell(v, self.rows.len());
+        let column = cell(u, self.columns[row].len());
+        self.cell_pdf(row, column, v)
+    }
+
+    /// sends photons in from a disk across the bounds, facing the direction picked
+    fn emit(&self, (center, radius): (Vec3, Float)) -> Option<Photon> {
+        let (towards, pdf) = self.sample_direction()?;
+        let direction = -towards;
+        let (r, phi) = (
+            random::<Float>().sqrt() * radius,
+            2. * PI * random::<Float>(),
+        );
+        let offset = Onb::new(direction).local(r * phi.cos(), r * phi.sin(), -radius);
+        Some(Photon {
+            ray: Ray::new(center + offset, direction),
+            power: PI * radius * radius * self.map.color(Ray::new(center, towards)) / pdf,
+        })
+    }
+}
+
+#[test]
+fn environment_light() {
+    use super::{HdrTexture, World};
+    use image::{Rgb, Rgb32FImage};
+    crate::math::seed_rng(3);
+    let map = |image: Rgb32FImage| {
+        let (width, height) = image.dimensions();
+        Arc::new(EnvironmentMap::new(HdrTexture::new(image)).with_resolution(width, height))
+    };
+
+    // an even gray sky lights a surface facing up with pi times its radiance
+    let sky = EnvironmentLight::new(map(Rgb32FImage::from_pixel(64, 32, Rgb([1.; 3]))));
+    let n = 20000;
+    let irradiance = (0..n)
+        .filter_map(|_| sky.sample(Vec3::ZERO))
+        .map(|sample| sample.radiance.g * sample.direction.y.max(0.))
+        .sum::<Float>()
+        / n as Float;
+    assert!((irradiance - PI).abs() < 0.05 * PI);
+
+    // a sky that's black but for one bright pixel only ever picks that pixel
+    let mut image = Rgb32FImage::new(64, 32);
+    image.put_pixel(40, 10, Rgb([1000.; 3]));
+    let sun = EnvironmentLight::new(map(image));
+    for _ in 0..100 {
+        let sample = sun.sample(Vec3::ZERO).unwrap();
+        assert_eq!(
+            sun.map.color(Ray::new(Vec3::ZERO, sample.direction)).g,
+            1000.
+        );
+        let pdf = sun.pdf(Vec3::ZERO, sample.direction);
+        assert!((pdf - sample.pdf).abs() < 1e-6 * pdf);
+    }
+    assert_eq!(sun.pdf(Vec3::ZERO, Vec3::Y), 0.);
+
+    // replacing the background takes the light sampling the old one along
+    let mut world = World::new();
+    world.add_light(PointLight::new(Vec3::Y, Color::WHITE));
+    world.set_environment(EnvironmentMap::new(HdrTexture::new(Rgb32FImage::new(2, 1))));
+    assert_eq!((world.lights.len(), world.environment), (2, Some(1)));
+    world.set_background(super::SolidBackground::BLACK);
+    assert_eq!((world.lights.len(), world.environment), (1, None));
+}
@@ -1,44 +1,231 @@
-use super::{Color, Ray, RayContact};
+use super::{cauchy_index, downcast, Color, Ray, RayContact, SolidColor, Texture};
+use crate::math::consts::PI;
 use crate::math::*;
-use rand::prelude::*;
+use std::sync::Arc;
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct RayScatter {
     pub ray: Ray,
+    /// brdf * cosine / pdf of the scattered direction
     pub attenuation: Color,
+    /// probability density, per steradian, the direction was sampled with.
+    /// None when lights aren't also sampled from here, as for perfectly specular scattering,
+    /// which picks its one direction with certainty
+    pub pdf: Option<Float>,
 }
 
 pub trait Material {
     fn scatter(&self, ray: Ray, contact: &RayContact) -> Option<RayScatter>;
+
+    /// light given off by the surface at the contact point
+    fn emitted(&self, _contact: &RayContact) -> Color {
+        Color::BLACK
+    }
+
+    /// fraction of light arriving from `direction` that is reflected back along `ray`, per steradian.
+    /// used when sampling lights directly, so perfectly specular materials that can only be lit
+    /// through the rays they scatter leave this as black
+    fn brdf(&self, _ray: Ray, _contact: &RayContact, _direction: Vec3) -> Color {
+        Color::BLACK
+    }
+
+    /// probability density, per steradian, of `scatter` sending the ray out along `direction`.
+    /// used to weigh scattered rays against sampled lights, so materials whose `brdf` is black
+    /// can leave this as zero
+    fn scattering_pdf(&self, _ray: Ray, _contact: &RayContact, _direction: Vec3) -> Float {
+        0.
+    }
+
+    /// overall surface color at the contact, for the albedo pass denoisers are guided by.
+    /// white for materials without one, such as glass
+    fn albedo(&self, _contact: &RayContact) -> Color {
+        Color::WHITE
+    }
+
+    /// what's inside the surface, for materials bounding a volume that rays refract into
+    fn interior(&self, _ray: Ray) -> Option<Interior> {
+        None
+    }
+
+    /// chance of a ray stopping at the contact rather than passing straight through it, for
+    /// cutouts like leaves and fences
+    fn opacity(&self, _contact: &RayContact) -> Float {
+        1.
+    }
+
+    /// whether the opacity is 1 everywhere, so hits don't need rolling against it
+    fn is_opaque(&self) -> bool {
+        true
+    }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+/// shared materials, such as ones picked at runtime, are materials too
+impl<M: Material + ?Sized> Material for Arc<M> {
+    fn scatter(&self, ray: Ray, contact: &RayContact) -> Option<RayScatter> {
+        (**self).scatter(ray, contact)
+    }
+
+    fn emitted(&self, contact: &RayContact) -> Color {
+        (**self).emitted(contact)
+    }
+
+    fn brdf(&self, ray: Ray, contact: &RayContact, direction: Vec3) -> Color {
+        (**self).brdf(ray, contact, direction)
+    }
+
+    fn scattering_pdf(&self, ray: Ray, contact: &RayContact, direction: Vec3) -> Float {
+        (**self).scattering_pdf(ray, contact, direction)
+    }
+
+    fn albedo(&self, contact: &RayContact) -> Color {
+        (**self).albedo(contact)
+    }
+
+    fn interior(&self, ray: Ray) -> Option<Interior> {
+        (**self).interior(ray)
+    }
+
+    fn opacity(&self, contact: &RayContact) -> Float {
+        (**self).opacity(contact)
+    }
+
+    fn is_opaque(&self) -> bool {
+        (**self).is_opaque()
+    }
+}
+
+#[derive(Clone)]
 pub struct Diffuse {
-    pub color: Color,
+    pub texture: Arc<dyn Texture + Send + Sync + 'static>,
+}
+
+impl Diffuse {
+    /// constructor
+    pub fn new<Tex>(texture: Tex) -> Self
+    where
+        Tex: Texture + Send + Sync + 'static,
+    {
+        Self {
+            texture: Arc::new(texture),
+        }
+    }
 }
 
 impl Material for Diffuse {
     /// returns the scattered ray and its corresponding attenuation
-    fn scatter(&self, _ray: Ray, contact: &RayContact) -> Option<RayScatter> {
-        let target = contact.point + Vec3::random_in_hemisphere(contact.normal);
+    fn scatter(&self, ray: Ray, contact: &RayContact) -> Option<RayScatter> {
+        // cosine weighted, so the lambertian brdf * cos / pdf is just the albedo
+        let direction = Vec3::random_cosine_direction(contact.normal);
         let scatter = RayScatter {
-            ray: Ray::new(contact.point, target - contact.point),
-            attenuation: self.color,
+            ray: contact.spawn(ray, direction),
+            attenuation: self.texture.at(contact),
+            pdf: Some(self.scattering_pdf(ray, contact, direction)),
         };
         Some(scatter)
     }
+
+    fn brdf(&self, _ray: Ray, contact: &RayContact, direction: Vec3) -> Color {
+        if direction.dot(contact.normal) <= 0. {
+            return Color::BLACK;
+        }
+        self.texture.at(contact) / PI
+    }
+
+    fn scattering_pdf(&self, _ray: Ray, contact: &RayContact, direction: Vec3) -> Float {
+        (direction.dot(contact.normal) / PI).max(0.)
+    }
+
+    fn albedo(&self, contact: &RayContact) -> Color {
+        self.texture.at(contact)
+    }
 }
 
 impl From<Color> for Diffuse {
     fn from(color: Color) -> Self {
-        Self { color }
+        Self::new(SolidColor::from(color))
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+/// oren-nayar rough diffuse. microfacets let more light bounce back towards its source,
+/// flattening the shading of matte surfaces like clay or concrete compared to `Diffuse`
+#[derive(Clone)]
+pub struct OrenNayar {
+    pub texture: Arc<dyn Texture + Send + Sync + 'static>,
+    /// terms of the model, from the roughness
+    a: Float,
+    b: Float,
+}
+
+impl OrenNayar {
+    /// constructor. `roughness` is the standard deviation of the microfacet angles, in radians.
+    /// 0 is lambertian
+    pub fn new<Tex>(texture: Tex, roughness: Float) -> Self
+    where
+        Tex: Texture + Send + Sync + 'static,
+    {
+        let sigma2 = roughness * roughness;
+        Self {
+            texture: Arc::new(texture),
+            a: 1. - 0.5 * sigma2 / (sigma2 + 0.33),
+            b: 0.45 * sigma2 / (sigma2 + 0.09),
+        }
+    }
+}
+
+impl Material for OrenNayar {
+    fn scatter(&self, ray: Ray, contact: &RayContact) -> Option<RayScatter> {
+        let direction = Vec3::random_cosine_direction(contact.normal);
+        let pdf = self.scattering_pdf(ray, contact, direction);
+        if pdf <= 0. {
+            return None;
+        }
+        Some(RayScatter {
+            ray: contact.spawn(ray, direction),
+            attenuation: direction.dot(contact.normal) / pdf * self.brdf(ray, contact, direction),
+            pdf: Some(pdf),
+        })
+    }
+
+    fn brdf(&self, ray: Ray, contact: &RayContact, direction: Vec3) -> Color {
+        let n = contact.normal;
+        let to_eye = -ray.direction.normalize();
+        let (cos_in, cos_out) = (direction.dot(n), to_eye.dot(n).max(0.));
+        if cos_in <= 0. {
+            return Color::BLACK;
+        }
+        // cos(phi_in - phi_out) * sin(alpha) * tan(beta), where alpha and beta are the larger
+        // and smaller of the two polar angles, through the projections onto the surface
+        let projected = (direction - cos_in * n).dot(to_eye - cos_out * n).max(0.);
+        let angular = projected / cos_in.max(cos_out).max(1e-6);
+        self.texture.at(contact) * (self.a + self.b * angular) / PI
+    }
+
+    fn scattering_pdf(&self, _ray: Ray, contact: &RayContact, direction: Vec3) -> Float {
+        (direction.dot(contact.normal) / PI).max(0.)
+    }
+
+    fn albedo(&self, contact: &RayContact) -> Color {
+        self.texture.at(contact)
+    }
+}
+
+#[derive(Clone)]
 pub struct Metal {
-    pub color: Color,
-    pub fuzz: f64,
+    pub texture: Arc<dyn Texture + Send + Sync + 'static>,
+    pub fuzz: Float,
+}
+
+impl Metal {
+    /// constructor
+    pub fn new<Tex>(texture: Tex, fuzz: Float) -> Self
+    where
+        Tex: Texture + Send + Sync + 'static,
+    {
+        Self {
+            texture: Arc::new(texture),
+            fuzz,
+        }
+    }
 }
 
 impl Material for Metal {
@@ -49,53 +236,1092 @@ impl Material for Metal {
             None
         } else {
             Some(RayScatter {
-                ray: Ray::new(
-                    contact.point,
-                    reflected + self.fuzz * Vec3::random_unit_sphere(),
-                ),
-                attenuation: self.color,
+                ray: contact.spawn(ray, reflected + self.fuzz * Vec3::random_unit_sphere()),
+                attenuation: self.texture.at(contact),
+                pdf: None,
             })
         }
     }
+
+    fn albedo(&self, contact: &RayContact) -> Color {
+        self.texture.at(contact)
+    }
+}
+
+/// physically based metallic-roughness material, as used by glTF and most asset pipelines.
+/// a ggx microfacet specular lobe over a lambertian base, which metals lack
+#[derive(Clone)]
+pub struct Pbr {
+    pub base_color: Arc<dyn Texture + Send + Sync + 'static>,
+    /// 0 for dielectrics, 1 for metals, whose specular reflection is tinted by the base color
+    pub metallic: Float,
+    /// perceptual roughness in [0, 1]. squared into the ggx alpha
+    pub roughness: Float,
+    /// scales `metallic` by its blue channel and `roughness` by its green, as glTF packs them
+    pub metallic_roughness: Option<Arc<dyn Texture + Send + Sync + 'static>>,
+    /// light given off by the surface, on top of what it reflects
+    pub emission: Option<Arc<dyn Texture + Send + Sync + 'static>>,
+}
+
+impl Pbr {
+    /// constructor
+    pub fn new<Tex>(base_color: Tex, metallic: Float, roughness: Float) -> Self
+    where
+        Tex: Texture + Send + Sync + 'static,
+    {
+        Self {
+            base_color: Arc::new(base_color),
+            metallic: metallic.clamp(0., 1.),
+            roughness: roughness.clamp(0., 1.),
+            metallic_roughness: None,
+            emission: None,
+        }
+    }
+
+    /// varies metallic and roughness over the surface with a texture
+    pub fn with_metallic_roughness<Tex>(self, texture: Tex) -> Self
+    where
+        Tex: Texture + Send + Sync + 'static,
+    {
+        Self {
+            metallic_roughness: Some(Arc::new(texture)),
+            ..self
+        }
+    }
+
+    /// makes the surface glow with a texture
+    pub fn with_emission<Tex>(self, texture: Tex) -> Self
+    where
+        Tex: Texture + Send + Sync + 'static,
+    {
+        Self {
+            emission: Some(Arc::new(texture)),
+            ..self
+        }
+    }
+
+    /// metallic and the ggx alpha at the contact
+    fn parameters(&self, contact: &RayContact) -> (Float, Float) {
+        let (metallic, roughness) = match &self.metallic_roughness {
+            Some(texture) => {
+                let texel = texture.at(contact);
+                (
+                    (self.metallic * texel.b).clamp(0., 1.),
+                    (self.roughness * texel.g).clamp(0., 1.),
+                )
+            }
+            None => (self.metallic, self.roughness),
+        };
+        (metallic, (roughness * roughness).max(1e-3))
+    }
+}
+
+/// chance of sampling the specular lobe of a pbr material rather than the diffuse one
+fn specular_probability(metallic: Float) -> Float {
+    0.5 + 0.5 * metallic
+}
+
+/// ggx normal distribution, for a half vector at `cos` to the normal
+fn ggx_d(cos: Float, alpha: Float) -> Float {
+    let a2 = alpha * alpha;
+    let denominator = cos * cos * (a2 - 1.) + 1.;
+    a2 / (PI * denominator * denominator)
+}
+
+/// smith masking of a single direction at `cos` to the normal, for the ggx distribution
+fn smith_g1(cos: Float, alpha: Float) -> Float {
+    let a2 = alpha * alpha;
+    2. * cos / (cos + (a2 + (1. - a2) * cos * cos).sqrt())
+}
+
+/// schlick's approximation of fresnel reflectance
+fn schlick(f0: Color, cos: Float) -> Color {
+    f0 + (1. - cos).max(0.).powi(5) * (Color::WHITE - f0)
+}
+
+impl Material for Pbr {
+    fn scatter(&self, ray: Ray, contact: &RayContact) -> Option<RayScatter> {
+        let n = contact.normal;
+        let (metallic, alpha) = self.parameters(contact);
+        let direction = if random::<Float>() < specular_probability(metallic) {
+            // pick a microfacet normal proportionally to its projected area, and mirror off it
+            let a2 = alpha * alpha;
+            let r = random::<Float>();
+            let cos = ((1. - r) / (1. + (a2 - 1.) * r)).sqrt();
+            let sin = (1. - cos * cos).max(0.).sqrt();
+            let phi = 2. * PI * random::<Float>();
+            let half = Onb::new(n).local(sin * phi.cos(), sin * phi.sin(), cos);
+            ray.direction.normalize().reflect(half)
+        } else {
+            Vec3::random_cosine_direction(n)
+        };
+        let cos = direction.dot(n);
+        let pdf = self.scattering_pdf(ray, contact, direction);
+        if cos <= 0. || pdf <= 0. {
+            return None;
+        }
+        Some(RayScatter {
+            ray: contact.spawn(ray, direction),
+            attenuation: cos / pdf * self.brdf(ray, contact, direction),
+            pdf: Some(pdf),
+        })
+    }
+
+    fn brdf(&self, ray: Ray, contact: &RayContact, direction: Vec3) -> Color {
+        let n = contact.normal;
+        let to_eye = -ray.direction.normalize();
+        let (cos_in, cos_out) = (direction.dot(n), to_eye.dot(n));
+        if cos_in <= 0. || cos_out <= 0. {
+            return Color::BLACK;
+        }
+        let half = (direction + to_eye).normalize();
+        let base = self.base_color.at(contact);
+        let (metallic, alpha) = self.parameters(contact);
+        let f0 = (1. - metallic) * Color::new(0.04, 0.04, 0.04) + metallic * base;
+        let fresnel = schlick(f0, direction.dot(half));
+
+        let specular =
+            ggx_d(half.dot(n), alpha) * smith_g1(cos_in, alpha) * smith_g1(cos_out, alpha)
+                / (4. * cos_in * cos_out)
+                * fresnel;
+        let diffuse = (1. - metallic) / PI * (Color::WHITE - fresnel) * base;
+        specular + diffuse
+    }
+
+    fn scattering_pdf(&self, ray: Ray, contact: &RayContact, direction: Vec3) -> Float {
+        let n = contact.normal;
+        let to_eye = -ray.direction.normalize();
+        let cos_in = direction.dot(n);
+        if cos_in <= 0. {
+            return 0.;
+        }
+        let half = (direction + to_eye).normalize();
+        let (cos_half, cos_eye) = (half.dot(n), to_eye.dot(half));
+        let (metallic, alpha) = self.parameters(contact);
+        let specular = if cos_half > 0. && cos_eye > 0. {
+            ggx_d(cos_half, alpha) * cos_half / (4. * cos_eye)
+        } else {
+            0.
+        };
+        let p = specular_probability(metallic);
+        p * specular + (1. - p) * cos_in / PI
+    }
+
+    fn emitted(&self, contact: &RayContact) -> Color {
+        match &self.emission {
+            Some(emission) => emission.at(contact),
+            None => Color::BLACK,
+        }
+    }
+
+    fn albedo(&self, contact: &RayContact) -> Color {
+        self.base_color.at(contact)
+    }
+}
+
+impl From<Color> for Pbr {
+    /// a rough dielectric
+    fn from(color: Color) -> Self {
+        Self::new(SolidColor::from(color), 0., 0.5)
+    }
+}
+
+/// brushed metal, whose microfacets are rougher along one surface direction than the other,
+/// stretching highlights across the grain. the grain follows the contact's tangent frame
+#[derive(Clone)]
+pub struct AnisotropicMetal {
+    pub texture: Arc<dyn Texture + Send + Sync + 'static>,
+    /// perceptual roughness along the tangent and along the bitangent, in [0, 1]
+    pub roughness: (Float, Float),
+}
+
+impl AnisotropicMetal {
+    /// constructor
+    pub fn new<Tex>(texture: Tex, tangent_roughness: Float, bitangent_roughness: Float) -> Self
+    where
+        Tex: Texture + Send + Sync + 'static,
+    {
+        Self {
+            texture: Arc::new(texture),
+            roughness: (
+                tangent_roughness.clamp(0., 1.),
+                bitangent_roughness.clamp(0., 1.),
+            ),
+        }
+    }
+
+    /// ggx alphas along the tangent and bitangent
+    fn alpha(&self) -> (Float, Float) {
+        let (x, y) = self.roughness;
+        ((x * x).max(1e-3), (y * y).max(1e-3))
+    }
+
+    /// tangent, bitangent and normal, made orthonormal around the shading normal
+    fn frame(contact: &RayContact) -> Onb {
+        Onb::with_tangent(contact.normal, contact.tangent)
+    }
+}
+
+/// anisotropic ggx normal distribution, for a half vector in tangent space
+fn ggx_d_anisotropic(h: Vec3, (ax, ay): (Float, Float)) -> Float {
+    let k = (h.x / ax).powi(2) + (h.y / ay).powi(2) + h.z * h.z;
+    1. / (PI * ax * ay * k * k)
+}
+
+/// smith masking of a single tangent space direction, for the anisotropic ggx distribution
+fn smith_g1_anisotropic(w: Vec3, (ax, ay): (Float, Float)) -> Float {
+    let tan2 = ((ax * w.x).powi(2) + (ay * w.y).powi(2)) / (w.z * w.z);
+    2. / (1. + (1. + tan2).sqrt())
+}
+
+impl Material for AnisotropicMetal {
+    fn scatter(&self, ray: Ray, contact: &RayContact) -> Option<RayScatter> {
+        // sample the slopes of a unit roughness surface, then stretch them along each axis
+        let (ax, ay) = self.alpha();
+        let r = random::<Float>();
+        let slope = (r / (1. - r)).sqrt();
+        let phi = 2. * PI * random::<Float>();
+        let half = Self::frame(contact)
+            .local(ax * slope * phi.cos(), ay * slope * phi.sin(), 1.)
+            .normalize();
+        let direction = ray.direction.normalize().reflect(half);
+
+        let cos = direction.dot(contact.normal);
+        let pdf = self.scattering_pdf(ray, contact, direction);
+        if cos <= 0. || pdf <= 0. {
+            return None;
+        }
+        Some(RayScatter {
+            ray: contact.spawn(ray, direction),
+            attenuation: cos / pdf * self.brdf(ray, contact, direction),
+            pdf: Some(pdf),
+        })
+    }
+
+    fn brdf(&self, ray: Ray, contact: &RayContact, direction: Vec3) -> Color {
+        let frame = Self::frame(contact);
+        let to_eye = frame.to_local(-ray.direction.normalize());
+        let to_light = frame.to_local(direction);
+        if to_light.z <= 0. || to_eye.z <= 0. {
+            return Color::BLACK;
+        }
+        let half = (to_light + to_eye).normalize();
+        let albedo = self.texture.at(contact);
+        let alpha = self.alpha();
+        ggx_d_anisotropic(half, alpha)
+            * smith_g1_anisotropic(to_light, alpha)
+            * smith_g1_anisotropic(to_eye, alpha)
+            / (4. * to_light.z * to_eye.z)
+            * schlick(albedo, to_light.dot(half))
+    }
+
+    fn scattering_pdf(&self, ray: Ray, contact: &RayContact, direction: Vec3) -> Float {
+        let frame = Self::frame(contact);
+        let to_eye = frame.to_local(-ray.direction.normalize());
+        let to_light = frame.to_local(direction);
+        let half = (to_light + to_eye).normalize();
+        let cos_eye = to_eye.dot(half);
+        if to_light.z <= 0. || half.z <= 0. || cos_eye <= 0. {
+            return 0.;
+        }
+        ggx_d_anisotropic(half, self.alpha()) * half.z / (4. * cos_eye)
+    }
+
+    fn albedo(&self, contact: &RayContact) -> Color {
+        self.texture.at(contact)
+    }
+}
+
+/// perturbs the shading normal of another material with a tangent-space normal map,
+/// where red, green and blue encode offsets along the tangent, bitangent and normal
+#[derive(Clone)]
+pub struct NormalMap<M: Material> {
+    pub material: M,
+    pub normals: Arc<dyn Texture + Send + Sync + 'static>,
+    /// scales the tangent-space offsets. 1 applies the map as-is, 0 disables it
+    pub strength: Float,
+}
+
+impl<M: Material> NormalMap<M> {
+    /// constructor
+    pub fn new<Tex>(material: M, normals: Tex, strength: Float) -> Self
+    where
+        Tex: Texture + Send + Sync + 'static,
+    {
+        Self {
+            material,
+            normals: Arc::new(normals),
+            strength,
+        }
+    }
+
+    /// the contact with its normal replaced by the mapped one
+    fn perturb<'a>(&self, contact: &RayContact<'a>) -> RayContact<'a> {
+        let (u, v) = contact.uv;
+        let texel = self.normals.value(u, v, contact.point);
+        let offset = 2. * Vec3::from(texel) - Vec3::ONE;
+        // green follows the bitangent, whichever side of the tangent it's on
+        let frame = Onb::with_tangent(contact.normal, contact.tangent);
+        let handedness = if frame.v.dot(contact.bitangent) < 0. {
+            -1.
+        } else {
+            1.
+        };
+        let normal = frame.local(
+            self.strength * offset.x,
+            handedness * self.strength * offset.y,
+            offset.z,
+        );
+        RayContact {
+            normal: normal.normalize(),
+            ..contact.clone()
+        }
+    }
+}
+
+impl<M: Material> Material for NormalMap<M> {
+    fn scatter(&self, ray: Ray, contact: &RayContact) -> Option<RayScatter> {
+        self.material.scatter(ray, &self.perturb(contact))
+    }
+
+    fn emitted(&self, contact: &RayContact) -> Color {
+        self.material.emitted(contact)
+    }
+
+    fn brdf(&self, ray: Ray, contact: &RayContact, direction: Vec3) -> Color {
+        self.material.brdf(ray, &self.perturb(contact), direction)
+    }
+
+    fn scattering_pdf(&self, ray: Ray, contact: &RayContact, direction: Vec3) -> Float {
+        self.material
+            .scattering_pdf(ray, &self.perturb(contact), direction)
+    }
+
+    fn albedo(&self, contact: &RayContact) -> Color {
+        self.material.albedo(contact)
+    }
+
+    fn interior(&self, ray: Ray) -> Option<Interior> {
+        self.material.interior(ray)
+    }
+
+    fn opacity(&self, contact: &RayContact) -> Float {
+        self.material.opacity(contact)
+    }
+
+    fn is_opaque(&self) -> bool {
+        self.material.is_opaque()
+    }
+}
+
+/// cuts holes into a material where the alpha texture is dark, its brightness being the chance
+/// of a ray stopping at the surface rather than passing through. makes leaves, fences and
+/// billboards out of flat shapes
+#[derive(Clone)]
+pub struct Cutout<M: Material> {
+    pub material: M,
+    pub alpha: Arc<dyn Texture + Send + Sync + 'static>,
+}
+
+impl<M: Material> Cutout<M> {
+    /// constructor. `ImageTexture::open_alpha` reads the alpha channel of an image to cut by
+    pub fn new<Tex>(material: M, alpha: Tex) -> Self
+    where
+        Tex: Texture + Send + Sync + 'static,
+    {
+        Self {
+            material,
+            alpha: Arc::new(alpha),
+        }
+    }
+}
+
+impl<M: Material> Material for Cutout<M> {
+    fn scatter(&self, ray: Ray, contact: &RayContact) -> Option<RayScatter> {
+        self.material.scatter(ray, contact)
+    }
+
+    fn emitted(&self, contact: &RayContact) -> Color {
+        self.material.emitted(contact)
+    }
+
+    fn brdf(&self, ray: Ray, contact: &RayContact, direction: Vec3) -> Color {
+        self.material.brdf(ray, contact, direction)
+    }
+
+    fn scattering_pdf(&self, ray: Ray, contact: &RayContact, direction: Vec3) -> Float {
+        self.material.scattering_pdf(ray, contact, direction)
+    }
+
+    fn albedo(&self, contact: &RayContact) -> Color {
+        self.material.albedo(contact)
+    }
+
+    fn interior(&self, ray: Ray) -> Option<Interior> {
+        self.material.interior(ray)
+    }
+
+    /// the average of the alpha texture's channels
+    fn opacity(&self, contact: &RayContact) -> Float {
+        let alpha = self.alpha.at(contact);
+        ((alpha.r + alpha.g + alpha.b) / 3.).clamp(0., 1.) * self.material.opacity(contact)
+    }
+
+    fn is_opaque(&self) -> bool {
+        false
+    }
+}
+
+/// blends two materials, picking between them per scattered ray.
+/// the mask texture's brightness is the chance of picking `b`, so a constant factor blends
+/// the whole surface evenly and an image mask can lay one material over another
+#[derive(Clone)]
+pub struct Mix<A: Material, B: Material> {
+    pub a: A,
+    pub b: B,
+    pub mask: Arc<dyn Texture + Send + Sync + 'static>,
+}
+
+impl<A: Material, B: Material> Mix<A, B> {
+    /// constructor for an even blend. 0 is all `a`, 1 is all `b`
+    pub fn new(a: A, b: B, factor: Float) -> Self {
+        Self::masked(a, b, SolidColor::from(Color::WHITE * factor))
+    }
+
+    /// constructor blending by the average of the mask's channels
+    pub fn masked<Tex>(a: A, b: B, mask: Tex) -> Self
+    where
+        Tex: Texture + Send + Sync + 'static,
+    {
+        Self {
+            a,
+            b,
+            mask: Arc::new(mask),
+        }
+    }
+
+    /// weight of `b` at the contact
+    fn factor(&self, contact: &RayContact) -> Float {
+        let m = self.mask.at(contact);
+        ((m.r + m.g + m.b) / 3.).clamp(0., 1.)
+    }
+}
+
+impl<A: Material, B: Material> Material for Mix<A, B> {
+    fn scatter(&self, ray: Ray, contact: &RayContact) -> Option<RayScatter> {
+        let scatter = if random::<Float>() < self.factor(contact) {
+            self.b.scatter(ray, contact)?
+        } else {
+            self.a.scatter(ray, contact)?
+        };
+        // a specular pick is weighed by the chance of picking it, which cancels with the blend.
+        // any other direction could have come from either material, so weigh it by both
+        if scatter.pdf.is_none() {
+            return Some(scatter);
+        }
+        let direction = scatter.ray.direction.normalize();
+        let pdf = self.scattering_pdf(ray, contact, direction);
+        if pdf <= 0. {
+            return Some(scatter);
+        }
+        Some(RayScatter {
+            attenuation: direction.dot(contact.normal).max(0.) / pdf
+                * self.brdf(ray, contact, direction),
+            pdf: Some(pdf),
+            ..scatter
+        })
+    }
+
+    fn emitted(&self, contact: &RayContact) -> Color {
+        let t = self.factor(contact);
+        self.a.emitted(contact).lerp(self.b.emitted(contact), t)
+    }
+
+    fn brdf(&self, ray: Ray, contact: &RayContact, direction: Vec3) -> Color {
+        let t = self.factor(contact);
+        self.a
+            .brdf(ray, contact, direction)
+            .lerp(self.b.brdf(ray, contact, direction), t)
+    }
+
+    fn scattering_pdf(&self, ray: Ray, contact: &RayContact, direction: Vec3) -> Float {
+        let t = self.factor(contact);
+        (1. - t) * self.a.scattering_pdf(ray, contact, direction)
+            + t * self.b.scattering_pdf(ray, contact, direction)
+    }
+
+    fn albedo(&self, contact: &RayContact) -> Color {
+        let t = self.factor(contact);
+        self.a.albedo(contact).lerp(self.b.albedo(contact), t)
+    }
+}
+
+/// phase function of a medium that scatters light evenly in every direction
+#[derive(Clone)]
+pub struct Isotropic {
+    pub texture: Arc<dyn Texture + Send + Sync + 'static>,
+}
+
+impl Isotropic {
+    /// constructor
+    pub fn new<Tex>(texture: Tex) -> Self
+    where
+        Tex: Texture + Send + Sync + 'static,
+    {
+        Self {
+            texture: Arc::new(texture),
+        }
+    }
+}
+
+impl Material for Isotropic {
+    fn scatter(&self, ray: Ray, contact: &RayContact) -> Option<RayScatter> {
+        Some(RayScatter {
+            ray: contact.spawn(ray, Vec3::random_unit()),
+            attenuation: self.texture.at(contact),
+            // lights are only sampled from surfaces
+            pdf: None,
+        })
+    }
+
+    fn albedo(&self, contact: &RayContact) -> Color {
+        self.texture.at(contact)
+    }
+}
+
+impl From<Color> for Isotropic {
+    fn from(color: Color) -> Self {
+        Self::new(SolidColor::from(color))
+    }
+}
+
+/// a light source. emits its texture from both sides, and absorbs everything that hits it.
+/// an image texture makes a glowing screen or sky panel out of it
+#[derive(Clone)]
+pub struct DiffuseLight {
+    pub texture: Arc<dyn Texture + Send + Sync + 'static>,
+    /// scales the texture, so images in [0, 1] can still light a scene brightly
+    pub intensity: Float,
+}
+
+impl DiffuseLight {
+    /// constructor
+    pub fn new<Tex>(texture: Tex, intensity: Float) -> Self
+    where
+        Tex: Texture + Send + Sync + 'static,
+    {
+        Self {
+            texture: Arc::new(texture),
+            intensity,
+        }
+    }
+}
+
+impl Material for DiffuseLight {
+    fn scatter(&self, _ray: Ray, _contact: &RayContact) -> Option<RayScatter> {
+        None
+    }
+
+    fn emitted(&self, contact: &RayContact) -> Color {
+        self.intensity * self.texture.at(contact)
+    }
+}
+
+impl From<Color> for OrenNayar {
+    /// a fairly rough surface, with a roughness of 0.5
+    fn from(color: Color) -> Self {
+        Self::new(SolidColor::from(color), 0.5)
+    }
+}
+
+impl From<Color> for DiffuseLight {
+    fn from(color: Color) -> Self {
+        Self::new(SolidColor::from(color), 1.)
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Dielectric {
-    pub refraction_index: f64,
+    /// at the sodium d line, 587.6nm
+    pub refraction_index: Float,
+    /// ggx roughness of the surface in [0, 1]. 0 is clear glass, higher values frost it
+    pub roughness: Float,
+    /// abbe number, for how much less the index is for red light than blue, splitting white
+    /// light into a rainbow when rendered spectrally. lower numbers split it further: crown
+    /// glass is around 60, flint glass 35, diamond 55. none refracts every wavelength alike
+    pub abbe: Option<Float>,
+    /// which of overlapping volumes fills the space they share, see `Interior`
+    pub priority: u32,
+}
+
+impl Dielectric {
+    /// smooth glass-like surface
+    pub fn new(refraction_index: Float) -> Self {
+        Self::rough(refraction_index, 0.)
+    }
+
+    /// frosted surface, refracting through microfacets
+    pub fn rough(refraction_index: Float, roughness: Float) -> Self {
+        Self {
+            refraction_index,
+            roughness: roughness.clamp(0., 1.),
+            abbe: None,
+            priority: 0,
+        }
+    }
+
+    /// disperses light with the given abbe number
+    pub fn with_dispersion(self, abbe: Float) -> Self {
+        Self {
+            abbe: Some(abbe),
+            ..self
+        }
+    }
+
+    /// fills the space it shares with lower priority volumes it overlaps. a glass of water
+    /// gives the glass the higher priority, and the water slightly overlapping its walls
+    pub fn with_priority(self, priority: u32) -> Self {
+        Self { priority, ..self }
+    }
+
+    /// the refraction index at the wavelength of a ray, in nanometers
+    pub fn refraction_index_at(&self, wavelength: Option<Float>) -> Float {
+        match (self.abbe, wavelength) {
+            (Some(abbe), Some(wavelength)) => cauchy_index(self.refraction_index, abbe, wavelength),
+            _ => self.refraction_index,
+        }
+    }
 }
 
 impl Material for Dielectric {
     fn scatter(&self, ray: Ray, contact: &RayContact) -> Option<RayScatter> {
         // schlick's approximation for reflectance
-        fn reflectance(cosine: f64, ref_idx: f64) -> f64 {
+        fn reflectance(cosine: Float, ref_idx: Float) -> Float {
             let mut r0 = (1. - ref_idx) / (1. + ref_idx);
             r0 *= r0;
             r0 + (1. - r0) * (1. - cosine).powf(5.)
         }
 
+        let refraction_index = self.refraction_index_at(ray.wavelength);
         let refraction_ratio = if contact.front_face {
-            1.0 / self.refraction_index
+            contact.outside_index / refraction_index
         } else {
-            self.refraction_index
+            refraction_index / contact.outside_index
         };
 
         let dir = ray.direction.normalize();
-        let cos_theta = (-dir).dot(contact.normal).min(1.0);
+        let alpha = (self.roughness * self.roughness).max(1e-3);
+        // rough surfaces reflect and refract through a microfacet normal picked from ggx
+        let normal = if self.roughness > 0. {
+            let a2 = alpha * alpha;
+            let r = random::<Float>();
+            let cos = ((1. - r) / (1. + (a2 - 1.) * r)).sqrt();
+            let sin = (1. - cos * cos).max(0.).sqrt();
+            let phi = 2. * PI * random::<Float>();
+            Onb::new(contact.normal).local(sin * phi.cos(), sin * phi.sin(), cos)
+        } else {
+            contact.normal
+        };
+        let cos_theta = (-dir).dot(normal).min(1.0);
+        if cos_theta <= 0. {
+            // the microfacet faces away from the ray
+            return None;
+        }
         let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
 
         let cannot_refract = refraction_ratio * sin_theta > 1.0;
 
-        let refracted =
-            if cannot_refract || reflectance(cos_theta, refraction_ratio) > random::<f64>() {
-                // cannot refract at this angle
-                dir.reflect(contact.normal)
-            } else {
-                dir.refract(contact.normal, refraction_ratio)
-            };
+        let reflect =
+            cannot_refract || reflectance(cos_theta, refraction_ratio) > random::<Float>();
+        let refracted = if reflect {
+            // cannot refract at this angle
+            dir.reflect(normal)
+        } else {
+            dir.refract(normal, refraction_ratio)
+        };
+
+        let attenuation = if self.roughness > 0. {
+            // leaves through the side it should, or is blocked by the surrounding microfacets
+            let cos_out = refracted.dot(contact.normal);
+            if reflect != (cos_out > 0.) {
+                return None;
+            }
+            // walter et al. weight for sampling microfacet normals by D * cos,
+            // clamped as it can spike at grazing angles
+            let cos_in = (-dir).dot(contact.normal);
+            let masking = smith_g1(cos_in, alpha) * smith_g1(cos_out.abs(), alpha);
+            let weight = cos_theta * masking / (cos_in * normal.dot(contact.normal));
+            weight.min(1.) * Color::WHITE
+        } else {
+            Color::WHITE
+        };
 
         Some(RayScatter {
-            ray: Ray::new(contact.point, refracted),
-            attenuation: Color::WHITE,
+            ray: contact.spawn(ray, refracted),
+            attenuation,
+            pdf: None,
+        })
+    }
+
+    fn interior(&self, ray: Ray) -> Option<Interior> {
+        Some(Interior {
+            refraction_index: self.refraction_index_at(ray.wavelength),
+            priority: self.priority,
         })
     }
 }
+
+/// the volume inside a closed refracting surface, like a glass or the water in it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Interior {
+    pub refraction_index: Float,
+    /// where volumes overlap, the one with the highest priority fills the overlap, and the
+    /// surfaces of the others are passed straight through inside it. ties go to the volume
+    /// entered last
+    pub priority: u32,
+}
+
+/// surfaces a path may pass straight through inside higher priority volumes, on top of its
+/// bounces, before it's given up on
+pub const MAX_PASS_THROUGHS: u32 = 64;
+
+/// the volumes a path is inside of, for refracting between nested ones by the index on either
+/// side rather than as if each were surrounded by air. paths are taken to start out in air
+#[derive(Clone, Debug, Default)]
+pub struct InteriorStack {
+    /// in the order they were entered, by the material of their surface
+    entered: Vec<(*const AnyMaterial, Interior)>,
+}
+
+impl InteriorStack {
+    /// called on reaching a surface, setting the index beyond it for the material to refract
+    /// by. false if the surface lies inside a higher priority volume, where the path should
+    /// carry on straight through it as if it wasn't there
+    pub fn hit(&mut self, ray: Ray, contact: &mut RayContact) -> bool {
+        let Some(interior) = contact.material.interior(ray) else {
+            return true;
+        };
+        let surrounding = self
+            .entered
+            .iter()
+            .filter(|(material, _)| !std::ptr::eq(*material, contact.material))
+            .map(|&(_, interior)| interior)
+            .max_by_key(|interior| interior.priority);
+        match surrounding {
+            Some(outside) if outside.priority > interior.priority => {
+                self.cross(contact, interior);
+                false
+            }
+            _ => {
+                contact.outside_index = surrounding.map_or(1., |outside| outside.refraction_index);
+                true
+            }
+        }
+    }
+
+    /// called with the ray a surface scattered, keeping track of it passing through
+    pub fn scattered(&mut self, contact: &RayContact, ray: Ray) {
+        let through = ray.direction.dot(contact.geometric_normal) < 0.;
+        if let (true, Some(interior)) = (through, contact.material.interior(ray)) {
+            self.cross(contact, interior);
+        }
+    }
+
+    fn cross(&mut self, contact: &RayContact, interior: Interior) {
+        if contact.front_face {
+            self.entered.push((contact.material, interior));
+        } else if let Some(i) = self
+            .entered
+            .iter()
+            .rposition(|(material, _)| std::ptr::eq(*material, contact.material))
+        {
+            self.entered.remove(i);
+        }
+    }
+}
+
+/// the built in materials, called without going through a vtable. any other material is kept
+/// behind an `Arc` in `Dyn`, so new ones can still be written outside the crate
+#[derive(Clone)]
+pub enum AnyMaterial {
+    Diffuse(Diffuse),
+    OrenNayar(OrenNayar),
+    Metal(Metal),
+    Pbr(Pbr),
+    AnisotropicMetal(AnisotropicMetal),
+    Isotropic(Isotropic),
+    DiffuseLight(DiffuseLight),
+    Dielectric(Dielectric),
+    Dyn(Arc<dyn Material + Send + Sync + 'static>),
+}
+
+/// calls the same method on whichever material is inside
+macro_rules! dispatch {
+    ($self:ident, $material:ident => $call:expr) => {
+        match $self {
+            AnyMaterial::Diffuse($material) => $call,
+            AnyMaterial::OrenNayar($material) => $call,
+            AnyMaterial::Metal($material) => $call,
+            AnyMaterial::Pbr($material) => $call,
+            AnyMaterial::AnisotropicMetal($material) => $call,
+            AnyMaterial::Isotropic($material) => $call,
+            AnyMaterial::DiffuseLight($material) => $call,
+            AnyMaterial::Dielectric($material) => $call,
+            AnyMaterial::Dyn($material) => $call,
+        }
+    };
+}
+
+impl AnyMaterial {
+    /// the variant for `material` if it's one of the built in materials, or `Dyn` otherwise
+    pub fn new<M: Material + Send + Sync + 'static>(material: M) -> Self {
+        let material = match downcast::<M, Self>(material) {
+            Ok(any) => return any,
+            Err(material) => material,
+        };
+        let material = match downcast(material) {
+            Ok(shared) => return Self::Dyn(shared),
+            Err(material) => material,
+        };
+        macro_rules! try_variants {
+            ($material:ident: $($variant:ident),*) => {$(
+                let $material = match downcast($material) {
+                    Ok(material) => return Self::$variant(material),
+                    Err(material) => material,
+                };
+            )*};
+        }
+        try_variants!(
+            material: Diffuse,
+            OrenNayar,
+            Metal,
+            Pbr,
+            AnisotropicMetal,
+            Isotropic,
+            DiffuseLight,
+            Dielectric
+        );
+        Self::Dyn(Arc::new(material))
+    }
+}
+
+impl Material for AnyMaterial {
+    #[inline]
+    fn scatter(&self, ray: Ray, contact: &RayContact) -> Option<RayScatter> {
+        dispatch!(self, material => material.scatter(ray, contact))
+    }
+
+    #[inline]
+    fn emitted(&self, contact: &RayContact) -> Color {
+        dispatch!(self, material => material.emitted(contact))
+    }
+
+    #[inline]
+    fn brdf(&self, ray: Ray, contact: &RayContact, direction: Vec3) -> Color {
+        dispatch!(self, material => material.brdf(ray, contact, direction))
+    }
+
+    #[inline]
+    fn scattering_pdf(&self, ray: Ray, contact: &RayContact, direction: Vec3) -> Float {
+        dispatch!(self, material => material.scattering_pdf(ray, contact, direction))
+    }
+
+    #[inline]
+    fn albedo(&self, contact: &RayContact) -> Color {
+        dispatch!(self, material => material.albedo(contact))
+    }
+
+    #[inline]
+    fn interior(&self, ray: Ray) -> Option<Interior> {
+        dispatch!(self, material => material.interior(ray))
+    }
+
+    #[inline]
+    fn opacity(&self, contact: &RayContact) -> Float {
+        dispatch!(self, material => material.opacity(contact))
+    }
+
+    #[inline]
+    fn is_opaque(&self) -> bool {
+        dispatch!(self, material => material.is_opaque())
+    }
+}
+
+/// materials chosen at runtime, such as ones a scene file describes, can still be shared
+impl From<Arc<dyn Material + Send + Sync + 'static>> for AnyMaterial {
+    fn from(material: Arc<dyn Material + Send + Sync + 'static>) -> Self {
+        Self::Dyn(material)
+    }
+}
+
+#[test]
+fn oren_nayar_brdf() {
+    let albedo = Color::new(0.8, 0.5, 0.2);
+    let ray = Ray::new(Vec3::new(-1., 1., 0.), Vec3::new(1., -1., 0.));
+    let diffuse = AnyMaterial::Diffuse(Diffuse::from(albedo));
+    let contact = RayContact::new(ray, 1., Vec3::Y, &diffuse);
+    let light = Vec3::new(1., 1., 0.).normalize();
+    let back = Vec3::new(-1., 1., 0.).normalize();
+
+    // no roughness is lambertian
+    let smooth = OrenNayar::new(SolidColor::from(albedo), 0.);
+    let diffuse = Diffuse::from(albedo);
+    for direction in [light, back, Vec3::Y] {
+        let delta = smooth.brdf(ray, &contact, direction) - diffuse.brdf(ray, &contact, direction);
+        assert!(delta.is_black());
+    }
+
+    // rough surfaces are darker overall, but bright back towards the light
+    let rough = OrenNayar::new(SolidColor::from(albedo), 1.);
+    let away = rough.brdf(ray, &contact, light);
+    let towards = rough.brdf(ray, &contact, back);
+    assert!(away.r < albedo.r / PI);
+    assert!(towards.r > away.r);
+    assert!(rough.brdf(ray, &contact, -Vec3::Y).is_black());
+}
+
+/// the average scatter weight estimates the directional albedo, as does integrating the
+/// brdf over uniformly picked directions, so the two only agree if the pdfs are right
+#[cfg(test)]
+fn assert_sampling_matches_brdf(material: &dyn Material, contact: &RayContact) {
+    let ray = Ray::new(Vec3::new(-1., 2., 0.5), Vec3::new(1., -2., -0.5));
+    const N: usize = 400000;
+    let mut sampled = Color::BLACK;
+    let mut uniform = Color::BLACK;
+    for _ in 0..N {
+        if let Some(scatter) = material.scatter(ray, contact) {
+            sampled += scatter.attenuation;
+        }
+        let mut direction = Vec3::random_unit();
+        if direction.y < 0. {
+            direction.y = -direction.y;
+        }
+        uniform += 2. * PI * direction.y * material.brdf(ray, contact, direction);
+    }
+    let (sampled, uniform) = (sampled / N as Float, uniform / N as Float);
+    assert!(
+        Vec3::from(sampled - uniform).length() < 0.02,
+        "{sampled:?} vs {uniform:?}"
+    );
+}
+
+#[test]
+fn pbr_sampling_matches_brdf() {
+    let ray = Ray::new(Vec3::new(-1., 2., 0.5), Vec3::new(1., -2., -0.5));
+    let white = AnyMaterial::Diffuse(Diffuse::from(Color::WHITE));
+    let contact = RayContact::new(ray, 1., Vec3::Y, &white);
+    let pbr = Pbr::new(SolidColor::from(Color::new(0.9, 0.6, 0.3)), 0.5, 0.5);
+    assert_sampling_matches_brdf(&pbr, &contact);
+}
+
+#[test]
+fn anisotropic_metal_sampling_matches_brdf() {
+    let ray = Ray::new(Vec3::new(-1., 2., 0.5), Vec3::new(1., -2., -0.5));
+    let white = AnyMaterial::Diffuse(Diffuse::from(Color::WHITE));
+    let contact = RayContact::new(ray, 1., Vec3::Y, &white).with_tangents(Vec3::X, Vec3::Z);
+    let metal = AnisotropicMetal::new(SolidColor::from(Color::new(0.9, 0.6, 0.3)), 0.6, 0.2);
+    assert_sampling_matches_brdf(&metal, &contact);
+}
+
+#[test]
+fn mix_sampling_matches_brdf() {
+    let ray = Ray::new(Vec3::new(-1., 2., 0.5), Vec3::new(1., -2., -0.5));
+    let white = AnyMaterial::Diffuse(Diffuse::from(Color::WHITE));
+    let contact = RayContact::new(ray, 1., Vec3::Y, &white);
+    let rust = Diffuse::from(Color::new(0.5, 0.2, 0.1));
+    let steel = Pbr::new(SolidColor::from(Color::new(0.8, 0.8, 0.8)), 1., 0.3);
+    assert_sampling_matches_brdf(&Mix::new(rust, steel, 0.3), &contact);
+}
+
+#[test]
+fn built_in_materials_skip_the_vtable() {
+    assert!(matches!(
+        AnyMaterial::new(Metal::new(SolidColor::from(Color::WHITE), 0.)),
+        AnyMaterial::Metal(_)
+    ));
+    let glass = AnyMaterial::new(Dielectric::new(1.5));
+    assert!(matches!(
+        AnyMaterial::new(glass),
+        AnyMaterial::Dielectric(_)
+    ));
+    let shared: Arc<dyn Material + Send + Sync> = Arc::new(DiffuseLight::from(Color::WHITE));
+    assert!(matches!(
+        AnyMaterial::new(shared.clone()),
+        AnyMaterial::Dyn(_)
+    ));
+
+    // materials outside the enum still work through it
+    let mix = AnyMaterial::new(Mix::new(Diffuse::from(Color::WHITE), shared, 0.5));
+    assert!(matches!(mix, AnyMaterial::Dyn(_)));
+    let ray = Ray::new(Vec3::new(0., 1., 0.), -Vec3::Y);
+    let contact = RayContact::new(ray, 1., Vec3::Y, &mix);
+    assert_eq!(mix.emitted(&contact), Color::gray(0.5));
+}
+
+#[test]
+fn nested_interiors() {
+    let glass = AnyMaterial::Dielectric(Dielectric::new(1.5).with_priority(2));
+    let water = AnyMaterial::Dielectric(Dielectric::new(1.33).with_priority(1));
+    let air = AnyMaterial::Diffuse(Diffuse::from(Color::WHITE));
+    let ray = Ray::new(Vec3::Y, -Vec3::Y);
+    // straight down through a glass wall the water overlaps, then out the bottom of the water
+    let enter = |material| RayContact::new(ray, 1., Vec3::Y, material);
+    let leave = |material| RayContact::new(ray, 1., -Vec3::Y, material);
+    let mut interiors = InteriorStack::default();
+
+    let mut contact = enter(&air);
+    assert!(interiors.hit(ray, &mut contact));
+    let mut contact = enter(&glass);
+    assert!(interiors.hit(ray, &mut contact));
+    assert_eq!(contact.outside_index, 1.);
+    interiors.scattered(&contact, ray);
+    // the water's surface is inside the glass, which fills the overlap
+    assert!(!interiors.hit(ray, &mut enter(&water)));
+    // a reflection off the inside of the glass stays in it
+    let mut contact = leave(&glass);
+    assert!(interiors.hit(ray, &mut contact));
+    assert_eq!(contact.outside_index, 1.33);
+    interiors.scattered(&contact, Ray::new(Vec3::ZERO, Vec3::Y));
+    let mut contact = leave(&glass);
+    assert!(interiors.hit(ray, &mut contact));
+    interiors.scattered(&contact, ray);
+    let mut contact = leave(&water);
+    assert!(interiors.hit(ray, &mut contact));
+    assert_eq!(contact.outside_index, 1.);
+
+    // at 45 degrees, light inside glass can leave into water, but is all reflected back off air
+    let slanted = Ray::new(Vec3::new(-1., 1., 0.), Vec3::new(1., -1., 0.));
+    let bend = |outside_index| {
+        let contact = RayContact {
+            outside_index,
+            ..RayContact::new(slanted, 1., -Vec3::Y, &glass)
+        };
+        // the angle it leaves at when it isn't reflected
+        (0..100)
+            .filter_map(|_| glass.scatter(slanted, &contact))
+            .map(|scatter| scatter.ray.direction.normalize().x)
+            .fold(0., Float::max)
+    };
+    let sin_45 = (0.5 as Float).sqrt();
+    assert!((bend(1.33) - 1.5 / 1.33 * sin_45).abs() < 1e-4);
+    assert!((bend(1.) - sin_45).abs() < 1e-4);
+}
+
+#[test]
+fn emission_textures() {
+    use super::{ColorSpace, ImageTexture, Quad, Shape};
+    // a screen, red on its left half and blue on its right
+    let image = image::RgbImage::from_fn(2, 1, |x, _| match x {
+        0 => image::Rgb([255, 0, 0]),
+        _ => image::Rgb([0, 0, 255]),
+    });
+    let light = DiffuseLight::new(ImageTexture::new(image, ColorSpace::Srgb), 4.);
+    let screen = Quad::new(Vec3::ZERO, Vec3::X, Vec3::Z, light);
+    let glow = |x| {
+        let ray = Ray::new(Vec3::new(x, -1., 0.5), Vec3::Y);
+        let contact = screen.hit(ray, 0.0..Float::INFINITY).unwrap();
+        contact.material.emitted(&contact)
+    };
+    assert_eq!(glow(0.25), Color::new(4., 0., 0.));
+    assert_eq!(glow(0.75), Color::new(0., 0., 4.));
+}
@@ -0,0 +1,85 @@
+use super::{AnyMaterial, Color, Isotropic, Material, Ray, RayContact, Shape};
+use crate::math::{random, Float};
+use std::ops::Range;
+
+/// fog or smoke of even density filling a boundary shape. rays pass through it until they
+/// randomly scatter somewhere inside, more often the denser it is
+pub struct ConstantMedium<S: Shape> {
+    pub boundary: S,
+    /// chance of scattering per unit of distance travelled
+    pub density: Float,
+    pub phase: AnyMaterial,
+}
+
+impl<S: Shape> ConstantMedium<S> {
+    /// constructor, scattering with the given phase function material
+    pub fn new<Mat>(boundary: S, density: Float, phase: Mat) -> Self
+    where
+        Mat: Material + Send + Sync + 'static,
+    {
+        Self {
+            boundary,
+            density,
+            phase: AnyMaterial::new(phase),
+        }
+    }
+
+    /// constructor for a medium scattering evenly in all directions
+    pub fn isotropic(boundary: S, density: Float, albedo: Color) -> Self {
+        Self::new(boundary, density, Isotropic::from(albedo))
+    }
+}
+
+impl<S: Shape> Shape for ConstantMedium<S> {
+    fn hit(&self, ray: Ray, bounds: Range<Float>) -> Option<RayContact<'_>> {
+        // the stretch of the ray inside the boundary, which must be convex
+        let enter = self
+            .boundary
+            .hit(ray, Float::NEG_INFINITY..Float::INFINITY)?;
+        let exit = self.boundary.hit(ray, enter.t + 0.0001..Float::INFINITY)?;
+        let start = enter.t.max(bounds.start).max(0.);
+        let end = exit.t.min(bounds.end);
+        if start >= end {
+            return None;
+        }
+
+        let speed = ray.direction.length();
+        let inside = (end - start) * speed;
+        let travelled = -random::<Float>().ln() / self.density;
+        if travelled > inside {
+            return None;
+        }
+        let t = start + travelled / speed;
+        // the normal is meaningless inside a volume, so point it back along the ray
+        Some(RayContact::new(ray, t, -ray.direction, &self.phase))
+    }
+}
+
+#[test]
+fn medium_density() {
+    use crate::math::Vec3;
+    use crate::rt::Sphere;
+    let fog = |density| {
+        ConstantMedium::isotropic(
+            Sphere::new(Vec3::ZERO, 1., Isotropic::from(Color::WHITE)),
+            density,
+            Color::WHITE,
+        )
+    };
+    let ray = Ray::new(Vec3::new(0., 0., -5.), Vec3::Z);
+
+    // a thick medium scatters right past its surface
+    let thick = fog(1e6);
+    let contact = thick.hit(ray, 0.001..Float::INFINITY).unwrap();
+    assert!((contact.t - 4.).abs() < 1e-3);
+
+    // a third of rays make it through a medium 2 units across with density ln(3) / 2
+    let thin = fog((3. as Float).ln() / 2.);
+    let through = (0..100000)
+        .filter(|_| thin.hit(ray, 0.001..Float::INFINITY).is_none())
+        .count();
+    assert!((through as Float / 100000. - 1. / 3.).abs() < 0.01);
+
+    // nothing scatters past the end of the range
+    assert!(fog(1e6).hit(ray, 0.001..3.9).is_none());
+}
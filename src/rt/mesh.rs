@@ -0,0 +1,525 @@
+//! triangle meshes, shaded either flat, face by face, or smoothly by interpolating normals
+//! across each face from its corners. a mesh may be made of several materials, each triangle
+//! picking one. the triangles are sorted into a tree of boxes, so a ray only tests the few in
+//! boxes it passes through
+
+use super::{hit_opaque, AnyMaterial, Color, Material, Ray, RayContact, Shape};
+use crate::math::{Float, Normalize, Vec3};
+use std::{mem::size_of_val, ops::Range, sync::Arc};
+
+/// the vertices and triangles of a mesh, shared by every mesh made of the same model
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MeshData {
+    pub positions: Vec<Vec3>,
+    /// corners of each triangle, as indices into `positions`. counter-clockwise seen from the
+    /// front
+    pub triangles: Vec<[usize; 3]>,
+    /// normals at the corners of each triangle, interpolated across it. empty for flat shading
+    pub normals: Vec<[Vec3; 3]>,
+    /// surface coordinates at the corners of each triangle. empty to use the weights of the
+    /// second and third corners
+    pub uvs: Vec<[(Float, Float); 3]>,
+    /// colors at the corners of each triangle, blended across it for `VertexColors` to read.
+    /// empty for white
+    pub colors: Vec<[Color; 3]>,
+    /// which of the mesh's materials each triangle is made of. empty for all of the first
+    pub groups: Vec<usize>,
+}
+
+impl MeshData {
+    /// shaded flat until given normals
+    pub fn new(positions: Vec<Vec3>, triangles: Vec<[usize; 3]>) -> Self {
+        Self {
+            positions,
+            triangles,
+            normals: vec![],
+            uvs: vec![],
+            colors: vec![],
+            groups: vec![],
+        }
+    }
+
+    /// bytes taken up by the vertices, triangles and everything at their corners
+    pub fn memory(&self) -> usize {
+        size_of_val(&self.positions[..])
+            + size_of_val(&self.triangles[..])
+            + size_of_val(&self.normals[..])
+            + size_of_val(&self.uvs[..])
+            + size_of_val(&self.colors[..])
+            + size_of_val(&self.groups[..])
+    }
+
+    /// shades smoothly between the given normals, one for each vertex. an error if there
+    /// aren't as many normals as vertices
+    pub fn with_vertex_normals(self, normals: &[Vec3]) -> Result<Self, String> {
+        if normals.len() != self.positions.len() {
+            return Err(format!(
+                "{} normals for {} vertices",
+                normals.len(),
+                self.positions.len()
+            ));
+        }
+        let normals = self
+            .triangles
+            .iter()
+            .map(|triangle| triangle.map(|i| normals[i].normalize()))
+            .collect();
+        Ok(Self { normals, ..self })
+    }
+
+    /// shades smoothly with normals averaged over the faces around each vertex, weighted by
+    /// their area. faces meeting at more than `max_angle` degrees keep a hard edge between
+    /// them, so a cube stays sharp while a low poly sphere's facets blend together
+    pub fn smooth(self, max_angle: Float) -> Self {
+        // twice each face's area in length
+        let faces: Vec<Vec3> = self
+            .triangles
+            .iter()
+            .map(|&[a, b, c]| {
+                let (a, b, c) = (self.positions[a], self.positions[b], self.positions[c]);
+                (b - a).cross(c - a)
+            })
+            .collect();
+        let mut around = vec![vec![]; self.positions.len()];
+        for (face, triangle) in self.triangles.iter().enumerate() {
+            for &vertex in triangle {
+                around[vertex].push(face);
+            }
+        }
+        let min_cos = max_angle.to_radians().cos();
+        let normals = self
+            .triangles
+            .iter()
+            .zip(&faces)
+            .map(|(triangle, face)| {
+                let face = face.normalize();
+                triangle.map(|vertex| {
+                    let sum = around[vertex]
+                        .iter()
+                        .map(|&other| faces[other])
+                        .filter(|other| other.normalize().dot(face) >= min_cos)
+                        .fold(Vec3::ZERO, |sum, other| sum + other);
+                    if sum.is_zero() {
+                        face
+                    } else {
+                        sum.normalize()
+                    }
+                })
+            })
+            .collect();
+        Self { normals, ..self }
+    }
+}
+
+/// triangles in a leaf of the tree at most
+const LEAF_SIZE: usize = 4;
+
+/// a box of the tree, with opposite corners around every triangle in it
+#[derive(Debug)]
+enum Node {
+    /// a range of `Tree::faces`
+    Leaf((Vec3, Vec3), Range<usize>),
+    /// split in two boxes, the first right after this node, and the second at the index given
+    Branch((Vec3, Vec3), usize),
+}
+
+impl Node {
+    fn bounds(&self) -> (Vec3, Vec3) {
+        match self {
+            Node::Leaf(bounds, _) | Node::Branch(bounds, _) => *bounds,
+        }
+    }
+}
+
+/// the triangles of a mesh, split in half along the longest side of their box again and again
+/// until few are left in each box
+#[derive(Debug)]
+struct Tree {
+    /// the root first, then each node's first half before its second
+    nodes: Vec<Node>,
+    /// indices of the triangles, in the order the leaves take ranges of
+    faces: Vec<usize>,
+}
+
+/// the smallest box around the points
+fn box_around(points: impl Iterator<Item = Vec3>) -> (Vec3, Vec3) {
+    let far = Vec3::ONE * Float::INFINITY;
+    points.fold((far, -far), |(min, max), p| (min.min(p), max.max(p)))
+}
+
+impl Tree {
+    fn new(data: &MeshData) -> Self {
+        let centers: Vec<Vec3> = data
+            .triangles
+            .iter()
+            .map(|triangle| {
+                let [a, b, c] = triangle.map(|i| data.positions[i]);
+                (a + b + c) / 3.
+            })
+            .collect();
+        let mut tree = Self {
+            nodes: vec![],
+            faces: (0..data.triangles.len()).collect(),
+        };
+        if !tree.faces.is_empty() {
+            tree.split(data, &centers, 0..centers.len());
+        }
+        tree
+    }
+
+    /// adds the nodes for a range of the faces
+    fn split(&mut self, data: &MeshData, centers: &[Vec3], range: Range<usize>) {
+        let faces = &mut self.faces[range.clone()];
+        let corners = faces.iter().flat_map(|&face| data.triangles[face]);
+        let bounds = box_around(corners.map(|i| data.positions[i]));
+        if faces.len() <= LEAF_SIZE {
+            self.nodes.push(Node::Leaf(bounds, range));
+            return;
+        }
+        let (min, max) = box_around(faces.iter().map(|&face| centers[face]));
+        let size = max - min;
+        let axis = if size.x >= size.y && size.x >= size.z {
+            0
+        } else if size.y >= size.z {
+            1
+        } else {
+            2
+        };
+        let middle = faces.len() / 2;
+        faces.select_nth_unstable_by(middle, |&a, &b| {
+            centers[a][axis].total_cmp(&centers[b][axis])
+        });
+        let node = self.nodes.len();
+        self.nodes.push(Node::Branch(bounds, 0));
+        self.split(data, centers, range.start..range.start + middle);
+        self.nodes[node] = Node::Branch(bounds, self.nodes.len());
+        self.split(data, centers, range.start + middle..range.end);
+    }
+
+    /// offers `visit` the triangles in every box the ray passes through before `end`, nearer
+    /// boxes first. `visit` may bring `end` in, and returns true to stop. whether it stopped
+    fn traverse(
+        &self,
+        ray: Ray,
+        start: Float,
+        mut end: Float,
+        mut visit: impl FnMut(usize, &mut Float) -> bool,
+    ) -> bool {
+        // deep enough for a tree of far more triangles than fit in memory
+        let mut stack = [0; 64];
+        let mut len = usize::from(!self.nodes.is_empty());
+        while len > 0 {
+            len -= 1;
+            let node = stack[len];
+            if enters_box(ray, self.nodes[node].bounds(), start..end).is_none() {
+                continue;
+            }
+            match &self.nodes[node] {
+                Node::Leaf(_, range) => {
+                    for &face in &self.faces[range.clone()] {
+                        if visit(face, &mut end) {
+                            return true;
+                        }
+                    }
+                }
+                &Node::Branch(_, second) => {
+                    let distance = |node: usize| {
+                        let near = enters_box(ray, self.nodes[node].bounds(), start..end);
+                        near.unwrap_or(Float::INFINITY)
+                    };
+                    let (near, far) = if distance(second) < distance(node + 1) {
+                        (second, node + 1)
+                    } else {
+                        (node + 1, second)
+                    };
+                    stack[len] = far;
+                    stack[len + 1] = near;
+                    len += 2;
+                }
+            }
+        }
+        false
+    }
+}
+
+/// triangles sharing a list of vertices
+pub struct Mesh {
+    pub data: Arc<MeshData>,
+    /// picked between by the groups of the data. never empty
+    pub materials: Vec<AnyMaterial>,
+    tree: Arc<Tree>,
+}
+
+impl Mesh {
+    /// constructor. shaded flat until given normals
+    pub fn new<Mat>(positions: Vec<Vec3>, triangles: Vec<[usize; 3]>, material: Mat) -> Self
+    where
+        Mat: Material + Send + Sync + 'static,
+    {
+        Self::shared(Arc::new(MeshData::new(positions, triangles)), material)
+    }
+
+    /// constructor for a mesh made of data other meshes may be made of too
+    pub fn shared<Mat>(data: Arc<MeshData>, material: Mat) -> Self
+    where
+        Mat: Material + Send + Sync + 'static,
+    {
+        Self::with_materials(data, vec![AnyMaterial::new(material)])
+    }
+
+    /// constructor for a mesh whose triangles are made of the materials their groups pick
+    pub fn with_materials(data: Arc<MeshData>, materials: Vec<AnyMaterial>) -> Self {
+        assert!(!materials.is_empty(), "a mesh needs a material");
+        let tree = Arc::new(Tree::new(&data));
+        Self {
+            data,
+            materials,
+            tree,
+        }
+    }
+
+    /// `MeshData::with_vertex_normals`, on a copy of the data if it's shared
+    pub fn with_vertex_normals(self, normals: &[Vec3]) -> Result<Self, String> {
+        let data = Arc::unwrap_or_clone(self.data).with_vertex_normals(normals)?;
+        Ok(Self {
+            data: Arc::new(data),
+            ..self
+        })
+    }
+
+    /// `MeshData::smooth`, on a copy of the data if it's shared
+    pub fn smooth(self, max_angle: Float) -> Self {
+        let data = Arc::unwrap_or_clone(self.data).smooth(max_angle);
+        Self {
+            data: Arc::new(data),
+            ..self
+        }
+    }
+
+    fn material(&self, face: usize) -> &AnyMaterial {
+        let group = self.data.groups.get(face).copied().unwrap_or(0);
+        self.materials.get(group).unwrap_or(&self.materials[0])
+    }
+}
+
+/// distance along the ray to where it enters the box between opposite corners `min` and `max`,
+/// or to the start of `bounds` if it's already inside. none if it misses it within `bounds`
+fn enters_box(ray: Ray, (min, max): (Vec3, Vec3), bounds: Range<Float>) -> Option<Float> {
+    let (mut near, mut far) = (bounds.start, bounds.end);
+    for axis in 0..3 {
+        let inverse = 1. / ray.direction[axis];
+        let t0 = (min[axis] - ray.origin[axis]) * inverse;
+        let t1 = (max[axis] - ray.origin[axis]) * inverse;
+        let (t0, t1) = if inverse < 0. { (t1, t0) } else { (t0, t1) };
+        near = near.max(t0);
+        far = far.min(t1);
+        if far < near {
+            return None;
+        }
+    }
+    Some(near)
+}
+
+/// distance along the ray to a triangle, with the weights of its second and third corners
+/// at the point it's crossed. the möller-trumbore algorithm
+fn hit_triangle(
+    ray: Ray,
+    [a, b, c]: [Vec3; 3],
+    bounds: Range<Float>,
+) -> Option<(Float, Float, Float)> {
+    let (ab, ac) = (b - a, c - a);
+    let h = ray.direction.cross(ac);
+    let det = ab.dot(h);
+    if det.abs() < 1e-12 {
+        // parallel to the triangle, or a triangle with no area
+        return None;
+    }
+    let s = ray.origin - a;
+    let u = s.dot(h) / det;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = s.cross(ab);
+    let v = ray.direction.dot(q) / det;
+    if v < 0. || u + v > 1. {
+        return None;
+    }
+    let t = ac.dot(q) / det;
+    bounds.contains(&t).then_some((t, u, v))
+}
+
+/// how far across the triangle a step of one in u and in v goes, given the surface coordinates
+/// at its corners. the edges themselves if the coordinates don't span an area
+fn uv_tangents(ab: Vec3, ac: Vec3, [a, b, c]: [(Float, Float); 3]) -> (Vec3, Vec3) {
+    let (d1, d2) = ((b.0 - a.0, b.1 - a.1), (c.0 - a.0, c.1 - a.1));
+    let det = d1.0 * d2.1 - d1.1 * d2.0;
+    if det.abs() < 1e-12 {
+        return (ab, ac);
+    }
+    ((d2.1 * ab - d1.1 * ac) / det, (d1.0 * ac - d2.0 * ab) / det)
+}
+
+impl Shape for Mesh {
+    fn hit(&self, ray: Ray, bounds: Range<Float>) -> Option<RayContact<'_>> {
+        let data = &*self.data;
+        let corners = |face: usize| data.triangles[face].map(|i| data.positions[i]);
+        let mut closest = None;
+        self.tree
+            .traverse(ray, bounds.start, bounds.end, |face, end| {
+                if let Some((t, u, v)) = hit_triangle(ray, corners(face), bounds.start..*end) {
+                    *end = t;
+                    closest = Some((face, t, u, v));
+                }
+                false
+            });
+        let (face, t, u, v) = closest?;
+        let [a, b, c] = corners(face);
+        let (ab, ac) = (b - a, c - a);
+        let (uv, (du, dv)) = match data.uvs.get(face) {
+            Some(&[ua, ub, uc]) => {
+                let uv = (
+                    (1. - u - v) * ua.0 + u * ub.0 + v * uc.0,
+                    (1. - u - v) * ua.1 + u * ub.1 + v * uc.1,
+                );
+                (uv, uv_tangents(ab, ac, [ua, ub, uc]))
+            }
+            None => ((u, v), (ab, ac)),
+        };
+        let contact = RayContact::new(ray, t, ab.cross(ac).normalize(), self.material(face))
+            .with_uv(uv.0, uv.1)
+            .with_uv_density(1. / (du.length() * dv.length()).sqrt())
+            .with_tangents(du, dv);
+        let contact = match data.colors.get(face) {
+            Some(&[ca, cb, cc]) => contact.with_color((1. - u - v) * ca + u * cb + v * cc),
+            None => contact,
+        };
+        let Some([na, nb, nc]) = data.normals.get(face) else {
+            return Some(contact);
+        };
+        let normal = ((1. - u - v) * *na + u * *nb + v * *nc).normalize();
+        Some(RayContact {
+            normal: if contact.front_face { normal } else { -normal },
+            ..contact
+        })
+    }
+
+    /// stops at the first triangle in the way, whichever it is
+    fn occluded(&self, ray: Ray, bounds: Range<Float>) -> bool {
+        if !self.materials.iter().all(|material| material.is_opaque()) {
+            return hit_opaque(|bounds| self.hit(ray, bounds), bounds).is_some();
+        }
+        let data = &*self.data;
+        self.tree
+            .traverse(ray, bounds.start, bounds.end, |face, _| {
+                let corners = data.triangles[face].map(|i| data.positions[i]);
+                hit_triangle(ray, corners, bounds.clone()).is_some()
+            })
+    }
+}
+
+#[test]
+fn smooth_shading() {
+    use crate::rt::{Color, Diffuse};
+    // an octahedron, whose neighboring faces meet at about 70 degrees
+    let positions = vec![Vec3::X, -Vec3::X, Vec3::Y, -Vec3::Y, Vec3::Z, -Vec3::Z];
+    let triangles: Vec<[usize; 3]> = [
+        [0, 2, 4],
+        [2, 1, 4],
+        [1, 3, 4],
+        [3, 0, 4],
+        [2, 0, 5],
+        [1, 2, 5],
+        [3, 1, 5],
+        [0, 3, 5],
+    ]
+    .into();
+    let octahedron = || {
+        Mesh::new(
+            positions.clone(),
+            triangles.clone(),
+            Diffuse::from(Color::WHITE),
+        )
+    };
+
+    // a ray at the +x+y+z face, close to the +x corner
+    let near_corner = Ray::new(Vec3::new(5., 0.1, 0.1), -Vec3::X);
+    let normal = |mesh: &Mesh, ray| mesh.hit(ray, 0.001..Float::INFINITY).unwrap().normal;
+    let face = Vec3::ONE.normalize();
+    assert!((normal(&octahedron(), near_corner) - face).is_zero());
+    assert!((normal(&octahedron().smooth(60.), near_corner) - face).is_zero());
+    // only blends with the faces sharing an edge, not the one across the corner
+    let partly = normal(&octahedron().smooth(80.), near_corner).dot(Vec3::X);
+    assert!(partly > face.x && partly < 0.95);
+    assert!(normal(&octahedron().smooth(120.), near_corner).dot(Vec3::X) > 0.95);
+    let exact = octahedron().with_vertex_normals(&positions).unwrap();
+    assert_eq!(
+        octahedron()
+            .with_vertex_normals(&positions[1..])
+            .err()
+            .unwrap(),
+        "5 normals for 6 vertices"
+    );
+    assert!(normal(&exact, near_corner).dot(Vec3::X) > 0.95);
+
+    // the middle of a face gets the face's own normal either way
+    let middle = Ray::new(Vec3::ONE, -Vec3::ONE);
+    assert!((normal(&exact, middle) - face).is_zero());
+
+    // rays that miss the box around it don't touch the triangles
+    let ray = Ray::new(Vec3::new(5., 2., 0.), -Vec3::X);
+    assert!(exact.hit(ray, 0.001..Float::INFINITY).is_none());
+}
+
+#[test]
+fn tree_of_triangles() {
+    use crate::rt::{Color, Diffuse};
+    // a bumpy 16 by 16 grid, two triangles to a square
+    let n = 16;
+    let positions: Vec<Vec3> = (0..=n)
+        .flat_map(|y| {
+            (0..=n).map(move |x| {
+                let z = ((x * 7 + y * 3) % 5) as Float * 0.2;
+                Vec3::new(x as Float, y as Float, z)
+            })
+        })
+        .collect();
+    let mut triangles = vec![];
+    for y in 0..n {
+        for x in 0..n {
+            let i = y * (n + 1) + x;
+            triangles.extend([[i, i + 1, i + n + 2], [i, i + n + 2, i + n + 1]]);
+        }
+    }
+    let mesh = Mesh::new(
+        positions.clone(),
+        triangles.clone(),
+        Diffuse::from(Color::WHITE),
+    );
+    assert!(mesh.tree.nodes.len() > 100);
+
+    // rays from every side find the nearest triangle, as testing every one of them would
+    crate::math::seed_rng(5);
+    let mut hits = 0;
+    for _ in 0..500 {
+        let origin = Vec3::new(8., 8., 0.5) + 20. * (Vec3::random() - Vec3::ONE / 2.);
+        let at = Vec3::new(8., 8., 0.5) + 16. * (Vec3::random() - Vec3::ONE / 2.);
+        let ray = Ray::new(origin, (at - origin).normalize());
+        let nearest = triangles
+            .iter()
+            .filter_map(|triangle| {
+                let corners = triangle.map(|i| positions[i]);
+                hit_triangle(ray, corners, 0.001..Float::INFINITY)
+            })
+            .map(|(t, _, _)| t)
+            .min_by(Float::total_cmp);
+        let hit = mesh.hit(ray, 0.001..Float::INFINITY);
+        assert_eq!(hit.map(|contact| contact.t), nearest);
+        assert_eq!(
+            mesh.occluded(ray, 0.001..Float::INFINITY),
+            nearest.is_some()
+        );
+        hits += usize::from(nearest.is_some());
+    }
+    // and some miss it
+    assert!(hits > 100 && hits < 400);
+}
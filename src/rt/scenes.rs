@@ -0,0 +1,204 @@
+//! canonical scenes, built in code so tests, benchmarks and the command line can share them, and
+//! picked by name with `by_name`
+
+use super::*;
+use crate::math::{random, Float, Vec3};
+
+/// the names `by_name` knows
+pub const NAMES: [&str; 5] = [
+    "spheres",
+    "cornell",
+    "glass-checker",
+    "materials",
+    "furnace",
+];
+
+/// one of the scenes here by its name in `NAMES`
+pub fn by_name(name: &str) -> Option<Scene> {
+    match name {
+        "spheres" => Some(spheres()),
+        "cornell" => Some(cornell_box()),
+        "glass-checker" => Some(glass_on_checker()),
+        "materials" => Some(materials()),
+        "furnace" => Some(furnace()),
+        _ => None,
+    }
+}
+
+/// a pinhole camera from `eye` at `look_at`
+fn view(eye: Vec3, look_at: Vec3, vfov: Float) -> View {
+    View {
+        eye,
+        look_at,
+        up: Vec3::Y,
+        vfov,
+        aperture: 0.,
+        aperture_shape: ApertureShape::Round,
+        projection: Projection::Perspective,
+        shutter: (0., 0.),
+        focus: Focus::LookAt,
+    }
+}
+
+/// the final scene of "ray tracing in one weekend": three large spheres of glass, metal and
+/// diffuse among a field of small random ones. they're placed by the thread's generator, so
+/// seed it first for the same layout
+pub fn spheres() -> Scene {
+    let mut scene = SceneBuilder::new()
+        .camera(View {
+            aperture: 0.01,
+            ..view(Vec3::new(13., 2., 3.), Vec3::ZERO, 20.)
+        })
+        .sphere(Vec3::new(0., -1000., -1.), 1000.)
+        .diffuse(Color::new(0.8, 0.5, 0.9));
+
+    for x in -8..8 {
+        for z in -8..8 {
+            let pos = Vec3 {
+                x: (random::<Float>() * 0.9) + (x as Float),
+                y: 0.2,
+                z: (random::<Float>() * 0.9) + (z as Float),
+            };
+
+            if (pos - Vec3::new(4., 0.2, 0.)).length() > 0.9 {
+                let choose_mat: Float = random();
+                scene = scene.sphere(pos, 0.2);
+
+                scene = if choose_mat < 0.8 {
+                    scene.diffuse(Color::random())
+                } else if choose_mat < 0.95 {
+                    scene.metal(Color::random() * 0.5 + Color::GRAY, random::<Float>() * 0.3)
+                } else {
+                    scene.glass(1.5)
+                };
+            }
+        }
+    }
+
+    scene
+        .sphere(Vec3::new(0., 1., 0.), 1.0)
+        .glass(1.5)
+        .sphere(Vec3::new(4., 1., 0.), 1.0)
+        .metal(Color::new(0.8, 0.8, 0.8), 0.0)
+        .sphere(Vec3::new(-4., 1., 0.), 1.0)
+        .diffuse(Color::new(0.8, 0.5, 0.2))
+        .build()
+}
+
+/// the cornell box, 555 units across, lit by an area light in its ceiling, with a glass
+/// sphere and a rough gold one inside
+pub fn cornell_box() -> Scene {
+    let (red, green, white) = (
+        Color::new(0.65, 0.05, 0.05),
+        Color::new(0.12, 0.45, 0.15),
+        Color::new(0.73, 0.73, 0.73),
+    );
+    let (x, y, z) = (Vec3::X * 555., Vec3::Y * 555., Vec3::Z * 555.);
+    SceneBuilder::new()
+        .camera(view(
+            Vec3::new(278., 278., -800.),
+            Vec3::new(278., 278., 0.),
+            40.,
+        ))
+        .background(SolidBackground::BLACK)
+        .quad(x, y, z)
+        .diffuse(green)
+        .quad(Vec3::ZERO, y, z)
+        .diffuse(red)
+        .quad(Vec3::ZERO, x, z)
+        .diffuse(white)
+        .quad(x + y + z, -x, -z)
+        .diffuse(white)
+        .quad(z, x, y)
+        .diffuse(white)
+        .quad(
+            Vec3::new(343., 554., 332.),
+            Vec3::new(-130., 0., 0.),
+            Vec3::new(0., 0., -105.),
+        )
+        .emissive(Color::WHITE * 15.)
+        .sphere(Vec3::new(190., 90., 190.), 90.)
+        .glass(1.5)
+        .sphere(Vec3::new(380., 120., 370.), 120.)
+        .pbr(Color::new(0.9, 0.7, 0.4), 1., 0.3)
+        .build()
+}
+
+/// a glass ball on a checkerboard under the sky, which it bends into a small upside down
+/// copy of
+pub fn glass_on_checker() -> Scene {
+    let checks = Checker::new(
+        SolidColor::from(Color::new(0.1, 0.1, 0.1)),
+        SolidColor::from(Color::new(0.9, 0.9, 0.9)),
+        0.5,
+        CheckerSpace::World,
+    );
+    SceneBuilder::new()
+        .camera(view(Vec3::new(0., 1.5, -5.), Vec3::new(0., 0.8, 0.), 35.))
+        .sphere(Vec3::new(0., -1000., 0.), 1000.)
+        .material(Diffuse::new(checks))
+        .sphere(Vec3::new(0., 1., 0.), 1.)
+        .glass(1.5)
+        .build()
+}
+
+/// a row of spheres, one of each kind of material: diffuse, rough and polished metal, glass,
+/// and plastic and gold through the pbr material, on a gray floor under the sky
+pub fn materials() -> Scene {
+    let scene = SceneBuilder::new()
+        .camera(view(Vec3::new(0., 2., -9.), Vec3::new(0., 0.6, 0.), 40.))
+        .sphere(Vec3::new(0., -1000., 0.), 1000.)
+        .diffuse(Color::GRAY);
+    let at = |i: Float| Vec3::new(1.2 * (i - 2.5), 0.5, 0.);
+    scene
+        .sphere(at(0.), 0.5)
+        .diffuse(Color::new(0.8, 0.3, 0.3))
+        .sphere(at(1.), 0.5)
+        .metal(Color::new(0.8, 0.8, 0.8), 0.3)
+        .sphere(at(2.), 0.5)
+        .metal(Color::new(0.8, 0.8, 0.8), 0.)
+        .sphere(at(3.), 0.5)
+        .glass(1.5)
+        .sphere(at(4.), 0.5)
+        .pbr(Color::new(0.2, 0.4, 0.8), 0., 0.2)
+        .sphere(at(5.), 0.5)
+        .pbr(Color::new(1., 0.78, 0.34), 1., 0.15)
+        .build()
+}
+
+/// the white furnace test: a diffuse sphere of albedo 0.8 lit evenly from every direction by a
+/// white background. light reaching it bounces off once and leaves, so it should come out 0.8
+/// all over, and anything else means the integrator loses or makes up energy
+pub fn furnace() -> Scene {
+    SceneBuilder::new()
+        .camera(view(Vec3::new(0., 0., -4.), Vec3::ZERO, 40.))
+        .background(SolidBackground::from(Color::WHITE))
+        .sphere(Vec3::ZERO, 1.)
+        .diffuse(Color::WHITE * 0.8)
+        .build()
+}
+
+#[test]
+fn scenes_by_name() {
+    for name in NAMES {
+        let scene = by_name(name).unwrap();
+        assert!(!scene.world.is_empty(), "{name}");
+    }
+    assert!(by_name("teapot").is_none());
+    // the cornell box is lit by its ceiling alone
+    assert_eq!(cornell_box().world.lights.len(), 1);
+}
+
+#[test]
+fn furnace_conserves_energy() {
+    use crate::integrator::PathTracer;
+    use crate::render::Film;
+    let scene = furnace();
+    let camera = scene.view.camera(1.);
+    let mut film = Film::new(16, 16, false);
+    film.render_pass(&camera, &scene.world, &PathTracer::new(8), 64);
+    let image = film.color();
+    let (sphere, around) = (image.get_pixel(8, 8).0, image.get_pixel(0, 0).0);
+    assert!(sphere.iter().all(|c| (c - 0.8).abs() < 0.01), "{sphere:?}");
+    assert_eq!(around, [1.; 3]);
+}
@@ -0,0 +1,90 @@
+use super::{AnyMaterial, Material, Ray, RayContact, Shape};
+use crate::math::{Float, Normalize, Vec3};
+use std::ops::Range;
+
+/// a surface defined implicitly by a signed distance function, negative inside.
+/// rendered by sphere tracing, so the function must never overestimate the true distance
+pub struct SdfShape {
+    pub distance: Box<dyn Fn(Vec3) -> Float + Send + Sync + 'static>,
+    /// march step limit before giving up on a ray
+    pub max_steps: u32,
+    /// distance to the surface at which the march counts as a hit
+    pub epsilon: Float,
+    pub material: AnyMaterial,
+}
+
+impl SdfShape {
+    /// constructor
+    pub fn new<F, Mat>(distance: F, material: Mat) -> Self
+    where
+        F: Fn(Vec3) -> Float + Send + Sync + 'static,
+        Mat: Material + Send + Sync + 'static,
+    {
+        Self {
+            distance: Box::new(distance),
+            max_steps: 256,
+            epsilon: 1e-4,
+            material: AnyMaterial::new(material),
+        }
+    }
+
+    /// surface normal from central differences of the distance field
+    fn normal(&self, p: Vec3) -> Vec3 {
+        let h = self.epsilon;
+        let d = |offset: Vec3| (self.distance)(p + offset) - (self.distance)(p - offset);
+        Vec3::new(d(h * Vec3::X), d(h * Vec3::Y), d(h * Vec3::Z)).normalize()
+    }
+}
+
+impl Shape for SdfShape {
+    fn hit(&self, ray: Ray, bounds: Range<Float>) -> Option<RayContact<'_>> {
+        // distances are measured along the unit direction, while t is in ray units
+        let scale = ray.direction.length();
+        let mut t = bounds.start;
+        for _ in 0..self.max_steps {
+            if t >= bounds.end {
+                return None;
+            }
+            // march on |d| so rays starting inside the surface find their way out as well
+            let d = (self.distance)(ray.at(t)).abs();
+            if d < self.epsilon {
+                let normal = self.normal(ray.at(t));
+                return Some(
+                    RayContact::new(ray, t, normal, &self.material).with_error(self.epsilon),
+                );
+            }
+            t += d / scale;
+        }
+        None
+    }
+}
+
+#[test]
+fn sdf_rounded_box() {
+    use crate::rt::{Color, Diffuse};
+    // box with half extents of 1, rounded off by 0.25
+    let rounded_box = SdfShape::new(
+        |p: Vec3| {
+            let q = Vec3::new(p.x.abs() - 0.75, p.y.abs() - 0.75, p.z.abs() - 0.75);
+            let outside = Vec3::new(q.x.max(0.), q.y.max(0.), q.z.max(0.)).length();
+            outside + q.x.max(q.y).max(q.z).min(0.) - 0.25
+        },
+        Diffuse::from(Color::WHITE),
+    );
+
+    let ray = Ray::new(Vec3::new(-5., 0., 0.), 2. * Vec3::X);
+    let contact = rounded_box.hit(ray, 0.001..Float::INFINITY).unwrap();
+    assert!((contact.t - 2.).abs() < 1e-3);
+    assert!((contact.normal - -Vec3::X).length() < 1e-3);
+    assert!(contact.front_face);
+
+    // from the center, out through the top
+    let ray = Ray::new(Vec3::ZERO, Vec3::Y);
+    let contact = rounded_box.hit(ray, 0.001..Float::INFINITY).unwrap();
+    assert!((contact.t - 1.).abs() < 1e-3);
+    assert!(!contact.front_face);
+
+    // grazing past a rounded corner
+    let ray = Ray::new(Vec3::new(-5., 0.98, 0.98), Vec3::X);
+    assert!(rounded_box.hit(ray, 0.001..Float::INFINITY).is_none());
+}
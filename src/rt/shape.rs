@@ -1,85 +1,1152 @@
-use super::{Material, Ray};
-use crate::math::{Normalize, Vec3};
+use super::{downcast, AnyMaterial, Color, Handle, Material, Mesh, Ray};
+use crate::math::{consts::PI, random, solve_quartic, Float, Normalize, Onb, Vec3};
 use std::{ops::Range, sync::Arc};
 
+/// rounding error of hit points, relative to the size of the coordinates involved. generous,
+/// as some shapes solve for their hits less exactly than others
+const RELATIVE_ERROR: Float = if cfg!(feature = "f32") { 1e-5 } else { 1e-9 };
+
+/// how far a point computed around `point` may be off from rounding, at most
+pub fn rounding_error(point: Vec3) -> Float {
+    RELATIVE_ERROR * point.abs().max_component()
+}
+
 #[derive(Clone)]
-pub struct RayContact {
-    pub t: f64,
+pub struct RayContact<'a> {
+    pub t: Float,
     pub point: Vec3,
+    /// the normal shading is done with, facing the side the ray came from
     pub normal: Vec3,
+    /// the normal of the surface itself, facing the same side, which rays leaving the contact
+    /// are pushed off along. differs from `normal` where shading is smoothed or bumped
+    pub geometric_normal: Vec3,
+    /// how far `point` may be off the true surface
+    pub error: Float,
     pub front_face: bool,
-    pub material: Arc<dyn Material>,
+    /// surface coordinates, for shapes that provide them
+    pub uv: (Float, Float),
+    /// color painted onto the surface, like the vertex colors of a scanned mesh. white for
+    /// shapes without
+    pub color: Color,
+    /// surface directions of increasing u and v, for tangent-space effects like normal mapping
+    pub tangent: Vec3,
+    pub bitangent: Vec3,
+    /// width of the ray's beam where it meets the surface, stretched by how glancingly it does
+    pub width: Float,
+    /// units of surface coordinates per unit of distance across the surface, roughly, for how
+    /// much of a texture `width` covers. zero for shapes that don't say, leaving their textures
+    /// point sampled
+    pub uv_density: Float,
+    /// refraction index of whatever surrounds the surface on the far side from its interior,
+    /// 1 for air. set by the integrator for surfaces nested in other refracting volumes
+    pub outside_index: Float,
+    /// borrowed from the shape that was hit, rather than shared, so hits cost no reference counting
+    pub material: &'a AnyMaterial,
+    /// index of the world light this surface belongs to, if it is sampled directly as an area light
+    pub light: Option<usize>,
+    /// the world object hit, set by the world
+    pub object: Option<Handle>,
+}
+
+impl<'a> RayContact<'a> {
+    /// constructor. orients the normal against the ray and records which side was hit
+    pub fn new(ray: Ray, t: Float, outward_normal: Vec3, material: &'a AnyMaterial) -> Self {
+        let front_face = ray.direction.dot(outward_normal) < 0.;
+        let Onb {
+            u: tangent,
+            v: bitangent,
+            ..
+        } = Onb::new(outward_normal);
+        let normal = if front_face {
+            outward_normal
+        } else {
+            -outward_normal
+        };
+        let point = ray.at(t);
+        let distance = t * ray.direction.length();
+        let cos = (ray.direction.dot(outward_normal) / ray.direction.length()).abs();
+        RayContact {
+            t,
+            point,
+            normal,
+            geometric_normal: normal,
+            error: rounding_error(ray.origin).max(rounding_error(point)),
+            front_face,
+            uv: (0., 0.),
+            color: Color::WHITE,
+            tangent,
+            bitangent,
+            // stretching no further than at a few degrees off grazing
+            width: ray.footprint.width_at(distance) / cos.max(0.05),
+            uv_density: 0.,
+            outside_index: 1.,
+            material,
+            light: None,
+            object: None,
+        }
+    }
+
+    /// widens how far the point may be off the surface, for shapes that find their hits less
+    /// exactly than rounding alone would, like ones marched towards
+    pub fn with_error(self, error: Float) -> Self {
+        Self {
+            error: self.error.max(error),
+            ..self
+        }
+    }
+
+    /// where a ray leaving along `direction` starts: pushed off the surface along its normal,
+    /// to the side the ray leaves by, by twice as much as the point may be off. far enough that
+    /// the ray can't find the surface it left again, and no further, so shapes at any scale
+    /// neither shadow themselves nor leak light where they meet
+    pub fn offset_point(&self, direction: Vec3) -> Vec3 {
+        let offset = 2. * self.error * self.geometric_normal;
+        if direction.dot(self.geometric_normal) < 0. {
+            self.point - offset
+        } else {
+            self.point + offset
+        }
+    }
+
+    /// a ray carrying on from `ray` off the contact, along `direction`
+    pub fn spawn(&self, ray: Ray, direction: Vec3) -> Ray {
+        ray.scattered(self.offset_point(direction), direction)
+    }
+
+    /// a ray off the contact towards the point `distance` along the unit `direction`, and the
+    /// stretch of it that stops short of that point, for shadow rays that mustn't find the
+    /// light they're aimed at. lights infinitely far away are blocked by anything on the way
+    pub fn shadow_ray(&self, ray: Ray, direction: Vec3, distance: Float) -> (Ray, Range<Float>) {
+        if distance.is_infinite() {
+            return (self.spawn(ray, direction), 0.0..Float::INFINITY);
+        }
+        let target = self.point + distance * direction;
+        let margin = 2. * (self.error + rounding_error(target));
+        (self.spawn(ray, direction), 0.0..distance - margin)
+    }
+
+    /// attaches surface coordinates to the contact
+    pub fn with_uv(self, u: Float, v: Float) -> Self {
+        Self { uv: (u, v), ..self }
+    }
+
+    /// attaches the color painted onto the surface
+    pub fn with_color(self, color: Color) -> Self {
+        Self { color, ..self }
+    }
+
+    /// attaches how many units of surface coordinates a unit of distance covers
+    pub fn with_uv_density(self, uv_density: Float) -> Self {
+        Self { uv_density, ..self }
+    }
+
+    /// width of the ray's beam in surface coordinates, for filtering textures over
+    pub fn uv_width(&self) -> Float {
+        self.width * self.uv_density
+    }
+
+    /// attaches the directions of increasing u and v. both are normalized.
+    /// degenerate tangents, like at the pole of a sphere, leave the arbitrary default in place
+    pub fn with_tangents(self, tangent: Vec3, bitangent: Vec3) -> Self {
+        if tangent.is_zero() || bitangent.is_zero() {
+            return self;
+        }
+        Self {
+            tangent: tangent.normalize(),
+            bitangent: bitangent.normalize(),
+            ..self
+        }
+    }
+}
+
+/// a point picked evenly over a whole surface
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SurfaceSample {
+    pub point: Vec3,
+    pub outward_normal: Vec3,
+    /// area of the whole surface, the inverse of the density of picking the point
+    pub area: Float,
 }
 
 pub trait Shape {
-    fn hit(&self, ray: Ray, bounds: Range<f64>) -> Option<RayContact>;
+    fn hit(&self, ray: Ray, bounds: Range<Float>) -> Option<RayContact<'_>>;
+
+    /// a random point on the surface as seen from `from`, for sampling the shape as an area light.
+    /// None for shapes that can't be sampled
+    fn sample_point(&self, _from: Vec3) -> Option<Vec3> {
+        None
+    }
+
+    /// probability density, per unit solid angle, of `sample_point` picking the point
+    /// seen from `origin` along `direction`
+    fn pdf_value(&self, _origin: Vec3, _direction: Vec3) -> Float {
+        0.
+    }
+
+    /// a random point anywhere on the surface, for sending light out of the shape as an area
+    /// light. None for shapes that can't be sampled
+    fn sample_surface(&self) -> Option<SurfaceSample> {
+        None
+    }
+
+    /// whether anything at all is hit within `bounds`, for shadow rays that only need to know
+    /// if a light is blocked. shapes can skip finding the nearest hit and filling in a contact,
+    /// as long as their material is opaque
+    fn occluded(&self, ray: Ray, bounds: Range<Float>) -> bool {
+        hit_opaque(|bounds| self.hit(ray, bounds), bounds).is_some()
+    }
+}
+
+/// the nearest of the hits `hit` finds within `bounds` that doesn't let the ray through,
+/// rolling against the opacity of each surface in turn
+pub fn hit_opaque<'a>(
+    mut hit: impl FnMut(Range<Float>) -> Option<RayContact<'a>>,
+    bounds: Range<Float>,
+) -> Option<RayContact<'a>> {
+    let mut start = bounds.start;
+    loop {
+        let contact = hit(start..bounds.end)?;
+        let material = contact.material;
+        if material.is_opaque() || random::<Float>() < material.opacity(&contact) {
+            return Some(contact);
+        }
+        // just past the surface, which is found at the same distance every time
+        start = contact.t.next_up();
+    }
+}
+
+/// shared shapes, such as one that's also sampled as a light, are shapes too
+impl<S: Shape + ?Sized> Shape for Arc<S> {
+    fn hit(&self, ray: Ray, bounds: Range<Float>) -> Option<RayContact<'_>> {
+        (**self).hit(ray, bounds)
+    }
+
+    fn sample_point(&self, from: Vec3) -> Option<Vec3> {
+        (**self).sample_point(from)
+    }
+
+    fn pdf_value(&self, origin: Vec3, direction: Vec3) -> Float {
+        (**self).pdf_value(origin, direction)
+    }
+
+    fn sample_surface(&self) -> Option<SurfaceSample> {
+        (**self).sample_surface()
+    }
+
+    fn occluded(&self, ray: Ray, bounds: Range<Float>) -> bool {
+        (**self).occluded(ray, bounds)
+    }
 }
 
 pub struct Sphere {
     pub center: Vec3,
-    pub radius: f64,
-    pub material: Arc<dyn Material + Send + Sync + 'static>,
+    pub radius: Float,
+    pub material: AnyMaterial,
 }
 
 impl Sphere {
     /// constructor
-    pub fn new<Mat>(center: Vec3, radius: f64, material: Mat) -> Self
+    pub fn new<Mat>(center: Vec3, radius: Float, material: Mat) -> Self
     where
         Mat: Material + Send + Sync + 'static,
     {
         Self {
             center,
             radius,
-            material: Arc::new(material),
+            material: AnyMaterial::new(material),
         }
     }
 }
 
-impl PartialEq for RayContact {
+impl PartialEq for RayContact<'_> {
     fn eq(&self, other: &Self) -> bool {
         self.t == other.t && self.point == other.point && self.normal == other.normal
     }
 }
 
-impl PartialOrd for RayContact {
+impl PartialOrd for RayContact<'_> {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
         self.t.partial_cmp(&other.t)
     }
 }
 
 impl Shape for Sphere {
-    fn hit(&self, ray: Ray, bounds: Range<f64>) -> Option<RayContact> {
-        let otc = ray.origin - self.center;
-        // quadratic parameters
-        let a = ray.direction.length_squared();
-        let half_b = otc.dot(ray.direction);
-        let c = otc.length_squared() - self.radius * self.radius;
-        let discriminant = half_b * half_b - a * c;
-        if discriminant < 0. {
-            None
-        } else {
-            let sqrtd = discriminant.sqrt();
-
-            // compute root that is in bounds
-            let mut root = (-half_b - sqrtd) / a;
-            if !bounds.contains(&root) {
-                root = (-half_b + sqrtd) / a;
-                if !bounds.contains(&root) {
-                    return None;
+    fn hit(&self, ray: Ray, bounds: Range<Float>) -> Option<RayContact<'_>> {
+        hit_sphere(self.center, self.radius, &self.material, ray, bounds)
+    }
+
+    fn occluded(&self, ray: Ray, bounds: Range<Float>) -> bool {
+        if !self.material.is_opaque() {
+            return hit_opaque(|bounds| self.hit(ray, bounds), bounds).is_some();
+        }
+        sphere_root(self.center, self.radius, ray, bounds).is_some()
+    }
+
+    /// samples the cone of directions the sphere covers as seen from `from`
+    fn sample_point(&self, from: Vec3) -> Option<Vec3> {
+        let to_center = self.center - from;
+        let distance_squared = to_center.length_squared();
+        if distance_squared <= self.radius * self.radius {
+            // no cone to sample from the inside
+            return None;
+        }
+        let cos_max = (1. - self.radius * self.radius / distance_squared).sqrt();
+        let (r1, r2) = (random::<Float>(), random::<Float>());
+        let z = 1. + r2 * (cos_max - 1.);
+        let phi = 2. * PI * r1;
+        let sin = (1. - z * z).sqrt();
+
+        let direction = Onb::new(to_center.normalize()).local(sin * phi.cos(), sin * phi.sin(), z);
+
+        // first intersection along the sampled direction, which always grazes the sphere at worst
+        let half_b = -to_center.dot(direction);
+        let c = distance_squared - self.radius * self.radius;
+        let t = -half_b - (half_b * half_b - c).max(0.).sqrt();
+        Some(from + t * direction)
+    }
+
+    fn pdf_value(&self, origin: Vec3, direction: Vec3) -> Float {
+        let distance_squared = (self.center - origin).length_squared();
+        if distance_squared <= self.radius * self.radius
+            || self
+                .hit(Ray::new(origin, direction), 0.0..Float::INFINITY)
+                .is_none()
+        {
+            return 0.;
+        }
+        let cos_max = (1. - self.radius * self.radius / distance_squared).sqrt();
+        1. / (2. * PI * (1. - cos_max))
+    }
+
+    fn sample_surface(&self) -> Option<SurfaceSample> {
+        let outward_normal = Vec3::random_unit();
+        Some(SurfaceSample {
+            point: self.center + self.radius * outward_normal,
+            outward_normal,
+            area: 4. * PI * self.radius * self.radius,
+        })
+    }
+}
+
+/// a sphere whose center moves linearly over time
+pub struct MovingSphere {
+    pub center: (Vec3, Vec3),
+    pub time: (Float, Float),
+    pub radius: Float,
+    pub material: AnyMaterial,
+}
+
+impl MovingSphere {
+    /// constructor. the sphere is at `center.0` at `time.0`, and at `center.1` at `time.1`
+    pub fn new<Mat>(
+        center: (Vec3, Vec3),
+        time: (Float, Float),
+        radius: Float,
+        material: Mat,
+    ) -> Self
+    where
+        Mat: Material + Send + Sync + 'static,
+    {
+        Self {
+            center,
+            time,
+            radius,
+            material: AnyMaterial::new(material),
+        }
+    }
+
+    /// center of the sphere at the given time. the first center throughout, if it moves in no
+    /// time at all
+    pub fn center_at(&self, time: Float) -> Vec3 {
+        let (t0, t1) = self.time;
+        let (c0, c1) = self.center;
+        if t0 == t1 {
+            return c0;
+        }
+        c0 + ((time - t0) / (t1 - t0)) * (c1 - c0)
+    }
+}
+
+impl Shape for MovingSphere {
+    fn hit(&self, ray: Ray, bounds: Range<Float>) -> Option<RayContact<'_>> {
+        let center = self.center_at(ray.time);
+        hit_sphere(center, self.radius, &self.material, ray, bounds)
+    }
+
+    fn occluded(&self, ray: Ray, bounds: Range<Float>) -> bool {
+        if !self.material.is_opaque() {
+            return hit_opaque(|bounds| self.hit(ray, bounds), bounds).is_some();
+        }
+        sphere_root(self.center_at(ray.time), self.radius, ray, bounds).is_some()
+    }
+}
+
+fn hit_sphere(
+    center: Vec3,
+    radius: Float,
+    material: &AnyMaterial,
+    ray: Ray,
+    bounds: Range<Float>,
+) -> Option<RayContact<'_>> {
+    sphere_root(center, radius, ray, bounds).map(|root| {
+        let normal = (ray.at(root) - center).normalize();
+        // latitude and longitude, starting from -x and the south pole
+        let u = ((-normal.z).atan2(normal.x) + PI) / (2. * PI);
+        let v = (-normal.y).acos() / PI;
+        // d/du points east, d/dv north
+        let tangent = Vec3::new(normal.z, 0., -normal.x);
+        // u runs around the equator and v half way round, spread the most there
+        let density = 1. / (PI * radius * (2.0 as Float).sqrt());
+        RayContact::new(ray, root, normal, material)
+            .with_uv(u, v)
+            .with_uv_density(density)
+            .with_tangents(tangent, normal.cross(tangent))
+    })
+}
+
+/// distance along the ray to where it first crosses a sphere within `bounds`
+fn sphere_root(center: Vec3, radius: Float, ray: Ray, bounds: Range<Float>) -> Option<Float> {
+    let otc = ray.origin - center;
+    // quadratic parameters
+    let a = ray.direction.length_squared();
+    let half_b = otc.dot(ray.direction);
+    let c = otc.length_squared() - radius * radius;
+    let discriminant = half_b * half_b - a * c;
+    if discriminant < 0. {
+        return None;
+    }
+    let sqrtd = discriminant.sqrt();
+    // the nearer root, unless it's out of bounds
+    [(-half_b - sqrtd) / a, (-half_b + sqrtd) / a]
+        .into_iter()
+        .find(|root| bounds.contains(root))
+}
+
+pub struct Cylinder {
+    pub base: Vec3,
+    pub axis: Vec3,
+    pub height: Float,
+    pub radius: Float,
+    pub capped: bool,
+    pub material: AnyMaterial,
+}
+
+impl Cylinder {
+    /// constructor. the cylinder runs from the center of its `base` disk to the center of its `top` disk
+    pub fn new<Mat>(base: Vec3, top: Vec3, radius: Float, capped: bool, material: Mat) -> Self
+    where
+        Mat: Material + Send + Sync + 'static,
+    {
+        let axis = top - base;
+        Self {
+            base,
+            axis: axis.normalize(),
+            height: axis.length(),
+            radius,
+            capped,
+            material: AnyMaterial::new(material),
+        }
+    }
+}
+
+impl Shape for Cylinder {
+    fn hit(&self, ray: Ray, bounds: Range<Float>) -> Option<RayContact<'_>> {
+        let otb = ray.origin - self.base;
+        // components of the ray perpendicular to the axis
+        let d_axis = ray.direction.dot(self.axis);
+        let o_axis = otb.dot(self.axis);
+        let d_perp = ray.direction - d_axis * self.axis;
+        let o_perp = otb - o_axis * self.axis;
+
+        let mut closest: Option<(Float, Vec3, Float)> = None;
+        let mut consider = |t: Float, normal: Vec3, v: Float| {
+            if bounds.contains(&t) && closest.is_none_or(|(min, _, _)| t < min) {
+                closest = Some((t, normal, v));
+            }
+        };
+
+        // side. v runs up along the axis
+        let a = d_perp.length_squared();
+        let half_b = d_perp.dot(o_perp);
+        let c = o_perp.length_squared() - self.radius * self.radius;
+        for t in quadratic_roots(a, half_b, c) {
+            let y = o_axis + t * d_axis;
+            if (0.0..=self.height).contains(&y) {
+                let normal = (otb + t * ray.direction - y * self.axis) / self.radius;
+                consider(t, normal, y / self.height);
+            }
+        }
+
+        // end caps. v runs out from the center
+        if self.capped {
+            let top = self.base + self.height * self.axis;
+            for (center, normal) in [(self.base, -self.axis), (top, self.axis)] {
+                if let Some(t) = disk_hit(ray, center, normal, self.radius) {
+                    consider(t, normal, (ray.at(t) - center).length() / self.radius);
                 }
             }
+        }
+
+        closest.map(|(t, normal, v)| {
+            let offset = ray.at(t) - self.base;
+            RayContact::new(ray, t, normal, &self.material)
+                .with_uv(azimuth(self.axis, offset), v)
+                .with_tangents_around(self.axis, offset, normal)
+        })
+    }
+}
+
+pub struct Cone {
+    pub base: Vec3,
+    pub axis: Vec3,
+    pub height: Float,
+    pub radius: Float,
+    pub capped: bool,
+    pub material: AnyMaterial,
+}
+
+impl Cone {
+    /// constructor. the cone tapers from a disk of `radius` centered on `base` to a point at `apex`
+    pub fn new<Mat>(base: Vec3, apex: Vec3, radius: Float, capped: bool, material: Mat) -> Self
+    where
+        Mat: Material + Send + Sync + 'static,
+    {
+        let axis = apex - base;
+        Self {
+            base,
+            axis: axis.normalize(),
+            height: axis.length(),
+            radius,
+            capped,
+            material: AnyMaterial::new(material),
+        }
+    }
+}
+
+impl Shape for Cone {
+    fn hit(&self, ray: Ray, bounds: Range<Float>) -> Option<RayContact<'_>> {
+        let otb = ray.origin - self.base;
+        let d_axis = ray.direction.dot(self.axis);
+        let o_axis = otb.dot(self.axis);
+        let d_perp = ray.direction - d_axis * self.axis;
+        let o_perp = otb - o_axis * self.axis;
+
+        let mut closest: Option<(Float, Vec3, Float)> = None;
+        let mut consider = |t: Float, normal: Vec3, v: Float| {
+            if bounds.contains(&t) && closest.is_none_or(|(min, _, _)| t < min) {
+                closest = Some((t, normal, v));
+            }
+        };
+
+        // side, with v running up to the apex. the radius at height y is k * (height - y)
+        let k = self.radius / self.height;
+        let k2 = k * k;
+        let m = self.height - o_axis;
+        let a = d_perp.length_squared() - k2 * d_axis * d_axis;
+        let half_b = d_perp.dot(o_perp) + k2 * m * d_axis;
+        let c = o_perp.length_squared() - k2 * m * m;
+        for t in quadratic_roots(a, half_b, c) {
+            let y = o_axis + t * d_axis;
+            if (0.0..=self.height).contains(&y) {
+                let radial = otb + t * ray.direction - y * self.axis;
+                let normal =
+                    (self.height * radial.normalize() + self.radius * self.axis).normalize();
+                consider(t, normal, y / self.height);
+            }
+        }
 
-            let point = ray.at(root);
-            let normal = (point - self.center).normalize();
-            let front_face = ray.direction.dot(normal) < 0.;
-            RayContact {
-                t: root,
-                point,
-                normal: if front_face { normal } else { -normal },
-                front_face,
-                material: self.material.clone(),
+        // base cap, with v running out from the center
+        if self.capped {
+            if let Some(t) = disk_hit(ray, self.base, -self.axis, self.radius) {
+                let v = (ray.at(t) - self.base).length() / self.radius;
+                consider(t, -self.axis, v);
             }
-            .into()
         }
+
+        closest.map(|(t, normal, v)| {
+            let offset = ray.at(t) - self.base;
+            RayContact::new(ray, t, normal, &self.material)
+                .with_uv(azimuth(self.axis, offset), v)
+                .with_tangents_around(self.axis, offset, normal)
+        })
+    }
+}
+
+pub struct Torus {
+    pub center: Vec3,
+    pub axis: Vec3,
+    pub major_radius: Float,
+    pub minor_radius: Float,
+    pub material: AnyMaterial,
+}
+
+impl Torus {
+    /// constructor. the ring lies in the plane perpendicular to `axis`
+    pub fn new<Mat>(
+        center: Vec3,
+        axis: Vec3,
+        major_radius: Float,
+        minor_radius: Float,
+        material: Mat,
+    ) -> Self
+    where
+        Mat: Material + Send + Sync + 'static,
+    {
+        Self {
+            center,
+            axis: axis.normalize(),
+            major_radius,
+            minor_radius,
+            material: AnyMaterial::new(material),
+        }
+    }
+}
+
+impl Shape for Torus {
+    fn hit(&self, ray: Ray, bounds: Range<Float>) -> Option<RayContact<'_>> {
+        let (big_r, small_r) = (self.major_radius, self.minor_radius);
+
+        // the quartic is badly conditioned far from the torus, so normalize the direction and
+        // start the ray where it enters the bounding sphere
+        let scale = ray.direction.length();
+        let dir = ray.direction / scale;
+        let otc = ray.origin - self.center;
+        let bound = big_r + small_r;
+        let half_b = otc.dot(dir);
+        let discriminant = half_b * half_b - (otc.length_squared() - bound * bound);
+        if discriminant < 0. {
+            return None;
+        }
+        let t_enter = (-half_b - discriminant.sqrt()).max(0.);
+        let o = otc + t_enter * dir;
+
+        // a point p (relative to the center) is on the torus when
+        // (|p|^2 + R^2 - r^2)^2 = 4R^2 (|p|^2 - (p.axis)^2)
+        let od = o.dot(dir);
+        let oo = o.length_squared();
+        let oa = o.dot(self.axis);
+        let da = dir.dot(self.axis);
+        let four_r2 = 4. * big_r * big_r;
+        let e1 = 2. * od;
+        let e0 = oo + big_r * big_r - small_r * small_r;
+        let roots = solve_quartic(
+            1.,
+            2. * e1,
+            e1 * e1 + 2. * e0 - four_r2 * (1. - da * da),
+            2. * e1 * e0 - 2. * four_r2 * (od - oa * da),
+            e0 * e0 - four_r2 * (oo - oa * oa),
+        );
+
+        roots
+            .into_iter()
+            .map(|t| (t + t_enter) / scale)
+            .find(|t| bounds.contains(t))
+            .map(|t| {
+                let p = ray.at(t) - self.center;
+                let h = p.dot(self.axis);
+                let normal = (p.length_squared() + big_r * big_r - small_r * small_r) * p
+                    - 2. * big_r * big_r * (p - h * self.axis);
+                // u runs around the ring, v around the tube
+                let outward = (p - h * self.axis).normalize();
+                let tube = p - big_r * outward;
+                let v = (tube.dot(self.axis).atan2(tube.dot(outward)) + PI) / (2. * PI);
+                let normal = normal.normalize();
+                let tangent = self.axis.cross(outward);
+                RayContact::new(ray, t, normal, &self.material)
+                    .with_uv(azimuth(self.axis, p), v)
+                    .with_tangents(tangent, normal.cross(tangent))
+            })
+    }
+}
+
+/// the axis-aligned plane a `Rect` lies in
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Plane {
+    XY,
+    XZ,
+    YZ,
+}
+
+impl Plane {
+    /// the two in-plane axes and the plane's normal
+    fn axes(self) -> (Vec3, Vec3, Vec3) {
+        match self {
+            Plane::XY => (Vec3::X, Vec3::Y, Vec3::Z),
+            Plane::XZ => (Vec3::X, Vec3::Z, Vec3::Y),
+            Plane::YZ => (Vec3::Y, Vec3::Z, Vec3::X),
+        }
+    }
+}
+
+/// an axis-aligned rectangle, facing the positive direction of the axis it is perpendicular to
+pub struct Rect {
+    pub plane: Plane,
+    pub min: (Float, Float),
+    pub max: (Float, Float),
+    /// offset of the plane along its normal
+    pub k: Float,
+    pub material: AnyMaterial,
+}
+
+impl Rect {
+    /// constructor. `min` and `max` are given in the plane's axes, i.e. (x, z) for `Plane::XZ`
+    pub fn new<Mat>(
+        plane: Plane,
+        min: (Float, Float),
+        max: (Float, Float),
+        k: Float,
+        material: Mat,
+    ) -> Self
+    where
+        Mat: Material + Send + Sync + 'static,
+    {
+        Self {
+            plane,
+            min,
+            max,
+            k,
+            material: AnyMaterial::new(material),
+        }
+    }
+}
+
+impl Shape for Rect {
+    fn hit(&self, ray: Ray, bounds: Range<Float>) -> Option<RayContact<'_>> {
+        let (a_axis, b_axis, normal) = self.plane.axes();
+        let t = (self.k - ray.origin.dot(normal)) / ray.direction.dot(normal);
+        if !bounds.contains(&t) {
+            return None;
+        }
+        let point = ray.at(t);
+        let (a, b) = (point.dot(a_axis), point.dot(b_axis));
+        if a < self.min.0 || a > self.max.0 || b < self.min.1 || b > self.max.1 {
+            return None;
+        }
+        let (width, height) = (self.max.0 - self.min.0, self.max.1 - self.min.1);
+        let u = (a - self.min.0) / width;
+        let v = (b - self.min.1) / height;
+        Some(
+            RayContact::new(ray, t, normal, &self.material)
+                .with_uv(u, v)
+                .with_uv_density(1. / (width * height).sqrt())
+                .with_tangents(a_axis, b_axis),
+        )
+    }
+
+    fn sample_point(&self, _from: Vec3) -> Option<Vec3> {
+        let (a_axis, b_axis, normal) = self.plane.axes();
+        let a = self.min.0 + random::<Float>() * (self.max.0 - self.min.0);
+        let b = self.min.1 + random::<Float>() * (self.max.1 - self.min.1);
+        Some(a * a_axis + b * b_axis + self.k * normal)
+    }
+
+    fn pdf_value(&self, origin: Vec3, direction: Vec3) -> Float {
+        let area = (self.max.0 - self.min.0) * (self.max.1 - self.min.1);
+        area_pdf(self, area, origin, direction)
+    }
+
+    fn sample_surface(&self) -> Option<SurfaceSample> {
+        Some(SurfaceSample {
+            point: self.sample_point(Vec3::ZERO)?,
+            outward_normal: self.plane.axes().2,
+            area: (self.max.0 - self.min.0) * (self.max.1 - self.min.1),
+        })
+    }
+}
+
+/// a parallelogram spanned by two edges from a corner. faces along `u x v`
+pub struct Quad {
+    pub corner: Vec3,
+    pub u: Vec3,
+    pub v: Vec3,
+    normal: Vec3,
+    /// `u x v` scaled for recovering planar coordinates
+    w: Vec3,
+    pub material: AnyMaterial,
+}
+
+impl Quad {
+    /// constructor
+    pub fn new<Mat>(corner: Vec3, u: Vec3, v: Vec3, material: Mat) -> Self
+    where
+        Mat: Material + Send + Sync + 'static,
+    {
+        let n = u.cross(v);
+        Self {
+            corner,
+            u,
+            v,
+            normal: n.normalize(),
+            w: n / n.length_squared(),
+            material: AnyMaterial::new(material),
+        }
+    }
+}
+
+impl Shape for Quad {
+    fn hit(&self, ray: Ray, bounds: Range<Float>) -> Option<RayContact<'_>> {
+        let denom = self.normal.dot(ray.direction);
+        if denom.abs() < 1e-12 {
+            // parallel to the plane
+            return None;
+        }
+        let t = (self.corner - ray.origin).dot(self.normal) / denom;
+        if !bounds.contains(&t) {
+            return None;
+        }
+        let planar = ray.at(t) - self.corner;
+        let alpha = self.w.dot(planar.cross(self.v));
+        let beta = self.w.dot(self.u.cross(planar));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return None;
+        }
+        Some(
+            RayContact::new(ray, t, self.normal, &self.material)
+                .with_uv(alpha, beta)
+                .with_uv_density(1. / (self.u.length() * self.v.length()).sqrt())
+                .with_tangents(self.u, self.v),
+        )
+    }
+
+    fn sample_point(&self, _from: Vec3) -> Option<Vec3> {
+        Some(self.corner + random::<Float>() * self.u + random::<Float>() * self.v)
+    }
+
+    fn pdf_value(&self, origin: Vec3, direction: Vec3) -> Float {
+        area_pdf(self, self.u.cross(self.v).length(), origin, direction)
+    }
+
+    fn sample_surface(&self) -> Option<SurfaceSample> {
+        Some(SurfaceSample {
+            point: self.sample_point(Vec3::ZERO)?,
+            outward_normal: self.normal,
+            area: self.u.cross(self.v).length(),
+        })
+    }
+}
+
+/// solid angle density of picking a point uniformly over a flat shape's area
+fn area_pdf<S: Shape + ?Sized>(shape: &S, area: Float, origin: Vec3, direction: Vec3) -> Float {
+    let ray = Ray::new(origin, direction);
+    match shape.hit(ray, 0.0..Float::INFINITY) {
+        Some(contact) => {
+            let distance_squared = contact.t * contact.t * direction.length_squared();
+            let cos = direction.normalize().dot(contact.normal).abs();
+            distance_squared / (cos * area)
+        }
+        None => 0.,
+    }
+}
+
+/// real roots of a*t^2 + 2*half_b*t + c, smallest first. degrades to the linear case when a is ~0.
+/// missing roots are NaN, which no range contains
+fn quadratic_roots(a: Float, half_b: Float, c: Float) -> [Float; 2] {
+    const E: Float = 1e-12;
+    if a.abs() < E {
+        let t = if half_b.abs() < E {
+            Float::NAN
+        } else {
+            -c / (2. * half_b)
+        };
+        return [t, t];
+    }
+    let discriminant = half_b * half_b - a * c;
+    if discriminant < 0. {
+        return [Float::NAN; 2];
+    }
+    let sqrtd = discriminant.sqrt();
+    let (t0, t1) = ((-half_b - sqrtd) / a, (-half_b + sqrtd) / a);
+    [t0.min(t1), t0.max(t1)]
+}
+
+/// the built in shapes, hit without going through a vtable. any other shape is boxed in `Dyn`
+pub enum AnyShape {
+    Sphere(Sphere),
+    MovingSphere(MovingSphere),
+    Cylinder(Cylinder),
+    Cone(Cone),
+    Torus(Torus),
+    Rect(Rect),
+    Quad(Quad),
+    Mesh(Mesh),
+    Dyn(Box<dyn Shape + Send + Sync + 'static>),
+}
+
+/// calls the same method on whichever shape is inside
+macro_rules! dispatch {
+    ($self:ident, $shape:ident => $call:expr) => {
+        match $self {
+            AnyShape::Sphere($shape) => $call,
+            AnyShape::MovingSphere($shape) => $call,
+            AnyShape::Cylinder($shape) => $call,
+            AnyShape::Cone($shape) => $call,
+            AnyShape::Torus($shape) => $call,
+            AnyShape::Rect($shape) => $call,
+            AnyShape::Quad($shape) => $call,
+            AnyShape::Mesh($shape) => $call,
+            AnyShape::Dyn($shape) => $call,
+        }
+    };
+}
+
+impl AnyShape {
+    /// the variant for `shape` if it's one of the built in shapes, or `Dyn` otherwise
+    pub fn new<S: Shape + Send + Sync + 'static>(shape: S) -> Self {
+        let shape = match downcast::<S, Self>(shape) {
+            Ok(any) => return any,
+            Err(shape) => shape,
+        };
+        let shape = match downcast(shape) {
+            Ok(boxed) => return Self::Dyn(boxed),
+            Err(shape) => shape,
+        };
+        macro_rules! try_variants {
+            ($shape:ident: $($variant:ident),*) => {$(
+                let $shape = match downcast($shape) {
+                    Ok(shape) => return Self::$variant(shape),
+                    Err(shape) => shape,
+                };
+            )*};
+        }
+        try_variants!(shape: Sphere, MovingSphere, Cylinder, Cone, Torus, Rect, Quad, Mesh);
+        Self::Dyn(Box::new(shape))
+    }
+}
+
+impl Shape for AnyShape {
+    #[inline]
+    fn hit(&self, ray: Ray, bounds: Range<Float>) -> Option<RayContact<'_>> {
+        dispatch!(self, shape => shape.hit(ray, bounds))
+    }
+
+    fn sample_point(&self, from: Vec3) -> Option<Vec3> {
+        dispatch!(self, shape => shape.sample_point(from))
+    }
+
+    fn pdf_value(&self, origin: Vec3, direction: Vec3) -> Float {
+        dispatch!(self, shape => shape.pdf_value(origin, direction))
+    }
+
+    fn sample_surface(&self) -> Option<SurfaceSample> {
+        dispatch!(self, shape => shape.sample_surface())
+    }
+
+    #[inline]
+    fn occluded(&self, ray: Ray, bounds: Range<Float>) -> bool {
+        dispatch!(self, shape => shape.occluded(ray, bounds))
+    }
+}
+
+/// shapes built at runtime, behind a trait object, are kept as they are
+impl From<Box<dyn Shape + Send + Sync + 'static>> for AnyShape {
+    fn from(shape: Box<dyn Shape + Send + Sync + 'static>) -> Self {
+        Self::Dyn(shape)
+    }
+}
+
+impl RayContact<'_> {
+    /// tangents for shapes with u running around `axis`. v runs along the surface away from the axis,
+    /// or up it where the surface is parallel to the axis
+    fn with_tangents_around(self, axis: Vec3, offset: Vec3, outward_normal: Vec3) -> Self {
+        let radial = offset - offset.dot(axis) * axis;
+        let tangent = axis.cross(radial);
+        if outward_normal.cross(axis).is_zero() {
+            // end caps
+            self.with_tangents(tangent, radial)
+        } else {
+            self.with_tangents(tangent, outward_normal.cross(tangent.normalize()))
+        }
+    }
+}
+
+/// angle of `offset` around `axis`, mapped to [0, 1]
+fn azimuth(axis: Vec3, offset: Vec3) -> Float {
+    let local = Onb::new(axis).to_local(offset);
+    (local.y.atan2(local.x) + PI) / (2. * PI)
+}
+
+/// distance along the ray to a disk, if the ray crosses it
+fn disk_hit(ray: Ray, center: Vec3, normal: Vec3, radius: Float) -> Option<Float> {
+    let denom = ray.direction.dot(normal);
+    if denom.abs() < 1e-12 {
+        return None;
+    }
+    let t = (center - ray.origin).dot(normal) / denom;
+    if (ray.at(t) - center).length_squared() <= radius * radius {
+        Some(t)
+    } else {
+        None
+    }
+}
+
+#[test]
+fn cylinder_and_cone_hits() {
+    use crate::rt::{Color, Diffuse};
+    let mat = Diffuse::from(Color::WHITE);
+    let cylinder = Cylinder::new(Vec3::ZERO, Vec3::Y, 1.0, true, mat.clone());
+    let cone = Cone::new(Vec3::ZERO, Vec3::Y, 1.0, true, mat);
+
+    // from the side, through the middle
+    let side = Ray::new(Vec3::new(-5., 0.5, 0.), Vec3::X);
+    let contact = cylinder.hit(side, 0.001..Float::INFINITY).unwrap();
+    assert!((contact.t - 4.).abs() < 1e-9);
+    assert_eq!(contact.normal, -Vec3::X);
+    assert!(contact.front_face);
+    // the cone's radius at y = 0.5 is 0.5
+    let contact = cone.hit(side, 0.001..Float::INFINITY).unwrap();
+    assert!((contact.t - 4.5).abs() < 1e-9);
+
+    // from below, onto the base cap
+    let below = Ray::new(Vec3::new(0.2, -5., 0.2), Vec3::Y);
+    for shape in [&cylinder as &dyn Shape, &cone] {
+        let contact = shape.hit(below, 0.001..Float::INFINITY).unwrap();
+        assert!((contact.t - 5.).abs() < 1e-9);
+        assert_eq!(contact.normal, -Vec3::Y);
+    }
+
+    // from inside the cylinder, out through the top cap
+    let inside = Ray::new(Vec3::new(0., 0.5, 0.), Vec3::Y);
+    let contact = cylinder.hit(inside, 0.001..Float::INFINITY).unwrap();
+    assert!((contact.t - 0.5).abs() < 1e-9);
+    assert!(!contact.front_face);
+    assert_eq!(contact.normal, -Vec3::Y);
+
+    // misses past the ends
+    let above = Ray::new(Vec3::new(-5., 1.5, 0.), Vec3::X);
+    assert!(cylinder.hit(above, 0.001..Float::INFINITY).is_none());
+    assert!(cone.hit(above, 0.001..Float::INFINITY).is_none());
+}
+
+#[test]
+fn torus_hits() {
+    use crate::rt::{Color, Diffuse};
+    let torus = Torus::new(Vec3::ZERO, Vec3::Y, 2., 0.5, Diffuse::from(Color::WHITE));
+
+    // through the ring, hitting the outer edge first
+    let through = Ray::new(Vec3::new(-10., 0., 0.), 2. * Vec3::X);
+    let contact = torus.hit(through, 0.001..Float::INFINITY).unwrap();
+    assert!((contact.t - 3.75).abs() < 1e-6);
+    assert!((contact.normal - -Vec3::X).is_zero());
+
+    // straight down the hole
+    let hole = Ray::new(Vec3::new(0., 10., 0.), -Vec3::Y);
+    assert!(torus.hit(hole, 0.001..Float::INFINITY).is_none());
+
+    // down onto the top of the tube
+    let top = Ray::new(Vec3::new(2., 10., 0.), -Vec3::Y);
+    let contact = torus.hit(top, 0.001..Float::INFINITY).unwrap();
+    assert!((contact.t - 9.5).abs() < 1e-6);
+    assert!((contact.normal - Vec3::Y).is_zero());
+}
+
+#[test]
+fn rect_and_quad_uvs() {
+    use crate::rt::{Color, Diffuse};
+    let mat = Diffuse::from(Color::WHITE);
+    let rect = Rect::new(Plane::XZ, (-1., -1.), (3., 1.), 2., mat.clone());
+    let quad = Quad::new(Vec3::new(-1., 2., -1.), 4. * Vec3::X, 2. * Vec3::Z, mat);
+
+    let ray = Ray::new(Vec3::new(0., 5., 0.5), -Vec3::Y);
+    for shape in [&rect as &dyn Shape, &quad] {
+        let contact = shape.hit(ray, 0.001..Float::INFINITY).unwrap();
+        assert!((contact.t - 3.).abs() < 1e-9);
+        assert!((contact.uv.0 - 0.25).abs() < 1e-9 && (contact.uv.1 - 0.75).abs() < 1e-9);
+    }
+
+    let outside = Ray::new(Vec3::new(3.5, 5., 0.), -Vec3::Y);
+    assert!(rect.hit(outside, 0.001..Float::INFINITY).is_none());
+    assert!(quad.hit(outside, 0.001..Float::INFINITY).is_none());
+}
+
+#[test]
+fn built_in_shapes_skip_the_vtable() {
+    use crate::rt::{Color, ConstantMedium, Diffuse};
+    let mat = Diffuse::from(Color::WHITE);
+    let sphere = AnyShape::new(Sphere::new(Vec3::ZERO, 1., mat.clone()));
+    assert!(matches!(sphere, AnyShape::Sphere(_)));
+    assert!(matches!(AnyShape::new(sphere), AnyShape::Sphere(_)));
+    let fog = ConstantMedium::isotropic(Sphere::new(Vec3::ZERO, 1., mat), 1., Color::WHITE);
+    let fog = AnyShape::new(fog);
+    assert!(matches!(fog, AnyShape::Dyn(_)));
+    let ray = Ray::new(Vec3::new(0., 0., -5.), Vec3::Z);
+    assert!(fog.hit(ray, 0.001..3.9).is_none());
+}
+
+#[test]
+fn occlusion_agrees_with_hits() {
+    use crate::math::Mat4;
+    use crate::rt::{Color, Diffuse, Instance, World};
+    let mat = Diffuse::from(Color::WHITE);
+    let mut world = World::new();
+    world.insert(Sphere::new(Vec3::new(-2., 0., 0.), 1., mat.clone()));
+    world.insert(MovingSphere::new(
+        (Vec3::new(2., 0., 0.), Vec3::new(2., 1., 0.)),
+        (0., 1.),
+        0.5,
+        mat.clone(),
+    ));
+    let still = MovingSphere::new((Vec3::ZERO, Vec3::X), (1., 1.), 0.5, mat.clone());
+    assert_eq!(still.center_at(0.5), Vec3::ZERO);
+    world.insert(Instance::new(
+        Torus::new(Vec3::ZERO, Vec3::Y, 1., 0.3, mat.clone()),
+        Mat4::scale(Vec3::ONE * 0.5),
+    ));
+    world.insert(Mesh::new(
+        vec![
+            Vec3::new(0., 2., 0.),
+            Vec3::new(1., 2., 0.),
+            Vec3::new(0., 3., 0.),
+        ],
+        vec![[0, 1, 2]],
+        mat,
+    ));
+    for _ in 0..10000 {
+        let mut ray = Ray::new(Vec3::random_unit() * 6., Vec3::random_unit());
+        ray.time = random();
+        let bounds = 0.001..random::<Float>() * 10.;
+        let hit = world.hit(ray, bounds.clone()).is_some();
+        assert_eq!(world.occluded(ray, bounds.clone()), hit);
+        for object in world.objects() {
+            let hit = object.shape.hit(ray, bounds.clone()).is_some();
+            assert_eq!(object.shape.occluded(ray, bounds.clone()), hit);
+        }
+    }
+}
+
+#[test]
+fn rays_leave_surfaces_at_any_scale() {
+    use crate::rt::{Color, Diffuse};
+    for scale in [1e-5, 1., 1e6] {
+        let center = Vec3::new(3., -2., 1.) * scale;
+        let sphere = Sphere::new(center, scale, Diffuse::from(Color::WHITE));
+        for _ in 0..1000 {
+            let origin = center + Vec3::random_unit() * 5. * scale;
+            let ray = Ray::new(origin, center + Vec3::random_unit() * 0.5 * scale - origin);
+            let contact = sphere.hit(ray, 0.0..Float::INFINITY).unwrap();
+            // rays bouncing off don't find the sphere again, and rays going in find its far side
+            let out = Vec3::random_in_hemisphere(contact.normal);
+            assert!(sphere
+                .hit(contact.spawn(ray, out), 0.0..Float::INFINITY)
+                .is_none());
+            let inward = -contact.normal + 0.5 * Vec3::random_unit_sphere();
+            let through = sphere.hit(contact.spawn(ray, inward), 0.0..Float::INFINITY);
+            assert!((through.unwrap().point - contact.point).length() > scale);
+        }
+
+        // something a hair's breadth above the surface still casts a shadow on it
+        let top = center + scale * Vec3::Y;
+        let leaf = Quad::new(
+            top + Vec3::new(-0.5, 1e-3, -0.5) * scale,
+            Vec3::Z * scale,
+            Vec3::X * scale,
+            Diffuse::from(Color::WHITE),
+        );
+        let down = Ray::new(top + Vec3::Y * scale, -Vec3::Y);
+        let contact = sphere.hit(down, 0.0..Float::INFINITY).unwrap();
+        let (shadow, bounds) = contact.shadow_ray(down, Vec3::Y, 10. * scale);
+        assert!(leaf.occluded(shadow, bounds.clone()));
+        assert!(!sphere.occluded(shadow, bounds));
+        let (shadow, bounds) = contact.shadow_ray(down, Vec3::Y, Float::INFINITY);
+        assert!(leaf.occluded(shadow, bounds));
     }
 }
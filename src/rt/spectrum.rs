@@ -0,0 +1,143 @@
+//! spectral rendering, where each sample follows light of a single wavelength so refraction can
+//! split it up by color, as in prisms and the fire of a diamond. the scene's colors stay rgb,
+//! filtered by how much of each channel the wavelength makes up once it reaches the film
+
+use super::Color;
+use crate::math::{random, Float};
+use std::sync::OnceLock;
+
+/// the wavelengths samples are spread over, in nanometers
+pub const MIN_WAVELENGTH: Float = 380.;
+pub const MAX_WAVELENGTH: Float = 780.;
+
+/// the wavelength refraction indices are usually given at, the sodium d line
+const D_LINE: Float = 587.6;
+/// the wavelengths of the hydrogen f and c lines, between which abbe numbers measure dispersion
+const F_LINE: Float = 486.1;
+const C_LINE: Float = 656.3;
+
+/// a random wavelength, spread evenly over the visible range
+pub fn sample_wavelength() -> Float {
+    MIN_WAVELENGTH + random::<Float>() * (MAX_WAVELENGTH - MIN_WAVELENGTH)
+}
+
+/// the cie 1931 color matching functions, with the multi-lobe fit of wyman, sloan and shirley
+pub fn cie_xyz(wavelength: Float) -> (Float, Float, Float) {
+    let lobe = |mean: Float, below: Float, above: Float| {
+        let spread = if wavelength < mean { below } else { above };
+        let t = (wavelength - mean) / spread;
+        (-0.5 * t * t).exp()
+    };
+    (
+        1.056 * lobe(599.8, 37.9, 31.0) + 0.362 * lobe(442.0, 16.0, 26.7)
+            - 0.065 * lobe(501.1, 20.4, 26.2),
+        0.821 * lobe(568.8, 46.9, 40.5) + 0.286 * lobe(530.9, 16.3, 31.1),
+        1.217 * lobe(437.0, 11.8, 36.0) + 0.681 * lobe(459.0, 26.0, 13.8),
+    )
+}
+
+/// the rgb a sample of `wavelength` adds to the film, per unit of the color it carries. it
+/// averages out to white over the sampled range, so scenes that don't split light come out
+/// the same as rendered in rgb
+pub fn wavelength_color(wavelength: Float) -> Color {
+    static AVERAGE: OnceLock<Color> = OnceLock::new();
+    let rgb = |wavelength| {
+        let (x, y, z) = cie_xyz(wavelength);
+        Color::from_xyz(x, y, z)
+    };
+    let average = AVERAGE.get_or_init(|| {
+        let steps = (MAX_WAVELENGTH - MIN_WAVELENGTH) as u32;
+        let sum = (0..steps).fold(Color::BLACK, |sum, i| {
+            sum + rgb(MIN_WAVELENGTH + i as Float + 0.5)
+        });
+        sum / steps as Float
+    });
+    rgb(wavelength) / *average
+}
+
+/// cie 1931 xyz of the light a black body gives off at `temperature` kelvin, scaled to a
+/// luminance y of 1. it glows red at 1000k, warm white at the 2700k of a tungsten bulb and
+/// bluish white past daylight's 6500k. temperatures below 500k are taken as 500k
+pub fn blackbody_xyz(temperature: Float) -> (Float, Float, Float) {
+    let temperature = temperature.max(500.);
+    // planck's law, leaving out the constant factor, which the scaling takes away anyway
+    let planck = |wavelength: Float| {
+        let meters = wavelength * 1e-9;
+        1. / (meters.powi(5) * ((0.014387769 / (meters * temperature)).exp() - 1.))
+    };
+    let steps = ((MAX_WAVELENGTH - MIN_WAVELENGTH) / 5.) as u32;
+    let (x, y, z) = (0..=steps).fold((0., 0., 0.), |(x, y, z), i| {
+        let wavelength = MIN_WAVELENGTH + 5. * i as Float;
+        let power = planck(wavelength);
+        let (cx, cy, cz) = cie_xyz(wavelength);
+        (x + power * cx, y + power * cy, z + power * cz)
+    });
+    (x / y, 1., z / y)
+}
+
+/// the color of a black body at `temperature` kelvin, with a luminance of 1, for lights
+/// given by their color temperature. colors srgb can't show are brought into it
+pub fn blackbody(temperature: Float) -> Color {
+    let (x, y, z) = blackbody_xyz(temperature);
+    let color = Color::from_xyz(x, y, z).max(Color::BLACK);
+    color / color.luminance()
+}
+
+/// the refraction index at `wavelength` of a material with index `refraction_index` at the
+/// d line and the given abbe number, following cauchy's equation. lower abbe numbers
+/// spread colors further apart
+pub fn cauchy_index(refraction_index: Float, abbe: Float, wavelength: Float) -> Float {
+    let b = (refraction_index - 1.) / (abbe * (F_LINE.powi(-2) - C_LINE.powi(-2)));
+    let a = refraction_index - b / (D_LINE * D_LINE);
+    a + b / (wavelength * wavelength)
+}
+
+#[test]
+fn wavelengths_to_color() {
+    // the peaks of the matching functions land where the eye is most sensitive
+    let (_, y, _) = cie_xyz(555.);
+    assert!((y - 1.).abs() < 0.02);
+    assert!(wavelength_color(450.).b > wavelength_color(450.).r);
+    assert!(wavelength_color(650.).r > wavelength_color(650.).g);
+
+    // seeded, as the deep blue samples are bright enough to throw the average out now and then
+    crate::math::seed_rng(7);
+    let n = 100000;
+    let average = (0..n).fold(Color::BLACK, |sum, _| {
+        sum + wavelength_color(sample_wavelength())
+    }) / n as Float;
+    let error = average - Color::WHITE;
+    assert!(error.r.abs() < 0.02 && error.g.abs() < 0.02 && error.b.abs() < 0.02);
+
+    // bk7 crown glass
+    let (n_d, abbe) = (1.5168, 64.17);
+    assert!((cauchy_index(n_d, abbe, D_LINE) - n_d).abs() < 1e-6);
+    let spread = cauchy_index(n_d, abbe, F_LINE) - cauchy_index(n_d, abbe, C_LINE);
+    assert!((spread - (n_d - 1.) / abbe).abs() < 1e-6);
+    assert!(cauchy_index(n_d, abbe, 400.) > cauchy_index(n_d, abbe, 700.));
+}
+
+#[test]
+fn blackbody_colors() {
+    // warm light is reddest, and the white of daylight about even
+    let (candle, tungsten, daylight, sky) = (
+        blackbody(1900.),
+        blackbody(2700.),
+        blackbody(6504.),
+        blackbody(12000.),
+    );
+    assert!(candle.r / candle.b > tungsten.r / tungsten.b);
+    assert!(tungsten.r > tungsten.g && tungsten.g > tungsten.b);
+    assert!((daylight.r / daylight.b - 1.).abs() < 0.1);
+    assert!(sky.b > sky.g && sky.g > sky.r);
+    for color in [candle, tungsten, daylight, sky, blackbody(0.)] {
+        assert!((color.luminance() - 1.).abs() < 1e-6);
+    }
+    // the chromaticity of a 6504k black body, close to the d65 white point
+    let (x, y, z) = blackbody_xyz(6504.);
+    let (cx, cy) = (x / (x + y + z), y / (x + y + z));
+    assert!(
+        (cx - 0.3135).abs() < 0.003 && (cy - 0.3237).abs() < 0.003,
+        "{cx} {cy}"
+    );
+}
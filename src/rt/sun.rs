@@ -0,0 +1,115 @@
+//! where the sun stands in the sky at a place and time on earth, for daylight studies. follows
+//! noaa's general solar position calculation, good to a fraction of a degree. scenes are laid
+//! out with +y up, north along -z and east along +x
+
+use super::{Color, DirectionalLight, PreethamSky};
+use crate::math::{consts::PI, Float, Vec3};
+use serde::{Deserialize, Serialize};
+
+/// a place and moment on earth to find the sun for
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub struct SunPosition {
+    /// degrees north of the equator, negative to the south
+    pub latitude: Float,
+    /// degrees east of greenwich, negative to the west
+    pub longitude: Float,
+    /// year, month and day of the month, from 1
+    pub date: (i32, u32, u32),
+    /// local clock time, in hours since midnight
+    pub time: Float,
+    /// hours the local clock is ahead of utc
+    #[serde(default)]
+    pub utc_offset: Float,
+}
+
+impl SunPosition {
+    /// days since the start of the year, from 0 on the first of january
+    fn day_of_year(&self) -> u32 {
+        const BEFORE_MONTH: [u32; 12] = [0, 31, 59, 90, 120, 151, 181, 212, 243, 273, 304, 334];
+        let (year, month, day) = self.date;
+        let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+        let month = month.clamp(1, 12) as usize;
+        BEFORE_MONTH[month - 1] + (leap && month > 2) as u32 + day.max(1) - 1
+    }
+
+    /// unit direction from the scene towards the sun, pointing below the horizon at night
+    pub fn direction(&self) -> Vec3 {
+        let utc = self.time - self.utc_offset;
+        // the fraction of the year gone, as an angle
+        let year = 2. * PI / 365. * (self.day_of_year() as Float + (utc - 12.) / 24.);
+        let (s1, c1, s2, c2, s3, c3) = (
+            year.sin(),
+            year.cos(),
+            (2. * year).sin(),
+            (2. * year).cos(),
+            (3. * year).sin(),
+            (3. * year).cos(),
+        );
+        // minutes the sun runs ahead of an even clock, from the tilt and eccentricity of the orbit
+        let equation_of_time =
+            229.18 * (0.000075 + 0.001868 * c1 - 0.032077 * s1 - 0.014615 * c2 - 0.040849 * s2);
+        let declination = 0.006918 - 0.399912 * c1 + 0.070257 * s1 - 0.006758 * c2 + 0.000907 * s2
+            - 0.002697 * c3
+            + 0.00148 * s3;
+        // solar time in minutes, where the sun crosses the meridian at noon
+        let solar = utc * 60. + equation_of_time + 4. * self.longitude;
+        let hour_angle = (solar / 4. - 180.).to_radians();
+
+        let latitude = self.latitude.to_radians();
+        let (sin_lat, cos_lat) = latitude.sin_cos();
+        let (sin_dec, cos_dec) = declination.sin_cos();
+        let east = -cos_dec * hour_angle.sin();
+        let north = sin_dec * cos_lat - cos_dec * sin_lat * hour_angle.cos();
+        let up = sin_dec * sin_lat + cos_dec * cos_lat * hour_angle.cos();
+        Vec3::new(east, up, -north)
+    }
+
+    /// degrees of the sun above the horizon, and clockwise from north around it
+    pub fn elevation_azimuth(&self) -> (Float, Float) {
+        let dir = self.direction();
+        let elevation = dir.y.clamp(-1., 1.).asin().to_degrees();
+        let azimuth = dir.x.atan2(-dir.z).to_degrees().rem_euclid(360.);
+        (elevation, azimuth)
+    }
+
+    /// the daylight sky with the sun in place. `turbidity` is as for `PreethamSky::new`
+    pub fn sky(&self, turbidity: Float) -> PreethamSky {
+        PreethamSky::new(self.direction(), turbidity)
+    }
+
+    /// sunlight along the sun's direction, as bright as `intensity`, with the sun's disk
+    pub fn light(&self, intensity: Color) -> DirectionalLight {
+        DirectionalLight::new(-self.direction(), intensity, 0.27)
+    }
+}
+
+#[test]
+fn sun_positions() {
+    let place = |latitude, longitude, date, time, utc_offset| SunPosition {
+        latitude,
+        longitude,
+        date,
+        time,
+        utc_offset,
+    };
+    let close = |a: Float, b: Float| (a - b).abs() < 0.5;
+
+    // greenwich at noon on the summer solstice, due south and tilted up by the earth's axis
+    let (elevation, azimuth) = place(51.48, 0., (2024, 6, 21), 13.03, 1.).elevation_azimuth();
+    assert!(close(elevation, 90. - 51.48 + 23.44), "{elevation}");
+    assert!((azimuth - 180.).abs() < 1., "{azimuth}");
+
+    // the southern hemisphere sees the noon sun to the north, low in its winter
+    let (elevation, azimuth) = place(-33.87, 151.21, (2023, 6, 21), 11.95, 10.).elevation_azimuth();
+    assert!(close(elevation, 90. - 33.87 - 23.44), "{elevation}");
+    assert!(!(1. ..=359.).contains(&azimuth), "{azimuth}");
+
+    // it rises in the east on an equinox, and is down at midnight
+    let (elevation, azimuth) = place(0., 0., (2024, 3, 20), 6.2, 0.).elevation_azimuth();
+    assert!(
+        elevation.abs() < 2. && close(azimuth, 90.),
+        "{elevation} {azimuth}"
+    );
+    assert!(place(40., -74., (2024, 1, 1), 0., -5.).direction().y < 0.);
+    assert_eq!(place(0., 0., (2024, 3, 1), 0., 0.).day_of_year(), 60);
+}
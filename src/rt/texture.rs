@@ -0,0 +1,808 @@
+use super::{Color, RayContact};
+use crate::math::{Float, Perlin, Vec3, Worley};
+use crate::post::Srgb;
+use image::{ImageResult, Rgb32FImage, RgbImage};
+use std::{
+    mem::size_of_val,
+    path::Path,
+    sync::{Arc, OnceLock},
+};
+
+pub trait Texture {
+    /// color of the texture at the given surface coordinates and point in space
+    fn value(&self, u: Float, v: Float, point: Vec3) -> Color;
+
+    /// color of the texture where a ray meets a surface. textures that depend on more than
+    /// where they're looked up, like which way the surface faces, look at the contact
+    fn at(&self, contact: &RayContact) -> Color {
+        self.value(contact.uv.0, contact.uv.1, contact.point)
+    }
+}
+
+/// shared textures, such as the nodes of a material graph, are textures too
+impl<T: Texture + ?Sized> Texture for Arc<T> {
+    fn value(&self, u: Float, v: Float, point: Vec3) -> Color {
+        (**self).value(u, v, point)
+    }
+
+    fn at(&self, contact: &RayContact) -> Color {
+        (**self).at(contact)
+    }
+}
+
+/// a single flat color
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SolidColor {
+    pub color: Color,
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: Float, _v: Float, _point: Vec3) -> Color {
+        self.color
+    }
+}
+
+/// the color painted onto the surface where it's hit, such as a mesh's vertex colors
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct VertexColors;
+
+impl Texture for VertexColors {
+    /// white, as there's no surface to look at
+    fn value(&self, _u: Float, _v: Float, _point: Vec3) -> Color {
+        Color::WHITE
+    }
+
+    fn at(&self, contact: &RayContact) -> Color {
+        contact.color
+    }
+}
+
+impl From<Color> for SolidColor {
+    fn from(color: Color) -> Self {
+        Self { color }
+    }
+}
+
+/// which coordinates a `Checker` pattern is laid out in
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CheckerSpace {
+    /// 3d checks in world space, independent of the shape's surface coordinates
+    World,
+    /// 2d checks over the shape's surface coordinates
+    Uv,
+}
+
+/// alternates between two textures in a checkerboard pattern
+#[derive(Clone)]
+pub struct Checker {
+    pub even: Arc<dyn Texture + Send + Sync + 'static>,
+    pub odd: Arc<dyn Texture + Send + Sync + 'static>,
+    /// size of a single check
+    pub scale: Float,
+    pub space: CheckerSpace,
+}
+
+impl Checker {
+    /// constructor
+    pub fn new<Even, Odd>(even: Even, odd: Odd, scale: Float, space: CheckerSpace) -> Self
+    where
+        Even: Texture + Send + Sync + 'static,
+        Odd: Texture + Send + Sync + 'static,
+    {
+        Self {
+            even: Arc::new(even),
+            odd: Arc::new(odd),
+            scale,
+            space,
+        }
+    }
+}
+
+impl Checker {
+    /// whether the check at the given coordinates is an even one
+    fn is_even(&self, u: Float, v: Float, point: Vec3) -> bool {
+        let cell = |x: Float| (x / self.scale).floor() as i64;
+        let sum = match self.space {
+            CheckerSpace::World => cell(point.x) + cell(point.y) + cell(point.z),
+            CheckerSpace::Uv => cell(u) + cell(v),
+        };
+        sum.rem_euclid(2) == 0
+    }
+}
+
+impl Texture for Checker {
+    fn value(&self, u: Float, v: Float, point: Vec3) -> Color {
+        if self.is_even(u, v, point) {
+            self.even.value(u, v, point)
+        } else {
+            self.odd.value(u, v, point)
+        }
+    }
+
+    fn at(&self, contact: &RayContact) -> Color {
+        if self.is_even(contact.uv.0, contact.uv.1, contact.point) {
+            self.even.at(contact)
+        } else {
+            self.odd.at(contact)
+        }
+    }
+}
+
+/// how a `NoiseTexture` samples its perlin noise
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NoiseMode {
+    /// a single octave of smooth noise
+    Smooth,
+    /// layered octaves of noise
+    Fbm(u32),
+    /// layered octaves of folded noise, for smoke and marble veins
+    Turbulence(u32),
+}
+
+/// procedural perlin noise, shading `color` from black to full intensity
+#[derive(Clone, Debug)]
+pub struct NoiseTexture {
+    pub perlin: Perlin,
+    pub color: Color,
+    /// frequency of the noise in world space
+    pub scale: Float,
+    pub mode: NoiseMode,
+}
+
+impl NoiseTexture {
+    /// constructor
+    pub fn new(color: Color, scale: Float, mode: NoiseMode) -> Self {
+        Self {
+            perlin: Perlin::new(),
+            color,
+            scale,
+            mode,
+        }
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, _u: Float, _v: Float, point: Vec3) -> Color {
+        let p = self.scale * point;
+        let intensity = match self.mode {
+            NoiseMode::Smooth => 0.5 * (1. + self.perlin.noise(p)),
+            NoiseMode::Fbm(octaves) => 0.5 * (1. + self.perlin.fbm(p, octaves)),
+            NoiseMode::Turbulence(octaves) => self.perlin.turbulence(p, octaves),
+        };
+        intensity.clamp(0., 1.) * self.color
+    }
+}
+
+/// colors placed along [0, 1], blended linearly between, for coloring procedural patterns
+#[derive(Clone, Debug, PartialEq)]
+pub struct ColorRamp {
+    /// positions and their colors, in increasing order of position
+    pub stops: Vec<(Float, Color)>,
+}
+
+impl ColorRamp {
+    /// constructor. sorts the stops by position
+    pub fn new(mut stops: Vec<(Float, Color)>) -> Self {
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Self { stops }
+    }
+
+    /// the color at `t`, holding the end colors past either end
+    pub fn at(&self, t: Float) -> Color {
+        let Some(&(start, first)) = self.stops.first() else {
+            return Color::BLACK;
+        };
+        if t <= start {
+            return first;
+        }
+        for pair in self.stops.windows(2) {
+            let [(a, from), (b, to)] = [pair[0], pair[1]];
+            if t <= b {
+                let f = if b > a { (t - a) / (b - a) } else { 1. };
+                return from.lerp(to, f);
+            }
+        }
+        self.stops[self.stops.len() - 1].1
+    }
+}
+
+/// veined marble: bands along x, warped by turbulence into veins
+#[derive(Clone, Debug)]
+pub struct Marble {
+    pub perlin: Perlin,
+    /// from the stone at 0 to the veins at 1
+    pub ramp: ColorRamp,
+    /// frequency of the bands in world space
+    pub scale: Float,
+    /// how far the turbulence warps the bands, in radians of them
+    pub warp: Float,
+    pub octaves: u32,
+}
+
+impl Marble {
+    /// white marble with gray veins
+    pub fn new(scale: Float) -> Self {
+        let ramp = ColorRamp::new(vec![
+            (0., Color::new(0.92, 0.91, 0.88)),
+            (0.75, Color::new(0.8, 0.79, 0.77)),
+            (1., Color::new(0.3, 0.3, 0.32)),
+        ]);
+        Self {
+            perlin: Perlin::new(),
+            ramp,
+            scale,
+            warp: 6.,
+            octaves: 7,
+        }
+    }
+
+    /// colored by another ramp
+    pub fn with_ramp(self, ramp: ColorRamp) -> Self {
+        Self { ramp, ..self }
+    }
+}
+
+impl Texture for Marble {
+    fn value(&self, _u: Float, _v: Float, point: Vec3) -> Color {
+        let turbulence = self.perlin.turbulence(point, self.octaves);
+        let bands = (self.scale * point.x + self.warp * turbulence).sin();
+        // the veins are where the bands cross zero, thin and sharp
+        self.ramp.at((1. - bands.abs()).powi(3))
+    }
+}
+
+/// wood grain: rings around the y axis, wobbled by noise
+#[derive(Clone, Debug)]
+pub struct Wood {
+    pub perlin: Perlin,
+    /// across each ring, from the early wood at 0 to the dark late wood at 1
+    pub ramp: ColorRamp,
+    /// rings per unit of distance from the axis
+    pub rings: Float,
+    /// how many rings the noise shifts them by at most
+    pub wobble: Float,
+    /// frequency of the noise in world space
+    pub scale: Float,
+}
+
+impl Wood {
+    /// pale wood with brown rings
+    pub fn new(rings: Float) -> Self {
+        let ramp = ColorRamp::new(vec![
+            (0., Color::new(0.76, 0.55, 0.33)),
+            (0.7, Color::new(0.66, 0.45, 0.25)),
+            (1., Color::new(0.4, 0.24, 0.12)),
+        ]);
+        Self {
+            perlin: Perlin::new(),
+            ramp,
+            rings,
+            wobble: 0.4,
+            scale: 2.,
+        }
+    }
+
+    /// colored by another ramp
+    pub fn with_ramp(self, ramp: ColorRamp) -> Self {
+        Self { ramp, ..self }
+    }
+}
+
+impl Texture for Wood {
+    fn value(&self, _u: Float, _v: Float, point: Vec3) -> Color {
+        // stretched along the grain, so the wobble runs with it
+        let p = self.scale * Vec3::new(point.x, 0.1 * point.y, point.z);
+        let distance = (point.x * point.x + point.z * point.z).sqrt();
+        let ring = self.rings * distance + self.wobble * self.perlin.fbm(p, 4);
+        self.ramp.at(ring.rem_euclid(1.))
+    }
+}
+
+/// which distances a `Voronoi` texture shades by
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VoronoiMode {
+    /// to the nearest feature point, dark at the center of each cell
+    F1,
+    /// to the second nearest, for rounded, scale-like cells
+    F2,
+    /// f2 - f1, dark along the borders between cells, like cracked earth
+    Edges,
+}
+
+/// procedural cellular noise, the distances it shades by running along a ramp
+#[derive(Clone, Debug)]
+pub struct Voronoi {
+    pub worley: Worley,
+    pub ramp: ColorRamp,
+    /// frequency of the cells in world space
+    pub scale: Float,
+    pub mode: VoronoiMode,
+}
+
+impl Voronoi {
+    /// constructor, shading from black to white
+    pub fn new(scale: Float, mode: VoronoiMode) -> Self {
+        Self {
+            worley: Worley::new(),
+            ramp: ColorRamp::new(vec![(0., Color::BLACK), (1., Color::WHITE)]),
+            scale,
+            mode,
+        }
+    }
+
+    /// colored by another ramp
+    pub fn with_ramp(self, ramp: ColorRamp) -> Self {
+        Self { ramp, ..self }
+    }
+}
+
+impl Texture for Voronoi {
+    fn value(&self, _u: Float, _v: Float, point: Vec3) -> Color {
+        let (f1, f2) = self.worley.distances(self.scale * point);
+        self.ramp.at(match self.mode {
+            VoronoiMode::F1 => f1,
+            VoronoiMode::F2 => f2 / 1.5,
+            VoronoiMode::Edges => f2 - f1,
+        })
+    }
+}
+
+/// scales, rotates and offsets the surface coordinates another texture is looked up by, to
+/// tile or move it over a shape without changing the texture itself
+#[derive(Clone)]
+pub struct UvTransform {
+    pub texture: Arc<dyn Texture + Send + Sync + 'static>,
+    /// repeats of the texture along u and v
+    pub scale: (Float, Float),
+    /// counter-clockwise, in degrees, about (0, 0)
+    pub rotation: Float,
+    /// added last, in units of the texture
+    pub offset: (Float, Float),
+    /// wraps the coordinates back into [0, 1), for textures like images that only cover that
+    /// square. off leaves them as they are, for patterns that go on forever like uv checks
+    pub wrap: bool,
+}
+
+impl UvTransform {
+    /// constructor, leaving the coordinates as they are until told otherwise
+    pub fn new<Tex>(texture: Tex) -> Self
+    where
+        Tex: Texture + Send + Sync + 'static,
+    {
+        Self {
+            texture: Arc::new(texture),
+            scale: (1., 1.),
+            rotation: 0.,
+            offset: (0., 0.),
+            wrap: true,
+        }
+    }
+
+    /// repeats the texture `u` and `v` times over the surface
+    pub fn scaled(self, u: Float, v: Float) -> Self {
+        Self {
+            scale: (u, v),
+            ..self
+        }
+    }
+
+    /// turns the texture counter-clockwise by `degrees`
+    pub fn rotated(self, degrees: Float) -> Self {
+        Self {
+            rotation: degrees,
+            ..self
+        }
+    }
+
+    /// moves the texture by `u` and `v`
+    pub fn offset(self, u: Float, v: Float) -> Self {
+        Self {
+            offset: (u, v),
+            ..self
+        }
+    }
+
+    /// whether to wrap the coordinates into [0, 1)
+    pub fn wrapping(self, wrap: bool) -> Self {
+        Self { wrap, ..self }
+    }
+
+    /// the coordinates the texture is looked up by at `u` and `v`
+    pub fn transform(&self, u: Float, v: Float) -> (Float, Float) {
+        let (u, v) = (u * self.scale.0, v * self.scale.1);
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        let (u, v) = (
+            u * cos - v * sin + self.offset.0,
+            u * sin + v * cos + self.offset.1,
+        );
+        if self.wrap {
+            (u.rem_euclid(1.), v.rem_euclid(1.))
+        } else {
+            (u, v)
+        }
+    }
+}
+
+impl Texture for UvTransform {
+    fn value(&self, u: Float, v: Float, point: Vec3) -> Color {
+        let (u, v) = self.transform(u, v);
+        self.texture.value(u, v, point)
+    }
+
+    fn at(&self, contact: &RayContact) -> Color {
+        let (u, v) = contact.uv;
+        self.texture.at(&RayContact {
+            uv: self.transform(u, v),
+            uv_density: contact.uv_density * (self.scale.0 * self.scale.1).abs().sqrt(),
+            ..*contact
+        })
+    }
+}
+
+/// projects a texture onto the surface along each axis of world space, blending the three by
+/// how squarely the surface faces each. covers shapes without surface coordinates of their
+/// own, like meshes and terrain, without stretching
+#[derive(Clone)]
+pub struct Triplanar {
+    pub texture: Arc<dyn Texture + Send + Sync + 'static>,
+    /// repeats of the texture per unit of world space
+    pub scale: Float,
+    /// how sharply the projections give way to each other as the surface turns. higher
+    /// values blend less, 1 blends the most
+    pub sharpness: Float,
+}
+
+impl Triplanar {
+    /// constructor, wrapping the texture over every `1 / scale` units
+    pub fn new<Tex>(texture: Tex, scale: Float) -> Self
+    where
+        Tex: Texture + Send + Sync + 'static,
+    {
+        Self {
+            texture: Arc::new(texture),
+            scale,
+            sharpness: 4.,
+        }
+    }
+
+    /// the three projections mixed by the weights along x, y and z, each looked up by `look`
+    /// at the coordinates it projects the point to
+    fn blend(&self, weights: Vec3, point: Vec3, look: impl Fn(Float, Float) -> Color) -> Color {
+        let p = self.scale * point;
+        let look = |u: Float, v: Float| look(u.rem_euclid(1.), v.rem_euclid(1.));
+        let sum = weights.x + weights.y + weights.z;
+        (weights.x * look(p.z, p.y) + weights.y * look(p.x, p.z) + weights.z * look(p.x, p.y)) / sum
+    }
+}
+
+impl Texture for Triplanar {
+    /// without a surface to face, the projections are blended evenly
+    fn value(&self, _u: Float, _v: Float, point: Vec3) -> Color {
+        self.blend(Vec3::ONE, point, |u, v| self.texture.value(u, v, point))
+    }
+
+    fn at(&self, contact: &RayContact) -> Color {
+        let n = contact.normal.abs();
+        let weights = Vec3::new(
+            n.x.powf(self.sharpness),
+            n.y.powf(self.sharpness),
+            n.z.powf(self.sharpness),
+        );
+        self.blend(weights, contact.point, |u, v| {
+            self.texture.at(&RayContact {
+                uv: (u, v),
+                uv_density: self.scale,
+                ..*contact
+            })
+        })
+    }
+}
+
+/// how the 8 bit values of an image stand for the linear values rendered with
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ColorSpace {
+    /// the values as they are, as normal, roughness and alpha maps hold them
+    Linear,
+    /// encoded with the srgb transfer function, as photos and painted colors are saved
+    Srgb,
+}
+
+impl ColorSpace {
+    /// the linear value of an 8 bit one
+    fn decode(self, value: u8) -> Float {
+        static SRGB: OnceLock<Vec<Float>> = OnceLock::new();
+        match self {
+            Self::Linear => value as Float / 255.,
+            Self::Srgb => {
+                let table = SRGB
+                    .get_or_init(|| (0..=255).map(|v| Srgb::decode(v as Float / 255.)).collect());
+                table[value as usize]
+            }
+        }
+    }
+
+    /// the nearest 8 bit value to a linear one
+    fn encode(self, linear: Float) -> u8 {
+        let value = match self {
+            Self::Linear => linear.clamp(0., 1.),
+            Self::Srgb => Srgb::encode(linear),
+        };
+        (value * 255.).round() as u8
+    }
+}
+
+/// an image wrapped over the shape's surface coordinates, with (0, 0) at the bottom left
+#[derive(Clone, Debug)]
+pub struct ImageTexture {
+    pub image: Arc<RgbImage>,
+    pub space: ColorSpace,
+    /// the image halved again and again down to a single pixel, for looking it up where a
+    /// ray's beam takes in many of its pixels at once
+    mips: Arc<Vec<RgbImage>>,
+}
+
+impl ImageTexture {
+    /// constructor
+    pub fn new(image: RgbImage, space: ColorSpace) -> Self {
+        let mut mips: Vec<RgbImage> = vec![];
+        let mut last = &image;
+        while last.width() > 1 || last.height() > 1 {
+            mips.push(half_size(last, space));
+            last = &mips[mips.len() - 1];
+        }
+        Self {
+            image: Arc::new(image),
+            space,
+            mips: Arc::new(mips),
+        }
+    }
+
+    /// loads any image format supported by the `image` crate
+    pub fn open<P: AsRef<Path>>(path: P, space: ColorSpace) -> ImageResult<Self> {
+        Ok(Self::new(image::open(path)?.into_rgb8(), space))
+    }
+
+    /// loads an image's alpha channel as gray, to cut shapes out by. opaque images load white
+    pub fn open_alpha<P: AsRef<Path>>(path: P) -> ImageResult<Self> {
+        let image = image::open(path)?.into_rgba8();
+        Ok(Self::new(
+            RgbImage::from_fn(image.width(), image.height(), |x, y| {
+                let alpha = image.get_pixel(x, y)[3];
+                image::Rgb([alpha; 3])
+            }),
+            ColorSpace::Linear,
+        ))
+    }
+
+    /// bytes taken up by the image and its smaller copies
+    pub fn memory(&self) -> usize {
+        self.image.len() + self.mips.iter().map(|mip| mip.len()).sum::<usize>()
+    }
+}
+
+/// the image at half the width and height, each pixel averaging the linear values of the ones it
+/// covers
+fn half_size(image: &RgbImage, space: ColorSpace) -> RgbImage {
+    let (width, height) = image.dimensions();
+    RgbImage::from_fn((width / 2).max(1), (height / 2).max(1), |x, y| {
+        let mut sum = [0.; 3];
+        for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+            let (sx, sy) = ((2 * x + dx).min(width - 1), (2 * y + dy).min(height - 1));
+            for (sum, channel) in sum.iter_mut().zip(image.get_pixel(sx, sy).0) {
+                *sum += space.decode(channel);
+            }
+        }
+        image::Rgb(sum.map(|sum| space.encode(sum / 4.)))
+    })
+}
+
+/// the pixel of a `width` by `height` image nearest the surface coordinates, with image rows
+/// running top to bottom
+fn pixel_at(width: u32, height: u32, u: Float, v: Float) -> (u32, u32) {
+    let u = u.clamp(0., 1.);
+    let v = 1. - v.clamp(0., 1.);
+    let x = ((u * width as Float) as u32).min(width - 1);
+    let y = ((v * height as Float) as u32).min(height - 1);
+    (x, y)
+}
+
+/// nearest pixel of `image` to the surface coordinates, as linear values
+fn texel(image: &RgbImage, space: ColorSpace, u: Float, v: Float) -> Color {
+    let (x, y) = pixel_at(image.width(), image.height(), u, v);
+    let [r, g, b] = image.get_pixel(x, y).0.map(|c| space.decode(c));
+    Color::new(r, g, b)
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: Float, v: Float, _point: Vec3) -> Color {
+        let (width, height) = self.image.dimensions();
+        if width == 0 || height == 0 {
+            // debug cyan, as there's nothing to sample
+            return Color::new(0., 1., 1.);
+        }
+        texel(&self.image, self.space, u, v)
+    }
+
+    /// from the two sizes of the image whose pixels come closest to the width of the ray's
+    /// beam, blended between
+    fn at(&self, contact: &RayContact) -> Color {
+        let (u, v) = contact.uv;
+        let (width, height) = self.image.dimensions();
+        let texels = contact.uv_width() * width.max(height) as Float;
+        if texels <= 1. || self.mips.is_empty() {
+            return self.value(u, v, contact.point);
+        }
+        let level = texels.log2().min(self.mips.len() as Float);
+        let lower = level.floor() as usize;
+        let image = |level: usize| match level {
+            0 => self.image.as_ref(),
+            _ => &self.mips[level - 1],
+        };
+        let near = texel(image(lower), self.space, u, v);
+        match self.mips.get(lower) {
+            Some(far) => near.lerp(texel(far, self.space, u, v), level - lower as Float),
+            None => near,
+        }
+    }
+}
+
+/// a high dynamic range image wrapped over the shape's surface coordinates, keeping light
+/// brighter than white, as in the sun of an environment map
+#[derive(Clone, Debug)]
+pub struct HdrTexture {
+    pub image: Arc<Rgb32FImage>,
+}
+
+impl HdrTexture {
+    /// constructor
+    pub fn new(image: Rgb32FImage) -> Self {
+        Self {
+            image: Arc::new(image),
+        }
+    }
+
+    /// loads any image format supported by the `image` crate, such as radiance .hdr or
+    /// openexr. 8 bit images load as they would for a linear `ImageTexture`
+    pub fn open<P: AsRef<Path>>(path: P) -> ImageResult<Self> {
+        Ok(Self::new(image::open(path)?.into_rgb32f()))
+    }
+
+    /// bytes taken up by the image
+    pub fn memory(&self) -> usize {
+        size_of_val(&**self.image.as_raw())
+    }
+}
+
+impl Texture for HdrTexture {
+    fn value(&self, u: Float, v: Float, _point: Vec3) -> Color {
+        let (width, height) = self.image.dimensions();
+        if width == 0 || height == 0 {
+            return Color::new(0., 1., 1.);
+        }
+        let (x, y) = pixel_at(width, height, u, v);
+        let [r, g, b] = self.image.get_pixel(x, y).0;
+        Color::new(r as Float, g as Float, b as Float)
+    }
+}
+
+#[test]
+fn procedural_textures() {
+    let ramp = ColorRamp::new(vec![
+        (1., Color::WHITE),
+        (0., Color::BLACK),
+        (0.5, Color::RED),
+    ]);
+    assert_eq!(ramp.at(-1.), Color::BLACK);
+    assert_eq!(ramp.at(0.25), Color::new(0.5, 0., 0.));
+    assert_eq!(ramp.at(0.75), Color::new(1., 0.5, 0.5));
+    assert_eq!(ramp.at(2.), Color::WHITE);
+    assert_eq!(ColorRamp::new(vec![]).at(0.5), Color::BLACK);
+
+    // everything they shade comes off their ramps
+    let (marble, wood) = (Marble::new(4.), Wood::new(8.));
+    let within = |color: Color, ramp: &ColorRamp| {
+        let (dark, light) = (ramp.stops[ramp.stops.len() - 1].1, ramp.stops[0].1);
+        color.r >= dark.r - 1e-6 && color.r <= light.r + 1e-6
+    };
+    for _ in 0..1000 {
+        let p = 4. * Vec3::random() - Vec3::ONE;
+        assert!(within(marble.value(0., 0., p), &marble.ramp));
+        assert!(within(wood.value(0., 0., p), &wood.ramp));
+    }
+}
+
+#[test]
+fn uv_transforms() {
+    let close = |(u, v): (Float, Float), (eu, ev): (Float, Float)| {
+        assert!((u - eu).abs() < 1e-6 && (v - ev).abs() < 1e-6, "({u}, {v})");
+    };
+    let identity = UvTransform::new(SolidColor::from(Color::WHITE));
+    close(identity.transform(0.25, 0.75), (0.25, 0.75));
+
+    // tiled twice across, wrapping round into the second copy
+    let tiled = identity.clone().scaled(2., 3.);
+    close(tiled.transform(0.75, 0.5), (0.5, 0.5));
+    close(
+        tiled.clone().wrapping(false).transform(0.75, 0.5),
+        (1.5, 1.5),
+    );
+
+    // a quarter turn takes u onto v, then the offset moves it
+    let turned = identity.rotated(90.).offset(0.5, 0.25).wrapping(false);
+    close(turned.transform(1., 0.), (0.5, 1.25));
+
+    // lookups go through to the texture at the new coordinates
+    let checks = Checker::new(
+        SolidColor::from(Color::BLACK),
+        SolidColor::from(Color::WHITE),
+        0.5,
+        CheckerSpace::Uv,
+    );
+    let shifted = UvTransform::new(checks).offset(0.5, 0.);
+    assert_eq!(shifted.value(0.25, 0.25, Vec3::ZERO), Color::WHITE);
+}
+
+#[test]
+fn triplanar_projection() {
+    use crate::math::Normalize;
+    use crate::rt::{AnyMaterial, Diffuse, Ray};
+    // shows the coordinates it's looked up at
+    struct Uvs;
+    impl Texture for Uvs {
+        fn value(&self, u: Float, v: Float, _point: Vec3) -> Color {
+            Color::new(u, v, 0.)
+        }
+    }
+    let triplanar = Triplanar::new(Uvs, 0.5);
+    let material = AnyMaterial::Diffuse(Diffuse::from(Color::WHITE));
+    let facing = |normal: Vec3, point: Vec3| {
+        let ray = Ray::new(point + normal, -normal);
+        triplanar.at(&RayContact::new(ray, 1., normal, &material))
+    };
+
+    // squarely facing an axis, only the projection along it shows, repeating every 2 units
+    let point = Vec3::new(0.5, 1., 2.5);
+    assert_eq!(facing(Vec3::X, point), Color::new(0.25, 0.5, 0.));
+    assert_eq!(facing(-Vec3::Y, point), Color::new(0.25, 0.25, 0.));
+    assert_eq!(facing(Vec3::Z, point), Color::new(0.25, 0.5, 0.));
+    // and turning between two of them blends them
+    let between = facing(Vec3::new(1., 1., 0.).normalize(), point);
+    assert!((between.r - 0.25).abs() < 1e-6 && (between.g - 0.375).abs() < 1e-6);
+}
+
+#[test]
+fn mipmaps() {
+    use crate::rt::{Diffuse, Footprint, Instance, Quad, Ray, Shape};
+    // single pixel checks, which average out to gray seen from far enough away
+    let image = RgbImage::from_fn(64, 64, |x, y| image::Rgb([((x + y) % 2 * 255) as u8; 3]));
+    let texture = ImageTexture::new(image.clone(), ColorSpace::Linear);
+    let quad = Quad::new(Vec3::ZERO, Vec3::X, Vec3::Z, Diffuse::new(texture.clone()));
+    let ray = |distance: Float, spread| Ray {
+        footprint: Footprint::spreading(spread),
+        ..Ray::new(Vec3::new(0.3, -distance, 0.3), Vec3::Y)
+    };
+    let seen = |shape: &dyn Shape, ray| {
+        let contact = shape.hit(ray, 0.0..Float::INFINITY).unwrap();
+        texture.at(&contact).r
+    };
+    let check = |r: Float| r == 0. || r == 1.;
+    assert!(check(seen(&quad, ray(1000., 0.))));
+    assert!(check(seen(&quad, ray(1., 1e-3))));
+    assert!((seen(&quad, ray(1000., 1e-3)) - 0.5).abs() < 0.01);
+    // srgb images are averaged once decoded, so they stay as bright from afar
+    let srgb = ImageTexture::new(image, ColorSpace::Srgb);
+    let contact = quad.hit(ray(1000., 1e-3), 0.0..Float::INFINITY).unwrap();
+    assert!((srgb.at(&contact).r - 0.5).abs() < 0.01);
+    let gray = ImageTexture::new(
+        RgbImage::from_pixel(1, 1, image::Rgb([128; 3])),
+        ColorSpace::Srgb,
+    );
+    assert!((gray.value(0., 0., Vec3::ZERO).r - 0.216).abs() < 1e-3);
+
+    // the beam keeps its width past a bounce, and shrinks with a shape scaled up around it
+    let far = ray(1000., 1e-3);
+    let bounced = far.scattered(Vec3::new(0.3, 0., 0.3), -Vec3::Y);
+    assert!((bounced.footprint.width - 1.).abs() < 1e-6);
+    let big = Instance::new(quad, crate::math::Mat4::IDENTITY).scale(Vec3::ONE * 100.);
+    assert!(check(seen(&big, ray(1000., 1e-3))));
+}
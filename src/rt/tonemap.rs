@@ -0,0 +1,249 @@
+use super::{blackbody_xyz, Color};
+use crate::math::Float;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// maps the unbounded radiance a render accumulates into the [0, 1] range of an image
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub enum ToneMap {
+    /// cuts everything above 1 off at white
+    #[default]
+    Clamp,
+    /// c / (1 + luminance), which compresses highlights while keeping their hue
+    Reinhard,
+    /// reinhard, reaching white at the given luminance instead of at infinity
+    ReinhardExtended { white: Float },
+    /// krzysztof narkowicz's fit of the aces filmic curve, with contrasty mid tones
+    Aces,
+}
+
+impl ToneMap {
+    /// tone maps `color`, after scaling it by `exposure` stops
+    pub fn apply(&self, color: Color, exposure: Float) -> Color {
+        let color = color * (2. as Float).powf(exposure);
+        let mapped = match *self {
+            ToneMap::Clamp => color,
+            ToneMap::Reinhard => color / (1. + color.luminance()),
+            ToneMap::ReinhardExtended { white } => {
+                let l = color.luminance();
+                color * (1. + l / (white * white)) / (1. + l)
+            }
+            ToneMap::Aces => {
+                let aces = |x: Float| (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14);
+                Color::new(aces(color.r), aces(color.g), aces(color.b))
+            }
+        };
+        mapped.clamp(0., 1.)
+    }
+}
+
+/// the settings of a real camera, which together decide how bright a scene comes out. with
+/// radiance taken as luminance in cd/m², lights with physical intensities come out as they would
+/// on film: a sunlit scene at iso 100, f/16 and 1/100s, a living room at iso 800, f/2.8 and 1/60s
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PhysicalExposure {
+    /// sensor sensitivity
+    pub iso: Float,
+    /// f-number of the aperture. it doesn't change the depth of field, which `View::aperture` sets
+    pub f_number: Float,
+    /// time the shutter is open for, in seconds
+    pub shutter_speed: Float,
+}
+
+impl Default for PhysicalExposure {
+    /// the sunny 16 rule, for daylight
+    fn default() -> Self {
+        Self {
+            iso: 100.,
+            f_number: 16.,
+            shutter_speed: 0.01,
+        }
+    }
+}
+
+impl PhysicalExposure {
+    /// exposure value at iso 100: 0 at f/1 and 1s, one more for every halving of the light let in
+    pub fn ev100(&self) -> Float {
+        (self.f_number * self.f_number / self.shutter_speed * 100. / self.iso).log2()
+    }
+
+    /// the exposure in stops for `ToneMap::apply`. by iso 12232's saturation based sensitivity,
+    /// a luminance of 1.2 * 2^ev100 comes out white
+    pub fn stops(&self) -> Float {
+        -self.ev100() - (1.2 as Float).log2()
+    }
+}
+
+/// corrects for the color of the light a scene is lit by, as a camera's white balance does: white
+/// lit by a black body at `temperature` kelvin comes out as if lit by daylight. `tint` is for light off the black
+/// body's colors, as the distance off them in cie 1960 uv, positive for greenish light such as
+/// from fluorescent tubes. around 0.01 is a strong cast
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WhiteBalance {
+    temperature: Float,
+    tint: Float,
+    /// scales of the cone responses, from the light's white to daylight's
+    scale: [Float; 3],
+}
+
+/// bradford's cone response matrix, from xyz to a space where adapting to a white point is a
+/// scale of each channel, and its inverse
+const BRADFORD: [[Float; 3]; 3] = [
+    [0.8951, 0.2664, -0.1614],
+    [-0.7502, 1.7135, 0.0367],
+    [0.0389, -0.0685, 1.0296],
+];
+const BRADFORD_INVERSE: [[Float; 3]; 3] = [
+    [0.9869929, -0.1470543, 0.1599627],
+    [0.4323053, 0.5183603, 0.0492912],
+    [-0.0085287, 0.0400428, 0.9684867],
+];
+
+fn transform(m: &[[Float; 3]; 3], v: [Float; 3]) -> [Float; 3] {
+    m.map(|row| row[0] * v[0] + row[1] * v[1] + row[2] * v[2])
+}
+
+impl WhiteBalance {
+    /// the temperature of daylight, which leaves colors as they are. it's the black body
+    /// closest to srgb's white
+    pub const DAYLIGHT: Float = 6504.;
+
+    /// constructor
+    pub fn new(temperature: Float, tint: Float) -> Self {
+        let cones = |temperature, tint| transform(&BRADFORD, Self::white(temperature, tint));
+        let (from, to) = (cones(temperature, tint), cones(Self::DAYLIGHT, 0.));
+        Self {
+            temperature,
+            tint,
+            scale: [0, 1, 2].map(|i| to[i] / from[i]),
+        }
+    }
+
+    pub fn temperature(&self) -> Float {
+        self.temperature
+    }
+
+    pub fn tint(&self) -> Float {
+        self.tint
+    }
+
+    /// xyz of the light's white, moved `tint` off the black body's colors at right angles to
+    /// them, towards green
+    fn white(temperature: Float, tint: Float) -> [Float; 3] {
+        let uv = |temperature| {
+            let (x, y, z) = blackbody_xyz(temperature);
+            let d = x + 15. * y + 3. * z;
+            (4. * x / d, 6. * y / d)
+        };
+        let (u, v) = uv(temperature);
+        let (u0, v0) = uv(temperature * 0.99);
+        let (u1, v1) = uv(temperature * 1.01);
+        let (du, dv) = (u1 - u0, v1 - v0);
+        let length = (du * du + dv * dv).sqrt();
+        // the normal on the side of higher v, where the greens are
+        let (nu, nv) = if du > 0. { (-dv, du) } else { (dv, -du) };
+        let (u, v) = (u + tint * nu / length, v + tint * nv / length);
+        // back through cie 1960 uv to xy, at a luminance of 1
+        let d = 2. * u - 8. * v + 4.;
+        let (x, y) = (3. * u / d, 2. * v / d);
+        [x / y, 1., (1. - x - y) / y]
+    }
+
+    pub fn apply(&self, color: Color) -> Color {
+        let (x, y, z) = color.to_xyz();
+        let cones = transform(&BRADFORD, [x, y, z]);
+        let [x, y, z] = transform(
+            &BRADFORD_INVERSE,
+            [0, 1, 2].map(|i| cones[i] * self.scale[i]),
+        );
+        Color::from_xyz(x, y, z)
+    }
+}
+
+impl Default for WhiteBalance {
+    fn default() -> Self {
+        Self::new(Self::DAYLIGHT, 0.)
+    }
+}
+
+impl FromStr for ToneMap {
+    type Err = String;
+
+    /// one of `clamp`, `reinhard`, `reinhard:<white>` or `aces`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "clamp" => Ok(ToneMap::Clamp),
+            "reinhard" => Ok(ToneMap::Reinhard),
+            "aces" => Ok(ToneMap::Aces),
+            other => match other.strip_prefix("reinhard:").map(str::parse) {
+                Some(Ok(white)) => Ok(ToneMap::ReinhardExtended { white }),
+                _ => Err(format!(
+                    "unknown tone map `{s}`, expected clamp, reinhard, reinhard:<white> or aces"
+                )),
+            },
+        }
+    }
+}
+
+#[test]
+fn tone_maps_stay_in_range() {
+    let operators = [
+        ToneMap::Clamp,
+        ToneMap::Reinhard,
+        ToneMap::ReinhardExtended { white: 4. },
+        ToneMap::Aces,
+    ];
+    for operator in operators {
+        for brightness in [0., 0.1, 1., 10., 1000.] {
+            let c = operator.apply(Color::new(1., 0.5, 0.25) * brightness, 0.);
+            for channel in [c.r, c.g, c.b] {
+                assert!((0.0..=1.0).contains(&channel));
+            }
+        }
+    }
+    // highlights keep their hue instead of clipping to white
+    let c = ToneMap::Reinhard.apply(Color::new(100., 50., 25.), 0.);
+    assert!(c.r > c.g && c.g > c.b);
+    // each stop of exposure doubles the light
+    let c = ToneMap::Clamp.apply(Color::new(0.1, 0.1, 0.1), 2.);
+    assert!((c - Color::new(0.4, 0.4, 0.4)).is_black());
+}
+
+#[test]
+fn physical_exposure() {
+    let exposure = |iso, f_number, shutter_speed| PhysicalExposure {
+        iso,
+        f_number,
+        shutter_speed,
+    };
+    assert!(exposure(100., 1., 1.).ev100().abs() < 1e-6);
+    // each stop of aperture, shutter or sensitivity is worth one ev
+    assert!((exposure(100., 2., 1.).ev100() - 2.).abs() < 1e-6);
+    assert!((exposure(100., 1., 0.5).ev100() - 1.).abs() < 1e-6);
+    assert!((exposure(400., 1., 1.).ev100() + 2.).abs() < 1e-6);
+    // sunlight on a white wall, around 30000 cd/m², is just short of white at sunny 16
+    let sunny = PhysicalExposure::default();
+    let white = ToneMap::Clamp.apply(Color::WHITE * 30000., sunny.stops());
+    assert!((0.9..1.).contains(&white.r));
+}
+
+#[test]
+fn white_balance() {
+    use super::blackbody;
+    use crate::math::Vec3;
+    let close = |a: Color, b: Color| (a - b).is_black() || Vec3::from(a - b).length() < 1e-3;
+    let gray = Color::gray(0.5);
+    assert!(close(WhiteBalance::default().apply(gray), gray));
+    // tungsten light on a white wall comes out like daylight balanced for it
+    let balanced = WhiteBalance::new(3200., 0.).apply(blackbody(3200.));
+    assert!(close(
+        balanced / balanced.luminance(),
+        blackbody(WhiteBalance::DAYLIGHT)
+    ));
+    // and balancing for it makes daylight bluer
+    let cool = WhiteBalance::new(3200., 0.).apply(gray);
+    assert!(cool.b > cool.g && cool.g > cool.r);
+    // light tinted green is balanced by adding magenta
+    let magenta = WhiteBalance::new(WhiteBalance::DAYLIGHT, 0.01).apply(gray);
+    assert!(magenta.g < magenta.r && magenta.g < magenta.b);
+}
@@ -0,0 +1,744 @@
+use crate::anim::{Animation, Key, Keyframes, Pose};
+use crate::assets::Assets;
+use crate::import;
+use crate::math::{Float, Vec3};
+use crate::post::{Bloom, Dither, Exposure, Pipeline, PostEffect, Srgb};
+use crate::rt::*;
+use serde::{Deserialize, Serialize};
+use std::{fs, io, path::Path};
+
+pub mod graph;
+#[cfg(feature = "rhai")]
+mod script;
+
+use graph::MaterialGraph;
+
+type V = (Float, Float, Float);
+
+fn v((x, y, z): V) -> Vec3 {
+    Vec3::new(x, y, z)
+}
+
+fn rgb((r, g, b): V) -> Color {
+    Color::new(r, g, b)
+}
+
+/// a scene described in a ron file
+#[derive(Clone, Debug, Deserialize)]
+pub struct SceneFile {
+    pub camera: CameraDesc,
+    #[serde(default)]
+    pub background: BackgroundDesc,
+    #[serde(default)]
+    pub objects: Vec<ObjectDesc>,
+    /// point-like lights, sampled directly. emissive objects are lights too
+    #[serde(default)]
+    pub lights: Vec<LightDesc>,
+    /// length and frame rate of the animation, if the scene moves
+    #[serde(default)]
+    pub animation: Animation,
+    /// where the camera is at moments of the animation, overriding `camera`
+    #[serde(default)]
+    pub camera_keys: Vec<Key<CameraDesc>>,
+    /// steps developing the rendered radiance for display, in order, instead of the command
+    /// line's. the command line's exposure still comes first
+    #[serde(default)]
+    pub post: Vec<PostDesc>,
+    /// a rhai script, relative to the file, adding objects generated by code to the world.
+    /// needs the `rhai` feature
+    #[serde(default)]
+    pub script: Option<String>,
+    /// images and meshes loaded so far, shared by everything using them, in every frame
+    #[serde(skip)]
+    assets: Assets,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CameraDesc {
+    pub eye: V,
+    pub look_at: V,
+    #[serde(default = "CameraDesc::default_up")]
+    pub up: V,
+    /// vertical field of view, in degrees
+    #[serde(default = "CameraDesc::default_vfov")]
+    pub vfov: Float,
+    #[serde(default)]
+    pub aperture: Float,
+    #[serde(default)]
+    pub aperture_shape: ApertureShape,
+    #[serde(default)]
+    pub projection: Projection,
+    /// interval the shutter is open for. moving shapes are blurred across it
+    #[serde(default)]
+    pub shutter: (Float, Float),
+    /// `LookAt`, `Distance(d)`, or `Auto(x, y)` to focus on what's seen at that point of the
+    /// image, from (0, 0) at the bottom left to (1, 1) at the top right
+    #[serde(default)]
+    pub focus: Focus,
+}
+
+impl CameraDesc {
+    fn default_up() -> V {
+        (0., 1., 0.)
+    }
+
+    fn default_vfov() -> Float {
+        40.
+    }
+
+    pub fn view(&self) -> View {
+        View {
+            eye: v(self.eye),
+            look_at: v(self.look_at),
+            up: v(self.up),
+            vfov: self.vfov,
+            aperture: self.aperture,
+            aperture_shape: self.aperture_shape,
+            projection: self.projection,
+            shutter: self.shutter,
+            focus: self.focus,
+        }
+    }
+}
+
+impl From<View> for CameraDesc {
+    fn from(view: View) -> Self {
+        let v = |v: Vec3| (v.x, v.y, v.z);
+        Self {
+            eye: v(view.eye),
+            look_at: v(view.look_at),
+            up: v(view.up),
+            vfov: view.vfov,
+            aperture: view.aperture,
+            aperture_shape: view.aperture_shape,
+            projection: view.projection,
+            shutter: view.shutter,
+            focus: view.focus,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub enum BackgroundDesc {
+    Solid(V),
+    /// the default daylight gradient
+    #[default]
+    Gradient,
+    Sky {
+        sun_direction: V,
+        turbidity: Float,
+    },
+    /// the daylight sky with the sun where it stands at a place and time
+    Daylight {
+        sun: SunPosition,
+        turbidity: Float,
+    },
+    /// path to a latitude/longitude image, relative to the scene file. high dynamic range
+    /// formats keep their full brightness, and the image lights the scene directly
+    Environment(String),
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ObjectDesc {
+    pub shape: ShapeDesc,
+    pub material: MaterialDesc,
+    /// also sample the object directly as an area light. only for emissive spheres,
+    /// rects and quads
+    #[serde(default)]
+    pub light: bool,
+    /// for finding the object in the built world with `World::find`
+    #[serde(default)]
+    pub name: Option<String>,
+    /// poses the object is moved through over the animation
+    #[serde(default)]
+    pub keys: Vec<Key<PoseDesc>>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct PoseDesc {
+    #[serde(default)]
+    pub translate: V,
+    /// degrees around the x, y and z axes
+    #[serde(default)]
+    pub rotate: V,
+    #[serde(default = "PoseDesc::default_scale")]
+    pub scale: V,
+}
+
+impl PoseDesc {
+    fn default_scale() -> V {
+        (1., 1., 1.)
+    }
+
+    pub fn pose(&self) -> Pose {
+        Pose {
+            translate: v(self.translate),
+            rotate: v(self.rotate),
+            scale: v(self.scale),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub enum ShapeDesc {
+    Sphere {
+        center: V,
+        radius: Float,
+    },
+    /// a sphere moving from `start` at `time.0` to `end` at `time.1`, blurred across
+    /// the camera's shutter
+    MovingSphere {
+        start: V,
+        end: V,
+        #[serde(default = "ShapeDesc::default_time")]
+        time: (Float, Float),
+        radius: Float,
+    },
+    Cylinder {
+        base: V,
+        top: V,
+        radius: Float,
+        #[serde(default)]
+        capped: bool,
+    },
+    Cone {
+        base: V,
+        apex: V,
+        radius: Float,
+        #[serde(default)]
+        capped: bool,
+    },
+    Torus {
+        center: V,
+        axis: V,
+        major_radius: Float,
+        minor_radius: Float,
+    },
+    Quad {
+        corner: V,
+        u: V,
+        v: V,
+    },
+    /// a mesh read from a model file, an obj, a ply, an stl or, with the gltf feature, a gltf or
+    /// glb, made of the materials it comes with. the object's material covers whatever has none.
+    /// `smooth` shades smoothly across edges meeting at less than that many degrees, for models
+    /// without normals of their own. stl models are smoothed at 30 degrees unless it's given
+    Model {
+        path: String,
+        #[serde(default)]
+        smooth: Option<Float>,
+    },
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub enum MaterialDesc {
+    Diffuse(V),
+    OrenNayar {
+        color: V,
+        roughness: Float,
+    },
+    Metal {
+        color: V,
+        #[serde(default)]
+        fuzz: Float,
+    },
+    Pbr {
+        color: V,
+        metallic: Float,
+        roughness: Float,
+    },
+    Dielectric {
+        refraction_index: Float,
+        #[serde(default)]
+        roughness: Float,
+        /// how much it splits light into colors when rendered spectrally, as an abbe number
+        #[serde(default)]
+        abbe: Option<Float>,
+        /// which of overlapping volumes fills the space they share, the highest winning
+        #[serde(default)]
+        priority: u32,
+    },
+    Light(LightColor),
+    /// a light showing an image file over its surface, such as a screen
+    ImageLight {
+        image: String,
+        #[serde(default = "MaterialDesc::default_intensity")]
+        intensity: Float,
+    },
+    /// another material with holes cut where an image is transparent, such as leaves
+    Cutout {
+        material: Box<MaterialDesc>,
+        alpha: String,
+    },
+    /// a material built from a graph of texture and material nodes
+    Graph(MaterialGraph),
+}
+
+/// the color of a light, as red, green and blue, or as the glow of a black body at a
+/// `temperature` in kelvin, with a luminance of `intensity`
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum LightColor {
+    Rgb(V),
+    Blackbody {
+        temperature: Float,
+        intensity: Float,
+    },
+}
+
+impl LightColor {
+    fn color(self) -> Color {
+        match self {
+            LightColor::Rgb(color) => rgb(color),
+            LightColor::Blackbody {
+                temperature,
+                intensity,
+            } => intensity * blackbody(temperature),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub enum LightDesc {
+    Point {
+        position: V,
+        intensity: LightColor,
+    },
+    Spot {
+        position: V,
+        direction: V,
+        intensity: LightColor,
+        inner_angle: Float,
+        outer_angle: Float,
+    },
+    Directional {
+        direction: V,
+        intensity: LightColor,
+        #[serde(default)]
+        angular_radius: Float,
+    },
+    /// sunlight from where the sun stands at a place and time
+    Sun {
+        sun: SunPosition,
+        intensity: LightColor,
+    },
+}
+
+/// a step of post processing
+#[derive(Clone, Debug, Deserialize)]
+pub enum PostDesc {
+    Exposure(Exposure),
+    /// `WhiteBalance(temperature: 3200)`, for the color temperature in kelvin of the light to
+    /// balance for, and optionally a tint
+    WhiteBalance {
+        temperature: Float,
+        #[serde(default)]
+        tint: Float,
+    },
+    Bloom(Bloom),
+    ToneMap(ToneMap),
+    Srgb,
+    /// `Dither()`, or `Dither(bits: 16)` for images saved at 16 bits
+    Dither {
+        #[serde(default = "PostDesc::default_bits")]
+        bits: u32,
+    },
+}
+
+impl PostDesc {
+    fn default_bits() -> u32 {
+        8
+    }
+
+    pub fn effect(&self) -> Box<dyn PostEffect> {
+        match *self {
+            PostDesc::Exposure(exposure) => Box::new(exposure),
+            PostDesc::WhiteBalance { temperature, tint } => {
+                Box::new(WhiteBalance::new(temperature, tint))
+            }
+            PostDesc::Bloom(bloom) => Box::new(bloom),
+            PostDesc::ToneMap(tone_map) => Box::new(tone_map),
+            PostDesc::Srgb => Box::new(Srgb),
+            PostDesc::Dither { bits } => Box::new(Dither { bits }),
+        }
+    }
+}
+
+impl ShapeDesc {
+    fn default_time() -> (Float, Float) {
+        (0., 1.)
+    }
+}
+
+impl MaterialDesc {
+    fn default_intensity() -> Float {
+        1.
+    }
+
+    /// builds the material, loading any images relative to `dir`
+    fn build(&self, dir: &Path, assets: &Assets) -> io::Result<AnyMaterial> {
+        Ok(match *self {
+            MaterialDesc::Diffuse(color) => AnyMaterial::Diffuse(Diffuse::from(rgb(color))),
+            MaterialDesc::OrenNayar { color, roughness } => {
+                AnyMaterial::OrenNayar(OrenNayar::new(SolidColor::from(rgb(color)), roughness))
+            }
+            MaterialDesc::Metal { color, fuzz } => {
+                AnyMaterial::Metal(Metal::new(SolidColor::from(rgb(color)), fuzz))
+            }
+            MaterialDesc::Pbr {
+                color,
+                metallic,
+                roughness,
+            } => AnyMaterial::Pbr(Pbr::new(SolidColor::from(rgb(color)), metallic, roughness)),
+            MaterialDesc::Dielectric {
+                refraction_index,
+                roughness,
+                abbe,
+                priority,
+            } => AnyMaterial::Dielectric(Dielectric {
+                abbe,
+                priority,
+                ..Dielectric::rough(refraction_index, roughness)
+            }),
+            MaterialDesc::Light(color) => {
+                AnyMaterial::DiffuseLight(DiffuseLight::from(color.color()))
+            }
+            MaterialDesc::ImageLight {
+                ref image,
+                intensity,
+            } => {
+                let image = assets.image(&dir.join(image), ColorSpace::Linear)?;
+                AnyMaterial::DiffuseLight(DiffuseLight::new(image, intensity))
+            }
+            MaterialDesc::Cutout {
+                ref material,
+                ref alpha,
+            } => {
+                let alpha = assets.alpha(&dir.join(alpha))?;
+                AnyMaterial::new(Cutout::new(material.build(dir, assets)?, alpha))
+            }
+            MaterialDesc::Graph(ref graph) => graph.compile(dir, assets)?,
+        })
+    }
+}
+
+impl SceneFile {
+    /// reads and parses a scene file
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        Self::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> io::Result<Self> {
+        let invalid = |e: String| io::Error::new(io::ErrorKind::InvalidData, e);
+        let scene: Self = ron::from_str(text).map_err(|e| invalid(e.to_string()))?;
+        let fps = scene.animation.fps;
+        if !(fps > 0. && fps.is_finite()) {
+            return Err(invalid(format!(
+                "the animation needs a positive fps, not {fps}"
+            )));
+        }
+        Ok(scene)
+    }
+
+    /// builds the scene the file describes. paths are looked up relative to `dir`
+    pub fn scene(&self, dir: &Path) -> io::Result<Scene> {
+        self.scene_at(dir, 0.)
+    }
+
+    /// builds the scene as it is `time` seconds into its animation
+    pub fn scene_at(&self, dir: &Path, time: Float) -> io::Result<Scene> {
+        Ok(Scene {
+            world: self.world_at(dir, time)?,
+            view: self.view_at(time),
+        })
+    }
+
+    /// the images and meshes the scene has loaded
+    pub fn assets(&self) -> &Assets {
+        &self.assets
+    }
+
+    /// the scene's post processing, if it has any
+    pub fn pipeline(&self) -> Option<Pipeline> {
+        (!self.post.is_empty()).then(|| self.post.iter().map(PostDesc::effect).collect())
+    }
+
+    pub fn view(&self) -> View {
+        self.view_at(0.)
+    }
+
+    /// the camera `time` seconds into the animation
+    pub fn view_at(&self, time: Float) -> View {
+        let keys = self.camera_keys.iter().map(|key| key.map(CameraDesc::view));
+        Keyframes::new(keys.collect())
+            .at(time)
+            .unwrap_or_else(|| self.camera.view())
+    }
+
+    /// builds the world the file describes. paths are looked up relative to `dir`
+    pub fn world(&self, dir: &Path) -> io::Result<World> {
+        self.world_at(dir, 0.)
+    }
+
+    /// builds the world as it is `time` seconds into the animation
+    pub fn world_at(&self, dir: &Path, time: Float) -> io::Result<World> {
+        let mut world = World::new();
+        match &self.background {
+            BackgroundDesc::Solid(color) => {
+                world.set_background(SolidBackground::from(rgb(*color)))
+            }
+            BackgroundDesc::Gradient => {}
+            BackgroundDesc::Sky {
+                sun_direction,
+                turbidity,
+            } => world.set_background(PreethamSky::new(v(*sun_direction), *turbidity)),
+            BackgroundDesc::Daylight { sun, turbidity } => {
+                world.set_background(sun.sky(*turbidity))
+            }
+            BackgroundDesc::Environment(path) => {
+                let image = self.assets.hdr(&dir.join(path))?;
+                let (width, height) = image.image.dimensions();
+                world.set_environment(EnvironmentMap::new(image).with_resolution(width, height))
+            }
+        }
+
+        for object in &self.objects {
+            let material = object.material.build(dir, &self.assets)?;
+            let keys = object.keys.iter().map(|key| key.map(PoseDesc::pose));
+            let pose = Keyframes::new(keys.collect()).at(time);
+            if pose.is_some() && object.light {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "keyframed objects can't be sampled as lights",
+                ));
+            }
+            if pose.is_some_and(|pose| pose.matrix().inverse().is_none()) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("an object's pose at {time}s flattens it, scales can't be zero"),
+                ));
+            }
+            let handle = match object.shape {
+                ShapeDesc::Sphere { center, radius } => insert(
+                    &mut world,
+                    Sphere::new(v(center), radius, material),
+                    object.light,
+                    pose,
+                ),
+                ShapeDesc::MovingSphere {
+                    start,
+                    end,
+                    time,
+                    radius,
+                } => insert(
+                    &mut world,
+                    MovingSphere::new((v(start), v(end)), time, radius, material),
+                    object.light,
+                    pose,
+                ),
+                ShapeDesc::Cylinder {
+                    base,
+                    top,
+                    radius,
+                    capped,
+                } => insert(
+                    &mut world,
+                    Cylinder::new(v(base), v(top), radius, capped, material),
+                    object.light,
+                    pose,
+                ),
+                ShapeDesc::Cone {
+                    base,
+                    apex,
+                    radius,
+                    capped,
+                } => insert(
+                    &mut world,
+                    Cone::new(v(base), v(apex), radius, capped, material),
+                    object.light,
+                    pose,
+                ),
+                ShapeDesc::Torus {
+                    center,
+                    axis,
+                    major_radius,
+                    minor_radius,
+                } => insert(
+                    &mut world,
+                    Torus::new(v(center), v(axis), major_radius, minor_radius, material),
+                    object.light,
+                    pose,
+                ),
+                ShapeDesc::Quad { corner, u, v: w } => insert(
+                    &mut world,
+                    Quad::new(v(corner), v(u), v(w), material),
+                    object.light,
+                    pose,
+                ),
+                ShapeDesc::Model { ref path, smooth } => {
+                    let mesh = import::open(&dir.join(path), &self.assets)?.into_mesh(material);
+                    let mesh = match smooth {
+                        Some(angle) => mesh.smooth(angle),
+                        None => mesh,
+                    };
+                    insert(&mut world, mesh, object.light, pose)
+                }
+            };
+            if let Some(inserted) = world.get_mut(handle) {
+                inserted.name.clone_from(&object.name);
+            }
+        }
+
+        for light in &self.lights {
+            match *light {
+                LightDesc::Point {
+                    position,
+                    intensity,
+                } => world.add_light(PointLight::new(v(position), intensity.color())),
+                LightDesc::Spot {
+                    position,
+                    direction,
+                    intensity,
+                    inner_angle,
+                    outer_angle,
+                } => world.add_light(SpotLight::new(
+                    v(position),
+                    v(direction),
+                    intensity.color(),
+                    inner_angle,
+                    outer_angle,
+                )),
+                LightDesc::Directional {
+                    direction,
+                    intensity,
+                    angular_radius,
+                } => world.add_light(DirectionalLight::new(
+                    v(direction),
+                    intensity.color(),
+                    angular_radius,
+                )),
+                LightDesc::Sun { sun, intensity } => world.add_light(sun.light(intensity.color())),
+            }
+        }
+
+        match &self.script {
+            #[cfg(feature = "rhai")]
+            Some(path) => script::run(&dir.join(path), world, time),
+            #[cfg(not(feature = "rhai"))]
+            Some(_) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "scripted scenes need the rhai feature",
+            )),
+            None => Ok(world),
+        }
+    }
+}
+
+/// adds a shape to the world, moved into its pose if it has one
+fn insert<S: Shape + Send + Sync + 'static>(
+    world: &mut World,
+    shape: S,
+    light: bool,
+    pose: Option<Pose>,
+) -> Handle {
+    if let Some(pose) = pose {
+        world.insert(Instance::new(shape, pose.matrix()))
+    } else if light {
+        world.insert_area_light(shape)
+    } else {
+        world.insert(shape)
+    }
+}
+
+#[test]
+fn example_scene_parses() {
+    let scene = SceneFile::open("scenes/cornell.ron").unwrap();
+    let world = scene.world(Path::new("scenes")).unwrap();
+    assert_eq!(world.lights.len(), 1);
+    assert!(!world.is_empty());
+    let view = scene.view();
+    let ray = Ray::new(view.eye, view.look_at - view.eye);
+    assert!(world.hit(ray, 0.001..Float::INFINITY).is_some());
+}
+
+#[test]
+fn animated_scene_moves() {
+    let scene = SceneFile::open("scenes/turntable.ron").unwrap();
+    assert_eq!(scene.animation.frames(), 96);
+    let (start, middle) = (scene.view_at(0.), scene.view_at(2.));
+    assert!((start.eye - Vec3::new(0., 2., -6.)).is_zero());
+    assert!((middle.eye - Vec3::new(0., 3., 6.)).is_zero());
+
+    // a quarter turn moves the torus's ring out of the way of a ray through its side
+    let ray = Ray::new(Vec3::new(0.6, 1.2, -5.), Vec3::Z);
+    let hits = |time| {
+        let world = scene.world_at(Path::new("scenes"), time).unwrap();
+        let torus = world.find("torus").unwrap();
+        torus.shape.hit(ray, 0.001..Float::INFINITY).is_some()
+    };
+    assert!(hits(0.));
+    assert!(!hits(1.));
+
+    let still = SceneFile::parse(
+        "(camera: (eye: (0, 0, -1), look_at: (0, 0, 0)), animation: (fps: 0, duration: 1))",
+    );
+    assert_eq!(
+        still.err().unwrap().to_string(),
+        "the animation needs a positive fps, not 0"
+    );
+
+    let flat = SceneFile::parse(
+        "(camera: (eye: (0, 0, -1), look_at: (0, 0, 0)), objects: [(
+            shape: Sphere(center: (0, 0, 0), radius: 1),
+            material: Diffuse((0.5, 0.5, 0.5)),
+            keys: [(time: 0, value: ()), (time: 1, value: (scale: (1, 0, 1)))],
+        )])",
+    )
+    .unwrap();
+    assert!(flat.world_at(Path::new("scenes"), 0.).is_ok());
+    let err = flat.world_at(Path::new("scenes"), 1.).err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn lights_by_temperature() {
+    let color = |ron: &str| ron::from_str::<LightColor>(ron).unwrap().color();
+    assert_eq!(color("(1, 2, 3)"), Color::new(1., 2., 3.));
+    let warm = color("(temperature: 2700, intensity: 5)");
+    assert!((warm.luminance() - 5.).abs() < 1e-6 && warm.r > warm.b);
+    let light: LightDesc =
+        ron::from_str("Point(position: (0, 2, 0), intensity: (temperature: 6500, intensity: 1))")
+            .unwrap();
+    assert!(matches!(light, LightDesc::Point { .. }));
+}
+
+#[test]
+fn post_processing_steps() {
+    let scene = SceneFile::parse(
+        "(camera: (eye: (0, 0, -1), look_at: (0, 0, 0)), post: [
+            Exposure((stops: 1)),
+            WhiteBalance(temperature: 3200),
+            Bloom((strength: 0.5)),
+            ToneMap(ReinhardExtended(white: 4)),
+            Srgb,
+            Dither(),
+        ])",
+    )
+    .unwrap();
+    assert!(matches!(
+        scene.post[2],
+        PostDesc::Bloom(Bloom { strength, threshold, .. }) if strength == 0.5 && threshold == 1.
+    ));
+    let image = image::Rgb32FImage::from_pixel(4, 4, image::Rgb([0.25; 3]));
+    let developed = scene.pipeline().unwrap().develop(&image);
+    assert!(developed
+        .pixels()
+        .all(|p| p.0.iter().all(|c| (0. ..=1.).contains(c))));
+    assert!(
+        SceneFile::parse("(camera: (eye: (0, 0, -1), look_at: (0, 0, 0)))")
+            .unwrap()
+            .pipeline()
+            .is_none()
+    );
+}
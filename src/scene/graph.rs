@@ -0,0 +1,341 @@
+//! materials described as a graph of named nodes, for layered looks the fixed materials can't
+//! express, like paint worn through to the metal beneath. texture nodes give a color over a
+//! surface, and material nodes a material, each taking the others as inputs by name. the
+//! material named by `output` is what the object is made of:
+//!
+//! ```text
+//! Graph((
+//!     nodes: {
+//!         "wear": Noise(scale: 4, octaves: 5),
+//!         "paint": Diffuse(color: (0.1, 0.3, 0.8)),
+//!         "metal": Metal(color: (0.8, 0.8, 0.8), fuzz: 0.2),
+//!         "worn": MixMaterial(a: "paint", b: "metal", factor: "wear"),
+//!     },
+//!     output: "worn",
+//! ))
+//! ```
+
+use super::{rgb, V};
+use crate::assets::Assets;
+use crate::math::{Float, Vec3};
+use crate::rt::*;
+use serde::Deserialize;
+use std::{collections::HashMap, io, path::Path, sync::Arc};
+
+type Tex = Arc<dyn Texture + Send + Sync + 'static>;
+
+/// a material built from nodes
+#[derive(Clone, Debug, Deserialize)]
+pub struct MaterialGraph {
+    pub nodes: HashMap<String, Node>,
+    /// the material node the graph makes
+    pub output: String,
+}
+
+/// what a node takes in: a gray level, a color, or the name of a texture node
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum Input {
+    Value(Float),
+    Color(V),
+    Node(String),
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub enum Node {
+    /// checks of `scale` across in world space, or over the surface coordinates with `uv`
+    Checker {
+        even: Input,
+        odd: Input,
+        scale: Float,
+        #[serde(default)]
+        uv: bool,
+    },
+    /// perlin noise from black to white, with `octaves` layers of detail, or smooth with 0
+    Noise {
+        scale: Float,
+        #[serde(default)]
+        octaves: u32,
+    },
+    /// an image file over the surface coordinates
+    Image(String),
+    /// a formula, such as `"sin(p.x * 10) * noise(p * 4)"`. see `rt::Expression` for what
+    /// it can use
+    Expression(String),
+    /// the colors painted onto a mesh's vertices, white elsewhere
+    VertexColors,
+    Add(Input, Input),
+    Multiply(Input, Input),
+    /// one minus the input, for turning masks around
+    Invert(Input),
+    /// blends from `a` to `b` by `factor`
+    Mix {
+        a: Input,
+        b: Input,
+        factor: Input,
+    },
+    Diffuse {
+        color: Input,
+    },
+    Metal {
+        color: Input,
+        #[serde(default)]
+        fuzz: Float,
+    },
+    Pbr {
+        color: Input,
+        metallic: Float,
+        roughness: Float,
+    },
+    Glass {
+        refraction_index: Float,
+        #[serde(default)]
+        roughness: Float,
+    },
+    /// glows with `color`
+    Emission {
+        color: Input,
+    },
+    /// picks between the materials `a` and `b`, choosing `b` by the brightness of `factor`
+    MixMaterial {
+        a: String,
+        b: String,
+        factor: Input,
+    },
+}
+
+impl Node {
+    fn is_material(&self) -> bool {
+        matches!(
+            self,
+            Node::Diffuse { .. }
+                | Node::Metal { .. }
+                | Node::Pbr { .. }
+                | Node::Glass { .. }
+                | Node::Emission { .. }
+                | Node::MixMaterial { .. }
+        )
+    }
+}
+
+/// textures combining the textures of other nodes
+enum Combine {
+    Add(Tex, Tex),
+    Multiply(Tex, Tex),
+    Invert(Tex),
+    Mix(Tex, Tex, Tex),
+}
+
+impl Combine {
+    /// combines the inputs, each looked up by `input`
+    fn eval(&self, input: impl Fn(&Tex) -> Color) -> Color {
+        match self {
+            Combine::Add(a, b) => input(a) + input(b),
+            Combine::Multiply(a, b) => input(a) * input(b),
+            Combine::Invert(a) => Color::WHITE - input(a),
+            Combine::Mix(a, b, factor) => {
+                let f = input(factor);
+                input(a).lerp(input(b), ((f.r + f.g + f.b) / 3.).clamp(0., 1.))
+            }
+        }
+    }
+}
+
+impl Texture for Combine {
+    fn value(&self, u: Float, v: Float, point: Vec3) -> Color {
+        self.eval(|texture| texture.value(u, v, point))
+    }
+
+    fn at(&self, contact: &RayContact) -> Color {
+        self.eval(|texture| texture.at(contact))
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// builds the nodes of a graph, each once however many take it in
+struct Compiler<'a> {
+    graph: &'a MaterialGraph,
+    dir: &'a Path,
+    assets: &'a Assets,
+    textures: HashMap<&'a str, Tex>,
+    materials: HashMap<&'a str, AnyMaterial>,
+    /// the nodes being built, to catch nodes that take themselves in
+    building: Vec<&'a str>,
+}
+
+impl<'a> Compiler<'a> {
+    fn node(&mut self, name: &'a str) -> io::Result<&'a Node> {
+        if self.building.contains(&name) {
+            return Err(invalid(format!("node `{name}` takes itself in")));
+        }
+        self.graph
+            .nodes
+            .get(name)
+            .ok_or_else(|| invalid(format!("no node named `{name}`")))
+    }
+
+    fn input(&mut self, input: &'a Input) -> io::Result<Tex> {
+        Ok(match input {
+            Input::Value(value) => Arc::new(SolidColor::from(Color::gray(*value))),
+            Input::Color(color) => Arc::new(SolidColor::from(rgb(*color))),
+            Input::Node(name) => self.texture(name)?,
+        })
+    }
+
+    fn texture(&mut self, name: &'a str) -> io::Result<Tex> {
+        if let Some(texture) = self.textures.get(name) {
+            return Ok(texture.clone());
+        }
+        let node = self.node(name)?;
+        if node.is_material() {
+            return Err(invalid(format!(
+                "node `{name}` is a material, not a texture"
+            )));
+        }
+        self.building.push(name);
+        let texture: Tex = match node {
+            Node::Checker {
+                even,
+                odd,
+                scale,
+                uv,
+            } => {
+                let space = if *uv {
+                    CheckerSpace::Uv
+                } else {
+                    CheckerSpace::World
+                };
+                Arc::new(Checker::new(
+                    self.input(even)?,
+                    self.input(odd)?,
+                    *scale,
+                    space,
+                ))
+            }
+            Node::Noise { scale, octaves } => {
+                let mode = match octaves {
+                    0 => NoiseMode::Smooth,
+                    &octaves => NoiseMode::Fbm(octaves),
+                };
+                Arc::new(NoiseTexture::new(Color::WHITE, *scale, mode))
+            }
+            Node::Image(path) => Arc::new(
+                self.assets
+                    .image(&self.dir.join(path), ColorSpace::Linear)?,
+            ),
+            Node::Expression(source) => Arc::new(
+                Expression::parse(source).map_err(|e| invalid(format!("node `{name}`: {e}")))?,
+            ),
+            Node::VertexColors => Arc::new(VertexColors),
+            Node::Add(a, b) => Arc::new(Combine::Add(self.input(a)?, self.input(b)?)),
+            Node::Multiply(a, b) => Arc::new(Combine::Multiply(self.input(a)?, self.input(b)?)),
+            Node::Invert(a) => Arc::new(Combine::Invert(self.input(a)?)),
+            Node::Mix { a, b, factor } => Arc::new(Combine::Mix(
+                self.input(a)?,
+                self.input(b)?,
+                self.input(factor)?,
+            )),
+            _ => unreachable!("materials are caught above"),
+        };
+        self.building.pop();
+        self.textures.insert(name, texture.clone());
+        Ok(texture)
+    }
+
+    fn material(&mut self, name: &'a str) -> io::Result<AnyMaterial> {
+        if let Some(material) = self.materials.get(name) {
+            return Ok(material.clone());
+        }
+        let node = self.node(name)?;
+        if !node.is_material() {
+            return Err(invalid(format!(
+                "node `{name}` is a texture, not a material"
+            )));
+        }
+        self.building.push(name);
+        let material = match node {
+            Node::Diffuse { color } => AnyMaterial::new(Diffuse::new(self.input(color)?)),
+            Node::Metal { color, fuzz } => AnyMaterial::new(Metal::new(self.input(color)?, *fuzz)),
+            Node::Pbr {
+                color,
+                metallic,
+                roughness,
+            } => AnyMaterial::new(Pbr::new(self.input(color)?, *metallic, *roughness)),
+            Node::Glass {
+                refraction_index,
+                roughness,
+            } => AnyMaterial::new(Dielectric::rough(*refraction_index, *roughness)),
+            Node::Emission { color } => AnyMaterial::new(DiffuseLight::new(self.input(color)?, 1.)),
+            Node::MixMaterial { a, b, factor } => AnyMaterial::new(Mix::masked(
+                self.material(a)?,
+                self.material(b)?,
+                self.input(factor)?,
+            )),
+            _ => unreachable!("textures are caught above"),
+        };
+        self.building.pop();
+        self.materials.insert(name, material.clone());
+        Ok(material)
+    }
+}
+
+impl MaterialGraph {
+    /// builds the material of the output node, loading any images relative to `dir`
+    pub fn compile(&self, dir: &Path, assets: &Assets) -> io::Result<AnyMaterial> {
+        Compiler {
+            graph: self,
+            dir,
+            assets,
+            textures: HashMap::new(),
+            materials: HashMap::new(),
+            building: vec![],
+        }
+        .material(&self.output)
+    }
+}
+
+#[test]
+fn compile_graph() {
+    let graph: MaterialGraph = ron::from_str(
+        r#"(
+            nodes: {
+                "checks": Checker(even: 0, odd: (1, 0.5, 0), scale: 1),
+                "dim": Multiply("checks", 0.5),
+                "paint": Diffuse(color: "dim"),
+                "chrome": Metal(color: 0.9),
+                "out": MixMaterial(a: "paint", b: "chrome", factor: 0),
+            },
+            output: "out",
+        )"#,
+    )
+    .unwrap();
+    let material = graph.compile(Path::new("."), &Assets::new()).unwrap();
+    let sphere = Sphere::new(Vec3::ZERO, 1., material);
+    let ray = Ray::new(Vec3::new(0.5, 0.5, -5.), Vec3::Z);
+    let contact = sphere.hit(ray, 0.001..Float::INFINITY).unwrap();
+    // the hit lands in an odd check, halved, with all of the blend on the paint
+    let albedo = contact.material.albedo(&contact);
+    assert!(
+        (albedo - Color::new(0.5, 0.25, 0.)).is_black(),
+        "{albedo:?}"
+    );
+
+    let broken = |nodes: &str, output: &str| {
+        let graph: MaterialGraph =
+            ron::from_str(&format!("(nodes: {{{nodes}}}, output: \"{output}\")")).unwrap();
+        graph
+            .compile(Path::new("."), &Assets::new())
+            .err()
+            .unwrap()
+            .to_string()
+    };
+    assert!(broken(r#""a": Diffuse(color: "b")"#, "a").contains("no node named `b`"));
+    assert!(broken(r#""a": Noise(scale: 1)"#, "a").contains("not a material"));
+    let cycle = r#""a": Diffuse(color: "b"), "b": Add("c", 1), "c": Multiply("b", 1)"#;
+    assert!(broken(cycle, "a").contains("takes itself in"));
+    let typo = r#""a": Diffuse(color: "b"), "b": Expression("sin(p.x")"#;
+    assert_eq!(broken(typo, "a"), "node `b`: expected `)` at 7");
+}
@@ -0,0 +1,191 @@
+//! objects generated by rhai scripts, for layouts easier to write as code than to list, like a
+//! field of randomly placed spheres:
+//!
+//! ```text
+//! for x in -8..8 {
+//!     for z in -8..8 {
+//!         sphere(vec3(x + 0.9 * random(), 0.2, z + 0.9 * random()), 0.2);
+//!         diffuse(random_color() * random_color());
+//!     }
+//! }
+//! ```
+//!
+//! as with `SceneBuilder`, each shape takes the material given after it, and numbers can be
+//! written with or without a point. the script can use:
+//!
+//! - `vec3(x, y, z)`, with `.x`, `.y` and `.z`, adding, subtracting and scaling, and `length`
+//! - `rgb(r, g, b)`, adding, multiplying and scaling colors
+//! - `random()` in [0, 1) and `random_color()`, the same every time the scene is loaded, so
+//!   every frame and every machine rendering it sees the same layout. `seed(n)` picks another
+//! - shapes: `sphere(center, radius)`, `cylinder(base, top, radius)`,
+//!   `cone(base, apex, radius)`, `torus(center, axis, major_radius, minor_radius)` and
+//!   `quad(corner, u, v)`
+//! - materials: `diffuse(color)`, `metal(color, fuzz)`, `pbr(color, metallic, roughness)`,
+//!   `glass(refraction_index)`, and `emissive(color)`, which samples the shape as a light too
+//! - `point_light(position, color)`
+//! - `time`, how many seconds into the animation the scene is built for
+
+use crate::math::{Float, Vec3};
+use crate::rt::*;
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+use rhai::{Dynamic, Engine, EvalAltResult, Scope, FLOAT, INT};
+use std::{cell::RefCell, fs, io, path::Path, rc::Rc};
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+/// the builder the script adds to, taken out and put back by each call
+type Shared = Rc<RefCell<Option<SceneBuilder>>>;
+
+/// a number, written as an integer or not
+fn num(value: Dynamic) -> ScriptResult<Float> {
+    match (value.as_float(), value.as_int()) {
+        (Ok(x), _) => Ok(x as Float),
+        (_, Ok(x)) => Ok(x as Float),
+        _ => Err(format!("expected a number, not {}", value.type_name()).into()),
+    }
+}
+
+fn update(builder: &Shared, f: impl FnOnce(SceneBuilder) -> SceneBuilder) {
+    let mut builder = builder.borrow_mut();
+    *builder = builder.take().map(f);
+}
+
+/// registers a function calling the builder method of the same name. arguments of kind `num`
+/// take either kind of number
+macro_rules! register {
+    (@type num) => { Dynamic };
+    (@type $ty:ty) => { $ty };
+    (@value num $arg:ident) => { num($arg)? };
+    (@value $ty:tt $arg:ident) => { $arg };
+    ($engine:ident, $builder:ident, $name:ident($($arg:ident: $kind:tt),*)) => {{
+        let builder = $builder.clone();
+        $engine.register_fn(
+            stringify!($name),
+            move |$($arg: register!(@type $kind)),*| -> ScriptResult<()> {
+                $(let $arg = register!(@value $kind $arg);)*
+                update(&builder, |b| b.$name($($arg),*));
+                Ok(())
+            },
+        );
+    }};
+}
+
+/// vectors and colors, and their arithmetic
+fn register_types(engine: &mut Engine) {
+    engine
+        .register_type_with_name::<Vec3>("Vec3")
+        .register_fn("vec3", |x: Dynamic, y: Dynamic, z: Dynamic| {
+            Ok::<_, Box<EvalAltResult>>(Vec3::new(num(x)?, num(y)?, num(z)?))
+        })
+        .register_get("x", |v: &mut Vec3| v.x as FLOAT)
+        .register_get("y", |v: &mut Vec3| v.y as FLOAT)
+        .register_get("z", |v: &mut Vec3| v.z as FLOAT)
+        .register_fn("length", |v: &mut Vec3| v.length() as FLOAT)
+        .register_fn("+", |a: Vec3, b: Vec3| a + b)
+        .register_fn("-", |a: Vec3, b: Vec3| a - b)
+        .register_fn("-", |a: Vec3| -a)
+        .register_fn("*", |a: Vec3, t: Dynamic| {
+            Ok::<_, Box<EvalAltResult>>(num(t)? * a)
+        })
+        .register_fn("*", |t: Dynamic, a: Vec3| {
+            Ok::<_, Box<EvalAltResult>>(num(t)? * a)
+        })
+        .register_fn("/", |a: Vec3, t: Dynamic| {
+            Ok::<_, Box<EvalAltResult>>(a / num(t)?)
+        });
+    engine
+        .register_type_with_name::<Color>("Color")
+        .register_fn("rgb", |r: Dynamic, g: Dynamic, b: Dynamic| {
+            Ok::<_, Box<EvalAltResult>>(Color::new(num(r)?, num(g)?, num(b)?))
+        })
+        .register_fn("+", |a: Color, b: Color| a + b)
+        .register_fn("*", |a: Color, b: Color| a * b)
+        .register_fn("*", |a: Color, t: Dynamic| {
+            Ok::<_, Box<EvalAltResult>>(num(t)? * a)
+        })
+        .register_fn("*", |t: Dynamic, a: Color| {
+            Ok::<_, Box<EvalAltResult>>(num(t)? * a)
+        });
+}
+
+/// runs the script at `path`, adding what it makes to `world`, as it is `time` seconds into
+/// the animation
+pub fn run(path: &Path, world: World, time: Float) -> io::Result<World> {
+    let source = fs::read_to_string(path)?;
+    let mut engine = Engine::new();
+    register_types(&mut engine);
+
+    let rng = Rc::new(RefCell::new(SmallRng::seed_from_u64(0)));
+    let random = rng.clone();
+    engine.register_fn("random", move || {
+        random.borrow_mut().gen::<Float>() as FLOAT
+    });
+    let random = rng.clone();
+    engine.register_fn("random_color", move || {
+        let mut rng = random.borrow_mut();
+        Color::new(rng.gen(), rng.gen(), rng.gen())
+    });
+    engine.register_fn("seed", move |seed: INT| {
+        *rng.borrow_mut() = SmallRng::seed_from_u64(seed as u64)
+    });
+
+    let builder: Shared = Rc::new(RefCell::new(Some(SceneBuilder::from(world))));
+    register!(engine, builder, sphere(center: Vec3, radius: num));
+    register!(engine, builder, cylinder(base: Vec3, top: Vec3, radius: num));
+    register!(engine, builder, cone(base: Vec3, apex: Vec3, radius: num));
+    register!(engine, builder, torus(center: Vec3, axis: Vec3, major: num, minor: num));
+    register!(engine, builder, quad(corner: Vec3, u: Vec3, v: Vec3));
+    register!(engine, builder, diffuse(color: Color));
+    register!(engine, builder, metal(color: Color, fuzz: num));
+    register!(engine, builder, pbr(color: Color, metallic: num, roughness: num));
+    register!(engine, builder, glass(refraction_index: num));
+    register!(engine, builder, emissive(color: Color));
+    let lights = builder.clone();
+    engine.register_fn("point_light", move |position: Vec3, color: Color| {
+        update(&lights, |b| b.light(PointLight::new(position, color)))
+    });
+
+    let mut scope = Scope::new();
+    scope.push_constant("time", time as FLOAT);
+    engine.run_with_scope(&mut scope, &source).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {e}", path.display()),
+        )
+    })?;
+    let builder = builder.borrow_mut().take();
+    Ok(builder
+        .expect("the builder is put back after every call")
+        .build()
+        .world)
+}
+
+#[test]
+fn scripted_objects() {
+    use crate::scene::SceneFile;
+    let scene = SceneFile::open("scenes/spheres.ron").unwrap();
+    let dir = Path::new("scenes");
+    let world = scene.world(dir).unwrap();
+    // the ground and three large spheres from the file, and the field from the script
+    assert!(world.len() > 200);
+    assert!(world.find("ground").is_some());
+    // generated alike every time
+    let again = scene.world(dir).unwrap();
+    let centers = |world: &World| {
+        let ray = Ray::new(Vec3::new(13., 2., 3.), Vec3::new(-13., -2., -3.));
+        world
+            .hit(ray, 0.001..Float::INFINITY)
+            .map(|contact| contact.point)
+    };
+    assert_eq!(centers(&world), centers(&again));
+
+    let error = |source: &str| {
+        let path = std::env::temp_dir().join("saraytracer-script-error.rhai");
+        fs::write(&path, source).unwrap();
+        let error = run(&path, World::new(), 0.).err().unwrap().to_string();
+        fs::remove_file(path).unwrap();
+        error
+    };
+    assert!(error("sphere(vec3(0, 0, 0), \"big\")").contains("expected a number"));
+    assert!(error("teapot()").contains("teapot"));
+}
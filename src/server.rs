@@ -0,0 +1,375 @@
+//! a small http api for driving renders from scripts and web pages:
+//!
+//! - `POST /renders?width=400&spp=100` with a ron scene file as the body, or none for the preset
+//!   scene named by `scene`, such as `cornell`, or else the demo scene, starts a render and
+//!   answers with its id, as `{"id": 0}`. the other settings are `height`, `max_depth`,
+//!   `pass_samples`, `seed`, `sampler`, `clamp`, `clamp_bounce`, `spectral` and
+//!   `light_candidates`, as on the command line, and `time` into the scene's animation. images
+//!   are 2 to `MAX_SIZE` pixels on a side, with up to `MAX_SPP` samples
+//! - `GET /renders/<id>` answers with its progress, as `{"samples": 20, "spp": 100, "done": false}`,
+//!   plus an `error` if the scene couldn't be built
+//! - `GET /renders/<id>/image.png` is the image so far, tone mapped by the `tone_map`,
+//!   `exposure` and `auto_exposure` settings, and `GET /renders/<id>/image.exr` is its linear
+//!   radiance
+//! - `DELETE /renders/<id>` stops a render and forgets it
+
+use crate::distributed::Job;
+use crate::math::{Float, Sampler};
+use crate::output::{encode_display, encode_linear, BitDepth};
+use crate::post::{Exposure, Pipeline};
+use crate::rt::{scenes, ToneMap};
+use crate::scene::SceneFile;
+use image::{ImageOutputFormat, Rgb32FImage};
+use serde_json::json;
+use std::{
+    collections::HashMap,
+    io::{self, Cursor},
+    net::SocketAddr,
+    path::PathBuf,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+/// the widest or tallest image a posted render can ask for
+pub const MAX_SIZE: u32 = 8192;
+/// the most samples per pixel a posted render can ask for
+pub const MAX_SPP: u32 = 1 << 16;
+
+/// a render started through the api
+struct Render {
+    spp: u32,
+    progress: Mutex<Progress>,
+    stopped: AtomicBool,
+}
+
+/// how far a render has got
+struct Progress {
+    samples: u32,
+    /// the estimate after the last pass
+    image: Rgb32FImage,
+    error: Option<String>,
+}
+
+type Reply = Response<Cursor<Vec<u8>>>;
+
+/// an http server rendering the scenes posted to it
+pub struct RenderServer {
+    server: Server,
+    /// directory the paths in posted scenes are relative to
+    dir: PathBuf,
+    renders: HashMap<u32, Arc<Render>>,
+    next_id: u32,
+}
+
+impl RenderServer {
+    /// starts listening on `addr`, as host:port
+    pub fn new(addr: &str, dir: PathBuf) -> io::Result<Self> {
+        let server = Server::http(addr).map_err(io::Error::other)?;
+        Ok(Self {
+            server,
+            dir,
+            renders: HashMap::new(),
+            next_id: 0,
+        })
+    }
+
+    pub fn addr(&self) -> Option<SocketAddr> {
+        self.server.server_addr().to_ip()
+    }
+
+    /// answers requests forever. renders run in the background, one thread each
+    pub fn run(mut self) {
+        while let Ok(mut request) = self.server.recv() {
+            let reply = self.handle(&mut request).unwrap_or_else(|(status, msg)| {
+                json_reply(json!({ "error": msg })).with_status_code(status)
+            });
+            // the client hanging up early isn't the server's problem
+            let _ = request.respond(reply);
+        }
+    }
+
+    fn handle(&mut self, request: &mut Request) -> Result<Reply, (u16, String)> {
+        let url = request.url().to_string();
+        let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+        let query = parse_query(query);
+        let segments: Vec<_> = path.split('/').filter(|s| !s.is_empty()).collect();
+        let render = |id: &str| {
+            id.parse()
+                .ok()
+                .and_then(|id| self.renders.get(&id))
+                .cloned()
+                .ok_or((404, format!("no render {id}")))
+        };
+
+        match (request.method(), &segments[..]) {
+            (Method::Post, ["renders"]) => {
+                let mut scene = String::new();
+                request
+                    .as_reader()
+                    .read_to_string(&mut scene)
+                    .map_err(|e| (400, e.to_string()))?;
+                let job = self.job(&query, scene).map_err(|e| (400, e))?;
+                let id = self.next_id;
+                self.next_id += 1;
+                self.renders.insert(id, start(job));
+                Ok(json_reply(json!({ "id": id })).with_status_code(201))
+            }
+            (Method::Get, ["renders", id]) => {
+                let render = render(id)?;
+                let progress = render.progress.lock().unwrap();
+                Ok(json_reply(json!({
+                    "samples": progress.samples,
+                    "spp": render.spp,
+                    "done": progress.samples >= render.spp,
+                    "error": progress.error,
+                })))
+            }
+            (Method::Get, ["renders", id, "image.png"]) => {
+                let tone_map = setting(&query, "tone_map", ToneMap::Aces)?;
+                let exposure = Exposure {
+                    stops: setting(&query, "exposure", 0.)?,
+                    auto: setting(&query, "auto_exposure", false)?,
+                };
+                let image = render(id)?.progress.lock().unwrap().image.clone();
+                let image = Pipeline::standard(exposure, tone_map).develop(&image);
+                let png = encode_display(&image, ImageOutputFormat::Png, BitDepth::Eight)
+                    .map_err(|e| (500, e.to_string()))?;
+                Ok(data_reply(png, "image/png"))
+            }
+            (Method::Get, ["renders", id, "image.exr"]) => {
+                let image = render(id)?.progress.lock().unwrap().image.clone();
+                let exr = encode_linear(&image).map_err(|e| (500, e.to_string()))?;
+                Ok(data_reply(exr, "image/x-exr"))
+            }
+            (Method::Delete, ["renders", id]) => {
+                render(id)?.stopped.store(true, Ordering::Relaxed);
+                self.renders.remove(&id.parse().unwrap());
+                Ok(Response::from_data(vec![]).with_status_code(204))
+            }
+            _ => Err((404, format!("no {} {path}", request.method()))),
+        }
+    }
+
+    /// the job of a posted scene and settings
+    fn job(&self, query: &HashMap<String, String>, scene: String) -> Result<Job, String> {
+        let number = |name, default: u32| setting(query, name, default).map_err(|(_, e)| e);
+        let width = number("width", 400)?;
+        let optional = |name: &str| {
+            query
+                .get(name)
+                .map(|v| v.parse::<Float>().map_err(|e| format!("bad {name}: {e}")))
+                .transpose()
+        };
+        let height = number("height", (width as f64 / (16.0 / 9.0)) as u32)?;
+        // a row or column of one pixel has nothing to spread samples across
+        if !(2..=MAX_SIZE).contains(&width) || !(2..=MAX_SIZE).contains(&height) {
+            return Err(format!(
+                "the image must be 2 to {MAX_SIZE} pixels on a side, not {width}x{height}"
+            ));
+        }
+        let spp = number("spp", 50)?;
+        if spp > MAX_SPP {
+            return Err(format!("spp can be at most {MAX_SPP}, not {spp}"));
+        }
+        let time = setting(query, "time", 0.).map_err(|(_, e)| e)?;
+        let preset = query.get("scene").cloned();
+        let (scene, camera) = if scene.trim().is_empty() {
+            let name = preset.as_deref().unwrap_or("spheres");
+            let scene = scenes::by_name(name).ok_or(format!("no preset scene {name}"))?;
+            (None, scene.view.into())
+        } else {
+            let view = SceneFile::parse(&scene)
+                .map_err(|e| e.to_string())?
+                .view_at(time);
+            (Some(scene), view.into())
+        };
+        Ok(Job {
+            scene,
+            preset,
+            dir: self.dir.clone(),
+            camera,
+            time,
+            width,
+            height,
+            spp,
+            pass_samples: number("pass_samples", 4)?,
+            max_depth: number("max_depth", 50)?,
+            seed: setting(query, "seed", 0).map_err(|(_, e)| e)?,
+            clamp_bounce: optional("clamp_bounce")?,
+            clamp_sample: optional("clamp")?,
+            sampler: setting(query, "sampler", Sampler::default()).map_err(|(_, e)| e)?,
+            spectral: setting(query, "spectral", false).map_err(|(_, e)| e)?,
+            light_candidates: query
+                .get("light_candidates")
+                .map(|v| v.parse().map_err(|e| format!("bad light_candidates: {e}")))
+                .transpose()?,
+            aovs: false,
+        })
+    }
+}
+
+/// renders a job pass by pass in the background
+fn start(job: Job) -> Arc<Render> {
+    let render = Arc::new(Render {
+        spp: job.spp,
+        progress: Mutex::new(Progress {
+            samples: 0,
+            image: Rgb32FImage::new(job.width, job.height),
+            error: None,
+        }),
+        stopped: AtomicBool::new(false),
+    });
+    let shared = render.clone();
+    thread::spawn(move || {
+        let scene = match job.scene() {
+            Ok(scene) => scene,
+            Err(e) => {
+                let mut progress = shared.progress.lock().unwrap();
+                progress.error = Some(e.to_string());
+                // nothing more will come of it
+                progress.samples = job.spp;
+                return;
+            }
+        };
+        let camera = scene.view.camera(job.width as Float / job.height as Float);
+        let (mut film, integrator) = (job.film(0..job.height), job.integrator());
+        while film.samples < job.spp && !shared.stopped.load(Ordering::Relaxed) {
+            let samples = job.pass_samples.max(1).min(job.spp - film.samples);
+            film.render_pass(&camera, &scene.world, &integrator, samples);
+            let image = film.color();
+            let mut progress = shared.progress.lock().unwrap();
+            progress.samples = film.samples;
+            progress.image = image;
+        }
+    });
+    render
+}
+
+/// a setting from the query, or its default if it's left out
+fn setting<T: FromStr>(
+    query: &HashMap<String, String>,
+    name: &str,
+    default: T,
+) -> Result<T, (u16, String)>
+where
+    T::Err: ToString,
+{
+    match query.get(name) {
+        Some(value) => value
+            .parse()
+            .map_err(|e: T::Err| (400, format!("bad {name}: {}", e.to_string()))),
+        None => Ok(default),
+    }
+}
+
+/// the `name=value` pairs of a query string, percent decoded
+fn parse_query(query: &str) -> HashMap<String, String> {
+    let decode = |s: &str| {
+        let s = s.replace('+', " ");
+        let mut bytes = vec![];
+        let mut rest = s.as_bytes();
+        while let Some((&b, tail)) = rest.split_first() {
+            let hex = tail
+                .get(..2)
+                .and_then(|h| std::str::from_utf8(h).ok())
+                .and_then(|h| u8::from_str_radix(h, 16).ok());
+            match (b, hex) {
+                (b'%', Some(byte)) => {
+                    bytes.push(byte);
+                    rest = &tail[2..];
+                }
+                _ => {
+                    bytes.push(b);
+                    rest = tail;
+                }
+            }
+        }
+        String::from_utf8_lossy(&bytes).into_owned()
+    };
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (decode(name), decode(value))
+        })
+        .collect()
+}
+
+fn json_reply(value: serde_json::Value) -> Reply {
+    data_reply(value.to_string().into_bytes(), "application/json")
+}
+
+fn data_reply(data: Vec<u8>, content_type: &str) -> Reply {
+    let header = Header::from_bytes("Content-Type", content_type).unwrap();
+    Response::from_data(data).with_header(header)
+}
+
+#[test]
+fn renders_through_the_api() {
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+
+    let server = RenderServer::new("127.0.0.1:0", PathBuf::from("scenes")).unwrap();
+    let addr = server.addr().unwrap();
+    thread::spawn(|| server.run());
+    let request = |method: &str, path: &str, body: &str| {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(
+            stream,
+            "{method} {path} HTTP/1.0\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        )
+        .unwrap();
+        let mut reply = vec![];
+        stream.read_to_end(&mut reply).unwrap();
+        let split = reply.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+        let head = String::from_utf8_lossy(&reply[..split]).into_owned();
+        (head, reply[split + 4..].to_vec())
+    };
+
+    let scene = std::fs::read_to_string("scenes/cornell.ron").unwrap();
+    let (head, body) = request("POST", "/renders?width=8&height=6&spp=2", &scene);
+    assert!(head.contains("201"), "{head}");
+    let id = serde_json::from_slice::<serde_json::Value>(&body).unwrap()["id"].clone();
+    loop {
+        let (_, body) = request("GET", &format!("/renders/{id}"), "");
+        let status: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert!(status["error"].is_null());
+        if status["done"] == true {
+            assert_eq!(status["samples"], 2);
+            break;
+        }
+        thread::sleep(std::time::Duration::from_millis(10));
+    }
+    let (_, png) = request(
+        "GET",
+        &format!("/renders/{id}/image.png?tone_map=reinhard%3A4"),
+        "",
+    );
+    let image = image::load_from_memory(&png).unwrap();
+    assert_eq!((image.width(), image.height()), (8, 6));
+
+    let (head, _) = request("POST", "/renders", "not a scene");
+    assert!(head.contains("400"), "{head}");
+    let (head, _) = request("POST", "/renders?scene=teapot", "");
+    assert!(head.contains("400"), "{head}");
+    for settings in [
+        "width=0",
+        "width=1",
+        "width=100000&height=100000",
+        "spp=4000000000",
+    ] {
+        let (head, _) = request("POST", &format!("/renders?{settings}"), "");
+        assert!(head.contains("400"), "{settings}: {head}");
+    }
+    let (head, _) = request("DELETE", &format!("/renders/{id}"), "");
+    assert!(head.contains("204"), "{head}");
+    let (head, _) = request("GET", &format!("/renders/{id}"), "");
+    assert!(head.contains("404"), "{head}");
+}
@@ -0,0 +1,386 @@
+//! stochastic progressive photon mapping: light is followed forwards from the lights as well as
+//! back from the camera, and the two meet where photons land near what each pixel sees. it finds
+//! caustics, like light focused through glass onto the floor, that paths from the camera alone
+//! almost never do, at the cost of a blur that fades as the iterations go on.
+//!
+//! only the world's lights send out photons, so emissive shapes that aren't sampled as lights,
+//! and the background, light what the camera sees directly or through mirrors and glass, but
+//! nothing by bouncing off other surfaces
+
+use crate::integrator::direct_light;
+use crate::math::{consts::PI, mix_seed, random, seed_rng, Float, Normalize, Vec3};
+use crate::rt::{
+    Camera, Color, Footprint, InteriorStack, Material, Photon, Ray, Shape, World, MAX_PASS_THROUGHS,
+};
+use image::{Rgb, Rgb32FImage};
+#[cfg(not(target_arch = "wasm32"))]
+use rayon::prelude::*;
+use std::collections::HashMap;
+
+/// fraction of the photons gathered each iteration that the radius keeps as it shrinks.
+/// lower values shrink it faster, blurring less but leaving more noise
+const ALPHA: Float = 2. / 3.;
+
+/// initial gathering radius, in pixels across at the surface first seen through the pixel
+const INITIAL_RADIUS: Float = 2.;
+
+/// where a photon landed
+#[derive(Clone, Copy, Debug)]
+struct PhotonHit {
+    point: Vec3,
+    /// unit direction it arrived along
+    direction: Vec3,
+    power: Color,
+}
+
+/// the photons of an iteration, binned into cubes to find the ones near a point
+struct PhotonGrid {
+    cell: Float,
+    cells: HashMap<(i64, i64, i64), Vec<PhotonHit>>,
+}
+
+impl PhotonGrid {
+    /// constructor, with cubes `cell` across
+    fn new(photons: Vec<PhotonHit>, cell: Float) -> Self {
+        let mut grid = Self {
+            cell,
+            cells: HashMap::new(),
+        };
+        for photon in photons {
+            let key = grid.key(photon.point);
+            grid.cells.entry(key).or_default().push(photon);
+        }
+        grid
+    }
+
+    /// the cube a point is in
+    fn key(&self, p: Vec3) -> (i64, i64, i64) {
+        let cell = |c: Float| (c / self.cell).floor() as i64;
+        (cell(p.x), cell(p.y), cell(p.z))
+    }
+
+    /// every photon within `radius` of `point`
+    fn near(&self, point: Vec3, radius: Float) -> impl Iterator<Item = &PhotonHit> {
+        let min = self.key(point - radius * Vec3::ONE);
+        let max = self.key(point + radius * Vec3::ONE);
+        let span = (max.0 - min.0 + 1) * (max.1 - min.1 + 1) * (max.2 - min.2 + 1);
+        // a radius far bigger than the cubes looks through every photon instead
+        let cells: Vec<&Vec<PhotonHit>> = if span as usize > self.cells.len() {
+            self.cells.values().collect()
+        } else {
+            (min.0..=max.0)
+                .flat_map(|x| {
+                    (min.1..=max.1).flat_map(move |y| (min.2..=max.2).map(move |z| (x, y, z)))
+                })
+                .filter_map(|key| self.cells.get(&key))
+                .collect()
+        };
+        cells
+            .into_iter()
+            .flatten()
+            .filter(move |photon| (photon.point - point).length_squared() <= radius * radius)
+    }
+}
+
+/// what's been gathered through a pixel
+#[derive(Clone, Copy, Debug, Default)]
+struct Pixel {
+    /// photons further than this from what the pixel sees aren't gathered
+    radius: Float,
+    /// photons gathered so far, discounted as the radius shrank
+    count: Float,
+    /// power of the photons gathered, scaled down with the area of the radius as it shrank
+    flux: Color,
+    /// light seen directly and through mirrors and glass, summed over the iterations
+    direct: Color,
+}
+
+/// a render by photon mapping, refined iteration by iteration
+pub struct Sppm {
+    pub width: u32,
+    pub height: u32,
+    /// photons sent out from the lights each iteration
+    pub photons: u32,
+    /// iterations done so far, each taking a sample of every pixel
+    pub iterations: u32,
+    /// every random number is derived from this, so the same settings render the same image
+    pub seed: u64,
+    /// a sphere around what the camera sees, as its center and radius
+    bounds: (Vec3, Float),
+    /// row by row from the top of the image
+    pixels: Vec<Pixel>,
+}
+
+impl Sppm {
+    /// constructor for a render sending out `photons` photons an iteration
+    pub fn new(width: u32, height: u32, photons: u32) -> Self {
+        Self {
+            width,
+            height,
+            photons,
+            iterations: 0,
+            seed: 0,
+            bounds: (Vec3::ZERO, 1.),
+            pixels: vec![Pixel::default(); (width * height) as usize],
+        }
+    }
+
+    /// sends out a round of photons and gathers them around a sample of every pixel, following
+    /// paths of up to `max_depth` bounces from either end
+    pub fn iterate<C>(&mut self, camera: &C, world: &World, max_depth: u32)
+    where
+        C: Camera + Sync,
+    {
+        if self.iterations == 0 {
+            self.start(camera, world);
+        }
+        let (width, height, bounds) = (self.width, self.height, self.bounds);
+        let seed = mix_seed(self.seed, self.iterations as u64);
+        let trace = |i: u32| {
+            seed_rng(mix_seed(seed, i as u64));
+            trace_photon(world, bounds, max_depth)
+        };
+        #[cfg(not(target_arch = "wasm32"))]
+        let photons = (0..self.photons).into_par_iter().flat_map_iter(trace);
+        #[cfg(target_arch = "wasm32")]
+        let photons = (0..self.photons).flat_map(trace);
+        // cubes about as big as the radius of a typical pixel
+        let mut radii: Vec<Float> = self.pixels.iter().map(|p| p.radius).collect();
+        let middle = radii.len() / 2;
+        let (_, &mut cell, _) = radii.select_nth_unstable_by(middle, |a, b| a.total_cmp(b));
+        let grid = PhotonGrid::new(photons.collect(), cell);
+
+        // the camera's random numbers, apart from the photons'
+        let seed = !seed;
+        #[cfg(not(target_arch = "wasm32"))]
+        let pixels = self.pixels.par_iter_mut();
+        #[cfg(target_arch = "wasm32")]
+        let pixels = self.pixels.iter_mut();
+        pixels.enumerate().for_each(|(i, pixel)| {
+            seed_rng(mix_seed(seed, i as u64));
+            let ray = pixel_ray(camera, width, height, i, (random(), random()));
+            pixel.gather(ray, world, &grid, max_depth);
+        });
+        self.iterations += 1;
+    }
+
+    /// sizes the radius of each pixel to a few pixels across at what it sees, and bounds the
+    /// scene around that
+    fn start<C>(&mut self, camera: &C, world: &World)
+    where
+        C: Camera + Sync,
+    {
+        let (width, height) = (self.width, self.height);
+        let seen = |i: usize| {
+            let ray = pixel_ray(camera, width, height, i, (0.5, 0.5));
+            let next = pixel_ray(camera, width, height, i, (1.5, 0.5));
+            let contact = world.hit(ray, 0.0..Float::INFINITY)?;
+            let angle = (next.direction.normalize() - ray.direction.normalize()).length();
+            let distance = contact.t * ray.direction.length();
+            Some((contact.point, INITIAL_RADIUS * distance * angle))
+        };
+        #[cfg(not(target_arch = "wasm32"))]
+        let seen: Vec<_> = (0..self.pixels.len()).into_par_iter().map(seen).collect();
+        #[cfg(target_arch = "wasm32")]
+        let seen: Vec<_> = (0..self.pixels.len()).map(seen).collect();
+
+        let hits: Vec<_> = seen.iter().flatten().collect();
+        if hits.is_empty() {
+            self.pixels.iter_mut().for_each(|p| p.radius = 1.);
+            return;
+        }
+        let (min, max) = hits.iter().fold(
+            (Vec3::ONE * Float::INFINITY, Vec3::ONE * -Float::INFINITY),
+            |(min, max), &&(point, _)| (min.min(point), max.max(point)),
+        );
+        self.bounds = ((min + max) / 2., ((max - min).length() / 2.).max(1e-3));
+        // pixels that see nothing through their center start out at the average
+        let average = hits.iter().map(|&&(_, r)| r).sum::<Float>() / hits.len() as Float;
+        for (pixel, seen) in self.pixels.iter_mut().zip(&seen) {
+            pixel.radius = seen.map_or(average, |(_, r)| r).max(1e-6);
+        }
+    }
+
+    /// current estimate of the linear radiance reaching each pixel
+    pub fn color(&self) -> Rgb32FImage {
+        let iterations = self.iterations.max(1) as Float;
+        let photons = iterations * self.photons.max(1) as Float;
+        Rgb32FImage::from_fn(self.width, self.height, |x, y| {
+            let pixel = &self.pixels[(y * self.width + x) as usize];
+            let area = PI * pixel.radius * pixel.radius;
+            let color = pixel.direct / iterations + pixel.flux / (photons * area);
+            Rgb(color.to_array())
+        })
+    }
+}
+
+impl Pixel {
+    /// follows a path from the camera through mirrors and glass to the first surface that
+    /// scatters light diffusely, adding the light seen on the way and the photons landed there
+    fn gather(&mut self, ray: Ray, world: &World, grid: &PhotonGrid, max_depth: u32) {
+        let mut throughput = Color::WHITE;
+        let mut ray = ray;
+        let mut interiors = InteriorStack::default();
+        let (mut bounce, mut passes) = (0, 0);
+        while bounce < max_depth {
+            let Some(mut contact) = world.hit(ray, 0.0..Float::INFINITY) else {
+                self.direct += throughput * world.background.color(ray);
+                return;
+            };
+            // passed through without a bounce, as in the path tracer
+            if !interiors.hit(ray, &mut contact) {
+                passes += 1;
+                if passes > MAX_PASS_THROUGHS {
+                    return;
+                }
+                ray = contact.spawn(ray, ray.direction);
+                continue;
+            }
+            bounce += 1;
+            self.direct += throughput * contact.material.emitted(&contact);
+            let Some(scatter) = contact.material.scatter(ray, &contact) else {
+                return;
+            };
+            if scatter.pdf.is_none() {
+                // specular, so the path finds anything lighting it by going on
+                interiors.scattered(&contact, scatter.ray);
+                throughput *= scatter.attenuation;
+                ray = scatter.ray;
+                continue;
+            }
+
+            // the photons only carry light that's bounced at least once, so the lights
+            // themselves are sampled in full
+            self.direct += throughput * direct_light(ray, world, &contact, false, None);
+            let (mut flux, mut count) = (Color::BLACK, 0.);
+            for photon in grid.near(contact.point, self.radius) {
+                flux += photon.power * contact.material.brdf(ray, &contact, -photon.direction);
+                count += 1.;
+            }
+            if count > 0. {
+                // shrink the radius, keeping just enough of the new photons as if they'd been
+                // gathered within it all along
+                let kept = self.count + ALPHA * count;
+                let radius = self.radius * (kept / (self.count + count)).sqrt();
+                let shrink = radius / self.radius;
+                self.flux = (self.flux + throughput * flux) * (shrink * shrink);
+                self.count = kept;
+                self.radius = radius;
+            }
+            return;
+        }
+    }
+}
+
+/// the ray through pixel `i`, counting row by row from the top, at `offset` within it
+fn pixel_ray<C: Camera>(
+    camera: &C,
+    width: u32,
+    height: u32,
+    i: usize,
+    offset: (Float, Float),
+) -> Ray {
+    let x = i as u32 % width;
+    // screen space runs from the bottom up
+    let y = height - 1 - i as u32 / width;
+    let dx = (x as Float + offset.0) / ((width - 1) as Float);
+    let dy = (y as Float + offset.1) / ((height - 1) as Float);
+    Ray {
+        footprint: Footprint::spreading(camera.pixel_spread(height)),
+        ..camera.get_screen_ray(dx, dy)
+    }
+}
+
+/// follows a photon out of a light picked at random, and returns everywhere it lands after its
+/// first bounce, which the camera's paths find by sampling the lights directly
+fn trace_photon(world: &World, bounds: (Vec3, Float), max_depth: u32) -> Vec<PhotonHit> {
+    let mut hits = vec![];
+    let lights = world.lights.len();
+    if lights == 0 {
+        return hits;
+    }
+    let light = &world.lights[((random::<Float>() * lights as Float) as usize).min(lights - 1)];
+    let Some(Photon { mut ray, power }) = light.emit(bounds) else {
+        return hits;
+    };
+    // over the chance of picking the light
+    let mut power = power * lights as Float;
+    let mut interiors = InteriorStack::default();
+    let (mut bounce, mut passes) = (0, 0);
+    while bounce < max_depth {
+        let Some(mut contact) = world.hit(ray, 0.0..Float::INFINITY) else {
+            break;
+        };
+        if !interiors.hit(ray, &mut contact) {
+            passes += 1;
+            if passes > MAX_PASS_THROUGHS {
+                break;
+            }
+            ray = contact.spawn(ray, ray.direction);
+            continue;
+        }
+        bounce += 1;
+        if bounce > 1 {
+            hits.push(PhotonHit {
+                point: contact.point,
+                direction: ray.direction.normalize(),
+                power,
+            });
+        }
+        let Some(scatter) = contact.material.scatter(ray, &contact) else {
+            break;
+        };
+        // russian roulette: photons that lose most of their power mostly stop there, and the
+        // rest carry on as bright as before
+        let scattered = power * scatter.attenuation;
+        let survival = if power.luminance() > 0. {
+            (scattered.luminance() / power.luminance()).min(1.)
+        } else {
+            0.
+        };
+        if random::<Float>() >= survival {
+            break;
+        }
+        power = scattered / survival;
+        interiors.scattered(&contact, scatter.ray);
+        ray = scatter.ray;
+    }
+    hits
+}
+
+#[test]
+fn photons_match_paths() {
+    use crate::integrator::PathTracer;
+    use crate::render::Film;
+    use crate::rt::*;
+    // a lit floor and a ball, where the floor lights the underside of the ball by bouncing
+    let scene = SceneBuilder::new()
+        .look_at(Vec3::new(0., 2., -5.), Vec3::new(0., 0.5, 0.))
+        .background(SolidBackground::from(Color::BLACK))
+        .sphere(Vec3::new(0., -100., 0.), 100.)
+        .diffuse(Color::gray(0.8))
+        .sphere(Vec3::new(0., 1., 0.), 1.)
+        .diffuse(Color::new(0.8, 0.5, 0.3))
+        .light(PointLight::new(Vec3::new(2., 5., -2.), Color::gray(20.)))
+        .build();
+    let camera = scene.view.camera(1.);
+    let average = |image: image::Rgb32FImage| {
+        let sum = image
+            .pixels()
+            .fold(Color::BLACK, |sum, p| sum + Color::from(p.0));
+        sum / (image.width() * image.height()) as Float
+    };
+
+    let mut film = Film::new(16, 16, false);
+    film.render_pass(&camera, &scene.world, &PathTracer::new(10), 256);
+    let mut sppm = Sppm::new(16, 16, 20000);
+    for _ in 0..16 {
+        sppm.iterate(&camera, &scene.world, 10);
+    }
+    let (path, photons) = (average(film.color()), average(sppm.color()));
+    let error = (photons - path) / path;
+    assert!(
+        error.r.abs() < 0.05 && error.g.abs() < 0.05 && error.b.abs() < 0.05,
+        "{path:?} {photons:?}"
+    );
+}
@@ -0,0 +1,115 @@
+//! counts of the work a render does, to see where its time goes. they're only kept with the
+//! `stats` feature on, as every thread counting into the same atomics slows the render down,
+//! and without it counting compiles away to nothing
+
+#[cfg(feature = "stats")]
+use std::cell::Cell;
+use std::fmt;
+#[cfg(feature = "stats")]
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// what's counted
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Counter {
+    /// rays traced through the world, shadow rays included
+    Rays,
+    /// rays traced towards a light to see if anything's in the way
+    ShadowRays,
+    /// rays tested against a shape the world holds
+    IntersectionTests,
+    /// paths traced from the camera
+    Paths,
+    /// rays traced along those paths
+    Bounces,
+}
+
+#[cfg(feature = "stats")]
+static COUNTS: [AtomicU64; 5] = [const { AtomicU64::new(0) }; 5];
+
+#[cfg(feature = "stats")]
+thread_local! {
+    /// what the current thread has counted, for work that's all done on one thread
+    static THREAD_COUNTS: [Cell<u64>; 5] = const { [const { Cell::new(0) }; 5] };
+}
+
+/// adds `n` to a counter
+#[inline(always)]
+pub fn count(counter: Counter, n: u64) {
+    #[cfg(feature = "stats")]
+    {
+        COUNTS[counter as usize].fetch_add(n, Ordering::Relaxed);
+        THREAD_COUNTS
+            .with(|counts| counts[counter as usize].set(counts[counter as usize].get() + n));
+    }
+    #[cfg(not(feature = "stats"))]
+    let _ = (counter, n);
+}
+
+/// what the current thread has counted so far, never started over. the difference across a
+/// call is what it counted, whatever other threads were doing. always zero without the `stats`
+/// feature
+pub fn thread_count(counter: Counter) -> u64 {
+    #[cfg(feature = "stats")]
+    return THREAD_COUNTS.with(|counts| counts[counter as usize].get());
+    #[cfg(not(feature = "stats"))]
+    {
+        let _ = counter;
+        0
+    }
+}
+
+/// the counts of a render
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub rays: u64,
+    pub shadow_rays: u64,
+    pub intersection_tests: u64,
+    pub paths: u64,
+    pub bounces: u64,
+}
+
+impl Stats {
+    /// the counts so far, starting them over from zero. always zero without the `stats` feature
+    pub fn take() -> Self {
+        #[cfg(feature = "stats")]
+        let take = |counter: Counter| COUNTS[counter as usize].swap(0, Ordering::Relaxed);
+        #[cfg(not(feature = "stats"))]
+        let take = |_: Counter| 0;
+        Self {
+            rays: take(Counter::Rays),
+            shadow_rays: take(Counter::ShadowRays),
+            intersection_tests: take(Counter::IntersectionTests),
+            paths: take(Counter::Paths),
+            bounces: take(Counter::Bounces),
+        }
+    }
+
+    /// rays traced along each path on average
+    pub fn average_depth(&self) -> f64 {
+        self.bounces as f64 / self.paths.max(1) as f64
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "rays:               {}", self.rays)?;
+        writeln!(f, "shadow rays:        {}", self.shadow_rays)?;
+        writeln!(f, "intersection tests: {}", self.intersection_tests)?;
+        writeln!(f, "paths:              {}", self.paths)?;
+        write!(f, "average path depth: {:.2}", self.average_depth())
+    }
+}
+
+#[test]
+fn stats_report() {
+    let stats = Stats {
+        rays: 30,
+        shadow_rays: 10,
+        intersection_tests: 120,
+        paths: 8,
+        bounces: 20,
+    };
+    assert_eq!(stats.average_depth(), 2.5);
+    assert!(stats.to_string().ends_with("average path depth: 2.50"));
+    assert_eq!(Stats::default().average_depth(), 0.);
+}
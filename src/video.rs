@@ -0,0 +1,97 @@
+use crate::math::Float;
+use crate::output::display_rgba8;
+use image::Rgb32FImage;
+use std::{
+    env,
+    io::{self, Write},
+    process::{Child, ChildStdin, Command, Stdio},
+};
+
+/// encodes tone mapped frames straight into a video, in the format given by the extension,
+/// such as `.mp4` or `.webm`. shells out to `ffmpeg`, or to `$FFMPEG` if set, piping it raw pixels
+pub struct Video {
+    ffmpeg: Child,
+    input: Option<ChildStdin>,
+    width: u32,
+    height: u32,
+}
+
+impl Video {
+    /// starts encoding a video of `width` × `height` frames to `path`
+    pub fn new(path: &str, width: u32, height: u32, fps: Float) -> io::Result<Self> {
+        let program = env::var("FFMPEG").unwrap_or_else(|_| "ffmpeg".to_string());
+        let mut ffmpeg = Command::new(&program)
+            .args(["-y", "-loglevel", "error"])
+            .args(["-f", "rawvideo", "-pix_fmt", "rgba"])
+            .args(["-s", &format!("{width}x{height}")])
+            .args(["-framerate", &fps.to_string()])
+            .args(["-i", "-"])
+            // the chroma subsampling most players expect, which needs even dimensions
+            .args(["-pix_fmt", "yuv420p"])
+            .args(["-vf", "pad=ceil(iw/2)*2:ceil(ih/2)*2"])
+            .arg(path)
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|e| io::Error::new(e.kind(), format!("could not run {program}: {e}")))?;
+        let input = ffmpeg.stdin.take();
+        Ok(Self {
+            ffmpeg,
+            input,
+            width,
+            height,
+        })
+    }
+
+    /// adds the next frame
+    pub fn push(&mut self, frame: &Rgb32FImage) -> io::Result<()> {
+        if frame.dimensions() != (self.width, self.height) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "frame is a different size from the video",
+            ));
+        }
+        match &mut self.input {
+            Some(input) => input.write_all(&display_rgba8(frame)),
+            None => Err(io::Error::other("ffmpeg isn't taking frames")),
+        }
+    }
+
+    /// waits for the video to be written out. a video dropped without finishing stops ffmpeg
+    /// where it is
+    pub fn finish(mut self) -> io::Result<()> {
+        // closing its input tells ffmpeg there are no more frames
+        drop(self.input.take());
+        let status = self.ffmpeg.wait()?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(io::Error::other(format!("ffmpeg failed with {status}")))
+        }
+    }
+}
+
+impl Drop for Video {
+    fn drop(&mut self) {
+        if let Ok(None) = self.ffmpeg.try_wait() {
+            self.ffmpeg.kill().ok();
+            self.ffmpeg.wait().ok();
+        }
+    }
+}
+
+#[test]
+fn dropped_videos_stop_ffmpeg() {
+    let program = env::var("FFMPEG").unwrap_or_else(|_| "ffmpeg".to_string());
+    if Command::new(&program).arg("-version").output().is_err() {
+        eprintln!("skipped, as there's no {program} to run");
+        return;
+    }
+    let path = env::temp_dir().join("saraytracer-dropped.mp4");
+    let mut video = Video::new(path.to_str().unwrap(), 4, 2, 24.).unwrap();
+    video.push(&Rgb32FImage::new(4, 2)).unwrap();
+    let pid = video.ffmpeg.id();
+    drop(video);
+    // killed and waited on, so not even a zombie is left behind
+    assert!(!std::path::Path::new(&format!("/proc/{pid}")).exists());
+    std::fs::remove_file(path).ok();
+}
@@ -0,0 +1,76 @@
+use crate::integrator::PathTracer;
+use crate::math::Float;
+use crate::output::{display_rgba8, encode_display, BitDepth};
+use crate::post::{Exposure, Pipeline};
+use crate::render::Film;
+use crate::rt::{Camera, Scene, ToneMap};
+use crate::scene::SceneFile;
+use image::ImageOutputFormat;
+use std::path::Path;
+use wasm_bindgen::prelude::*;
+
+/// renders a scene progressively in the browser, a pass at a time between frames,
+/// for the page to draw on a canvas. see `web/` for a page that does
+#[wasm_bindgen]
+pub struct WebRender {
+    scene: Scene,
+    camera: Box<dyn Camera + Send + Sync>,
+    film: Film,
+    /// bounces per path. every pass blocks the page, so keep it low
+    pub max_depth: u32,
+    /// in stops
+    pub exposure: Float,
+}
+
+#[wasm_bindgen]
+impl WebRender {
+    /// reads a scene from the text of a ron scene file. it can't use images,
+    /// having no files to load them from
+    #[wasm_bindgen(constructor)]
+    pub fn new(scene: &str, width: u32, height: u32) -> Result<WebRender, JsError> {
+        let scene = SceneFile::parse(scene)?.scene(Path::new("."))?;
+        let aspect_ratio = width as Float / height as Float;
+        let camera = scene
+            .view
+            .autofocus(&scene.world, aspect_ratio)
+            .camera(aspect_ratio);
+        Ok(Self {
+            scene,
+            camera,
+            film: Film::new(width, height, false),
+            max_depth: 8,
+            exposure: 0.,
+        })
+    }
+
+    /// takes `samples` more samples of every pixel
+    pub fn render_pass(&mut self, samples: u32) {
+        let (camera, world) = (&self.camera, &self.scene.world);
+        let integrator = PathTracer::new(self.max_depth);
+        self.film.render_pass(camera, world, &integrator, samples);
+    }
+
+    /// samples taken of every pixel so far
+    pub fn samples(&self) -> u32 {
+        self.film.samples
+    }
+
+    /// the render so far as rgba bytes, for an `ImageData`
+    pub fn pixels(&self) -> Vec<u8> {
+        display_rgba8(&self.display())
+    }
+
+    /// the render so far as a png file, to download
+    pub fn png(&self) -> Result<Vec<u8>, JsError> {
+        let png = encode_display(&self.display(), ImageOutputFormat::Png, BitDepth::Eight)?;
+        Ok(png)
+    }
+
+    fn display(&self) -> image::Rgb32FImage {
+        let exposure = Exposure {
+            stops: self.exposure,
+            auto: false,
+        };
+        Pipeline::standard(exposure, ToneMap::Aces).develop(&self.film.color())
+    }
+}
@@ -0,0 +1,36 @@
+//! the counts a render keeps with the `stats` feature. a test binary of its own, as the counts
+//! are shared by every thread in the process and other tests would add to them
+
+#![cfg(feature = "stats")]
+
+use raytracer::integrator::PathTracer;
+use raytracer::math::Vec3;
+use raytracer::render::Film;
+use raytracer::rt::*;
+use raytracer::stats::Stats;
+
+#[test]
+fn render_counts() {
+    // a wall filling the view, lit by a point light in front of it
+    let scene = SceneBuilder::new()
+        .look_at(Vec3::new(0., 0., -5.), Vec3::ZERO)
+        .background(SolidBackground::from(Color::BLACK))
+        .quad(
+            Vec3::new(-50., -50., 5.),
+            Vec3::new(100., 0., 0.),
+            Vec3::new(0., 100., 0.),
+        )
+        .diffuse(Color::WHITE)
+        .light(PointLight::new(Vec3::new(0., 0., 2.), Color::WHITE))
+        .build();
+    let camera = scene.view.camera(4. / 3.);
+    Stats::take();
+    let mut film = Film::new(4, 3, false);
+    film.render_pass(&camera, &scene.world, &PathTracer::new(1), 2);
+
+    // each of the 24 paths is a ray to the wall and a shadow ray back to the light
+    let stats = Stats::take();
+    assert_eq!((stats.paths, stats.bounces), (24, 24));
+    assert_eq!((stats.rays, stats.shadow_rays), (48, 24));
+    assert_eq!(stats.intersection_tests, 48);
+}
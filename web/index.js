@@ -0,0 +1,30 @@
+// renders scenes/cornell.ron a sample per pixel each frame, drawing the estimate so far.
+// expects the wasm-bindgen output in web/pkg, and the repository root to be served
+import init, { WebRender } from "./pkg/raytracer.js";
+
+const MAX_SAMPLES = 1024;
+
+await init();
+const canvas = document.getElementById("canvas");
+const context = canvas.getContext("2d");
+const scene = await (await fetch("../scenes/cornell.ron")).text();
+const render = new WebRender(scene, canvas.width, canvas.height);
+
+function frame() {
+  render.render_pass(1);
+  const pixels = new Uint8ClampedArray(render.pixels());
+  context.putImageData(new ImageData(pixels, canvas.width, canvas.height), 0, 0);
+  document.getElementById("samples").textContent = `${render.samples()} samples`;
+  if (render.samples() < MAX_SAMPLES) {
+    requestAnimationFrame(frame);
+  }
+}
+requestAnimationFrame(frame);
+
+document.getElementById("save").onclick = (event) => {
+  event.preventDefault();
+  const link = document.createElement("a");
+  link.href = URL.createObjectURL(new Blob([render.png()], { type: "image/png" }));
+  link.download = "render.png";
+  link.click();
+};